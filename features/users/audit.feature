@@ -0,0 +1,72 @@
+Feature: Audit feature
+
+  Scenario: A failed login is audited
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password wrong
+    And I list the login.failed audit events
+    Then the latest audit event is login.failed targeting the user
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A login is audited once the user gets its tokens
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I list the audit events
+    Then the latest audit event is login.succeeded targeting the user
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A login waiting for an MFA code is only audited as a verified password
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    And I login with username <username> and password <password>
+    And I enroll in TOTP
+    And I confirm TOTP with a valid code
+    When I login with username <username> and password <password>
+    And I list the audit events
+    Then the latest audit event is login.password_verified targeting the user
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A role grant is audited
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I create a role auditor allowed to read users
+    And I grant the role auditor to the user
+    And I list the audit events
+    Then the latest audit event is role.granted targeting the user
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A registration is audited in the audit file
+    Given I have registered a user with username <username> and email <email> and password <password>
+    Then the audit file records user.registered of the user
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user cannot list audit events
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I list the audit events with my token
+    Then I get an authorization error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
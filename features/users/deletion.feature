@@ -0,0 +1,46 @@
+Feature: User deactivation and deletion feature
+
+  Background:
+    Given I am logged in as an administrator
+    And I have registered a user with username alice and email alice@secret.org and password s3cr3t
+    And I have verified my email
+
+  Scenario: A deactivated user cannot login
+    When I deactivate the user
+    And I login with username alice and password s3cr3t
+    Then I get an inactive account error
+
+  Scenario: A deactivated user loses its sessions
+    When I login with username alice and password s3cr3t
+    And I deactivate the user
+    Then I cannot access content for users
+
+  Scenario: A reactivated user can login again
+    When I deactivate the user
+    And I reactivate the user
+    And I login with username alice and password s3cr3t
+    Then I receive a token and a refresh token
+
+  Scenario: A soft deleted user is hidden and cannot login
+    When I soft delete the user
+    And I login with username alice and password s3cr3t
+    And I search for a user with username alice
+    Then I get an unknown user error
+    And I can verify the user does not exists
+
+  Scenario: A soft deleted user keeps its username
+    When I soft delete the user
+    And I register a user with username alice and email alice2@secret.org and password s3cr3t in organization default
+    Then I get a duplicate username error
+
+  Scenario: A purged user loses its sessions
+    When I login with username alice and password s3cr3t
+    And I soft delete the user
+    And I purge the user
+    Then I cannot access content for users
+
+  Scenario: A purged user releases its username
+    When I soft delete the user
+    And I purge the user
+    And I register a user with username alice and email alice2@secret.org and password s3cr3t in organization default
+    Then I get no error
@@ -0,0 +1,28 @@
+Feature: Login lockout feature
+
+  Background:
+    Given I am logged in as an administrator
+    And I have registered a user with username alice and email alice@secret.org and password s3cr3t
+    And I have verified my email
+
+  Scenario: A user failing to login too often is locked out
+    When I fail to login as alice 3 times
+    And I login with username alice and password s3cr3t
+    Then I get a lockout error
+
+  Scenario: A user failing to login less often is not locked out
+    When I fail to login as alice 2 times
+    And I login with username alice and password s3cr3t
+    Then I receive a token and a refresh token
+
+  Scenario: An unlocked user can login again
+    When I fail to login as alice 3 times
+    And I unlock the user
+    And I login with username alice and password s3cr3t
+    Then I receive a token and a refresh token
+
+  Scenario: Unlocking is audited
+    When I fail to login as alice 3 times
+    And I unlock the user
+    And I list the audit events
+    Then the latest audit event is account.unlocked targeting the user
@@ -0,0 +1,59 @@
+Feature: User lookup feature
+
+  Scenario: A user can find out who it is
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I ask who I am
+    Then I can verify the username <username> in the response
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user can look up its own record
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I look up the user by id
+    And I look up the user with email <email>
+    Then I can verify the username <username> in the response
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user cannot look up someone else
+    Given I have registered a user with username bob and email bob@secret.org and password b0b
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I look up the user with email bob@secret.org
+    Then I get an authorization error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user cannot look up someone else by username
+    Given I have registered a user with username bob and email bob@secret.org and password b0b
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I look up the user with username bob
+    Then I get an authorization error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: An administrator can look up anyone in its organization
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    When I look up the user by id
+    And I look up the user with email <email>
+    Then I can verify the username <username> in the response
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
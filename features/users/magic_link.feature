@@ -0,0 +1,33 @@
+Feature: Magic link feature
+
+  Background:
+    Given I have registered a user with username alice and email alice@secret.org and password s3cr3t
+    And I have verified my email
+
+  Scenario: A user logs in with a magic link
+    When I request a magic link for alice@secret.org
+    And I follow the magic link sent to alice@secret.org
+    Then I receive a token and a refresh token
+    And my token identifies me
+
+  Scenario: A magic link can only be used once
+    When I request a magic link for alice@secret.org
+    And I follow the magic link sent to alice@secret.org
+    And I follow the same magic link
+    Then I get an invalid magic link error
+
+  Scenario: A forged magic link is rejected
+    When I request a magic link for alice@secret.org
+    And I follow the magic link sent to alice@secret.org
+    And I follow a forged magic link
+    Then I get an invalid magic link error
+
+  Scenario: A browser bound magic link works in the browser which asked for it
+    When I request a browser bound magic link for alice@secret.org
+    And I follow the magic link sent to alice@secret.org
+    Then I receive a token and a refresh token
+
+  Scenario: A browser bound magic link does not work in another browser
+    When I request a browser bound magic link for alice@secret.org
+    And I follow the magic link sent to alice@secret.org in another browser
+    Then I get a browser binding error
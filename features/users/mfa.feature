@@ -0,0 +1,56 @@
+Feature: Two-factor authentication feature
+
+  Background:
+    Given I have registered a user with username alice and email alice@secret.org and password s3cr3t
+    And I have verified my email
+    And I login with username alice and password s3cr3t
+    And I enroll in TOTP
+    And I confirm TOTP with a valid code
+
+  Scenario: A user with TOTP is asked for a code at login
+    When I login with username alice and password s3cr3t
+    Then I am asked for an MFA code
+
+  Scenario: A user with TOTP logs in with a code
+    When I login with username alice and password s3cr3t
+    And I verify the login with a TOTP code
+    Then I receive a token and a refresh token
+
+  Scenario: A user with TOTP logs in with a recovery code
+    When I login with username alice and password s3cr3t
+    And I verify the login with a recovery code
+    Then I receive a token and a refresh token
+
+  Scenario: A TOTP code cannot be used twice
+    When I login with username alice and password s3cr3t
+    And I verify the login with a TOTP code
+    And I login with username alice and password s3cr3t
+    And I verify the login with the same code
+    Then I get an invalid MFA code error
+
+  Scenario: A wrong code is rejected
+    When I login with username alice and password s3cr3t
+    And I verify the login with a wrong code
+    Then I get an invalid MFA code error
+
+  Scenario: A recovery code cannot be used twice
+    When I login with username alice and password s3cr3t
+    And I verify the login with a recovery code
+    And I login with username alice and password s3cr3t
+    And I verify the login with the same code
+    Then I get an invalid MFA code error
+
+  Scenario: Wrong codes lock the user out
+    When I login with username alice and password s3cr3t
+    And I verify the login with a wrong code
+    And I verify the login with a wrong code
+    And I verify the login with a wrong code
+    And I login with username alice and password s3cr3t
+    Then I get a lockout error
+
+  Scenario: A user deactivated while asked for a code cannot complete the login
+    Given I am logged in as an administrator
+    When I login with username alice and password s3cr3t
+    And I deactivate the user
+    And I verify the login with a TOTP code
+    Then I get an inactive account error
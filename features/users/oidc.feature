@@ -0,0 +1,42 @@
+Feature: OpenID Connect feature
+
+  Background:
+    Given I am logged in as an administrator
+    And I have registered the OpenID Connect client Acme App with redirect URI https://app.acme.com/callback
+    And I have registered a user with username alice and email alice@secret.org and password s3cr3t
+    And I have verified my email
+
+  Scenario: A client discovers the provider
+    When I fetch the OpenID Connect configuration
+    Then the OpenID Connect issuer is https://auth.acme.com
+    And the OpenID Connect token endpoint is https://auth.acme.com/token
+
+  Scenario: A user logs in to a client
+    When I authorize the client as alice with password s3cr3t
+    And I exchange the authorization code
+    Then I receive an ID token for alice
+    And the user info is for alice
+    And the access token only gives access to the user info
+
+  Scenario: An authorization code can only be exchanged once
+    When I authorize the client as alice with password s3cr3t
+    And I exchange the authorization code
+    And I exchange the same authorization code
+    Then I get an OAuth error: invalid_grant
+
+  Scenario: An authorization code is bound to its code verifier
+    When I authorize the client as alice with password s3cr3t
+    And I exchange the authorization code with another code verifier
+    Then I get an OAuth error: invalid_grant
+
+  Scenario: A client cannot use an unregistered redirect URI
+    When I authorize the client as alice with password s3cr3t and redirect URI https://evil.com/callback
+    Then I get an OAuth error: invalid_request
+
+  Scenario: A user logs in with a wrong password
+    When I authorize the client as alice with password wrong
+    Then I get an invalid credentials error
+
+  Scenario: A redirect URI must use https
+    When I register the OpenID Connect client Evil App with redirect URI http://evil.com/callback
+    Then I get an invalid redirect URI error
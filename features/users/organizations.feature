@@ -0,0 +1,76 @@
+Feature: Organizations feature
+
+  Background:
+    Given I am logged in as an administrator
+    And I have created the organization acme
+
+  Scenario: A username can be taken in another organization
+    Given I have registered a user with username <username> and email <email> and password <password>
+    When I register a user with username <username> and email <email> and password <password> in organization acme
+    Then I can verify the username <username> in the response
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: Listing users only shows members of the organization
+    Given I have initialized the user database
+    When I register a user with username <username> and email <email> and password <password> in organization acme
+    And I list users
+    Then the response's users count is 1
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user of another organization cannot be added as a member
+    When I register a user with username <username> and email <email> and password <password> in organization acme
+    And I add the user as a member
+    Then I get an authorization error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: Another organization of a user cannot deactivate it
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    And the user has the role admin in every organization
+    When I login with username <username> and password <password>
+    And I create the organization <organization> with my token
+    And I switch to the organization
+    And I deactivate the user with my token
+    Then I get an authorization error
+
+    Examples:
+      | username | email            | password | organization |
+      | alice    | alice@secret.org | s3cr3t   | wonderland   |
+
+  Scenario: Another organization of a user can only remove it from its members
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    And the user has the role admin in every organization
+    When I login with username <username> and password <password>
+    And I create the organization <organization> with my token
+    And I switch to the organization
+    And I purge the user with my token
+    And I login with username <username> and password <password>
+    Then I get no error
+
+    Examples:
+      | username | email            | password | organization |
+      | alice    | alice@secret.org | s3cr3t   | wonderland   |
+
+  Scenario: A user can erase its own account from another organization
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    And the user has the role admin in every organization
+    When I login with username <username> and password <password>
+    And I create the organization <organization> with my token
+    And I switch to the organization
+    And I erase my account
+    Then a tombstone records the erasure of the user
+
+    Examples:
+      | username | email            | password | organization |
+      | alice    | alice@secret.org | s3cr3t   | wonderland   |
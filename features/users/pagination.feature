@@ -0,0 +1,45 @@
+Feature: Pagination feature
+
+  Background:
+    Given I am logged in as an administrator
+    And I have added the users alice, bob, carol
+
+  Scenario: Listing the first page of users
+    When I list the first 2 users
+    Then the page has 2 users
+    And the users are admin, alice
+    And there is a next page
+    And there is no previous page
+    And the response's users count is 4
+
+  Scenario: Listing the next page of users
+    When I list the first 2 users
+    And I list the next 2 users
+    Then the users are bob, carol
+    And there is no next page
+    And there is a previous page
+
+  Scenario: Listing the users before a page
+    When I list the first 2 users
+    And I list the next 2 users
+    And I list the 1 users before the first one
+    Then the users are alice
+    And there is a next page
+    And there is a previous page
+
+  Scenario: Mixing forward and backward pagination
+    When I list the first 2 users and the last 2 users
+    Then I get an invalid pagination error
+
+  Scenario: Requesting too many users
+    When I list the first 1000 users
+    Then I get an invalid pagination error
+
+  Scenario: Filtering users
+    When I list the inactive users
+    Then the users are alice, bob, carol
+    And the response's users count is 3
+
+  Scenario: Sorting users
+    When I list users by username descending
+    Then the users are carol, bob, alice, admin
@@ -0,0 +1,30 @@
+Feature: Passkeys feature
+
+  Background:
+    Given I have registered a user with username alice and email alice@secret.org and password s3cr3t
+    And I have verified my email
+    And I login with username alice and password s3cr3t
+    And I register a passkey
+
+  Scenario: A user logs in with a passkey
+    When I login with my passkey as alice
+    Then I receive a token and a refresh token
+    And my token identifies me
+
+  Scenario: A passkey login cannot be replayed
+    When I login with my passkey as alice
+    And I replay my passkey login
+    Then I get a passkey error: Invalid or expired passkey challenge
+
+  Scenario: A passkey signed by another authenticator is rejected
+    When I login with another authenticator as alice
+    Then I get a passkey error: Invalid signature
+
+  Scenario: A passkey cannot be registered twice
+    When I register a passkey
+    Then I get a passkey error: The passkey is already registered
+
+  Scenario: A user without a passkey cannot login with one
+    Given I have registered a user with username bob and email bob@secret.org and password s3cr3t
+    When I login with my passkey as bob
+    Then I get a passkey error: The user has no passkey
@@ -0,0 +1,56 @@
+Feature: Password reset feature
+
+  Scenario: A user can reset a forgotten password
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I request a password reset
+    And I reset my password to <new_password>
+    And I login with username <username> and password <new_password>
+    Then I receive a token and a refresh token
+    And I cannot login with username <username> and password <password>
+
+    Examples:
+      | username | email            | password | new_password |
+      | alice    | alice@secret.org | s3cr3t   | n3ws3cr3t    |
+
+  Scenario: Resetting the password revokes the refresh tokens
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I request a password reset
+    And I reset my password to <new_password>
+    Then I cannot refresh my token with my latest refresh token
+
+    Examples:
+      | username | email            | password | new_password |
+      | alice    | alice@secret.org | s3cr3t   | n3ws3cr3t    |
+
+  Scenario: A user can use the token of a login right after a password reset
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I request a password reset
+    And I reset my password to <new_password>
+    And I login with username <username> and password <new_password>
+    And I ask who I am
+    Then I get no error
+    And I can verify the username <username> in the response
+
+    Examples:
+      | username | email            | password | new_password |
+      | alice    | alice@secret.org | s3cr3t   | n3ws3cr3t    |
+
+  Scenario: A password reset token can only be used once
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I request a password reset
+    And I reset my password to <new_password>
+    And I reset my password to <password>
+    Then I get an invalid password reset token error
+
+    Examples:
+      | username | email            | password | new_password |
+      | alice    | alice@secret.org | s3cr3t   | n3ws3cr3t    |
+
+  Scenario: Requesting a password reset does not reveal unknown emails
+    When I request a password reset for nobody@secret.org
+    Then I get no error
@@ -0,0 +1,49 @@
+Feature: Privacy feature
+
+  Scenario: A user can export its data
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I export my data
+    Then my data export holds the username <username> and 1 login
+    And my data export holds no password
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: An administrator can erase a user
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I erase the user
+    And I login with username <username> and password <password>
+    Then a tombstone records the erasure of the user
+    And I get an unknown user error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: An erased user releases its username and email
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    When I erase the user
+    And I register a user with username <username> and email <email> and password <password> in organization default
+    Then I get no error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user can erase its own account
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I erase my account
+    Then a tombstone records the erasure of the user
+    And I cannot access content for users
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
@@ -0,0 +1,91 @@
+Feature: Profile feature
+
+  Scenario: A user can change its password
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I change my password from <password> to <new_password>
+    And I login with username <username> and password <new_password>
+    Then I receive a token and a refresh token
+    And I cannot login with username <username> and password <password>
+
+    Examples:
+      | username | email            | password | new_password |
+      | alice    | alice@secret.org | s3cr3t   | n3ws3cr3t    |
+
+  Scenario: Changing the password requires the current password
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I change my password from <new_password> to <new_password>
+    Then I get an invalid credentials error
+
+    Examples:
+      | username | email            | password | new_password |
+      | alice    | alice@secret.org | s3cr3t   | n3ws3cr3t    |
+
+  Scenario: Changing the password keeps the current token, and revokes the refresh tokens
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I change my password from <password> to <new_password>
+    Then I can access content for users
+    And I cannot refresh my token with my latest refresh token
+
+    Examples:
+      | username | email            | password | new_password |
+      | alice    | alice@secret.org | s3cr3t   | n3ws3cr3t    |
+
+  Scenario: Wrong current passwords lock the user out
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I change my password from wrong to <new_password>
+    And I change my password from wrong to <new_password>
+    And I change my password from wrong to <new_password>
+    And I login with username <username> and password <password>
+    Then I get a lockout error
+
+    Examples:
+      | username | email            | password | new_password |
+      | alice    | alice@secret.org | s3cr3t   | n3ws3cr3t    |
+
+  Scenario: A user can change its username and email
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I change my username to alicia
+    And I change my email to alicia@secret.org
+    Then I can verify the username alicia in the response
+    And I can verify the email <email> in the response
+    When I verify the email sent to alicia@secret.org
+    And I ask who I am
+    Then I can verify the email alicia@secret.org in the response
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user cannot take the username of another user
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    And I have registered a user with username bob and email bob@secret.org and password b0b
+    When I login with username <username> and password <password>
+    And I change my username to bob
+    Then I get a conflict error on the username
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user cannot take the email of another user
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    And I have registered a user with username bob and email bob@secret.org and password b0b
+    When I login with username <username> and password <password>
+    And I change my email to bob@secret.org
+    Then I get a conflict error on the email
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
@@ -0,0 +1,45 @@
+Feature: Rate limit feature
+
+  Scenario: A user going over the rate limit of an operation is refused
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I export my data 3 times
+    Then I get a too many requests error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user within the rate limit of an operation is served
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I export my data 2 times
+    Then I get no error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user cannot go around the rate limit with aliases
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I export my data 3 times in a single request
+    Then I get a too many requests error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user cannot go around the rate limit with a batch
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I export my data 3 times in a batch
+    Then I get a too many requests error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
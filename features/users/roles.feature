@@ -0,0 +1,81 @@
+Feature: Roles feature
+
+  Scenario: An administrator can access content for admins
+    Given I am logged in as an administrator
+    Then I can access content for admins
+
+  Scenario: A user cannot access content for admins
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    Then I can access content for users
+    And I cannot access content for admins
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A user cannot list users
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I list users with my token
+    Then I get an authorization error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A role grants its permissions with the next token
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I create a role auditor allowed to read users
+    And I grant the role auditor to the user
+    And I login with username <username> and password <password>
+    Then I can list users with my token
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: Revoking a role removes its permissions
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I create a role auditor allowed to read users
+    And I grant the role auditor to the user
+    And I revoke the role auditor from the user
+    And I login with username <username> and password <password>
+    And I list users with my token
+    Then I get an authorization error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: An administrator of the organization cannot create roles
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I grant the role admin to the user
+    And I login with username <username> and password <password>
+    And I create a role auditor allowed to read users with my token
+    Then I get an authorization error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: Revoking a role granted in every organization removes its permissions
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    And the user has the role admin in every organization
+    When I revoke the role admin from the user
+    And I login with username <username> and password <password>
+    Then I cannot access content for admins
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
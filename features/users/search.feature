@@ -0,0 +1,17 @@
+Feature: User search feature
+
+  Background:
+    Given I am logged in as an administrator
+    And I have added the users john.doe, jane, bob
+
+  Scenario: Searching a user by part of its username
+    When I search users for jon
+    Then the first user found is john.doe
+
+  Scenario: Searching a user by email
+    When I search users for bob@secret
+    Then the first user found is bob
+
+  Scenario: Searching for an unknown user
+    When I search users for xyzzy
+    Then no user is found
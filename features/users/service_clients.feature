@@ -0,0 +1,44 @@
+Feature: Service clients feature
+
+  Background:
+    Given I am logged in as an administrator
+    And I have registered the service client Billing with scopes users:read roles:read
+
+  Scenario: A service client gets a token for all its scopes
+    When the service client requests a token
+    Then I receive a machine token for the service client with scope users:read roles:read
+
+  Scenario: A service client gets a token for some of its scopes
+    When the service client requests a token for scope users:read
+    Then I receive a machine token for the service client with scope users:read
+
+  Scenario: A service client uses its token
+    Given I have registered a user with username alice and email alice@secret.org and password s3cr3t
+    When the service client requests a token for scope users:read
+    And the service client looks up the user by id
+    Then I can verify the username alice in the response
+
+  Scenario: A service client cannot act as a user
+    When the service client requests a token
+    And the service client asks who it is
+    Then I get an authorization error
+
+  Scenario: A service client cannot request other scopes
+    When the service client requests a token for scope audit:read
+    Then I get an OAuth error: invalid_scope
+
+  Scenario: A service client with a wrong secret gets no token
+    When the service client requests a token with a wrong secret
+    Then I get an OAuth error: invalid_client
+
+  Scenario: A deleted service client gets no token
+    When I delete the service client
+    And the service client requests a token
+    Then I get an OAuth error: invalid_client
+
+  Scenario: The tokens of a deleted service client are refused
+    Given I have registered a user with username alice and email alice@secret.org and password s3cr3t
+    When the service client requests a token for scope users:read
+    And I delete the service client
+    And the service client looks up the user by id
+    Then I get an unknown service client error
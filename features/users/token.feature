@@ -0,0 +1,48 @@
+Feature: Token feature
+
+  Scenario: Login returns a refresh token
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    Then I receive a token and a refresh token
+    And my token identifies me
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: Refreshing a token rotates the refresh token
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I refresh my token
+    Then I receive a new refresh token
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: Reusing a rotated refresh token revokes the family
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    And I refresh my token
+    And I refresh my token with my first refresh token
+    Then I get a refresh token reuse error
+    And I cannot refresh my token with my latest refresh token
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A token is rejected after logout
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    Then I can access content for users
+    When I logout
+    Then I cannot access content for users
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
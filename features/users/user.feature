@@ -1,9 +1,12 @@
 Feature: User feature
 
+  Background:
+    Given I am logged in as an administrator
+
   Scenario: Initial empty database
     Given I have initialized the user database
     When I list users
-    Then the response's users count is 0
+    Then the response's users count is 1
 
   Scenario: Adding a new user
     Given I have initialized the user database
@@ -14,6 +17,16 @@ Feature: User feature
       | username | email            | password |
       | alice    | alice@secret.org | s3cr3t   |
 
+  Scenario: An added user can login once verified
+    When I add a new user with username <username> and email <email> and password <password>
+    And I have verified my email
+    And I login with username <username> and password <password>
+    Then I receive a token and a refresh token
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
   Scenario: Adding a duplicate user
     Given I have a user with username <username> and email <email> and password <password>
     When I add a new user with username <username> and email <email> and password <password>
@@ -27,7 +40,7 @@ Feature: User feature
     Given I have a user with username <username0> and email <email0> and password <password0>
     When I add a new user with username <username1> and email <email1> and password <password1>
     And I list users
-    Then I can verify the response's users count is 2
+    Then I can verify the response's users count is 3
 
     Examples:
       | username0 | email0           | password0 | username1 | email1           | password1 |
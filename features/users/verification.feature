@@ -0,0 +1,43 @@
+Feature: Email verification feature
+
+  Scenario: A user cannot login before verifying its email
+    Given I have registered a user with username <username> and email <email> and password <password>
+    When I login with username <username> and password <password>
+    Then I get an inactive account error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: Verifying the email activates the account
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I login with username <username> and password <password>
+    Then my account is active
+    And I receive a token and a refresh token
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A verification token can only be used once
+    Given I have registered a user with username <username> and email <email> and password <password>
+    And I have verified my email
+    When I verify my email again
+    Then I get an invalid verification token error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
+
+  Scenario: A deactivated user cannot reactivate itself by verifying its email
+    Given I am logged in as an administrator
+    And I have registered a user with username <username> and email <email> and password <password>
+    When I deactivate the user
+    And I verify my email
+    And I login with username <username> and password <password>
+    Then I get an inactive account error
+
+    Examples:
+      | username | email            | password |
+      | alice    | alice@secret.org | s3cr3t   |
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS main.refresh_tokens;
@@ -0,0 +1,12 @@
+CREATE TABLE main.refresh_tokens (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  family_id UUID NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  expires_at TIMESTAMPTZ NOT NULL,
+  rotated_at TIMESTAMPTZ,
+  revoked_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX refresh_tokens_family_id_idx ON main.refresh_tokens (family_id);
@@ -0,0 +1,11 @@
+CREATE TABLE main.sessions (
+  id VARCHAR(64) PRIMARY KEY,
+  csrf VARCHAR(64) NOT NULL,
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  fingerprint TEXT,
+  ip TEXT,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX sessions_user_id_idx ON main.sessions (user_id);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS main.user_revocations;
+DROP TABLE IF EXISTS main.revoked_tokens;
@@ -0,0 +1,16 @@
+-- Individual tokens, revoked by their id (jti)
+CREATE TABLE main.revoked_tokens (
+  jti TEXT PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  expires_at TIMESTAMPTZ NOT NULL,
+  revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX revoked_tokens_expires_at_idx ON main.revoked_tokens (expires_at);
+
+-- All the tokens of a user issued before a given time
+CREATE TABLE main.user_revocations (
+  user_id UUID PRIMARY KEY REFERENCES main.users(id) ON DELETE CASCADE,
+  revoked_before TIMESTAMPTZ NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL
+);
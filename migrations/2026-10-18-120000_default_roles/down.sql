@@ -0,0 +1 @@
+ALTER TABLE main.users ALTER COLUMN roles SET DEFAULT '{}';
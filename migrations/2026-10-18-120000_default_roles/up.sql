@@ -0,0 +1,2 @@
+ALTER TABLE main.users ALTER COLUMN roles SET DEFAULT '{user}';
+UPDATE main.users SET roles = '{user}' WHERE roles IS NULL OR roles = '{}';
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS main.role_permissions;
+DROP TABLE IF EXISTS main.roles;
@@ -0,0 +1,29 @@
+CREATE TABLE main.roles (
+  name VARCHAR(128) PRIMARY KEY CHECK (name <> ''),
+  description TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE main.role_permissions (
+  role VARCHAR(128) NOT NULL REFERENCES main.roles(name) ON DELETE CASCADE,
+  permission VARCHAR(128) NOT NULL CHECK (permission <> ''),
+  PRIMARY KEY (role, permission)
+);
+
+INSERT INTO main.roles ( name, description ) VALUES
+  ( 'admin', 'Administrators manage users and roles' ),
+  ( 'moderator', 'Moderators can see users' ),
+  ( 'user', 'Registered users' );
+
+INSERT INTO main.role_permissions ( role, permission ) VALUES
+  ( 'admin', 'users:read' ),
+  ( 'admin', 'users:write' ),
+  ( 'admin', 'roles:read' ),
+  ( 'admin', 'roles:write' ),
+  ( 'admin', 'content:admin' ),
+  ( 'admin', 'content:moderator' ),
+  ( 'admin', 'content:user' ),
+  ( 'moderator', 'users:read' ),
+  ( 'moderator', 'content:moderator' ),
+  ( 'moderator', 'content:user' ),
+  ( 'user', 'content:user' );
@@ -0,0 +1,7 @@
+DELETE FROM main.role_permissions WHERE permission = 'organizations:write';
+ALTER TABLE main.refresh_tokens DROP COLUMN IF EXISTS organization_id;
+DROP TABLE IF EXISTS main.memberships;
+ALTER TABLE main.users DROP CONSTRAINT IF EXISTS users_organization_username_key;
+ALTER TABLE main.users ADD CONSTRAINT users_username_key UNIQUE (username);
+ALTER TABLE main.users DROP COLUMN IF EXISTS organization_id;
+DROP TABLE IF EXISTS main.organizations;
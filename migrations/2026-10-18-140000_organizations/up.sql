@@ -0,0 +1,37 @@
+CREATE TABLE main.organizations (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  name VARCHAR(128) NOT NULL UNIQUE CHECK (name <> ''),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Users registered before organizations existed belong to the default organization.
+INSERT INTO main.organizations ( name ) VALUES ( 'default' );
+
+-- A user's organization is the namespace of its username.
+ALTER TABLE main.users ADD COLUMN organization_id UUID REFERENCES main.organizations(id);
+UPDATE main.users SET organization_id = ( SELECT id FROM main.organizations WHERE name = 'default' );
+ALTER TABLE main.users ALTER COLUMN organization_id SET NOT NULL;
+ALTER TABLE main.users DROP CONSTRAINT users_username_key;
+ALTER TABLE main.users ADD CONSTRAINT users_organization_username_key UNIQUE (organization_id, username);
+
+-- A user is a member of its own organization, and possibly of others. The roles of a
+-- membership only apply in that organization.
+CREATE TABLE main.memberships (
+  organization_id UUID NOT NULL REFERENCES main.organizations(id) ON DELETE CASCADE,
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  roles VARCHAR(128)[] NOT NULL DEFAULT '{}',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (organization_id, user_id)
+);
+
+CREATE INDEX memberships_user_id_idx ON main.memberships (user_id);
+
+INSERT INTO main.memberships ( organization_id, user_id )
+SELECT organization_id, id FROM main.users;
+
+-- Refreshing a token keeps the organization it was issued for.
+ALTER TABLE main.refresh_tokens ADD COLUMN organization_id UUID REFERENCES main.organizations(id) ON DELETE CASCADE;
+UPDATE main.refresh_tokens t SET organization_id = u.organization_id FROM main.users u WHERE u.id = t.user_id;
+ALTER TABLE main.refresh_tokens ALTER COLUMN organization_id SET NOT NULL;
+
+INSERT INTO main.role_permissions ( role, permission ) VALUES ( 'admin', 'organizations:write' );
@@ -0,0 +1,13 @@
+CREATE TABLE main.email_verifications (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  token_hash TEXT NOT NULL UNIQUE,
+  expires_at TIMESTAMPTZ NOT NULL,
+  used_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX email_verifications_user_id_idx ON main.email_verifications (user_id);
+
+-- Users registered before email verification existed can still login.
+UPDATE main.users SET active = TRUE;
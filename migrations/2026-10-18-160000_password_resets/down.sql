@@ -0,0 +1,2 @@
+ALTER TABLE main.users DROP CONSTRAINT IF EXISTS users_organization_email_key;
+DROP TABLE IF EXISTS main.password_resets;
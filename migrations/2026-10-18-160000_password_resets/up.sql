@@ -0,0 +1,36 @@
+CREATE TABLE main.password_resets (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  token_hash TEXT NOT NULL UNIQUE,
+  expires_at TIMESTAMPTZ NOT NULL,
+  used_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX password_resets_user_id_idx ON main.password_resets (user_id);
+
+-- A password reset is requested by email, which must then identify a single account
+-- in the organization.
+-- We cannot tell which of the accounts sharing an email it belongs to, so an operator
+-- must give them their own emails first. The migration fails with the duplicates.
+DO $$
+DECLARE
+  duplicates TEXT;
+BEGIN
+  SELECT string_agg(format('%s in organization %s', email, organization_id), ', ')
+  INTO duplicates
+  FROM (
+    SELECT organization_id, email
+    FROM main.users
+    GROUP BY organization_id, email
+    HAVING COUNT(*) > 1
+  ) AS duplicated;
+
+  IF duplicates IS NOT NULL THEN
+    RAISE EXCEPTION 'Emails shared by several users of an organization: %', duplicates
+      USING HINT = 'Give each user of an organization its own email, then migrate again.';
+  END IF;
+END
+$$;
+
+ALTER TABLE main.users ADD CONSTRAINT users_organization_email_key UNIQUE (organization_id, email);
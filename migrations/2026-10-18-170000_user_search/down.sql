@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS main.users_email_trgm_idx;
+DROP INDEX IF EXISTS main.users_username_trgm_idx;
@@ -0,0 +1,4 @@
+-- Trigram indexes for searching users approximately by username or email.
+-- pg_trgm is installed in the main schema by the init migration.
+CREATE INDEX users_username_trgm_idx ON main.users USING GIN (username main.gin_trgm_ops);
+CREATE INDEX users_email_trgm_idx ON main.users USING GIN (email main.gin_trgm_ops);
@@ -0,0 +1 @@
+ALTER TABLE main.users DROP COLUMN IF EXISTS deleted_at;
@@ -0,0 +1,2 @@
+-- Soft deleted users are kept, so that their usernames stay taken.
+ALTER TABLE main.users ADD COLUMN deleted_at TIMESTAMPTZ;
@@ -0,0 +1,8 @@
+-- The record that a user was erased. The user's row is kept, anonymized, so that
+-- what refers to it stays valid. The tombstone outlives a purge of that row.
+CREATE TABLE main.tombstones (
+  user_id UUID PRIMARY KEY,
+  organization_id UUID NOT NULL,
+  erased_by UUID,
+  erased_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+DELETE FROM main.role_permissions WHERE permission = 'audit:read';
+DROP TABLE IF EXISTS main.audit_events;
+DROP FUNCTION IF EXISTS main.audit_events_append_only();
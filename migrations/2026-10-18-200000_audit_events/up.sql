@@ -0,0 +1,30 @@
+-- Security relevant events. There are no foreign keys, so that the events outlive
+-- the users and organizations they refer to.
+CREATE TABLE main.audit_events (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  action VARCHAR(128) NOT NULL CHECK (action <> ''),
+  actor_id UUID,
+  target_id UUID,
+  organization_id UUID,
+  ip TEXT,
+  user_agent TEXT,
+  details TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_events_organization_id_created_at_idx ON main.audit_events (organization_id, created_at DESC);
+CREATE INDEX audit_events_actor_id_idx ON main.audit_events (actor_id);
+CREATE INDEX audit_events_target_id_idx ON main.audit_events (target_id);
+
+-- The log is append only.
+CREATE FUNCTION main.audit_events_append_only() RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'audit events cannot be updated nor deleted';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+  BEFORE UPDATE OR DELETE ON main.audit_events
+  FOR EACH ROW EXECUTE PROCEDURE main.audit_events_append_only();
+
+INSERT INTO main.role_permissions ( role, permission ) VALUES ( 'admin', 'audit:read' );
@@ -0,0 +1,8 @@
+-- The failed logins of a user since its last successful login, and the time until
+-- which it cannot login, if it is locked out.
+CREATE TABLE main.login_lockouts (
+  user_id UUID PRIMARY KEY REFERENCES main.users(id) ON DELETE CASCADE,
+  failed_logins INTEGER NOT NULL DEFAULT 0 CHECK (failed_logins >= 0),
+  locked_until TIMESTAMPTZ,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS main.mfa_challenges;
+DROP TABLE IF EXISTS main.recovery_codes;
+DROP TABLE IF EXISTS main.totp_secrets;
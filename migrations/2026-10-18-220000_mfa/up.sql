@@ -0,0 +1,31 @@
+-- The TOTP secret of a user. It only protects logins once it is confirmed, and the
+-- last step used keeps codes from being replayed.
+CREATE TABLE main.totp_secrets (
+  user_id UUID PRIMARY KEY REFERENCES main.users(id) ON DELETE CASCADE,
+  secret TEXT NOT NULL,
+  confirmed_at TIMESTAMPTZ,
+  last_used_step BIGINT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Single use codes, for users who lost their authenticator.
+CREATE TABLE main.recovery_codes (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  code_hash TEXT NOT NULL,
+  used_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  UNIQUE (user_id, code_hash)
+);
+
+-- The first step of a login with MFA, completed by verifying a code.
+CREATE TABLE main.mfa_challenges (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  organization_id UUID NOT NULL REFERENCES main.organizations(id) ON DELETE CASCADE,
+  token_hash TEXT NOT NULL UNIQUE,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX mfa_challenges_user_id_idx ON main.mfa_challenges (user_id);
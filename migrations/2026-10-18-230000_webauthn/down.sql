@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS main.webauthn_challenges;
+DROP TABLE IF EXISTS main.webauthn_credentials;
@@ -0,0 +1,28 @@
+-- The passkeys of a user. The public key is stored raw, as ring verifies it, and the
+-- signature counter helps spotting cloned authenticators.
+CREATE TABLE main.webauthn_credentials (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  credential_id BYTEA NOT NULL UNIQUE,
+  public_key BYTEA NOT NULL,
+  algorithm INTEGER NOT NULL,
+  sign_count BIGINT NOT NULL DEFAULT 0,
+  name TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX webauthn_credentials_user_id_idx ON main.webauthn_credentials (user_id);
+
+-- The challenge of a registration or an authentication ceremony, used once.
+CREATE TABLE main.webauthn_challenges (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  organization_id UUID NOT NULL REFERENCES main.organizations(id) ON DELETE CASCADE,
+  challenge_hash TEXT NOT NULL UNIQUE,
+  ceremony TEXT NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX webauthn_challenges_user_id_idx ON main.webauthn_challenges (user_id);
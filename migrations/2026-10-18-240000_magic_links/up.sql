@@ -0,0 +1,14 @@
+-- The magic links sent to users. The token is signed, the row makes it single use.
+-- A link bound to the browser which asked for it also needs the binding, which is
+-- only stored hashed.
+CREATE TABLE main.magic_links (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  organization_id UUID NOT NULL REFERENCES main.organizations(id) ON DELETE CASCADE,
+  binding_hash TEXT,
+  expires_at TIMESTAMPTZ NOT NULL,
+  used_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX magic_links_user_id_idx ON main.magic_links (user_id);
@@ -0,0 +1,3 @@
+DELETE FROM main.role_permissions WHERE permission = 'clients:write';
+DROP TABLE IF EXISTS main.oauth_authorization_codes;
+DROP TABLE IF EXISTS main.oauth_clients;
@@ -0,0 +1,28 @@
+-- The clients of the OpenID Connect provider. They are public clients, which prove
+-- they started the authorization with PKCE, and the codes are only sent to the
+-- redirect URIs registered here.
+CREATE TABLE main.oauth_clients (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  name VARCHAR(256) NOT NULL CHECK (name <> ''),
+  redirect_uris TEXT[] NOT NULL CHECK (cardinality(redirect_uris) > 0),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- The authorization codes, exchanged once for tokens. Only a hash of the code is kept.
+CREATE TABLE main.oauth_authorization_codes (
+  id UUID PRIMARY KEY,
+  code_hash TEXT NOT NULL UNIQUE,
+  client_id UUID NOT NULL REFERENCES main.oauth_clients(id) ON DELETE CASCADE,
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  organization_id UUID NOT NULL REFERENCES main.organizations(id) ON DELETE CASCADE,
+  redirect_uri TEXT NOT NULL,
+  scope TEXT NOT NULL,
+  nonce TEXT,
+  code_challenge TEXT NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX oauth_authorization_codes_user_id_idx ON main.oauth_authorization_codes (user_id);
+
+INSERT INTO main.role_permissions ( role, permission ) VALUES ( 'admin', 'clients:write' );
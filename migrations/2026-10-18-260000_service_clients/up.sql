@@ -0,0 +1,13 @@
+-- The clients of the client credentials grant, ie other services of an organization.
+-- Only an argon hash of the secret is kept. The scopes are the permissions their
+-- tokens may carry.
+CREATE TABLE main.service_clients (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  organization_id UUID NOT NULL REFERENCES main.organizations(id) ON DELETE CASCADE,
+  name VARCHAR(256) NOT NULL CHECK (name <> ''),
+  secret_hash TEXT NOT NULL,
+  scopes TEXT[] NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX service_clients_organization_id_idx ON main.service_clients (organization_id);
@@ -0,0 +1,14 @@
+-- When the user verified its email. Verifying only activates users who never did, so
+-- that users deactivated since cannot reactivate themselves.
+ALTER TABLE main.users ADD COLUMN email_verified_at TIMESTAMPTZ;
+
+UPDATE main.users
+SET email_verified_at = v.used_at
+FROM ( SELECT user_id, MAX(used_at) AS used_at
+       FROM main.email_verifications
+       WHERE used_at IS NOT NULL
+       GROUP BY user_id ) v
+WHERE v.user_id = main.users.id;
+
+-- Users who could login before email verification existed count as verified.
+UPDATE main.users SET email_verified_at = created_at WHERE email_verified_at IS NULL AND active;
@@ -0,0 +1 @@
+ALTER TABLE main.email_verifications DROP COLUMN IF EXISTS email;
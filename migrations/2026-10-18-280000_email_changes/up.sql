@@ -0,0 +1,4 @@
+-- The new address of a user changing its email. It only replaces the current one once
+-- the user follows the verification sent to it. NULL when verifying the address the
+-- user registered with.
+ALTER TABLE main.email_verifications ADD COLUMN email TEXT;
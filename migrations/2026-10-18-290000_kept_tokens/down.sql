@@ -0,0 +1 @@
+ALTER TABLE main.user_revocations DROP COLUMN IF EXISTS kept_jti;
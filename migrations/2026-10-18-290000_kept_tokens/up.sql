@@ -0,0 +1,3 @@
+-- The token, if any, which a revocation of all the tokens of a user leaves valid: the
+-- token of the user changing its password, who need not login again.
+ALTER TABLE main.user_revocations ADD COLUMN kept_jti TEXT;
@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::convert::TryFrom;
+
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::db::model::{self, EntityId, ProvideAuthn};
+use crate::db::Db;
+use crate::error;
+use crate::state::audit::AuditAction;
+
+/// The response body for multiple audit events
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiAuditEventsResponseBody {
+    pub events: Vec<AuditEvent>,
+    pub events_count: i32,
+}
+
+impl From<Vec<AuditEvent>> for MultiAuditEventsResponseBody {
+    fn from(events: Vec<AuditEvent>) -> Self {
+        let events_count = i32::try_from(events.len()).unwrap();
+        Self {
+            events,
+            events_count,
+        }
+    }
+}
+
+/// The criteria audit events must match
+#[derive(Debug, Clone, Default, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEventFilter {
+    pub action: Option<AuditAction>,
+    pub actor_id: Option<EntityId>,
+    pub target_id: Option<EntityId>,
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl From<AuditEventFilter> for model::AuditEventFilter {
+    fn from(filter: AuditEventFilter) -> Self {
+        let AuditEventFilter {
+            action,
+            actor_id,
+            target_id,
+            after,
+            before,
+        } = filter;
+
+        model::AuditEventFilter {
+            action: action.map(|action| String::from(action.as_str())),
+            actor_id,
+            target_id,
+            after,
+            before,
+        }
+    }
+}
+
+/// The number of events returned, when the client does not say.
+const DEFAULT_LIMIT: i32 = 50;
+
+/// The largest number of events returned.
+const MAX_LIMIT: i32 = 1000;
+
+/// Retrieve the latest events of the organization matching the filter.
+pub async fn list_audit_events(
+    organization_id: EntityId,
+    filter: AuditEventFilter,
+    limit: Option<i32>,
+    context: &Context,
+) -> Result<MultiAuditEventsResponseBody, error::Error> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if limit < 0 || limit > MAX_LIMIT {
+        return Err(error::Error::MiscError {
+            msg: format!("The limit must be between 0 and {}", MAX_LIMIT),
+        });
+    }
+
+    let mut conn = context.state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let entities = conn
+        .get_audit_events(
+            organization_id,
+            &model::AuditEventFilter::from(filter),
+            i64::from(limit),
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get audit events",
+        })?;
+
+    let events = entities.into_iter().map(AuditEvent::from).collect();
+
+    Ok(MultiAuditEventsResponseBody::from(events))
+}
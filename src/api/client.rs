@@ -2,7 +2,31 @@ use futures::future::TryFutureExt;
 use snafu::futures::try_future::TryFutureExt as SnafuTryFutureExt;
 use snafu::ResultExt;
 
-use super::users::{MultiUsersResponseBody, SingleUserResponseBody, UserRequestBody};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::de::DeserializeOwned;
+
+use super::audit::{AuditEventFilter, MultiAuditEventsResponseBody};
+use super::gql::ContentResponseBody;
+use super::magic_links::MagicLinkResponseBody;
+use super::mfa::{RecoveryCodesResponseBody, TotpEnrollmentResponseBody};
+use super::model::Pagination;
+use super::oauth_clients::{OauthClientRequestBody, SingleOauthClientResponseBody};
+use super::organizations::{SingleMembershipResponseBody, SingleOrganizationResponseBody};
+use super::passkeys::{
+    PasskeyLoginOptions, PasskeyLoginRequestBody, PasskeyRegistrationOptions,
+    PasskeyRegistrationRequestBody, PasskeyResponseBody,
+};
+use super::privacy::{DataExportResponseBody, ErasureResponseBody};
+use super::roles::{RoleRequestBody, SingleRoleResponseBody};
+use super::service_clients::{
+    ServiceClientRequestBody, ServiceClientSecretResponseBody, SingleServiceClientResponseBody,
+};
+use super::users::{
+    AuthenticatedUserResponseBody, CredentialsRequestBody, DeleteUserResponseBody,
+    LoginResponseBody, LogoutResponseBody, MultiUsersResponseBody, PasswordResetResponseBody,
+    SingleUserResponseBody, UserConnection, UserFilter, UserOrder, UserRequestBody,
+};
+use crate::db::model::EntityId;
 use crate::error;
 use crate::utils::{construct_headers, get_service_url};
 
@@ -10,73 +34,26 @@ use crate::utils::{construct_headers, get_service_url};
 // TODO We rely on a helper function `get_service_url` to identify the target service
 // but this is probably not the best solution. Maybe the service's url needs to be
 // passed as another function argument.
-pub async fn list_users() -> Result<MultiUsersResponseBody, error::Error> {
-    let data = get_graphql_str_list_users();
-    let url = get_service_url();
-    let client = reqwest::Client::new();
-    client
-        .post(&url)
-        .headers(construct_headers())
-        .body(data)
-        .send()
-        .context(error::ReqwestError {
-            msg: String::from("Could not query users"),
-        })
-        .and_then(|resp| {
-            resp.json::<serde_json::Value>()
-                .context(error::ReqwestError {
-                    msg: String::from("Could not deserialize MultiUsersResponseBody"),
-                })
-        })
-        .and_then(|json| {
-            // This json object can be either { data: { users: { } } } if the call was successful,
-            // or { data: null, error: [ ] } if the call was not successful,
-            // FIXME Lots of unwrap in the following code, also I don't extract the 'message' part
-            // of the error.
-            async move {
-                let data = &json["data"];
-                if data.is_null() {
-                    if let Some(errors) = json.get("errors") {
-                        let errors = errors.clone();
-                        let errors = errors.as_array().expect("errors is an array");
-                        let error = &errors.first().expect("at least one error");
-                        let msg = error
-                            .get("extensions")
-                            .expect("error to have an extension field")
-                            .get("internal_error")
-                            .expect("extension to have an internal_error field");
-                        return Err(error::Error::MiscError {
-                            msg: format!("Error while requesting users: {}", msg),
-                        });
-                    } else {
-                        return Err(error::Error::MiscError {
-                            msg: String::from("Data is null, and there are no errors."),
-                        });
-                    }
-                } else {
-                    if let Some(users) = data.get("users") {
-                        let users = users.clone();
-                        serde_json::from_value(users).context(error::JSONError {
-                            msg: String::from("Could not deserialize users"),
-                        })
-                    } else {
-                        Err(error::Error::MiscError {
-                            msg: String::from("Data is not null, and there are no users."),
-                        })
-                    }
-                }
-            }
-        })
-        .await
+pub async fn list_users(
+    filter: Option<UserFilter>,
+    order_by: Option<UserOrder>,
+    pagination: Pagination,
+    token: Option<String>,
+) -> Result<UserConnection, error::Error> {
+    let data = get_graphql_str_list_users(filter, order_by, pagination);
+    request(data, "users", token).await
 }
 
-pub async fn add_user(user: UserRequestBody) -> Result<SingleUserResponseBody, error::Error> {
+pub async fn add_user(
+    user: UserRequestBody,
+    token: Option<String>,
+) -> Result<SingleUserResponseBody, error::Error> {
     let data = get_graphql_str_add_user(user);
     let url = get_service_url();
     let client = reqwest::Client::new();
     client
         .post(&url)
-        .headers(construct_headers())
+        .headers(construct_auth_headers(token))
         .body(data)
         .send()
         .context(error::ReqwestError {
@@ -118,13 +95,14 @@ pub async fn add_user(user: UserRequestBody) -> Result<SingleUserResponseBody, e
 
 pub async fn find_user_by_username(
     username: String,
+    token: Option<String>,
 ) -> Result<SingleUserResponseBody, error::Error> {
     let data = get_graphql_str_find_user(&username);
     let url = get_service_url();
     let client = reqwest::Client::new();
     client
         .post(&url)
-        .headers(construct_headers())
+        .headers(construct_auth_headers(token))
         .body(data)
         .send()
         .context(error::ReqwestError {
@@ -170,14 +148,604 @@ pub async fn find_user_by_username(
         .await
 }
 
+pub async fn me(token: String) -> Result<SingleUserResponseBody, error::Error> {
+    let data = String::from(
+        r#"{ "query": "{ me { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" }"#,
+    );
+    request(data, "me", Some(token)).await
+}
+
+pub async fn user_by_id(
+    id: EntityId,
+    token: String,
+) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "query userById($id: Uuid!) { userById(id: $id) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "id": "{id}" }} }}"#,
+        query = query,
+        id = id
+    );
+    request(data, "userById", Some(token)).await
+}
+
+pub async fn user_by_email(
+    email: String,
+    token: String,
+) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "query userByEmail($email: String!) { userByEmail(email: $email) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "email": {email} }} }}"#,
+        query = query,
+        email = serde_json::to_string(&email).unwrap()
+    );
+    request(data, "userByEmail", Some(token)).await
+}
+
+pub async fn search_users(
+    query: String,
+    limit: Option<i32>,
+    token: Option<String>,
+) -> Result<MultiUsersResponseBody, error::Error> {
+    let gql = r#" "query searchUsers($query: String!, $limit: Int) { searchUsers(query: $query, limit: $limit) { users { id, username, email, roles, active, createdAt, updatedAt, organizationId }, usersCount } }" "#;
+    let data = format!(
+        r#"{{ "query": {gql}, "variables": {{ "query": {query}, "limit": {limit} }} }}"#,
+        gql = gql,
+        query = serde_json::to_string(&query).unwrap(),
+        limit = serde_json::to_string(&limit).unwrap()
+    );
+    request(data, "searchUsers", token).await
+}
+
+pub async fn register_user(user: UserRequestBody) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "mutation registerUser($user: UserRequestBody!) { registerUser(user: $user) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let variables = serde_json::to_string(&user).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "user": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "registerUser", None).await
+}
+
+pub async fn verify_email(token: String) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "mutation verifyEmail($token: String!) { verifyEmail(token: $token) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let variables = serde_json::to_string(&token).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "token": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "verifyEmail", None).await
+}
+
+pub async fn request_password_reset(
+    email: String,
+) -> Result<PasswordResetResponseBody, error::Error> {
+    let query = r#" "mutation requestPasswordReset($email: String!) { requestPasswordReset(email: $email) { success } }" "#;
+    let variables = serde_json::to_string(&email).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "email": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "requestPasswordReset", None).await
+}
+
+pub async fn request_magic_link(
+    email: String,
+    bind_browser: bool,
+) -> Result<MagicLinkResponseBody, error::Error> {
+    let query = r#" "mutation requestMagicLink($email: String!, $bindBrowser: Boolean) { requestMagicLink(email: $email, bindBrowser: $bindBrowser) { success, binding } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "email": {email}, "bindBrowser": {bind_browser} }} }}"#,
+        query = query,
+        email = serde_json::to_string(&email).unwrap(),
+        bind_browser = bind_browser
+    );
+    request(data, "requestMagicLink", None).await
+}
+
+pub async fn consume_magic_link(
+    token: String,
+    binding: Option<String>,
+) -> Result<LoginResponseBody, error::Error> {
+    let query = r#" "mutation consumeMagicLink($token: String!, $binding: String) { consumeMagicLink(token: $token, binding: $binding) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId }, token, refreshToken, mfaRequired } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "token": {token}, "binding": {binding} }} }}"#,
+        query = query,
+        token = serde_json::to_string(&token).unwrap(),
+        binding = serde_json::to_string(&binding).unwrap()
+    );
+    request(data, "consumeMagicLink", None).await
+}
+
+pub async fn reset_password(
+    token: String,
+    new_password: String,
+) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "mutation resetPassword($token: String!, $newPassword: String!) { resetPassword(token: $token, newPassword: $newPassword) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let token = serde_json::to_string(&token).unwrap();
+    let new_password = serde_json::to_string(&new_password).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "token": {token}, "newPassword": {new_password} }} }}"#,
+        query = query,
+        token = token,
+        new_password = new_password
+    );
+    request(data, "resetPassword", None).await
+}
+
+pub async fn change_password(
+    current_password: String,
+    new_password: String,
+    token: String,
+) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "mutation changePassword($currentPassword: String!, $newPassword: String!) { changePassword(currentPassword: $currentPassword, newPassword: $newPassword) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "currentPassword": {current_password}, "newPassword": {new_password} }} }}"#,
+        query = query,
+        current_password = serde_json::to_string(&current_password).unwrap(),
+        new_password = serde_json::to_string(&new_password).unwrap()
+    );
+    request(data, "changePassword", Some(token)).await
+}
+
+pub async fn update_profile(
+    username: Option<String>,
+    email: Option<String>,
+    token: String,
+) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "mutation updateProfile($username: String, $email: String) { updateProfile(username: $username, email: $email) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "username": {username}, "email": {email} }} }}"#,
+        query = query,
+        username = serde_json::to_string(&username).unwrap(),
+        email = serde_json::to_string(&email).unwrap()
+    );
+    request(data, "updateProfile", Some(token)).await
+}
+
+pub async fn login_user(
+    credentials: CredentialsRequestBody,
+) -> Result<LoginResponseBody, error::Error> {
+    let query = r#" "mutation loginUser($credentials: CredentialsRequestBody!) { loginUser(credentials: $credentials) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId }, token, refreshToken, mfaRequired } }" "#;
+    let variables = serde_json::to_string(&credentials).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "credentials": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "loginUser", None).await
+}
+
+pub async fn verify_mfa(
+    challenge: String,
+    code: String,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    let query = r#" "mutation verifyMfa($challenge: String!, $code: String!) { verifyMfa(challenge: $challenge, code: $code) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId }, token, refreshToken } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "challenge": {challenge}, "code": {code} }} }}"#,
+        query = query,
+        challenge = serde_json::to_string(&challenge).unwrap(),
+        code = serde_json::to_string(&code).unwrap()
+    );
+    request(data, "verifyMfa", None).await
+}
+
+pub async fn enroll_totp(token: String) -> Result<TotpEnrollmentResponseBody, error::Error> {
+    let query = r#" "mutation { enrollTotp { secret, uri } }" "#;
+    let data = format!(r#"{{ "query": {query} }}"#, query = query);
+    request(data, "enrollTotp", Some(token)).await
+}
+
+pub async fn confirm_totp(
+    code: String,
+    token: String,
+) -> Result<RecoveryCodesResponseBody, error::Error> {
+    let query = r#" "mutation confirmTotp($code: String!) { confirmTotp(code: $code) { recoveryCodes } }" "#;
+    let variables = serde_json::to_string(&code).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "code": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "confirmTotp", Some(token)).await
+}
+
+pub async fn begin_passkey_registration(
+    token: String,
+) -> Result<PasskeyRegistrationOptions, error::Error> {
+    let query = r#" "mutation { beginPasskeyRegistration { challenge, rpId, rpName, userHandle, username, algorithms, excludeCredentials } }" "#;
+    let data = format!(r#"{{ "query": {query} }}"#, query = query);
+    request(data, "beginPasskeyRegistration", Some(token)).await
+}
+
+pub async fn finish_passkey_registration(
+    credential: PasskeyRegistrationRequestBody,
+    token: String,
+) -> Result<PasskeyResponseBody, error::Error> {
+    let query = r#" "mutation finishPasskeyRegistration($credential: PasskeyRegistrationRequestBody!) { finishPasskeyRegistration(credential: $credential) { passkey { id, name, createdAt, lastUsedAt } } }" "#;
+    let variables = serde_json::to_string(&credential).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "credential": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "finishPasskeyRegistration", Some(token)).await
+}
+
+pub async fn begin_passkey_login(username: String) -> Result<PasskeyLoginOptions, error::Error> {
+    let query = r#" "mutation beginPasskeyLogin($username: String!) { beginPasskeyLogin(username: $username) { challenge, rpId, allowCredentials } }" "#;
+    let variables = serde_json::to_string(&username).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "username": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "beginPasskeyLogin", None).await
+}
+
+pub async fn finish_passkey_login(
+    credential: PasskeyLoginRequestBody,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    let query = r#" "mutation finishPasskeyLogin($credential: PasskeyLoginRequestBody!) { finishPasskeyLogin(credential: $credential) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId }, token, refreshToken } }" "#;
+    let variables = serde_json::to_string(&credential).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "credential": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "finishPasskeyLogin", None).await
+}
+
+pub async fn refresh_token(
+    refresh_token: String,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    let query = r#" "mutation refreshToken($refreshToken: String!) { refreshToken(refreshToken: $refreshToken) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId }, token, refreshToken } }" "#;
+    let variables = serde_json::to_string(&refresh_token).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "refreshToken": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "refreshToken", None).await
+}
+
+pub async fn logout_user(token: String) -> Result<LogoutResponseBody, error::Error> {
+    let data = String::from(r#"{ "query": "mutation { logoutUser { success } }" }"#);
+    request(data, "logoutUser", Some(token)).await
+}
+
+pub async fn content_for_user(token: String) -> Result<ContentResponseBody, error::Error> {
+    let data = String::from(r#"{ "query": "{ contentForUser { content } }" }"#);
+    request(data, "contentForUser", Some(token)).await
+}
+
+pub async fn content_for_admin(token: String) -> Result<ContentResponseBody, error::Error> {
+    let data = String::from(r#"{ "query": "{ contentForAdmin { content } }" }"#);
+    request(data, "contentForAdmin", Some(token)).await
+}
+
+pub async fn create_role(
+    role: RoleRequestBody,
+    token: String,
+) -> Result<SingleRoleResponseBody, error::Error> {
+    let query = r#" "mutation createRole($role: RoleRequestBody!) { createRole(role: $role) { role { name, description, permissions, createdAt } } }" "#;
+    let variables = serde_json::to_string(&role).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "role": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "createRole", Some(token)).await
+}
+
+pub async fn grant_role(
+    user_id: EntityId,
+    role: String,
+    token: String,
+) -> Result<SingleMembershipResponseBody, error::Error> {
+    let query = r#" "mutation grantRole($userId: Uuid!, $role: String!) { grantRole(userId: $userId, role: $role) { membership { organizationId, userId, roles, createdAt } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}", "role": {role} }} }}"#,
+        query = query,
+        user_id = user_id,
+        role = serde_json::to_string(&role).unwrap()
+    );
+    request(data, "grantRole", Some(token)).await
+}
+
+pub async fn revoke_role(
+    user_id: EntityId,
+    role: String,
+    token: String,
+) -> Result<SingleMembershipResponseBody, error::Error> {
+    let query = r#" "mutation revokeRole($userId: Uuid!, $role: String!) { revokeRole(userId: $userId, role: $role) { membership { organizationId, userId, roles, createdAt } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}", "role": {role} }} }}"#,
+        query = query,
+        user_id = user_id,
+        role = serde_json::to_string(&role).unwrap()
+    );
+    request(data, "revokeRole", Some(token)).await
+}
+
+pub async fn deactivate_user(
+    user_id: EntityId,
+    token: String,
+) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "mutation deactivateUser($userId: Uuid!) { deactivateUser(userId: $userId) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}" }} }}"#,
+        query = query,
+        user_id = user_id
+    );
+    request(data, "deactivateUser", Some(token)).await
+}
+
+pub async fn reactivate_user(
+    user_id: EntityId,
+    token: String,
+) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "mutation reactivateUser($userId: Uuid!) { reactivateUser(userId: $userId) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}" }} }}"#,
+        query = query,
+        user_id = user_id
+    );
+    request(data, "reactivateUser", Some(token)).await
+}
+
+pub async fn unlock_user(
+    user_id: EntityId,
+    token: String,
+) -> Result<SingleUserResponseBody, error::Error> {
+    let query = r#" "mutation unlockUser($userId: Uuid!) { unlockUser(userId: $userId) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}" }} }}"#,
+        query = query,
+        user_id = user_id
+    );
+    request(data, "unlockUser", Some(token)).await
+}
+
+pub async fn delete_user(
+    user_id: EntityId,
+    soft: bool,
+    token: String,
+) -> Result<DeleteUserResponseBody, error::Error> {
+    let query = r#" "mutation deleteUser($userId: Uuid!, $soft: Boolean) { deleteUser(userId: $userId, soft: $soft) { success } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}", "soft": {soft} }} }}"#,
+        query = query,
+        user_id = user_id,
+        soft = soft
+    );
+    request(data, "deleteUser", Some(token)).await
+}
+
+pub async fn purge_user(
+    user_id: EntityId,
+    token: String,
+) -> Result<DeleteUserResponseBody, error::Error> {
+    let query =
+        r#" "mutation purgeUser($userId: Uuid!) { purgeUser(userId: $userId) { success } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}" }} }}"#,
+        query = query,
+        user_id = user_id
+    );
+    request(data, "purgeUser", Some(token)).await
+}
+
+pub async fn export_my_data(token: String) -> Result<DataExportResponseBody, error::Error> {
+    let query = r#" "query { exportMyData { document } }" "#;
+    let data = format!(r#"{{ "query": {query} }}"#, query = query);
+    request(data, "exportMyData", Some(token)).await
+}
+
+pub async fn erase_user(
+    user_id: EntityId,
+    token: String,
+) -> Result<ErasureResponseBody, error::Error> {
+    let query = r#" "mutation eraseUser($userId: Uuid!) { eraseUser(userId: $userId) { tombstone { userId, organizationId, erasedBy, erasedAt } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}" }} }}"#,
+        query = query,
+        user_id = user_id
+    );
+    request(data, "eraseUser", Some(token)).await
+}
+
+pub async fn audit_events(
+    filter: Option<AuditEventFilter>,
+    limit: Option<i32>,
+    token: String,
+) -> Result<MultiAuditEventsResponseBody, error::Error> {
+    let query = r#" "query auditEvents($filter: AuditEventFilter, $limit: Int) { auditEvents(filter: $filter, limit: $limit) { events { id, action, actorId, targetId, organizationId, ip, userAgent, details, createdAt }, eventsCount } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "filter": {filter}, "limit": {limit} }} }}"#,
+        query = query,
+        filter = serde_json::to_string(&filter).unwrap(),
+        limit = serde_json::to_string(&limit).unwrap()
+    );
+    request(data, "auditEvents", Some(token)).await
+}
+
+pub async fn add_member(
+    user_id: EntityId,
+    roles: Vec<String>,
+    token: String,
+) -> Result<SingleMembershipResponseBody, error::Error> {
+    let query = r#" "mutation addMember($userId: Uuid!, $roles: [String!]!) { addMember(userId: $userId, roles: $roles) { membership { organizationId, userId, roles, createdAt } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "userId": "{user_id}", "roles": {roles} }} }}"#,
+        query = query,
+        user_id = user_id,
+        roles = serde_json::to_string(&roles).unwrap()
+    );
+    request(data, "addMember", Some(token)).await
+}
+
+pub async fn create_organization(
+    name: String,
+    token: String,
+) -> Result<SingleOrganizationResponseBody, error::Error> {
+    let query = r#" "mutation createOrganization($name: String!) { createOrganization(name: $name) { organization { id, name, createdAt } } }" "#;
+    let variables = serde_json::to_string(&name).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "name": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "createOrganization", Some(token)).await
+}
+
+pub async fn switch_organization(
+    organization_id: EntityId,
+    token: String,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    let query = r#" "mutation switchOrganization($organizationId: Uuid!) { switchOrganization(organizationId: $organizationId) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId }, token, refreshToken } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "organizationId": "{organization_id}" }} }}"#,
+        query = query,
+        organization_id = organization_id
+    );
+    request(data, "switchOrganization", Some(token)).await
+}
+
+pub async fn create_oauth_client(
+    client: OauthClientRequestBody,
+    token: String,
+) -> Result<SingleOauthClientResponseBody, error::Error> {
+    let query = r#" "mutation createOauthClient($client: OauthClientRequestBody!) { createOauthClient(client: $client) { client { id, name, redirectUris, createdAt } } }" "#;
+    let variables = serde_json::to_string(&client).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "client": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "createOauthClient", Some(token)).await
+}
+
+pub async fn create_service_client(
+    client: ServiceClientRequestBody,
+    token: String,
+) -> Result<ServiceClientSecretResponseBody, error::Error> {
+    let query = r#" "mutation createServiceClient($client: ServiceClientRequestBody!) { createServiceClient(client: $client) { client { id, name, scopes, createdAt }, secret } }" "#;
+    let variables = serde_json::to_string(&client).unwrap();
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "client": {variables} }} }}"#,
+        query = query,
+        variables = variables
+    );
+    request(data, "createServiceClient", Some(token)).await
+}
+
+pub async fn delete_service_client(
+    id: EntityId,
+    token: String,
+) -> Result<SingleServiceClientResponseBody, error::Error> {
+    let query = r#" "mutation deleteServiceClient($id: Uuid!) { deleteServiceClient(id: $id) { client { id, name, scopes, createdAt } } }" "#;
+    let data = format!(
+        r#"{{ "query": {query}, "variables": {{ "id": "{id}" }} }}"#,
+        query = query,
+        id = id
+    );
+    request(data, "deleteServiceClient", Some(token)).await
+}
+
+// This is a helper function which adds the bearer token, if any, to the default headers.
+fn construct_auth_headers(token: Option<String>) -> HeaderMap {
+    let mut headers = construct_headers();
+    if let Some(token) = token {
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+    }
+    headers
+}
+
+// This is a helper function which sends a GraphQL request, with an optional bearer token,
+// and extracts the field from the response's data. If data is null, we return the first
+// error in the errors array.
+async fn request<T: DeserializeOwned>(
+    data: String,
+    field: &'static str,
+    token: Option<String>,
+) -> Result<T, error::Error> {
+    let url = get_service_url();
+    let client = reqwest::Client::new();
+    client
+        .post(&url)
+        .headers(construct_auth_headers(token))
+        .body(data)
+        .send()
+        .context(error::ReqwestError {
+            msg: format!("Could not request {}", field),
+        })
+        .and_then(|resp| {
+            let status = resp.status();
+            resp.json::<serde_json::Value>()
+                .context(error::ReqwestError {
+                    msg: format!("Could not deserialize {} response", field),
+                })
+                .map_ok(move |json| (status, json))
+        })
+        .and_then(|(status, json)| async move {
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                // This is not a GraphQL response.
+                Err(error::Error::MiscError {
+                    msg: format!("Too many requests: {}", json),
+                })
+            } else if json["data"].is_null() || json["data"][field].is_null() {
+                let errors = json["errors"].as_array().expect("errors");
+                let error = &errors.first().expect("at least one error");
+                Err(error::Error::MiscError {
+                    msg: format!("{}", error),
+                })
+            } else {
+                let res = json["data"][field].clone();
+                serde_json::from_value(res).context(error::JSONError {
+                    msg: format!("Can not retrieve {}", field),
+                })
+            }
+        })
+        .await
+}
+
 // This is a helper function which generates the GraphQL query for listing users
-pub fn get_graphql_str_list_users() -> String {
-    String::from("{ \"query\": \"{ users { users { id, username, email, roles, active, createdAt, updatedAt }, usersCount } }\" }")
+pub fn get_graphql_str_list_users(
+    filter: Option<UserFilter>,
+    order_by: Option<UserOrder>,
+    pagination: Pagination,
+) -> String {
+    let query = r#" "query users($filter: UserFilter, $orderBy: UserOrder, $first: Int, $after: String, $last: Int, $before: String) { users(filter: $filter, orderBy: $orderBy, first: $first, after: $after, last: $last, before: $before) { edges { node { id, username, email, roles, active, createdAt, updatedAt, organizationId }, cursor }, pageInfo { hasNextPage, hasPreviousPage, startCursor, endCursor }, totalCount } }" "#;
+    let Pagination {
+        first,
+        after,
+        last,
+        before,
+    } = pagination;
+    let variables = serde_json::json!({
+        "filter": filter,
+        "orderBy": order_by,
+        "first": first,
+        "after": after,
+        "last": last,
+        "before": before,
+    });
+    format!(
+        r#"{{ "query": {query}, "variables": {variables} }}"#,
+        query = query,
+        variables = variables
+    )
 }
 
 // This is a helper function which generates the GraphQL query for adding a user.
 pub fn get_graphql_str_add_user(user: UserRequestBody) -> String {
-    let query = r#" "mutation addUser($user: UserRequestBody!) { addUser(user: $user) { user { id, username, email, roles, active, createdAt, updatedAt } } }" "#;
+    let query = r#" "mutation addUser($user: UserRequestBody!) { addUser(user: $user) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
     let variables = serde_json::to_string(&user).unwrap();
     format!(
         r#"{{ "query": {query}, "variables": {{ "user": {variables} }} }}"#,
@@ -188,7 +756,7 @@ pub fn get_graphql_str_add_user(user: UserRequestBody) -> String {
 
 // This is a helper function which generates the GraphQL query for finding a user.
 pub fn get_graphql_str_find_user(username: &str) -> String {
-    let query = r#" "query findUser($username: String!) { findUserByUsername(username: $username) { user { id, username, email, roles, active, createdAt, updatedAt } } }" "#;
+    let query = r#" "query findUser($username: String!) { findUserByUsername(username: $username) { user { id, username, email, roles, active, createdAt, updatedAt, organizationId } } }" "#;
     let variables = serde_json::to_string(username).unwrap();
     format!(
         r#"{{ "query": {query}, "variables": {{ "username": {variables} }} }}"#,
@@ -198,34 +766,454 @@ pub fn get_graphql_str_find_user(username: &str) -> String {
 }
 
 pub mod blocking {
-    use crate::api::users::{MultiUsersResponseBody, SingleUserResponseBody, UserRequestBody};
+    use crate::api::audit::{AuditEventFilter, MultiAuditEventsResponseBody};
+    use crate::api::gql::ContentResponseBody;
+    use crate::api::magic_links::MagicLinkResponseBody;
+    use crate::api::mfa::{RecoveryCodesResponseBody, TotpEnrollmentResponseBody};
+    use crate::api::model::Pagination;
+    use crate::api::oauth_clients::{OauthClientRequestBody, SingleOauthClientResponseBody};
+    use crate::api::organizations::{SingleMembershipResponseBody, SingleOrganizationResponseBody};
+    use crate::api::passkeys::{
+        PasskeyLoginOptions, PasskeyLoginRequestBody, PasskeyRegistrationOptions,
+        PasskeyRegistrationRequestBody, PasskeyResponseBody,
+    };
+    use crate::api::privacy::{DataExportResponseBody, ErasureResponseBody};
+    use crate::api::roles::{RoleRequestBody, SingleRoleResponseBody};
+    use crate::api::service_clients::{
+        ServiceClientRequestBody, ServiceClientSecretResponseBody, SingleServiceClientResponseBody,
+    };
+    use crate::api::users::{
+        AuthenticatedUserResponseBody, CredentialsRequestBody, DeleteUserResponseBody,
+        LoginResponseBody, LogoutResponseBody, MultiUsersResponseBody, PasswordResetResponseBody,
+        SingleUserResponseBody, UserConnection, UserFilter, UserOrder, UserRequestBody,
+    };
+    use crate::db::model::EntityId;
     use crate::error;
-    pub fn list_users() -> Result<MultiUsersResponseBody, error::Error> {
+    pub fn list_users(
+        filter: Option<UserFilter>,
+        order_by: Option<UserOrder>,
+        pagination: Pagination,
+        token: Option<String>,
+    ) -> Result<UserConnection, error::Error> {
         // We use the Client API, which is async, so we need to wrap it around some
         // tokio machinery to spin the async code in a thread, and wait for the result.
         let handle = tokio::runtime::Handle::current();
         let th = std::thread::spawn(move || {
-            match handle.block_on(async { super::list_users().await }) {
+            match handle
+                .block_on(async { super::list_users(filter, order_by, pagination, token).await })
+            {
                 Ok(m) => Ok(m),
                 Err(err) => Err(err),
             }
         });
         th.join().unwrap()
     }
-    pub fn add_user(user: UserRequestBody) -> Result<SingleUserResponseBody, error::Error> {
+    pub fn add_user(
+        user: UserRequestBody,
+        token: Option<String>,
+    ) -> Result<SingleUserResponseBody, error::Error> {
         // We use the Client API, which is async, so we need to wrap it around some
         // tokio machinery to spin the async code in a thread, and wait for the result.
         // FIXME We're not extracting the error properly
         let handle = tokio::runtime::Handle::current();
-        let th = std::thread::spawn(move || handle.block_on(async { super::add_user(user).await }));
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::add_user(user, token).await })
+        });
         th.join().unwrap()
     }
-    pub fn find_user_by_username(username: String) -> Result<SingleUserResponseBody, error::Error> {
+    pub fn find_user_by_username(
+        username: String,
+        token: Option<String>,
+    ) -> Result<SingleUserResponseBody, error::Error> {
         // We use the Client API, which is async, so we need to wrap it around some
         // tokio machinery to spin the async code in a thread, and wait for the result.
         let handle = tokio::runtime::Handle::current();
         let th = std::thread::spawn(move || {
-            handle.block_on(async { super::find_user_by_username(username).await })
+            handle.block_on(async { super::find_user_by_username(username, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn me(token: String) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || handle.block_on(async { super::me(token).await }));
+        th.join().unwrap()
+    }
+    pub fn user_by_id(id: EntityId, token: String) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::user_by_id(id, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn user_by_email(
+        email: String,
+        token: String,
+    ) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::user_by_email(email, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn search_users(
+        query: String,
+        limit: Option<i32>,
+        token: Option<String>,
+    ) -> Result<MultiUsersResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::search_users(query, limit, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn register_user(user: UserRequestBody) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th =
+            std::thread::spawn(move || handle.block_on(async { super::register_user(user).await }));
+        th.join().unwrap()
+    }
+    pub fn verify_email(token: String) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th =
+            std::thread::spawn(move || handle.block_on(async { super::verify_email(token).await }));
+        th.join().unwrap()
+    }
+    pub fn request_password_reset(
+        email: String,
+    ) -> Result<PasswordResetResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::request_password_reset(email).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn request_magic_link(
+        email: String,
+        bind_browser: bool,
+    ) -> Result<MagicLinkResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::request_magic_link(email, bind_browser).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn consume_magic_link(
+        token: String,
+        binding: Option<String>,
+    ) -> Result<LoginResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::consume_magic_link(token, binding).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn reset_password(
+        token: String,
+        new_password: String,
+    ) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::reset_password(token, new_password).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn change_password(
+        current_password: String,
+        new_password: String,
+        token: String,
+    ) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async {
+                super::change_password(current_password, new_password, token).await
+            })
+        });
+        th.join().unwrap()
+    }
+    pub fn update_profile(
+        username: Option<String>,
+        email: Option<String>,
+        token: String,
+    ) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::update_profile(username, email, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn login_user(
+        credentials: CredentialsRequestBody,
+    ) -> Result<LoginResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::login_user(credentials).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn verify_mfa(
+        challenge: String,
+        code: String,
+    ) -> Result<AuthenticatedUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::verify_mfa(challenge, code).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn enroll_totp(token: String) -> Result<TotpEnrollmentResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th =
+            std::thread::spawn(move || handle.block_on(async { super::enroll_totp(token).await }));
+        th.join().unwrap()
+    }
+    pub fn confirm_totp(
+        code: String,
+        token: String,
+    ) -> Result<RecoveryCodesResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::confirm_totp(code, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn begin_passkey_registration(
+        token: String,
+    ) -> Result<PasskeyRegistrationOptions, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::begin_passkey_registration(token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn finish_passkey_registration(
+        credential: PasskeyRegistrationRequestBody,
+        token: String,
+    ) -> Result<PasskeyResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::finish_passkey_registration(credential, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn begin_passkey_login(username: String) -> Result<PasskeyLoginOptions, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::begin_passkey_login(username).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn finish_passkey_login(
+        credential: PasskeyLoginRequestBody,
+    ) -> Result<AuthenticatedUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::finish_passkey_login(credential).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn refresh_token(
+        refresh_token: String,
+    ) -> Result<AuthenticatedUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::refresh_token(refresh_token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn logout_user(token: String) -> Result<LogoutResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th =
+            std::thread::spawn(move || handle.block_on(async { super::logout_user(token).await }));
+        th.join().unwrap()
+    }
+    pub fn content_for_user(token: String) -> Result<ContentResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::content_for_user(token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn content_for_admin(token: String) -> Result<ContentResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::content_for_admin(token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn create_role(
+        role: RoleRequestBody,
+        token: String,
+    ) -> Result<SingleRoleResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::create_role(role, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn grant_role(
+        user_id: EntityId,
+        role: String,
+        token: String,
+    ) -> Result<SingleMembershipResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::grant_role(user_id, role, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn revoke_role(
+        user_id: EntityId,
+        role: String,
+        token: String,
+    ) -> Result<SingleMembershipResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::revoke_role(user_id, role, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn deactivate_user(
+        user_id: EntityId,
+        token: String,
+    ) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::deactivate_user(user_id, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn reactivate_user(
+        user_id: EntityId,
+        token: String,
+    ) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::reactivate_user(user_id, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn unlock_user(
+        user_id: EntityId,
+        token: String,
+    ) -> Result<SingleUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::unlock_user(user_id, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn delete_user(
+        user_id: EntityId,
+        soft: bool,
+        token: String,
+    ) -> Result<DeleteUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::delete_user(user_id, soft, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn purge_user(
+        user_id: EntityId,
+        token: String,
+    ) -> Result<DeleteUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::purge_user(user_id, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn export_my_data(token: String) -> Result<DataExportResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::export_my_data(token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn erase_user(
+        user_id: EntityId,
+        token: String,
+    ) -> Result<ErasureResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::erase_user(user_id, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn audit_events(
+        filter: Option<AuditEventFilter>,
+        limit: Option<i32>,
+        token: String,
+    ) -> Result<MultiAuditEventsResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::audit_events(filter, limit, token).await })
+        });
+        th.join().unwrap()
+    }
+    pub fn add_member(
+        user_id: EntityId,
+        roles: Vec<String>,
+        token: String,
+    ) -> Result<SingleMembershipResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::add_member(user_id, roles, token).await })
+        });
+        th.join().unwrap()
+    }
+
+    pub fn create_organization(
+        name: String,
+        token: String,
+    ) -> Result<SingleOrganizationResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::create_organization(name, token).await })
+        });
+        th.join().unwrap()
+    }
+
+    pub fn switch_organization(
+        organization_id: EntityId,
+        token: String,
+    ) -> Result<AuthenticatedUserResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::switch_organization(organization_id, token).await })
+        });
+        th.join().unwrap()
+    }
+
+    pub fn create_oauth_client(
+        client: OauthClientRequestBody,
+        token: String,
+    ) -> Result<SingleOauthClientResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::create_oauth_client(client, token).await })
+        });
+        th.join().unwrap()
+    }
+
+    pub fn create_service_client(
+        client: ServiceClientRequestBody,
+        token: String,
+    ) -> Result<ServiceClientSecretResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::create_service_client(client, token).await })
+        });
+        th.join().unwrap()
+    }
+
+    pub fn delete_service_client(
+        id: EntityId,
+        token: String,
+    ) -> Result<SingleServiceClientResponseBody, error::Error> {
+        let handle = tokio::runtime::Handle::current();
+        let th = std::thread::spawn(move || {
+            handle.block_on(async { super::delete_service_client(id, token).await })
         });
         th.join().unwrap()
     }
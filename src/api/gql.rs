@@ -1,11 +1,30 @@
+use biscuit::ClaimsSet;
 use juniper::GraphQLObject;
 use juniper::{EmptySubscription, FieldResult, IntoFieldError, RootNode};
 use serde::{Deserialize, Serialize};
-use slog::info;
+use slog::{info, warn};
+use snafu::ResultExt;
+use std::net::IpAddr;
 
+use super::audit;
+use super::magic_links;
+use super::mfa;
+use super::model::Pagination;
+use super::oauth_clients;
+use super::organizations;
+use super::passkeys;
+use super::privacy;
+use super::roles;
+use super::service_clients;
 use super::users;
+use crate::auth;
+use crate::auth::permission::Permission;
+use crate::auth::role::Role;
+use crate::db::model::{EntityId, ProvideAuthn, SessionEntity};
+use crate::db::Db;
 use crate::error;
 //use crate::state::jwt::Jwt;
+use crate::state::audit::AuditEvent;
 use crate::state::state::State;
 
 /// A test content
@@ -25,68 +44,272 @@ impl From<String> for ContentResponseBody {
 pub struct Context {
     pub state: State,
     pub token: Option<String>,
+    /// The browser session, when the token came from a session cookie.
+    pub session: Option<SessionEntity>,
+    /// The address of the client, and its user agent, for the audit log.
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
 }
 
 impl juniper::Context for Context {}
 
 impl Context {
-    pub fn is_authenticated(&self) -> bool {
-        info!(self.state.logger, "auth check: token: {:?}", self.token);
-        match self
-            .token
+    /// Decode and validate the token, and check that it has not been revoked, and that
+    /// its user, if any, can still use it. The tokens issued to OpenID Connect clients
+    /// are refused: they only give access to the user info.
+    pub async fn claims(&self) -> Result<ClaimsSet<auth::PrivateClaims>, error::Error> {
+        let claims = self.token_claims().await?;
+        if claims.private.scope.is_some() && !claims.private.machine {
+            return Err(error::Error::AuthorizationError {
+                msg: String::from("This token only gives access to the user info"),
+            });
+        }
+        Ok(claims)
+    }
+
+    /// Guard for the user info: returns the claims of a user token issued to an OpenID
+    /// Connect client.
+    pub async fn userinfo_claims(&self) -> Result<ClaimsSet<auth::PrivateClaims>, error::Error> {
+        let claims = self.token_claims().await?;
+        if claims.private.machine || claims.private.scope.is_none() {
+            return Err(error::Error::AuthorizationError {
+                msg: String::from("This operation requires a token issued to a client"),
+            });
+        }
+        Ok(claims)
+    }
+
+    /// Decode and validate the token, and check that it has not been revoked, and that
+    /// its user, if any, can still use it.
+    async fn token_claims(&self) -> Result<ClaimsSet<auth::PrivateClaims>, error::Error> {
+        let token = self.token.as_deref().ok_or(error::Error::MiscError {
+            msg: String::from("Unauthenticated Access"),
+        })?;
+
+        let claims = self.state.jwt.decode(token)?;
+
+        let user_id = auth::subject(&claims)?;
+        let jti = claims
+            .registered
+            .id
             .as_deref()
             .ok_or(error::Error::MiscError {
-                msg: String::from("Unauthenticated Access"),
-            })
-            .and_then(|tok| self.state.jwt.decode(tok))
-        {
-            Ok(claimset) => {
-                let options: biscuit::Validation<biscuit::TemporalOptions> =
-                    biscuit::Validation::Validate(Default::default());
-                match claimset.registered.validate_exp(options) {
-                    Ok(_) => true,
-                    Err(_) => false,
-                }
+                msg: String::from("Token has no id"),
+            })?;
+        let issued_at =
+            claims
+                .registered
+                .issued_at
+                .map(|iat| *iat)
+                .ok_or(error::Error::MiscError {
+                    msg: String::from("Token has no issue time"),
+                })?;
+
+        let mut conn = self.state.pool.conn().await.context(error::DBError {
+            msg: "could not get connection",
+        })?;
+
+        let revoked = conn
+            .is_token_revoked(jti, user_id, issued_at)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not check token revocation",
+            })?;
+
+        if revoked {
+            return Err(error::Error::MiscError {
+                msg: String::from("Revoked token"),
+            });
+        }
+
+        // The revocations of a user are purged with it, so we also check that the
+        // subject of a user token still exists, and is active. Likewise, the tokens of a
+        // service client are only valid as long as the client exists.
+        if claims.private.machine {
+            let exists = conn
+                .get_service_client(user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get service client",
+                })?
+                .map_or(false, |client| {
+                    Some(client.organization_id) == claims.private.org
+                });
+            if !exists {
+                return Err(error::Error::MiscError {
+                    msg: String::from("Unknown service client"),
+                });
+            }
+        } else {
+            let active = conn
+                .get_user_by_id(user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get user by id",
+                })?
+                .map_or(false, |user| user.active);
+            if !active {
+                return Err(error::Error::MiscError {
+                    msg: String::from("Unknown or inactive user"),
+                });
             }
-            Err(_) => false,
         }
+
+        Ok(claims)
     }
-    pub fn is_admin(&self) -> bool {
-        match self
-            .token
-            .as_deref()
+
+    /// Guard for resolvers acting on the caller as a user: returns the caller's claims,
+    /// unless the token was issued to a service client, whose subject is not a user.
+    pub async fn claims_for_user(&self) -> Result<ClaimsSet<auth::PrivateClaims>, error::Error> {
+        let claims = self.claims().await?;
+        if claims.private.machine {
+            return Err(error::Error::AuthorizationError {
+                msg: String::from("This operation requires a user token"),
+            });
+        }
+        Ok(claims)
+    }
+
+    /// Guard for resolvers acting on every organization: returns the caller's claims if
+    /// the caller has the given role in every organization, as granted by an operator,
+    /// and an error otherwise. Roles in the caller's organization are not enough.
+    pub async fn require_global_role(
+        &self,
+        role: Role,
+    ) -> Result<ClaimsSet<auth::PrivateClaims>, error::Error> {
+        let claims = self.claims_for_user().await?;
+        let user_id = auth::subject(&claims)?;
+
+        let mut conn = self.state.pool.conn().await.context(error::DBError {
+            msg: "could not get connection",
+        })?;
+
+        let user = conn
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
             .ok_or(error::Error::MiscError {
-                msg: String::from("Unauthenticated Access"),
+                msg: String::from("Unknown user"),
+            })?;
+
+        if user.roles.iter().any(|granted| granted == role.as_str()) {
+            Ok(claims)
+        } else {
+            Err(error::Error::AuthorizationError {
+                msg: format!(
+                    "This operation requires the {} role in every organization",
+                    role
+                ),
+            })
+        }
+    }
+
+    /// Record an audit event, from the client, and by default by the caller.
+    /// The event is only logged if it cannot be recorded, so that auditing never
+    /// fails an operation.
+    pub async fn audit(&self, event: AuditEvent) {
+        let actor_id = event.actor_id.or_else(|| {
+            self.token
+                .as_deref()
+                .and_then(|token| self.state.jwt.decode(token).ok())
+                .and_then(|claims| auth::subject(&claims).ok())
+        });
+        let event = AuditEvent {
+            actor_id,
+            ip: self.ip.map(|ip| ip.to_string()),
+            user_agent: self.user_agent.clone(),
+            ..event
+        };
+        if let Err(err) = self.state.audit.record(&event).await {
+            warn!(
+                self.state.logger,
+                "Could not record audit event {}: {}", event.action, err
+            );
+        }
+    }
+
+    pub async fn is_authenticated(&self) -> bool {
+        // Tokens are credentials, they must not end up in the logs.
+        info!(
+            self.state.logger,
+            "auth check: token present: {}",
+            self.token.is_some()
+        );
+        self.claims().await.is_ok()
+    }
+
+    /// Whether the caller is authenticated, with a token granting the given permission.
+    pub async fn has_permission(&self, permission: Permission) -> bool {
+        self.require_permission(permission).await.is_ok()
+    }
+
+    /// Guard for resolvers: returns the caller's claims if the caller's token grants
+    /// the given permission, and an error otherwise.
+    pub async fn require_permission(
+        &self,
+        permission: Permission,
+    ) -> Result<ClaimsSet<auth::PrivateClaims>, error::Error> {
+        let claims = self.claims().await?;
+        if claims.private.has_permission(permission) {
+            Ok(claims)
+        } else {
+            Err(error::Error::AuthorizationError {
+                msg: format!("This operation requires the {} permission", permission),
             })
-            .and_then(|tok| self.state.jwt.decode(tok))
-        {
-            Ok(_) => false,
-            Err(_) => false,
         }
     }
 }
+
 pub struct Query;
 
 #[juniper::graphql_object(
     Context = Context
 )]
 impl Query {
-    /// Returns a list of users
-    async fn users(&self, context: &Context) -> FieldResult<users::MultiUsersResponseBody> {
-        if let Some(token) = &context.token {
-            info!(context.state.logger, "auth token: {}", token);
-        }
-        users::list_users(context)
+    /// Returns a page of the members of the caller's organization matching the filter,
+    /// in the given order, by default by creation time.
+    /// Pages are selected with first and after, or with last and before.
+    /// This requires the users:read permission.
+    async fn users(
+        &self,
+        filter: Option<users::UserFilter>,
+        order_by: Option<users::UserOrder>,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+        context: &Context,
+    ) -> FieldResult<users::UserConnection> {
+        let organization_id = context
+            .require_permission(Permission::UsersRead)
             .await
-            .map_err(IntoFieldError::into_field_error)
-            .into()
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        let pagination = Pagination {
+            first,
+            after,
+            last,
+            before,
+        };
+        users::list_users(
+            organization_id,
+            filter.unwrap_or_default(),
+            order_by.unwrap_or_default(),
+            pagination,
+            context,
+        )
+        .await
+        .map_err(IntoFieldError::into_field_error)
+        .into()
     }
 
     /// Returns content for all
     /// This content is for anyone, and there are no checks
     async fn content_for_all(&self, context: &Context) -> FieldResult<ContentResponseBody> {
-        if let Some(token) = &context.token {
-            info!(context.state.logger, "all auth token: {}", token);
+        if context.token.is_some() {
+            info!(context.state.logger, "all: authenticated caller");
         }
         let res = ContentResponseBody::from(String::from("Hello, all"));
         let res: Result<ContentResponseBody, error::Error> = Ok(res);
@@ -96,38 +319,225 @@ impl Query {
     /// Returns content for user
     /// This content is for registered user.
     async fn content_for_user(&self, context: &Context) -> FieldResult<ContentResponseBody> {
-        if !context.is_authenticated() {
-            return Err(IntoFieldError::into_field_error(error::Error::MiscError {
-                msg: String::from("Unauthenticated Access"),
-            }));
-        }
-        let res = ContentResponseBody::from(String::from("Hello, user"));
-        let res: Result<ContentResponseBody, error::Error> = Ok(res);
-        res.map_err(IntoFieldError::into_field_error)
+        context
+            .require_permission(Permission::ContentUser)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        Ok(ContentResponseBody::from(String::from("Hello, user")))
     }
 
     /// Returns content for moderator
-    /// This content is for registered user.
+    /// This content is for moderators and admins.
+    async fn content_for_moderator(&self, context: &Context) -> FieldResult<ContentResponseBody> {
+        context
+            .require_permission(Permission::ContentModerator)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        Ok(ContentResponseBody::from(String::from("Hello, moderator")))
+    }
+
+    /// Returns content for admin
+    /// This content is for admins.
     async fn content_for_admin(&self, context: &Context) -> FieldResult<ContentResponseBody> {
-        if !context.is_admin() {
-            return Err(IntoFieldError::into_field_error(error::Error::MiscError {
-                msg: String::from("Unauthenticated Access"),
-            }));
-        }
-        let res = ContentResponseBody::from(String::from("Hello, admin"));
-        let res: Result<ContentResponseBody, error::Error> = Ok(res);
-        res.map_err(IntoFieldError::into_field_error)
+        context
+            .require_permission(Permission::ContentAdmin)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        Ok(ContentResponseBody::from(String::from("Hello, admin")))
+    }
+
+    /// Returns the roles, and the permissions they grant
+    /// This requires the roles:read permission.
+    async fn roles(&self, context: &Context) -> FieldResult<roles::MultiRolesResponseBody> {
+        context
+            .require_permission(Permission::RolesRead)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        roles::list_roles(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the clients of the OpenID Connect provider
+    /// This requires the clients:write permission.
+    async fn oauth_clients(
+        &self,
+        context: &Context,
+    ) -> FieldResult<oauth_clients::MultiOauthClientsResponseBody> {
+        context
+            .require_permission(Permission::ClientsWrite)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        oauth_clients::list_oauth_clients(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the service clients of the caller's organization
+    /// This requires the clients:write permission.
+    async fn service_clients(
+        &self,
+        context: &Context,
+    ) -> FieldResult<service_clients::MultiServiceClientsResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::ClientsWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        service_clients::list_service_clients(organization_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
     }
 
-    /// Find a user by username
+    /// Returns the organizations the caller is a member of
+    async fn organizations(
+        &self,
+        context: &Context,
+    ) -> FieldResult<organizations::MultiOrganizationsResponseBody> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        organizations::list_organizations(user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the caller
+    async fn me(&self, context: &Context) -> FieldResult<users::SingleUserResponseBody> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::find_user_by_id(context, user_id)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the latest audit events of the caller's organization matching the filter
+    /// This requires the audit:read permission.
+    async fn audit_events(
+        &self,
+        filter: Option<audit::AuditEventFilter>,
+        limit: Option<i32>,
+        context: &Context,
+    ) -> FieldResult<audit::MultiAuditEventsResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::AuditRead)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        audit::list_audit_events(organization_id, filter.unwrap_or_default(), limit, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns everything we hold about the caller, as a JSON document
+    async fn export_my_data(
+        &self,
+        context: &Context,
+    ) -> FieldResult<privacy::DataExportResponseBody> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        privacy::export_user_data(user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Find a user by id, in the caller's organization
+    /// Anyone but the caller requires the users:read permission.
+    async fn user_by_id(
+        &self,
+        id: EntityId,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        let organization_id = context
+            .claims()
+            .await
+            .and_then(|claims| {
+                auth::require_self_or_permission(&claims, Some(id), Permission::UsersRead)?;
+                auth::organization(&claims)
+            })
+            .map_err(IntoFieldError::into_field_error)?;
+        users::find_member_by_id(organization_id, context, id)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Find a user by email, in the caller's organization
+    /// Anyone but the caller requires the users:read permission.
+    async fn user_by_email(
+        &self,
+        email: String,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        let claims = context
+            .claims()
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        let organization_id =
+            auth::organization(&claims).map_err(IntoFieldError::into_field_error)?;
+        let resp = users::find_user_by_email(organization_id, context, &email)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        // We can only tell who the user is once we found it.
+        auth::require_self_or_permission(
+            &claims,
+            resp.user.as_ref().map(|user| user.id),
+            Permission::UsersRead,
+        )
+        .map_err(IntoFieldError::into_field_error)?;
+        Ok(resp)
+    }
+
+    /// Search users by username or email, in the caller's organization
+    /// Users are matched approximately, the closest first. This requires the
+    /// users:read permission.
+    async fn search_users(
+        &self,
+        query: String,
+        limit: Option<i32>,
+        context: &Context,
+    ) -> FieldResult<users::MultiUsersResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::UsersRead)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::search_users(organization_id, &query, limit, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Find a user by username, in the caller's organization
+    /// Anyone but the caller requires the users:read permission.
     async fn findUserByUsername(
         &self,
         username: String,
         context: &Context,
     ) -> FieldResult<users::SingleUserResponseBody> {
-        users::find_user_by_username(context, &username)
+        let claims = context
+            .claims()
             .await
-            .map_err(IntoFieldError::into_field_error)
+            .map_err(IntoFieldError::into_field_error)?;
+        let organization_id =
+            auth::organization(&claims).map_err(IntoFieldError::into_field_error)?;
+        let resp = users::find_user_by_username(organization_id, context, &username)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        // We can only tell who the user is once we found it.
+        auth::require_self_or_permission(
+            &claims,
+            resp.user.as_ref().map(|user| user.id),
+            Permission::UsersRead,
+        )
+        .map_err(IntoFieldError::into_field_error)?;
+        Ok(resp)
     }
 }
 
@@ -137,12 +547,19 @@ pub struct Mutation;
     Context = Context
 )]
 impl Mutation {
+    /// Add a user to the caller's organization
+    /// This requires the users:write permission, anyone else should register.
     async fn add_user(
         &self,
         user: users::UserRequestBody,
         context: &Context,
     ) -> FieldResult<users::SingleUserResponseBody> {
-        users::add_user(user, context)
+        let organization_id = context
+            .require_permission(Permission::UsersWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::add_user(organization_id, user, context)
             .await
             .map_err(IntoFieldError::into_field_error)
     }
@@ -157,17 +574,552 @@ impl Mutation {
             .map_err(IntoFieldError::into_field_error)
     }
 
+    /// Verify the email of a registered user with the token we sent, which activates
+    /// its account
+    async fn verify_email(
+        &self,
+        token: String,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        users::verify_email(&token, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Email a password reset token to the user with this email, if there is one
+    async fn request_password_reset(
+        &self,
+        email: String,
+        organization: Option<String>,
+        context: &Context,
+    ) -> FieldResult<users::PasswordResetResponseBody> {
+        users::request_password_reset(&email, organization.as_deref(), context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Set a new password with a password reset token, which logs the user out everywhere
+    async fn reset_password(
+        &self,
+        token: String,
+        new_password: String,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        users::reset_password(&token, new_password, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Change the caller's password, which requires its current password
+    async fn change_password(
+        &self,
+        current_password: String,
+        new_password: String,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        let (user_id, jti) = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| {
+                let jti = claims.registered.id.clone().unwrap_or_default();
+                Ok((auth::subject(&claims)?, jti))
+            })
+            .map_err(IntoFieldError::into_field_error)?;
+        users::change_password(user_id, &jti, current_password, new_password, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Change the caller's username and/or email
+    async fn update_profile(
+        &self,
+        username: Option<String>,
+        email: Option<String>,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::update_profile(user_id, username, email, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Login with a username and a password
+    /// Users with MFA get an mfaRequired challenge rather than tokens, and complete the
+    /// login with verifyMfa.
     async fn login_user(
         &self,
         credentials: users::CredentialsRequestBody,
         context: &Context,
-    ) -> FieldResult<users::AuthenticatedUserResponseBody> {
+    ) -> FieldResult<users::LoginResponseBody> {
         users::login_user(credentials, context)
             .await
             .map_err(IntoFieldError::into_field_error)
     }
+
+    /// Email a magic link to the user with this email, if there is one
+    /// With bindBrowser, the link only works with the binding returned, which the
+    /// browser keeps.
+    async fn request_magic_link(
+        &self,
+        email: String,
+        organization: Option<String>,
+        bind_browser: Option<bool>,
+        context: &Context,
+    ) -> FieldResult<magic_links::MagicLinkResponseBody> {
+        magic_links::request_magic_link(
+            &email,
+            organization.as_deref(),
+            bind_browser.unwrap_or(false),
+            context,
+        )
+        .await
+        .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Login with the token of a magic link
+    /// Users with MFA get an mfaRequired challenge rather than tokens, as with loginUser.
+    async fn consume_magic_link(
+        &self,
+        token: String,
+        binding: Option<String>,
+        context: &Context,
+    ) -> FieldResult<users::LoginResponseBody> {
+        magic_links::consume_magic_link(&token, binding.as_deref(), context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Complete a login with the challenge, and a TOTP code or a recovery code
+    async fn verify_mfa(
+        &self,
+        challenge: String,
+        code: String,
+        context: &Context,
+    ) -> FieldResult<users::AuthenticatedUserResponseBody> {
+        mfa::verify_mfa(&challenge, &code, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Start the caller's enrollment in TOTP, with a new secret
+    async fn enroll_totp(&self, context: &Context) -> FieldResult<mfa::TotpEnrollmentResponseBody> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        mfa::enroll_totp(user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Confirm the caller's enrollment in TOTP with a first code
+    /// This returns the recovery codes, which are not shown again.
+    async fn confirm_totp(
+        &self,
+        code: String,
+        context: &Context,
+    ) -> FieldResult<mfa::RecoveryCodesResponseBody> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        mfa::confirm_totp(user_id, &code, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Start the registration of a passkey for the caller
+    async fn begin_passkey_registration(
+        &self,
+        context: &Context,
+    ) -> FieldResult<passkeys::PasskeyRegistrationOptions> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        passkeys::begin_passkey_registration(user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Complete the registration of a passkey for the caller, with the response of the
+    /// authenticator
+    async fn finish_passkey_registration(
+        &self,
+        credential: passkeys::PasskeyRegistrationRequestBody,
+        context: &Context,
+    ) -> FieldResult<passkeys::PasskeyResponseBody> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        passkeys::finish_passkey_registration(user_id, credential, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Start a login with a passkey
+    async fn begin_passkey_login(
+        &self,
+        username: String,
+        organization: Option<String>,
+        context: &Context,
+    ) -> FieldResult<passkeys::PasskeyLoginOptions> {
+        passkeys::begin_passkey_login(&username, organization.as_deref(), context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Complete a login with a passkey, with the response of the authenticator
+    async fn finish_passkey_login(
+        &self,
+        credential: passkeys::PasskeyLoginRequestBody,
+        context: &Context,
+    ) -> FieldResult<users::AuthenticatedUserResponseBody> {
+        passkeys::finish_passkey_login(credential, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Exchange a refresh token for a new token and refresh token
+    async fn refresh_token(
+        &self,
+        refresh_token: String,
+        context: &Context,
+    ) -> FieldResult<users::AuthenticatedUserResponseBody> {
+        users::refresh_token(&refresh_token, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Revoke the caller's token, and the refresh token if given
+    async fn logout_user(
+        &self,
+        refresh_token: Option<String>,
+        context: &Context,
+    ) -> FieldResult<users::LogoutResponseBody> {
+        users::logout_user(refresh_token, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Revoke all the caller's tokens, refresh tokens and sessions
+    async fn logout_all_sessions(
+        &self,
+        context: &Context,
+    ) -> FieldResult<users::LogoutResponseBody> {
+        users::logout_all_sessions(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Create a role granting the given permissions
+    /// Roles are shared by all the organizations, so this requires the admin role in
+    /// every organization.
+    async fn create_role(
+        &self,
+        role: roles::RoleRequestBody,
+        context: &Context,
+    ) -> FieldResult<roles::SingleRoleResponseBody> {
+        context
+            .require_global_role(Role::Admin)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        roles::create_role(role, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Delete a role, and remove it from the users who had it
+    /// Roles are shared by all the organizations, so this requires the admin role in
+    /// every organization.
+    async fn delete_role(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<roles::SingleRoleResponseBody> {
+        context
+            .require_global_role(Role::Admin)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        roles::delete_role(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Register a client of the OpenID Connect provider, with its redirect URIs
+    /// This requires the clients:write permission.
+    async fn create_oauth_client(
+        &self,
+        client: oauth_clients::OauthClientRequestBody,
+        context: &Context,
+    ) -> FieldResult<oauth_clients::SingleOauthClientResponseBody> {
+        context
+            .require_permission(Permission::ClientsWrite)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        oauth_clients::create_oauth_client(client, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Delete a client of the OpenID Connect provider
+    /// This requires the clients:write permission.
+    async fn delete_oauth_client(
+        &self,
+        id: EntityId,
+        context: &Context,
+    ) -> FieldResult<oauth_clients::SingleOauthClientResponseBody> {
+        context
+            .require_permission(Permission::ClientsWrite)
+            .await
+            .map_err(IntoFieldError::into_field_error)?;
+        oauth_clients::delete_oauth_client(id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Register a service client of the caller's organization, with the scopes it may
+    /// request, and return its secret
+    /// This requires the clients:write permission, and the permissions it grants.
+    async fn create_service_client(
+        &self,
+        client: service_clients::ServiceClientRequestBody,
+        context: &Context,
+    ) -> FieldResult<service_clients::ServiceClientSecretResponseBody> {
+        let (organization_id, claims) = context
+            .require_permission(Permission::ClientsWrite)
+            .await
+            .and_then(|claims| Ok((auth::organization(&claims)?, claims)))
+            .map_err(IntoFieldError::into_field_error)?;
+        service_clients::create_service_client(organization_id, &claims.private, client, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Delete a service client of the caller's organization
+    /// This requires the clients:write permission.
+    async fn delete_service_client(
+        &self,
+        id: EntityId,
+        context: &Context,
+    ) -> FieldResult<service_clients::SingleServiceClientResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::ClientsWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        service_clients::delete_service_client(organization_id, id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Deactivate a user of the caller's organization, and revoke its sessions
+    /// This requires the users:write permission.
+    async fn deactivate_user(
+        &self,
+        user_id: EntityId,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::UsersWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::deactivate_user(organization_id, user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Reactivate a user of the caller's organization
+    /// This requires the users:write permission.
+    async fn reactivate_user(
+        &self,
+        user_id: EntityId,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::UsersWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::reactivate_user(organization_id, user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Lift the lockout of a user of the caller's organization, after too many
+    /// failed logins. This requires the users:write permission.
+    async fn unlock_user(
+        &self,
+        user_id: EntityId,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::UsersWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::unlock_user(organization_id, user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Delete a member of the caller's organization
+    /// The deletion is soft unless soft is false: the user is hidden, but its username
+    /// stays taken. A member from another organization is only removed from the caller's.
+    /// This requires the users:write permission.
+    async fn delete_user(
+        &self,
+        user_id: EntityId,
+        soft: Option<bool>,
+        context: &Context,
+    ) -> FieldResult<users::DeleteUserResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::UsersWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::delete_user(organization_id, user_id, soft.unwrap_or(true), context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Purge a member of the caller's organization, soft deleted or not
+    /// A member from another organization is only removed from the caller's.
+    /// This requires the users:write permission.
+    async fn purge_user(
+        &self,
+        user_id: EntityId,
+        context: &Context,
+    ) -> FieldResult<users::DeleteUserResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::UsersWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        users::purge_user(organization_id, user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Erase a member of the caller's organization: the user is anonymized, and a
+    /// tombstone records the erasure
+    /// Anyone but the user itself requires the users:write permission.
+    async fn erase_user(
+        &self,
+        user_id: EntityId,
+        context: &Context,
+    ) -> FieldResult<privacy::ErasureResponseBody> {
+        let (organization_id, erased_by) = context
+            .claims()
+            .await
+            .and_then(|claims| {
+                auth::require_self_or_permission(&claims, Some(user_id), Permission::UsersWrite)?;
+                Ok((auth::organization(&claims)?, auth::subject(&claims)?))
+            })
+            .map_err(IntoFieldError::into_field_error)?;
+        privacy::erase_user(organization_id, user_id, erased_by, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Grant a role to a member of the caller's organization, effective with the
+    /// user's next token
+    async fn grant_role(
+        &self,
+        user_id: EntityId,
+        role: String,
+        context: &Context,
+    ) -> FieldResult<organizations::SingleMembershipResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::RolesWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        roles::grant_role(organization_id, user_id, &role, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Revoke a role from a member of the caller's organization, effective with the
+    /// user's next token
+    async fn revoke_role(
+        &self,
+        user_id: EntityId,
+        role: String,
+        context: &Context,
+    ) -> FieldResult<organizations::SingleMembershipResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::RolesWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        roles::revoke_role(organization_id, user_id, &role, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Create an organization, with the caller as its admin
+    async fn create_organization(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<organizations::SingleOrganizationResponseBody> {
+        let user_id = context
+            .require_permission(Permission::OrganizationsWrite)
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        organizations::create_organization(&name, user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Make a user of the caller's organization a member of it again, with the given
+    /// roles there
+    async fn add_member(
+        &self,
+        user_id: EntityId,
+        roles: Vec<String>,
+        context: &Context,
+    ) -> FieldResult<organizations::SingleMembershipResponseBody> {
+        let organization_id = context
+            .require_permission(Permission::UsersWrite)
+            .await
+            .and_then(|claims| auth::organization(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        organizations::add_member(organization_id, user_id, roles, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Exchange the caller's token for tokens in another of its organizations
+    async fn switch_organization(
+        &self,
+        organization_id: EntityId,
+        context: &Context,
+    ) -> FieldResult<users::AuthenticatedUserResponseBody> {
+        let user_id = context
+            .claims_for_user()
+            .await
+            .and_then(|claims| auth::subject(&claims))
+            .map_err(IntoFieldError::into_field_error)?;
+        organizations::switch_organization(organization_id, user_id, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
 }
-type Schema = RootNode<'static, Query, Mutation, EmptySubscription<Context>>;
+
+pub type Schema = RootNode<'static, Query, Mutation, EmptySubscription<Context>>;
 
 pub fn schema() -> Schema {
     Schema::new(Query, Mutation, EmptySubscription::new())
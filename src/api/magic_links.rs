@@ -0,0 +1,193 @@
+use futures::TryFutureExt;
+use juniper::GraphQLObject;
+use ring::constant_time;
+use serde::{Deserialize, Serialize};
+use slog::warn;
+use snafu::ResultExt;
+use sqlx::Connection;
+
+use crate::api::gql::Context;
+use crate::api::organizations;
+use crate::api::users::{self, LoginResponseBody};
+use crate::auth;
+use crate::db::model::{ProvideAuthn, ProvideData};
+use crate::db::Db;
+use crate::error;
+
+/// The response body for a magic link request
+/// It is the same whether the email belongs to a user or not. The binding is for the
+/// browser which asked for the link to keep, it is required to use the link.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MagicLinkResponseBody {
+    pub success: bool,
+    pub binding: Option<String>,
+}
+
+/// Send a magic link to the user with this email, in the given organization or the
+/// default one. A link bound to the browser can only be used with the binding returned
+/// here, so that a forwarded link is of no use.
+pub async fn request_magic_link(
+    email: &str,
+    organization: Option<&str>,
+    bind_browser: bool,
+    context: &Context,
+) -> Result<MagicLinkResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+        let magic_link = &context.state.magic_link;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let organization_id = organizations::organization_id(&mut tx, organization).await?;
+
+        let user =
+            tx.get_user_by_email(organization_id, email)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get user by email",
+                })?;
+
+        // There is a binding even without a user, so that the answer does not tell.
+        let binding = if bind_browser {
+            Some(auth::random_token(32))
+        } else {
+            None
+        };
+
+        let message = match user {
+            None => None,
+            Some(user) => {
+                let binding_hash = binding.as_deref().map(auth::hash_token);
+                let link = tx
+                    .create_magic_link(
+                        user.id,
+                        organization_id,
+                        binding_hash.as_deref(),
+                        magic_link.expires_at(),
+                    )
+                    .await
+                    .context(error::DBProvideError {
+                        msg: "Could not create magic link",
+                    })?;
+                let token = magic_link.sign(link.id, link.expires_at);
+                Some(magic_link.message(&user.email, &user.username, &token))
+            }
+        };
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        // The email is sent in the background, so that the response does not take
+        // longer when there is a user.
+        if let Some(message) = message {
+            let mailer = context.state.mailer.clone();
+            let logger = context.state.logger.clone();
+            tokio::spawn(async move {
+                if let Err(err) = mailer.send(message).await {
+                    warn!(logger, "Could not send magic link email: {}", err);
+                }
+            });
+        }
+
+        Ok(MagicLinkResponseBody {
+            success: true,
+            binding,
+        })
+    }
+    .await
+}
+
+/// Log the user in with the token of a magic link, and the binding if the link is bound
+/// to a browser. The link can only be used once. Users with MFA get a challenge rather
+/// than tokens, as with a password.
+pub async fn consume_magic_link(
+    token: &str,
+    binding: Option<&str>,
+    context: &Context,
+) -> Result<LoginResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let id = context.state.magic_link.verify(token)?;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let link = tx
+            .get_magic_link(id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get magic link",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Invalid or expired magic link"),
+            })?;
+
+        // A link opened in another browser is not used up, the user can still open it in
+        // the right one.
+        if let Some(binding_hash) = &link.binding_hash {
+            let is_bound = binding.map_or(false, |binding| {
+                constant_time::verify_slices_are_equal(
+                    auth::hash_token(binding).as_bytes(),
+                    binding_hash.as_bytes(),
+                )
+                .is_ok()
+            });
+            if !is_bound {
+                return Err(error::Error::MiscError {
+                    msg: String::from(
+                        "The magic link must be opened in the browser which asked for it",
+                    ),
+                });
+            }
+        }
+
+        // Another request may have used the link in the meantime.
+        tx.use_magic_link(id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not use magic link",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Invalid or expired magic link"),
+            })?;
+
+        let user = tx
+            .get_user_by_id(link.user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        if !user.active {
+            return Err(error::Error::InactiveAccountError {
+                msg: String::from(
+                    "The account is deactivated, or its email address has not been verified",
+                ),
+            });
+        }
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        users::complete_login(user, Some("magic link"), context).await
+    }
+    .await
+}
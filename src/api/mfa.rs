@@ -0,0 +1,354 @@
+use futures::TryFutureExt;
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use sqlx::{Connection, PgConnection};
+
+use crate::api::gql::Context;
+use crate::api::users::{self, AuthenticatedUserResponseBody};
+use crate::auth;
+use crate::db::model::{EntityId, ProvideAuthn, ProvideData, UserEntity};
+use crate::db::Db;
+use crate::error;
+use crate::state::audit::{AuditAction, AuditEvent};
+
+// Recovery codes are typed by hand, so they are short, but long enough not to be guessed
+// before the lockout kicks in.
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// The response body for a TOTP enrollment
+/// The secret is for authenticator apps which cannot scan the URI as a QR code.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpEnrollmentResponseBody {
+    pub secret: String,
+    pub uri: String,
+}
+
+/// The response body for a TOTP confirmation
+/// The recovery codes are only shown once, each can be used once instead of a TOTP code.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryCodesResponseBody {
+    pub recovery_codes: Vec<String>,
+}
+
+/// Start the enrollment of the user in TOTP, with a new secret.
+/// The secret only protects logins once it is confirmed.
+pub async fn enroll_totp(
+    user_id: EntityId,
+    context: &Context,
+) -> Result<TotpEnrollmentResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let user = tx
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        let totp = tx.get_totp(user_id).await.context(error::DBProvideError {
+            msg: "Could not get TOTP",
+        })?;
+
+        // Replacing a confirmed secret would let a stolen token disable MFA.
+        if totp.map_or(false, |totp| totp.confirmed_at.is_some()) {
+            return Err(error::Error::MiscError {
+                msg: String::from("TOTP is already enabled"),
+            });
+        }
+
+        let secret = context.state.totp.generate_secret();
+        tx.create_totp(user_id, &secret)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not create TOTP",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        let uri = context.state.totp.uri(&secret, &user.username);
+
+        Ok(TotpEnrollmentResponseBody { secret, uri })
+    }
+    .await
+}
+
+/// Confirm the enrollment of the user in TOTP with a first code, and issue its
+/// recovery codes. From then on, logins require a code.
+pub async fn confirm_totp(
+    user_id: EntityId,
+    code: &str,
+    context: &Context,
+) -> Result<RecoveryCodesResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let totp = tx
+            .get_totp(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get TOTP",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("TOTP is not enrolled"),
+            })?;
+
+        if totp.confirmed_at.is_some() {
+            return Err(error::Error::MiscError {
+                msg: String::from("TOTP is already enabled"),
+            });
+        }
+
+        // The code only proves the enrollment, it can still be used for a login.
+        if context
+            .state
+            .totp
+            .verify(&totp.secret, code, None)
+            .is_none()
+        {
+            return Err(error::Error::MiscError {
+                msg: String::from("Invalid MFA code"),
+            });
+        }
+
+        tx.confirm_totp(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not confirm TOTP",
+            })?;
+
+        let recovery_codes: Vec<String> = (0..context.state.totp.recovery_codes())
+            .map(|_| auth::random_token(RECOVERY_CODE_LENGTH))
+            .collect();
+        let code_hashes: Vec<String> = recovery_codes
+            .iter()
+            .map(|code| auth::hash_token(code))
+            .collect();
+
+        tx.create_recovery_codes(user_id, &code_hashes)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not create recovery codes",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                target_id: Some(user_id),
+                ..AuditEvent::new(AuditAction::MfaEnabled)
+            })
+            .await;
+
+        Ok(RecoveryCodesResponseBody { recovery_codes })
+    }
+    .await
+}
+
+/// The challenge completing the login of the user, if it has MFA.
+pub async fn challenge(
+    user: &UserEntity,
+    context: &Context,
+) -> Result<Option<String>, error::Error> {
+    let mut conn = context.state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    if !is_enabled(&mut conn, user.id).await? {
+        return Ok(None);
+    }
+
+    let challenge = auth::random_token(64);
+    conn.create_mfa_challenge(
+        user.id,
+        user.organization_id,
+        &auth::hash_token(&challenge),
+        context.state.totp.challenge_expires_at(),
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not create MFA challenge",
+    })?;
+
+    Ok(Some(challenge))
+}
+
+/// Complete a login with a code: a TOTP code, or a recovery code.
+/// Wrong codes count as failed logins, and lock the user out just the same. The challenge
+/// is taken for good with the right code only: concurrent attempts wait for each other,
+/// and only the first right code completes the login.
+pub async fn verify_mfa(
+    challenge: &str,
+    code: &str,
+    context: &Context,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let challenge = tx
+            .take_mfa_challenge(&auth::hash_token(challenge))
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not take MFA challenge",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Invalid or expired MFA challenge"),
+            })?;
+
+        let user = tx
+            .get_user_by_id(challenge.user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        // The user may have been deactivated since it verified its password.
+        if !user.active {
+            return Err(error::Error::InactiveAccountError {
+                msg: String::from("The account is deactivated"),
+            });
+        }
+
+        let failed_logins = users::check_lockout(&mut tx, user.id).await?;
+
+        if !verify_code(&mut tx, user.id, code, context).await? {
+            // The transaction is rolled back, which puts the challenge back for another try.
+            users::record_failed_login(user.id, challenge.organization_id, "mfa", context).await?;
+            return Err(error::Error::MiscError {
+                msg: String::from("Invalid MFA code"),
+            });
+        }
+
+        if failed_logins > 0 {
+            tx.clear_login_lockout(user.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not clear login lockout",
+                })?;
+        }
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                actor_id: Some(user.id),
+                target_id: Some(user.id),
+                organization_id: Some(challenge.organization_id),
+                ..AuditEvent::new(AuditAction::MfaVerified)
+            })
+            .await;
+
+        let tokens = users::issue_tokens(user, context).await?;
+        users::audit_login(
+            tokens.user.id,
+            challenge.organization_id,
+            Some("mfa"),
+            context,
+        )
+        .await;
+
+        Ok(tokens)
+    }
+    .await
+}
+
+/// Whether the user has confirmed its TOTP enrollment.
+pub async fn is_enabled(conn: &mut PgConnection, user_id: EntityId) -> Result<bool, error::Error> {
+    let totp = conn
+        .get_totp(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get TOTP",
+        })?;
+
+    Ok(totp.map_or(false, |totp| totp.confirmed_at.is_some()))
+}
+
+/// Check a code of the user, a TOTP code or else a recovery code, and use it up.
+pub async fn verify_code(
+    conn: &mut PgConnection,
+    user_id: EntityId,
+    code: &str,
+    context: &Context,
+) -> Result<bool, error::Error> {
+    let totp = conn
+        .get_totp(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get TOTP",
+        })?;
+
+    if let Some(totp) = totp.filter(|totp| totp.confirmed_at.is_some()) {
+        if let Some(step) = context
+            .state
+            .totp
+            .verify(&totp.secret, code, totp.last_used_step)
+        {
+            // Another login may have used the same code in the meantime.
+            return conn
+                .use_totp_step(user_id, step)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not use TOTP code",
+                });
+        }
+    }
+
+    let used = conn
+        .use_recovery_code(user_id, &auth::hash_token(code))
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not use recovery code",
+        })?;
+
+    if used {
+        context
+            .audit(AuditEvent {
+                actor_id: Some(user_id),
+                target_id: Some(user_id),
+                ..AuditEvent::new(AuditAction::RecoveryCodeUsed)
+            })
+            .await;
+    }
+
+    Ok(used)
+}
@@ -1,4 +1,13 @@
+pub mod audit;
 pub mod client;
 pub mod gql;
+pub mod magic_links;
+pub mod mfa;
 pub mod model;
+pub mod oauth_clients;
+pub mod organizations;
+pub mod passkeys;
+pub mod privacy;
+pub mod roles;
+pub mod service_clients;
 pub mod users;
@@ -16,6 +16,7 @@ pub struct User {
     pub active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub organization_id: EntityId,
 }
 
 impl From<UserEntity> for User {
@@ -28,6 +29,7 @@ impl From<UserEntity> for User {
             active,
             created_at,
             updated_at,
+            organization_id,
             ..
         } = entity;
 
@@ -39,6 +41,329 @@ impl From<UserEntity> for User {
             active,
             created_at,
             updated_at,
+            organization_id,
         }
     }
 }
+
+/// A role, and the permissions it grants
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Role {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<RoleEntity> for Role {
+    fn from(entity: RoleEntity) -> Self {
+        let RoleEntity {
+            name,
+            description,
+            permissions,
+            created_at,
+        } = entity;
+
+        Role {
+            name,
+            description,
+            permissions,
+            created_at,
+        }
+    }
+}
+
+/// An organization
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Organization {
+    pub id: EntityId,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<OrganizationEntity> for Organization {
+    fn from(entity: OrganizationEntity) -> Self {
+        let OrganizationEntity {
+            id,
+            name,
+            created_at,
+        } = entity;
+
+        Organization {
+            id,
+            name,
+            created_at,
+        }
+    }
+}
+
+/// The membership of a user in an organization, and its roles there
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Membership {
+    pub organization_id: EntityId,
+    pub user_id: EntityId,
+    pub roles: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<MembershipEntity> for Membership {
+    fn from(entity: MembershipEntity) -> Self {
+        let MembershipEntity {
+            organization_id,
+            user_id,
+            roles,
+            created_at,
+        } = entity;
+
+        Membership {
+            organization_id,
+            user_id,
+            roles,
+            created_at,
+        }
+    }
+}
+
+/// A browser session, as exported to its user
+/// The session id and CSRF token are secrets, and are left out.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub fingerprint: Option<String>,
+    pub ip: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<SessionEntity> for Session {
+    fn from(entity: SessionEntity) -> Self {
+        let SessionEntity {
+            fingerprint,
+            ip,
+            expires_at,
+            created_at,
+            ..
+        } = entity;
+
+        Session {
+            fingerprint,
+            ip,
+            expires_at,
+            created_at,
+        }
+    }
+}
+
+/// A login, as exported to its user: the refresh token issued then, without its hash
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Login {
+    pub organization_id: EntityId,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<RefreshTokenEntity> for Login {
+    fn from(entity: RefreshTokenEntity) -> Self {
+        let RefreshTokenEntity {
+            organization_id,
+            created_at,
+            expires_at,
+            rotated_at,
+            revoked_at,
+            ..
+        } = entity;
+
+        Login {
+            organization_id,
+            created_at,
+            expires_at,
+            rotated_at,
+            revoked_at,
+        }
+    }
+}
+
+/// A passkey of a user
+/// The public key is left out, it is of no use outside of the service.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Passkey {
+    pub id: EntityId,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<WebauthnCredentialEntity> for Passkey {
+    fn from(entity: WebauthnCredentialEntity) -> Self {
+        let WebauthnCredentialEntity {
+            id,
+            name,
+            created_at,
+            last_used_at,
+            ..
+        } = entity;
+
+        Passkey {
+            id,
+            name,
+            created_at,
+            last_used_at,
+        }
+    }
+}
+
+/// A client of the OpenID Connect provider. Its id is the client id.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct OauthClient {
+    pub id: EntityId,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<OauthClientEntity> for OauthClient {
+    fn from(entity: OauthClientEntity) -> Self {
+        let OauthClientEntity {
+            id,
+            name,
+            redirect_uris,
+            created_at,
+        } = entity;
+
+        OauthClient {
+            id,
+            name,
+            redirect_uris,
+            created_at,
+        }
+    }
+}
+
+/// A client of the client credentials grant, without its secret. Its id is the client id,
+/// and its scopes are the permissions its tokens may carry.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceClient {
+    pub id: EntityId,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ServiceClientEntity> for ServiceClient {
+    fn from(entity: ServiceClientEntity) -> Self {
+        let ServiceClientEntity {
+            id,
+            name,
+            scopes,
+            created_at,
+            ..
+        } = entity;
+
+        ServiceClient {
+            id,
+            name,
+            scopes,
+            created_at,
+        }
+    }
+}
+
+/// The record that a user was erased
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    pub user_id: EntityId,
+    pub organization_id: EntityId,
+    pub erased_by: Option<EntityId>,
+    pub erased_at: DateTime<Utc>,
+}
+
+impl From<TombstoneEntity> for Tombstone {
+    fn from(entity: TombstoneEntity) -> Self {
+        let TombstoneEntity {
+            user_id,
+            organization_id,
+            erased_by,
+            erased_at,
+        } = entity;
+
+        Tombstone {
+            user_id,
+            organization_id,
+            erased_by,
+            erased_at,
+        }
+    }
+}
+
+/// A security relevant event: who (the actor) did what (the action) to whom (the target)
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    pub id: EntityId,
+    pub action: String,
+    pub actor_id: Option<EntityId>,
+    pub target_id: Option<EntityId>,
+    pub organization_id: Option<EntityId>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<AuditEventEntity> for AuditEvent {
+    fn from(entity: AuditEventEntity) -> Self {
+        let AuditEventEntity {
+            id,
+            action,
+            actor_id,
+            target_id,
+            organization_id,
+            ip,
+            user_agent,
+            details,
+            created_at,
+        } = entity;
+
+        AuditEvent {
+            id,
+            action,
+            actor_id,
+            target_id,
+            organization_id,
+            ip,
+            user_agent,
+            details,
+            created_at,
+        }
+    }
+}
+
+/// Where a page of a connection stands in the whole list
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+}
+
+/// The arguments selecting a page of a connection: the first users after a cursor,
+/// or the last users before a cursor.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Pagination {
+    pub first: Option<i32>,
+    pub after: Option<String>,
+    pub last: Option<i32>,
+    pub before: Option<String>,
+}
@@ -0,0 +1,168 @@
+use futures::TryFutureExt;
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::convert::TryFrom;
+
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::db::model::{EntityId, ProvideAuthn};
+use crate::db::Db;
+use crate::error;
+use crate::state::oidc;
+
+/// The response body for single OAuth client
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleOauthClientResponseBody {
+    pub client: Option<OauthClient>,
+}
+
+impl From<OauthClient> for SingleOauthClientResponseBody {
+    fn from(client: OauthClient) -> Self {
+        Self {
+            client: Some(client),
+        }
+    }
+}
+
+/// The response body for multiple OAuth clients
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiOauthClientsResponseBody {
+    pub clients: Vec<OauthClient>,
+    pub clients_count: i32,
+}
+
+impl From<Vec<OauthClient>> for MultiOauthClientsResponseBody {
+    fn from(clients: Vec<OauthClient>) -> Self {
+        let clients_count = i32::try_from(clients.len()).unwrap();
+        Self {
+            clients,
+            clients_count,
+        }
+    }
+}
+
+/// The query body for registering an OAuth client
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct OauthClientRequestBody {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+}
+
+/// Retrieve all OAuth clients
+pub async fn list_oauth_clients(
+    context: &Context,
+) -> Result<MultiOauthClientsResponseBody, error::Error> {
+    let mut conn = context.state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let entities = conn
+        .get_oauth_clients()
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get OAuth clients",
+        })?;
+
+    let clients = entities
+        .into_iter()
+        .map(OauthClient::from)
+        .collect::<Vec<_>>();
+
+    Ok(MultiOauthClientsResponseBody::from(clients))
+}
+
+/// Register an OAuth client, with the redirect URIs it may receive codes at.
+pub async fn create_oauth_client(
+    client_request: OauthClientRequestBody,
+    context: &Context,
+) -> Result<SingleOauthClientResponseBody, error::Error> {
+    let OauthClientRequestBody {
+        name,
+        redirect_uris,
+    } = client_request;
+
+    if name.is_empty() {
+        return Err(error::Error::MiscError {
+            msg: String::from("The client needs a name"),
+        });
+    }
+    if redirect_uris.is_empty() {
+        return Err(error::Error::MiscError {
+            msg: String::from("The client needs at least one redirect URI"),
+        });
+    }
+    for redirect_uri in redirect_uris.iter() {
+        oidc::validate_redirect_uri(redirect_uri)?;
+    }
+
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = tx
+        .create_oauth_client(&name, &redirect_uris)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create OAuth client",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(SingleOauthClientResponseBody::from(OauthClient::from(
+        entity,
+    )))
+}
+
+/// Delete an OAuth client, returning the deleted client. The codes it has not exchanged
+/// yet are deleted too, the tokens it got remain valid until they expire.
+pub async fn delete_oauth_client(
+    id: EntityId,
+    context: &Context,
+) -> Result<SingleOauthClientResponseBody, error::Error> {
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = tx
+        .get_oauth_client(id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get OAuth client",
+        })?
+        .ok_or(error::Error::MiscError {
+            msg: format!("Unknown OAuth client {}", id),
+        })?;
+
+    tx.delete_oauth_client(id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete OAuth client",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(SingleOauthClientResponseBody::from(OauthClient::from(
+        entity,
+    )))
+}
@@ -0,0 +1,246 @@
+use futures::TryFutureExt;
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::api::users::{issue_refresh_token, AuthenticatedUserResponseBody};
+use crate::auth;
+use crate::db::model::{EntityId, ProvideAuthn};
+use crate::db::Db;
+use crate::error;
+
+/// The organization of users who did not pick one.
+pub const DEFAULT_ORGANIZATION: &str = "default";
+
+/// The response body for single organization
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleOrganizationResponseBody {
+    pub organization: Option<Organization>,
+}
+
+impl From<Organization> for SingleOrganizationResponseBody {
+    fn from(organization: Organization) -> Self {
+        Self {
+            organization: Some(organization),
+        }
+    }
+}
+
+/// The response body for multiple organizations
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiOrganizationsResponseBody {
+    pub organizations: Vec<Organization>,
+    pub organizations_count: i32,
+}
+
+impl From<Vec<Organization>> for MultiOrganizationsResponseBody {
+    fn from(organizations: Vec<Organization>) -> Self {
+        let organizations_count = i32::try_from(organizations.len()).unwrap();
+        Self {
+            organizations,
+            organizations_count,
+        }
+    }
+}
+
+/// The response body for single membership
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleMembershipResponseBody {
+    pub membership: Option<Membership>,
+}
+
+impl From<Membership> for SingleMembershipResponseBody {
+    fn from(membership: Membership) -> Self {
+        Self {
+            membership: Some(membership),
+        }
+    }
+}
+
+/// The id of the organization with the given name, or of the default organization.
+pub async fn organization_id(
+    conn: &mut sqlx::PgConnection,
+    name: Option<&str>,
+) -> Result<EntityId, error::Error> {
+    let name = name.unwrap_or(DEFAULT_ORGANIZATION);
+    let organization = conn
+        .get_organization_by_name(name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get organization by name",
+        })?
+        .ok_or(error::Error::MiscError {
+            msg: format!("Unknown organization {}", name),
+        })?;
+    Ok(organization.id)
+}
+
+/// Retrieve the organizations the user is a member of
+pub async fn list_organizations(
+    user_id: EntityId,
+    context: &Context,
+) -> Result<MultiOrganizationsResponseBody, error::Error> {
+    let mut conn = context.state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let entities = conn
+        .get_user_organizations(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get organizations",
+        })?;
+
+    let organizations = entities
+        .into_iter()
+        .map(Organization::from)
+        .collect::<Vec<_>>();
+
+    Ok(MultiOrganizationsResponseBody::from(organizations))
+}
+
+/// Create an organization. Its creator becomes a member, and its admin.
+pub async fn create_organization(
+    name: &str,
+    user_id: EntityId,
+    context: &Context,
+) -> Result<SingleOrganizationResponseBody, error::Error> {
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = tx
+        .create_organization(name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create organization",
+        })?;
+
+    tx.create_membership(entity.id, user_id, &[String::from("admin")])
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create membership",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(SingleOrganizationResponseBody::from(Organization::from(
+        entity,
+    )))
+}
+
+/// Make a user a member of the organization, with the given roles there.
+/// Only the users of the organization can be added, as a member can be deactivated or
+/// erased by the organization: the users of others are refused as if they did not exist.
+pub async fn add_member(
+    organization_id: EntityId,
+    user_id: EntityId,
+    roles: Vec<String>,
+    context: &Context,
+) -> Result<SingleMembershipResponseBody, error::Error> {
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    tx.get_user_by_id(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get user by id",
+        })?
+        .filter(|user| user.organization_id == organization_id)
+        .ok_or(error::Error::AuthorizationError {
+            msg: String::from("Only the users of the organization can be added as members"),
+        })?;
+
+    for role in roles.iter() {
+        tx.get_role(role)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get role",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: format!("Unknown role {}", role),
+            })?;
+    }
+
+    let entity = tx
+        .create_membership(organization_id, user_id, &roles)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create membership",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(SingleMembershipResponseBody::from(Membership::from(entity)))
+}
+
+/// Issue a new token and refresh token, for another organization the user is a member of.
+pub async fn switch_organization(
+    organization_id: EntityId,
+    user_id: EntityId,
+    context: &Context,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let user = tx
+        .get_user_by_id(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get user by id",
+        })?
+        .ok_or(error::Error::MiscError {
+            msg: String::from("Unknown user"),
+        })?;
+
+    // This fails if the user is not a member of the organization.
+    let claims = auth::user_claims(&mut tx, &user, organization_id).await?;
+
+    let refresh_token =
+        issue_refresh_token(&mut tx, user.id, organization_id, Uuid::new_v4(), context).await?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    let user = User::from(user);
+    let token = context.state.jwt.encode(user.id, claims)?;
+
+    Ok(AuthenticatedUserResponseBody::from((
+        user,
+        token,
+        refresh_token,
+    )))
+}
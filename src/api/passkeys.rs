@@ -0,0 +1,439 @@
+use futures::TryFutureExt;
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use sqlx::Connection;
+
+use crate::api::gql::Context;
+use crate::api::model::Passkey;
+use crate::api::organizations;
+use crate::api::users::{self, AuthenticatedUserResponseBody};
+use crate::auth;
+use crate::db::model::{EntityId, ProvideAuthn, ProvideData};
+use crate::db::Db;
+use crate::error;
+use crate::state::audit::{AuditAction, AuditEvent};
+use crate::state::webauthn::{Ceremony, EDDSA, ES256};
+
+/// The options of a passkey registration, for navigator.credentials.create()
+/// The binary values (challenge, user handle, credential ids) are in base64url.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRegistrationOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub rp_name: String,
+    pub user_handle: String,
+    pub username: String,
+    /// The COSE algorithms we accept, the preferred first
+    pub algorithms: Vec<i32>,
+    /// The passkeys the user already has, so that an authenticator is not registered twice
+    pub exclude_credentials: Vec<String>,
+}
+
+/// The response of the authenticator to a registration, in base64url
+#[derive(Debug, Deserialize, Serialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRegistrationRequestBody {
+    pub client_data_json: String,
+    pub attestation_object: String,
+    /// A name for the user to tell its passkeys apart
+    pub name: Option<String>,
+}
+
+/// The response body for a passkey registration
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyResponseBody {
+    pub passkey: Passkey,
+}
+
+/// The options of a passkey login, for navigator.credentials.get()
+/// The binary values (challenge, credential ids) are in base64url.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyLoginOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub allow_credentials: Vec<String>,
+}
+
+/// The response of the authenticator to a login, in base64url
+#[derive(Debug, Clone, Deserialize, Serialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyLoginRequestBody {
+    pub credential_id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+}
+
+/// Start the registration of a passkey for the user.
+pub async fn begin_passkey_registration(
+    user_id: EntityId,
+    context: &Context,
+) -> Result<PasskeyRegistrationOptions, error::Error> {
+    async move {
+        let webauthn = &context.state.webauthn;
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let user = tx
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        let credentials =
+            tx.get_webauthn_credentials(user.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get passkeys",
+                })?;
+
+        let challenge = webauthn.generate_challenge();
+        tx.create_webauthn_challenge(
+            user.id,
+            user.organization_id,
+            &auth::hash_token(&challenge),
+            Ceremony::Registration.as_str(),
+            webauthn.challenge_expires_at(),
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create passkey challenge",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        Ok(PasskeyRegistrationOptions {
+            challenge,
+            rp_id: String::from(webauthn.rp_id()),
+            rp_name: String::from(webauthn.rp_name()),
+            user_handle: encode(user.id.as_bytes()),
+            username: user.username,
+            algorithms: vec![ES256, EDDSA],
+            exclude_credentials: credentials
+                .iter()
+                .map(|credential| encode(&credential.credential_id))
+                .collect(),
+        })
+    }
+    .await
+}
+
+/// Complete the registration of a passkey for the user, with the response of its
+/// authenticator to the challenge.
+pub async fn finish_passkey_registration(
+    user_id: EntityId,
+    request: PasskeyRegistrationRequestBody,
+    context: &Context,
+) -> Result<PasskeyResponseBody, error::Error> {
+    async move {
+        let webauthn = &context.state.webauthn;
+        let pool = &context.state.pool;
+
+        let client_data_json = decode("clientDataJson", &request.client_data_json)?;
+        let attestation_object = decode("attestationObject", &request.attestation_object)?;
+
+        let challenge = webauthn.challenge(&client_data_json, Ceremony::Registration)?;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let challenge = tx
+            .take_webauthn_challenge(
+                &auth::hash_token(&challenge),
+                Ceremony::Registration.as_str(),
+            )
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get passkey challenge",
+            })?
+            .filter(|challenge| challenge.user_id == user_id)
+            .ok_or(error::Error::WebauthnError {
+                msg: String::from("Invalid or expired passkey challenge"),
+            })?;
+
+        let credential = webauthn.verify_registration(&attestation_object)?;
+
+        let existing =
+            tx.get_webauthn_credential(&credential.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get passkey",
+                })?;
+
+        if existing.is_some() {
+            return Err(error::Error::ConflictError {
+                field: String::from("credentialId"),
+                msg: String::from("The passkey is already registered"),
+            });
+        }
+
+        let entity = tx
+            .create_webauthn_credential(
+                user_id,
+                &credential.id,
+                &credential.public_key,
+                credential.algorithm,
+                i64::from(credential.sign_count),
+                request.name.as_deref(),
+            )
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not create passkey",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                actor_id: Some(user_id),
+                target_id: Some(user_id),
+                organization_id: Some(challenge.organization_id),
+                details: request.name,
+                ..AuditEvent::new(AuditAction::PasskeyRegistered)
+            })
+            .await;
+
+        Ok(PasskeyResponseBody {
+            passkey: Passkey::from(entity),
+        })
+    }
+    .await
+}
+
+/// Start the login of the user with one of its passkeys.
+pub async fn begin_passkey_login(
+    username: &str,
+    organization: Option<&str>,
+    context: &Context,
+) -> Result<PasskeyLoginOptions, error::Error> {
+    async move {
+        let webauthn = &context.state.webauthn;
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let organization_id = organizations::organization_id(&mut tx, organization).await?;
+
+        let user = tx
+            .get_user_by_username(organization_id, username)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by username",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        users::check_lockout(&mut tx, user.id).await?;
+
+        let credentials =
+            tx.get_webauthn_credentials(user.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get passkeys",
+                })?;
+
+        if credentials.is_empty() {
+            return Err(error::Error::WebauthnError {
+                msg: String::from("The user has no passkey"),
+            });
+        }
+
+        let challenge = webauthn.generate_challenge();
+        tx.create_webauthn_challenge(
+            user.id,
+            organization_id,
+            &auth::hash_token(&challenge),
+            Ceremony::Authentication.as_str(),
+            webauthn.challenge_expires_at(),
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create passkey challenge",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        Ok(PasskeyLoginOptions {
+            challenge,
+            rp_id: String::from(webauthn.rp_id()),
+            allow_credentials: credentials
+                .iter()
+                .map(|credential| encode(&credential.credential_id))
+                .collect(),
+        })
+    }
+    .await
+}
+
+/// Complete the login with the response of the authenticator to the challenge, and
+/// issue tokens. The passkey verifies the user, so there is no further MFA.
+/// Invalid signatures count as failed logins, and lock the user out just the same.
+pub async fn finish_passkey_login(
+    request: PasskeyLoginRequestBody,
+    context: &Context,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    async move {
+        let webauthn = &context.state.webauthn;
+        let pool = &context.state.pool;
+
+        let credential_id = decode("credentialId", &request.credential_id)?;
+        let client_data_json = decode("clientDataJson", &request.client_data_json)?;
+        let authenticator_data = decode("authenticatorData", &request.authenticator_data)?;
+        let signature = decode("signature", &request.signature)?;
+
+        let challenge = webauthn.challenge(&client_data_json, Ceremony::Authentication)?;
+
+        // The challenge is used up even if the login fails, so it is taken outside of the
+        // transaction.
+        let mut conn = pool.conn().await.context(error::DBError {
+            msg: "could not get connection",
+        })?;
+
+        let challenge = conn
+            .take_webauthn_challenge(
+                &auth::hash_token(&challenge),
+                Ceremony::Authentication.as_str(),
+            )
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get passkey challenge",
+            })?
+            .ok_or(error::Error::WebauthnError {
+                msg: String::from("Invalid or expired passkey challenge"),
+            })?;
+
+        let mut tx = conn.begin().await.context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+        let credential = tx
+            .get_webauthn_credential(&credential_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get passkey",
+            })?
+            .filter(|credential| credential.user_id == challenge.user_id)
+            .ok_or(error::Error::WebauthnError {
+                msg: String::from("Unknown passkey"),
+            })?;
+
+        let user = tx
+            .get_user_by_id(challenge.user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        let failed_logins = users::check_lockout(&mut tx, user.id).await?;
+
+        let sign_count = match webauthn.verify_authentication(
+            &client_data_json,
+            &authenticator_data,
+            &signature,
+            &credential.public_key,
+            credential.algorithm,
+        ) {
+            Ok(sign_count) => sign_count,
+            Err(err) => {
+                users::record_failed_login(user.id, challenge.organization_id, "passkey", context)
+                    .await?;
+                return Err(err);
+            }
+        };
+
+        // A counter going backwards means two authenticators share the key.
+        let used = tx
+            .use_webauthn_credential(credential.id, i64::from(sign_count))
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not use passkey",
+            })?;
+
+        if !used {
+            return Err(error::Error::WebauthnError {
+                msg: String::from("The passkey counter went backwards, it may have been cloned"),
+            });
+        }
+
+        // Only tell the user the account is inactive once it has proven who it is.
+        if !user.active {
+            return Err(error::Error::InactiveAccountError {
+                msg: String::from(
+                    "The account is deactivated, or its email address has not been verified",
+                ),
+            });
+        }
+
+        if failed_logins > 0 {
+            tx.clear_login_lockout(user.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not clear login lockout",
+                })?;
+        }
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        let tokens = users::issue_tokens(user, context).await?;
+        users::audit_login(
+            tokens.user.id,
+            challenge.organization_id,
+            Some("passkey"),
+            context,
+        )
+        .await;
+
+        Ok(tokens)
+    }
+    .await
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, error::Error> {
+    base64::decode_config(value, base64::URL_SAFE_NO_PAD).map_err(|err| {
+        error::Error::WebauthnError {
+            msg: format!("Invalid {}: {}", field, err),
+        }
+    })
+}
@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use futures::TryFutureExt;
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use sqlx::Connection;
+
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::api::users::revoke_all_sessions;
+use crate::db::model::{EntityId, ProvideAuthn};
+use crate::db::Db;
+use crate::error;
+use crate::state::audit::{self, AuditAction};
+
+/// Everything we hold about a user, for a subject access request
+/// The password hash, and the secrets of sessions and tokens, are left out.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataExport {
+    pub exported_at: DateTime<Utc>,
+    pub user: User,
+    pub memberships: Vec<Membership>,
+    pub sessions: Vec<Session>,
+    pub logins: Vec<Login>,
+    /// The audit events the user is the actor or the target of
+    pub audit_events: Vec<AuditEvent>,
+}
+
+/// The response body for a data export
+/// The document is the data export, as JSON.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct DataExportResponseBody {
+    pub document: String,
+}
+
+/// The response body for an erasure
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ErasureResponseBody {
+    pub tombstone: Tombstone,
+}
+
+/// Export everything we hold about the user.
+pub async fn export_user_data(
+    user_id: EntityId,
+    context: &Context,
+) -> Result<DataExportResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        // A transaction gives a consistent snapshot of the user's data.
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let user = tx
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        let memberships =
+            tx.get_user_memberships(user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get memberships",
+                })?;
+
+        let sessions = tx
+            .get_user_sessions(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get sessions",
+            })?;
+
+        let logins = tx
+            .get_user_refresh_tokens(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get refresh tokens",
+            })?;
+
+        let audit_events =
+            tx.get_user_audit_events(user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get audit events",
+                })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        let export = DataExport {
+            exported_at: Utc::now(),
+            user: User::from(user),
+            memberships: memberships.into_iter().map(Membership::from).collect(),
+            sessions: sessions.into_iter().map(Session::from).collect(),
+            logins: logins.into_iter().map(Login::from).collect(),
+            audit_events: audit_events.into_iter().map(AuditEvent::from).collect(),
+        };
+
+        let document = serde_json::to_string(&export).context(error::JSONError {
+            msg: String::from("Could not serialize data export"),
+        })?;
+
+        Ok(DataExportResponseBody { document })
+    }
+    .await
+}
+
+/// Erase a member of the organization, soft deleted or not.
+/// The user is anonymized rather than deleted, so that what refers to it stays valid,
+/// its tokens are revoked, and a tombstone records the erasure.
+/// Only the organization of the user, or the user itself, can erase it.
+pub async fn erase_user(
+    organization_id: EntityId,
+    user_id: EntityId,
+    erased_by: EntityId,
+    context: &Context,
+) -> Result<ErasureResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let membership =
+            tx.get_membership(organization_id, user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get membership",
+                })?;
+
+        if membership.is_none() {
+            return Err(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            });
+        }
+
+        let tombstone = tx
+            .erase_user(organization_id, user_id, Some(erased_by))
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not erase user",
+            })?
+            .ok_or(error::Error::AuthorizationError {
+                msg: String::from(
+                    "Only the organization of the user, or the user itself, can erase it",
+                ),
+            })?;
+
+        revoke_all_sessions(&mut tx, user_id, context).await?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(audit::AuditEvent {
+                actor_id: Some(erased_by),
+                target_id: Some(user_id),
+                organization_id: Some(organization_id),
+                ..audit::AuditEvent::new(AuditAction::UserErased)
+            })
+            .await;
+
+        Ok(ErasureResponseBody {
+            tombstone: Tombstone::from(tombstone),
+        })
+    }
+    .await
+}
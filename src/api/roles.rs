@@ -0,0 +1,269 @@
+use futures::TryFutureExt;
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::api::organizations::SingleMembershipResponseBody;
+use crate::auth::permission::Permission;
+use crate::auth::role;
+use crate::db::model::{EntityId, ProvideAuthn};
+use crate::db::Db;
+use crate::error;
+use crate::state::audit::{AuditAction, AuditEvent};
+
+/// The response body for single role
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleRoleResponseBody {
+    pub role: Option<Role>,
+}
+
+impl From<Role> for SingleRoleResponseBody {
+    fn from(role: Role) -> Self {
+        Self { role: Some(role) }
+    }
+}
+
+/// The response body for multiple roles
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiRolesResponseBody {
+    pub roles: Vec<Role>,
+    pub roles_count: i32,
+}
+
+impl From<Vec<Role>> for MultiRolesResponseBody {
+    fn from(roles: Vec<Role>) -> Self {
+        let roles_count = i32::try_from(roles.len()).unwrap();
+        Self { roles, roles_count }
+    }
+}
+
+/// The query body for creating a role
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+pub struct RoleRequestBody {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<Permission>,
+}
+
+/// Retrieve all roles
+pub async fn list_roles(context: &Context) -> Result<MultiRolesResponseBody, error::Error> {
+    let mut conn = context.state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let entities = conn.get_all_roles().await.context(error::DBProvideError {
+        msg: "Could not get all roles",
+    })?;
+
+    let roles = entities.into_iter().map(Role::from).collect::<Vec<_>>();
+
+    Ok(MultiRolesResponseBody::from(roles))
+}
+
+/// Create a new role, with its permissions.
+pub async fn create_role(
+    role_request: RoleRequestBody,
+    context: &Context,
+) -> Result<SingleRoleResponseBody, error::Error> {
+    let RoleRequestBody {
+        name,
+        description,
+        permissions,
+    } = role_request;
+
+    let permissions = permissions
+        .iter()
+        .map(|permission| String::from(permission.as_str()))
+        .collect::<Vec<_>>();
+
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = tx
+        .create_role(&name, description.as_deref(), &permissions)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create role",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(SingleRoleResponseBody::from(Role::from(entity)))
+}
+
+/// Delete a role, and remove it from the users who had it, returning the deleted role.
+/// The built-in roles cannot be deleted.
+pub async fn delete_role(
+    name: &str,
+    context: &Context,
+) -> Result<SingleRoleResponseBody, error::Error> {
+    if role::Role::from_str(name).is_ok() {
+        return Err(error::Error::MiscError {
+            msg: format!("The built-in role {} cannot be deleted", name),
+        });
+    }
+
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = tx
+        .get_role(name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get role",
+        })?
+        .ok_or(error::Error::MiscError {
+            msg: format!("Unknown role {}", name),
+        })?;
+
+    tx.delete_role(name).await.context(error::DBProvideError {
+        msg: "Could not delete role",
+    })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(SingleRoleResponseBody::from(Role::from(entity)))
+}
+
+/// Grant a role to a member of the organization, which only applies in that organization.
+/// The user's permissions change when its next token is issued.
+pub async fn grant_role(
+    organization_id: EntityId,
+    user_id: EntityId,
+    name: &str,
+    context: &Context,
+) -> Result<SingleMembershipResponseBody, error::Error> {
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    tx.get_role(name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get role",
+        })?
+        .ok_or(error::Error::MiscError {
+            msg: format!("Unknown role {}", name),
+        })?;
+
+    let entity = tx
+        .add_membership_role(organization_id, user_id, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not grant role",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    context
+        .audit(AuditEvent {
+            target_id: Some(user_id),
+            organization_id: Some(organization_id),
+            details: Some(String::from(name)),
+            ..AuditEvent::new(AuditAction::RoleGranted)
+        })
+        .await;
+
+    Ok(SingleMembershipResponseBody::from(Membership::from(entity)))
+}
+
+/// Revoke a role from a member of the organization.
+/// A role the user has in every organization is revoked too, but only by the user's own
+/// organization: the others cannot take it away.
+pub async fn revoke_role(
+    organization_id: EntityId,
+    user_id: EntityId,
+    name: &str,
+    context: &Context,
+) -> Result<SingleMembershipResponseBody, error::Error> {
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = tx
+        .remove_membership_role(organization_id, user_id, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not revoke role",
+        })?;
+
+    let user = tx
+        .get_user_by_id(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get user by id",
+        })?
+        .ok_or(error::Error::MiscError {
+            msg: String::from("Unknown user"),
+        })?;
+
+    if user.roles.iter().any(|role| role == name) {
+        if user.organization_id != organization_id {
+            return Err(error::Error::AuthorizationError {
+                msg: format!(
+                    "The role {} is granted in every organization, only the user's organization can revoke it",
+                    name
+                ),
+            });
+        }
+        tx.remove_user_role(user_id, name)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not revoke user role",
+            })?;
+    }
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    context
+        .audit(AuditEvent {
+            target_id: Some(user_id),
+            organization_id: Some(organization_id),
+            details: Some(String::from(name)),
+            ..AuditEvent::new(AuditAction::RoleRevoked)
+        })
+        .await;
+
+    Ok(SingleMembershipResponseBody::from(Membership::from(entity)))
+}
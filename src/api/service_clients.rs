@@ -0,0 +1,193 @@
+use futures::TryFutureExt;
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::convert::TryFrom;
+
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::api::users::hash_password;
+use crate::auth::permission::Permission;
+use crate::auth::{random_token, PrivateClaims};
+use crate::db::model::{EntityId, ProvideAuthn};
+use crate::db::Db;
+use crate::error;
+
+// The length of a client secret. Secrets are random, yet we hash them with argon like
+// passwords, as they are as long lived.
+const SECRET_LENGTH: usize = 48;
+
+/// The response body for single service client
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleServiceClientResponseBody {
+    pub client: Option<ServiceClient>,
+}
+
+impl From<ServiceClient> for SingleServiceClientResponseBody {
+    fn from(client: ServiceClient) -> Self {
+        Self {
+            client: Some(client),
+        }
+    }
+}
+
+/// The response body for a registered service client
+/// The secret is only shown once, only its hash is kept.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceClientSecretResponseBody {
+    pub client: ServiceClient,
+    pub secret: String,
+}
+
+/// The response body for multiple service clients
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiServiceClientsResponseBody {
+    pub clients: Vec<ServiceClient>,
+    pub clients_count: i32,
+}
+
+impl From<Vec<ServiceClient>> for MultiServiceClientsResponseBody {
+    fn from(clients: Vec<ServiceClient>) -> Self {
+        let clients_count = i32::try_from(clients.len()).unwrap();
+        Self {
+            clients,
+            clients_count,
+        }
+    }
+}
+
+/// The query body for registering a service client
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+pub struct ServiceClientRequestBody {
+    pub name: String,
+    pub scopes: Vec<Permission>,
+}
+
+/// Retrieve the service clients of the organization
+pub async fn list_service_clients(
+    organization_id: EntityId,
+    context: &Context,
+) -> Result<MultiServiceClientsResponseBody, error::Error> {
+    let mut conn = context.state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let entities =
+        conn.get_service_clients(organization_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get service clients",
+            })?;
+
+    let clients = entities
+        .into_iter()
+        .map(ServiceClient::from)
+        .collect::<Vec<_>>();
+
+    Ok(MultiServiceClientsResponseBody::from(clients))
+}
+
+/// Register a service client of the organization, with the scopes it may request.
+/// The caller can only grant the permissions it has.
+pub async fn create_service_client(
+    organization_id: EntityId,
+    claims: &PrivateClaims,
+    client_request: ServiceClientRequestBody,
+    context: &Context,
+) -> Result<ServiceClientSecretResponseBody, error::Error> {
+    let ServiceClientRequestBody { name, scopes } = client_request;
+
+    if name.is_empty() {
+        return Err(error::Error::MiscError {
+            msg: String::from("The client needs a name"),
+        });
+    }
+    let mut granted = Vec::new();
+    for scope in scopes.into_iter() {
+        if !claims.has_permission(scope) {
+            return Err(error::Error::AuthorizationError {
+                msg: format!("Cannot grant the {} permission without having it", scope),
+            });
+        }
+        let scope = String::from(scope.as_str());
+        if !granted.contains(&scope) {
+            granted.push(scope);
+        }
+    }
+
+    let secret = random_token(SECRET_LENGTH);
+    let secret_hash = hash_password(secret.clone(), context)?;
+
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = tx
+        .create_service_client(organization_id, &name, &secret_hash, &granted)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create service client",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(ServiceClientSecretResponseBody {
+        client: ServiceClient::from(entity),
+        secret,
+    })
+}
+
+/// Delete a service client of the organization, returning the deleted client. The tokens
+/// it got remain valid until they expire.
+pub async fn delete_service_client(
+    organization_id: EntityId,
+    id: EntityId,
+    context: &Context,
+) -> Result<SingleServiceClientResponseBody, error::Error> {
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = tx
+        .get_service_client(id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get service client",
+        })?
+        .filter(|client| client.organization_id == organization_id)
+        .ok_or(error::Error::MiscError {
+            msg: format!("Unknown service client {}", id),
+        })?;
+
+    tx.delete_service_client(organization_id, id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete service client",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(SingleServiceClientResponseBody::from(ServiceClient::from(
+        entity,
+    )))
+}
@@ -1,18 +1,24 @@
+use chrono::{DateTime, Duration, Utc};
 use futures::TryFutureExt;
-use juniper::{GraphQLInputObject, GraphQLObject};
+use juniper::{GraphQLEnum, GraphQLInputObject, GraphQLObject};
 use serde::{Deserialize, Serialize};
-use slog::info;
+use slog::{info, warn};
 use snafu::ResultExt;
 use sqlx::Connection;
 use std::convert::TryFrom;
+use uuid::Uuid;
 
 use crate::api::gql::Context;
+use crate::api::mfa;
 use crate::api::model::*;
+use crate::api::organizations;
 use crate::auth;
 use crate::db::model::ProvideAuthn;
 use crate::db::model::ProvideData;
+use crate::db::model::{self, EntityId, ProvideError, UserEntity};
 use crate::db::Db;
 use crate::error;
+use crate::state::audit::{AuditAction, AuditEvent};
 // use crate::state::{argon, jwt};
 // use crate::fsm;
 
@@ -31,56 +37,255 @@ impl From<User> for SingleUserResponseBody {
     }
 }
 
+/// The response body for multiple users
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiUsersResponseBody {
+    pub users: Vec<User>,
+    pub users_count: i32,
+}
+
+impl From<Vec<User>> for MultiUsersResponseBody {
+    fn from(users: Vec<User>) -> Self {
+        let users_count = i32::try_from(users.len()).unwrap();
+        Self { users, users_count }
+    }
+}
+
 /// The response body for a user login
+/// The token is short lived, and the refresh token can be exchanged
+/// for a new pair of token and refresh token.
 #[derive(Debug, Deserialize, Serialize, GraphQLObject)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthenticatedUserResponseBody {
     pub user: User,
     pub token: String,
+    pub refresh_token: String,
 }
 
-impl From<(User, String)> for AuthenticatedUserResponseBody {
-    fn from(auth: (User, String)) -> Self {
+impl From<(User, String, String)> for AuthenticatedUserResponseBody {
+    fn from(auth: (User, String, String)) -> Self {
         Self {
             user: auth.0,
             token: auth.1,
+            refresh_token: auth.2,
         }
     }
 }
 
-/// The response body for multiple users
+/// The response body for a login
+/// Users with MFA get a challenge, to complete the login with verifyMfa, rather
+/// than tokens.
 #[derive(Debug, Deserialize, Serialize, GraphQLObject)]
 #[serde(rename_all = "camelCase")]
-pub struct MultiUsersResponseBody {
-    pub users: Vec<User>,
-    pub users_count: i32,
+pub struct LoginResponseBody {
+    pub user: User,
+    pub token: Option<String>,
+    pub refresh_token: Option<String>,
+    pub mfa_required: Option<String>,
 }
 
-impl From<Vec<User>> for MultiUsersResponseBody {
-    fn from(users: Vec<User>) -> Self {
-        let users_count = i32::try_from(users.len()).unwrap();
-        Self { users, users_count }
+impl From<AuthenticatedUserResponseBody> for LoginResponseBody {
+    fn from(auth: AuthenticatedUserResponseBody) -> Self {
+        Self {
+            user: auth.user,
+            token: Some(auth.token),
+            refresh_token: Some(auth.refresh_token),
+            mfa_required: None,
+        }
+    }
+}
+
+/// The response body for a logout
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct LogoutResponseBody {
+    pub success: bool,
+}
+
+/// The response body for a password reset request
+/// It is the same whether the email belongs to a user or not.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordResetResponseBody {
+    pub success: bool,
+}
+
+/// The response body for a user deletion
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteUserResponseBody {
+    pub success: bool,
+}
+
+/// A user in a page of users, with its cursor
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEdge {
+    pub node: User,
+    pub cursor: String,
+}
+
+/// A page of users (a Relay connection)
+/// The total count is the number of users matching the filter, in all the pages.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct UserConnection {
+    pub edges: Vec<UserEdge>,
+    pub page_info: PageInfo,
+    pub total_count: i32,
+}
+
+/// The criteria users must match
+#[derive(Debug, Clone, Default, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct UserFilter {
+    pub active: Option<bool>,
+    /// A role the user has, on its own or in the organization
+    pub role: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+impl From<UserFilter> for model::UserFilter {
+    fn from(filter: UserFilter) -> Self {
+        let UserFilter {
+            active,
+            role,
+            created_after,
+            created_before,
+        } = filter;
+
+        model::UserFilter {
+            active,
+            role,
+            created_after,
+            created_before,
+        }
+    }
+}
+
+/// The fields users can be ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, GraphQLEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum UserOrderField {
+    CreatedAt,
+    UpdatedAt,
+    Username,
+    Email,
+}
+
+impl From<UserOrderField> for model::UserSortKey {
+    fn from(field: UserOrderField) -> Self {
+        match field {
+            UserOrderField::CreatedAt => model::UserSortKey::CreatedAt,
+            UserOrderField::UpdatedAt => model::UserSortKey::UpdatedAt,
+            UserOrderField::Username => model::UserSortKey::Username,
+            UserOrderField::Email => model::UserSortKey::Email,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, GraphQLEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderDirection {
+    Asc,
+    Desc,
+}
+
+/// How to order users. Users with the same value are ordered by id.
+#[derive(Debug, Clone, Serialize, Deserialize, GraphQLInputObject)]
+pub struct UserOrder {
+    pub field: UserOrderField,
+    pub direction: OrderDirection,
+}
+
+impl Default for UserOrder {
+    fn default() -> Self {
+        UserOrder {
+            field: UserOrderField::CreatedAt,
+            direction: OrderDirection::Asc,
+        }
     }
 }
 
+/// The number of users in a page, when the client does not say.
+const DEFAULT_PAGE_SIZE: i32 = 20;
+
+/// The largest number of users in a page.
+const MAX_PAGE_SIZE: i32 = 100;
+
+/// The number of users found by a search, when the client does not say.
+const DEFAULT_SEARCH_LIMIT: i32 = 10;
+
 /// The query body for creating (registering) a user
+/// A user registers in the given organization, or the default one. A user added
+/// by an administrator is created in the administrator's organization.
 #[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
 pub struct UserRequestBody {
     pub username: String,
     pub email: String,
     pub password: String,
+    pub organization: Option<String>,
 }
 
 /// The query body for login a user
+/// The username is looked up in the given organization, or the default one.
 #[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
 pub struct CredentialsRequestBody {
     pub username: String,
     pub password: String,
+    pub organization: Option<String>,
 }
 
-/// Retrieve all users
-pub async fn list_users(context: &Context) -> Result<MultiUsersResponseBody, error::Error> {
+/// Retrieve a page of the members of the organization matching the filter.
+pub async fn list_users(
+    organization_id: EntityId,
+    filter: UserFilter,
+    order: UserOrder,
+    pagination: Pagination,
+    context: &Context,
+) -> Result<UserConnection, error::Error> {
     async move {
+        let Pagination {
+            first,
+            after,
+            last,
+            before,
+        } = pagination;
+
+        let (backward, size, cursor) = match (first, after, last, before) {
+            (first, after, None, None) => (false, first.unwrap_or(DEFAULT_PAGE_SIZE), after),
+            (None, None, Some(last), before) => (true, last, before),
+            _ => {
+                return Err(error::Error::MiscError {
+                    msg: String::from("Paginate with first and after, or with last and before"),
+                })
+            }
+        };
+
+        if size < 0 || size > MAX_PAGE_SIZE {
+            return Err(error::Error::MiscError {
+                msg: format!("The page size must be between 0 and {}", MAX_PAGE_SIZE),
+            });
+        }
+
+        let sort = model::UserSortKey::from(order.field);
+        let cursor = cursor
+            .map(|cursor| decode_cursor(&cursor, order.field))
+            .transpose()?;
+        let has_cursor = cursor.is_some();
+
+        // We ask for one more user, to know if there is another page.
+        let page = model::UserPage {
+            sort,
+            descending: order.direction == OrderDirection::Desc,
+            backward,
+            cursor,
+            limit: i64::from(size) + 1,
+        };
+        let filter = model::UserFilter::from(filter);
+
         let pool = &context.state.pool;
 
         let mut tx = pool
@@ -91,23 +296,78 @@ pub async fn list_users(context: &Context) -> Result<MultiUsersResponseBody, err
                 msg: "could not initiate transaction",
             })?;
 
-        let entities = tx.get_all_users().await.context(error::DBProvideError {
-            msg: "Could not get all them users",
-        })?;
+        let mut entities = tx
+            .get_users(organization_id, &filter, &page)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get users",
+            })?;
 
-        let users = entities.into_iter().map(User::from).collect::<Vec<_>>();
+        let count =
+            tx.count_users(organization_id, &filter)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not count users",
+                })?;
 
         tx.commit().await.context(error::DBError {
             msg: "could not commit transaction",
         })?;
 
-        Ok(MultiUsersResponseBody::from(users))
+        let has_more = entities.len() > size as usize;
+        entities.truncate(size as usize);
+        if backward {
+            entities.reverse();
+        }
+
+        let edges = entities
+            .into_iter()
+            .map(|entity| UserEdge {
+                cursor: encode_cursor(order.field, &entity.cursor(sort)),
+                node: User::from(entity),
+            })
+            .collect::<Vec<_>>();
+
+        // We only know there are users on the other side of the cursor.
+        let page_info = PageInfo {
+            has_next_page: if backward { has_cursor } else { has_more },
+            has_previous_page: if backward { has_more } else { has_cursor },
+            start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+            end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+        };
+
+        Ok(UserConnection {
+            edges,
+            page_info,
+            total_count: i32::try_from(count).unwrap(),
+        })
     }
     .await
 }
 
-/// Create a new user.
+/// Cursors are opaque to clients. They hold the order field, so that a cursor cannot be
+/// used with another order.
+fn encode_cursor(field: UserOrderField, cursor: &model::UserCursor) -> String {
+    let cursor = serde_json::json!([field, cursor.key, cursor.id]);
+    base64::encode_config(cursor.to_string(), base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_cursor(cursor: &str, field: UserOrderField) -> Result<model::UserCursor, error::Error> {
+    let invalid = || error::Error::MiscError {
+        msg: String::from("Invalid cursor"),
+    };
+    let cursor = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+    let (cursor_field, key, id): (UserOrderField, String, EntityId) =
+        serde_json::from_slice(&cursor).map_err(|_| invalid())?;
+    if cursor_field != field {
+        return Err(invalid());
+    }
+    Ok(model::UserCursor { key, id })
+}
+
+/// Create a new user in the organization.
 pub async fn add_user(
+    organization_id: EntityId,
     user_request: UserRequestBody,
     context: &Context,
 ) -> Result<SingleUserResponseBody, error::Error> {
@@ -116,8 +376,11 @@ pub async fn add_user(
             username,
             email,
             password,
+            ..
         } = user_request;
 
+        let password = hash_password(password, context)?;
+
         let pool = &context.state.pool;
 
         let mut tx = pool
@@ -130,6 +393,7 @@ pub async fn add_user(
 
         let entity = ProvideData::create_user(
             &mut tx as &mut sqlx::PgConnection,
+            organization_id,
             &username,
             &email,
             &password,
@@ -139,12 +403,22 @@ pub async fn add_user(
             msg: "Could not create user",
         })?;
 
+        send_verification(&mut tx, &entity, None, context).await?;
+
         let user = User::from(entity);
 
         tx.commit().await.context(error::DBError {
             msg: "could not retrieve indexes",
         })?;
 
+        context
+            .audit(AuditEvent {
+                target_id: Some(user.id),
+                organization_id: Some(organization_id),
+                ..AuditEvent::new(AuditAction::UserAdded)
+            })
+            .await;
+
         Ok(SingleUserResponseBody::from(user))
     }
     .await
@@ -152,6 +426,7 @@ pub async fn add_user(
 
 /// Register a new user.
 /// This is really the same thing as new user...
+/// The user must verify its email before it can login.
 pub async fn register_user(
     user_request: UserRequestBody,
     context: &Context,
@@ -161,17 +436,10 @@ pub async fn register_user(
             username,
             email,
             password,
+            organization,
         } = user_request;
 
-        let password = context
-            .state
-            .argon
-            .hasher()
-            .with_password(password)
-            .hash()
-            .map_err(|err| error::Error::HasherError {
-                msg: format!("could not hash password: {}", err),
-            })?;
+        let password = hash_password(password, context)?;
 
         let pool = &context.state.pool;
 
@@ -183,8 +451,12 @@ pub async fn register_user(
                 msg: "could not initiate register user transaction",
             })?;
 
+        let organization_id =
+            organizations::organization_id(&mut tx, organization.as_deref()).await?;
+
         let entity = ProvideAuthn::create_user(
             &mut tx as &mut sqlx::PgConnection,
+            organization_id,
             &username,
             &email,
             &password,
@@ -194,19 +466,32 @@ pub async fn register_user(
             msg: "Could not create user",
         })?;
 
+        // The account stays inactive until the user follows the link we send.
+        send_verification(&mut tx, &entity, None, context).await?;
+
         let user = User::from(entity);
 
         tx.commit().await.context(error::DBError {
             msg: "could not commit register user transaction",
         })?;
 
+        context
+            .audit(AuditEvent {
+                actor_id: Some(user.id),
+                target_id: Some(user.id),
+                organization_id: Some(organization_id),
+                ..AuditEvent::new(AuditAction::UserRegistered)
+            })
+            .await;
+
         Ok(SingleUserResponseBody::from(user))
     }
     .await
 }
 
-/// Retrieve a single user given its username
+/// Retrieve a single user given its username, in the organization
 pub async fn find_user_by_username(
+    organization_id: EntityId,
     context: &Context,
     username: &str,
 ) -> Result<SingleUserResponseBody, error::Error> {
@@ -222,7 +507,7 @@ pub async fn find_user_by_username(
             })?;
 
         let entity = tx
-            .get_user_by_username(username)
+            .get_user_by_username(organization_id, username)
             .await
             .context(error::DBProvideError {
                 msg: "Could not get user by username",
@@ -250,18 +535,27 @@ pub async fn find_user_by_username(
     .await
 }
 
-/// user login
-pub async fn login_user(
-    credentials: CredentialsRequestBody,
+/// Search the members of the organization whose username or email resembles the query.
+/// The closest users come first.
+pub async fn search_users(
+    organization_id: EntityId,
+    query: &str,
+    limit: Option<i32>,
     context: &Context,
-) -> Result<AuthenticatedUserResponseBody, error::Error> {
+) -> Result<MultiUsersResponseBody, error::Error> {
     async move {
-        // First we lookup an account based on the username
-        // If there is no such account, return Ok(None)
-        // 2. Compare using password hasher
-        //
-        // I am not reusing the find_user_by_username function because it
-        // doesn't return enough information.
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        if limit < 0 || limit > MAX_PAGE_SIZE {
+            return Err(error::Error::MiscError {
+                msg: format!("The search limit must be between 0 and {}", MAX_PAGE_SIZE),
+            });
+        }
+
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(MultiUsersResponseBody::from(Vec::new()));
+        }
+
         let pool = &context.state.pool;
 
         let mut tx = pool
@@ -272,55 +566,1460 @@ pub async fn login_user(
                 msg: "could not initiate transaction",
             })?;
 
-        let entity = tx
-            .get_user_by_username(&credentials.username)
+        let entities = tx
+            .search_users(organization_id, query, i64::from(limit))
             .await
             .context(error::DBProvideError {
-                msg: "Could not get user by username",
+                msg: "Could not search users",
             })?;
 
-        if entity.is_none() {
-            info!(context.state.logger, "Cannot find user");
-            return Err(error::Error::MiscError {
-                msg: String::from("Unknown user"),
-            });
-        }
-
-        let entity = entity.unwrap();
         tx.commit().await.context(error::DBError {
             msg: "could not commit transaction",
         })?;
 
-        let is_valid = context
-            .state
-            .argon
-            .verifier()
-            .with_hash(&entity.password)
-            .with_password(credentials.password)
-            .verify()
-            .map_err(|err| error::Error::HasherError {
-                msg: format!("could not verify password: {}", err),
+        let users = entities.into_iter().map(User::from).collect::<Vec<_>>();
+
+        Ok(MultiUsersResponseBody::from(users))
+    }
+    .await
+}
+
+/// Retrieve a single user given its id
+pub async fn find_user_by_id(
+    context: &Context,
+    user_id: EntityId,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let mut conn = context.state.pool.conn().await.context(error::DBError {
+            msg: "could not get connection",
+        })?;
+
+        let entity = conn
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
             })?;
 
-        if !is_valid {
-            return Err(error::Error::MiscError {
-                msg: String::from("Invalid credentials"),
-            });
+        Ok(SingleUserResponseBody {
+            user: entity.map(User::from),
+        })
+    }
+    .await
+}
+
+/// Retrieve a single user given its id, if it is a member of the organization
+pub async fn find_member_by_id(
+    organization_id: EntityId,
+    context: &Context,
+    user_id: EntityId,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let mut conn = context.state.pool.conn().await.context(error::DBError {
+            msg: "could not get connection",
+        })?;
+
+        let membership = conn
+            .get_membership(organization_id, user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get membership",
+            })?;
+
+        if membership.is_none() {
+            return Ok(SingleUserResponseBody { user: None });
         }
 
-        // User is authenticated, so build the jwt token
-        let claims = auth::PrivateClaims {
-            roles: entity
-                .roles
-                .iter()
-                .map(|role| String::from(role))
-                .collect::<Vec<String>>(),
-        };
+        let entity = conn
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?;
 
-        let user = User::from(entity);
-        let token = context.state.jwt.encode(claims)?;
+        Ok(SingleUserResponseBody {
+            user: entity.map(User::from),
+        })
+    }
+    .await
+}
+
+/// Retrieve a single user given its email, in the organization
+pub async fn find_user_by_email(
+    organization_id: EntityId,
+    context: &Context,
+    email: &str,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let mut conn = context.state.pool.conn().await.context(error::DBError {
+            msg: "could not get connection",
+        })?;
+
+        let entity = conn
+            .get_user_by_email(organization_id, email)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by email",
+            })?;
+
+        Ok(SingleUserResponseBody {
+            user: entity.map(User::from),
+        })
+    }
+    .await
+}
 
-        Ok(AuthenticatedUserResponseBody::from((user, token)))
+/// user login
+/// Users with MFA get a challenge rather than tokens, and complete the login with
+/// verifyMfa.
+pub async fn login_user(
+    credentials: CredentialsRequestBody,
+    context: &Context,
+) -> Result<LoginResponseBody, error::Error> {
+    async move {
+        let entity = verify_credentials(credentials, context).await?;
+        complete_login(entity, None, context).await
     }
     .await
 }
+
+/// Issue tokens to the authenticated user, unless it has MFA, in which case it gets a
+/// challenge to complete the login with.
+/// The details of the login, eg how the user authenticated, go to the audit log.
+pub async fn complete_login(
+    entity: UserEntity,
+    details: Option<&str>,
+    context: &Context,
+) -> Result<LoginResponseBody, error::Error> {
+    if let Some(challenge) = mfa::challenge(&entity, context).await? {
+        return Ok(LoginResponseBody {
+            user: User::from(entity),
+            token: None,
+            refresh_token: None,
+            mfa_required: Some(challenge),
+        });
+    }
+
+    let (user_id, organization_id) = (entity.id, entity.organization_id);
+    let tokens = issue_tokens(entity, context).await?;
+    audit_login(user_id, organization_id, details, context).await;
+
+    Ok(LoginResponseBody::from(tokens))
+}
+
+/// Record a successful login, once the user got its tokens, its session, or its
+/// authorization code.
+pub async fn audit_login(
+    user_id: EntityId,
+    organization_id: EntityId,
+    details: Option<&str>,
+    context: &Context,
+) {
+    context
+        .audit(AuditEvent {
+            actor_id: Some(user_id),
+            target_id: Some(user_id),
+            organization_id: Some(organization_id),
+            details: details.map(String::from),
+            ..AuditEvent::new(AuditAction::LoginSucceeded)
+        })
+        .await;
+}
+
+/// Issue a token to the authenticated user, and start a new family of refresh tokens.
+pub async fn issue_tokens(
+    entity: UserEntity,
+    context: &Context,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    let pool = &context.state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let organization_id = entity.organization_id;
+    let refresh_token =
+        issue_refresh_token(&mut tx, entity.id, organization_id, Uuid::new_v4(), context).await?;
+
+    let claims = auth::user_claims(&mut tx, &entity, organization_id).await?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    let user = User::from(entity);
+    let token = context.state.jwt.encode(user.id, claims)?;
+
+    Ok(AuthenticatedUserResponseBody::from((
+        user,
+        token,
+        refresh_token,
+    )))
+}
+
+/// Check the credentials, and return the matching user.
+/// Accounts failing to login too often are locked out, and so are IP addresses, before
+/// their password is even verified.
+pub async fn verify_credentials(
+    credentials: CredentialsRequestBody,
+    context: &Context,
+) -> Result<UserEntity, error::Error> {
+    // First we lookup an account based on the username
+    // If there is no such account, return an error
+    // 2. Compare using password hasher
+    //
+    // I am not reusing the find_user_by_username function because it
+    // doesn't return enough information.
+    let pool = &context.state.pool;
+    let lockout = &context.state.lockout;
+
+    if let Some(retry_after) = context.ip.and_then(|ip| lockout.ip_retry_after(ip)) {
+        return Err(locked_out(
+            "Too many failed logins from this address",
+            retry_after,
+        ));
+    }
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let organization_id =
+        organizations::organization_id(&mut tx, credentials.organization.as_deref()).await?;
+
+    let entity = tx
+        .get_user_by_username(organization_id, &credentials.username)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get user by username",
+        })?;
+
+    if entity.is_none() {
+        info!(context.state.logger, "Cannot find user");
+        if let Some(ip) = context.ip {
+            lockout.record_ip_failure(ip);
+        }
+        context
+            .audit(AuditEvent {
+                organization_id: Some(organization_id),
+                details: Some(credentials.username.clone()),
+                ..AuditEvent::new(AuditAction::LoginFailed)
+            })
+            .await;
+        return Err(error::Error::MiscError {
+            msg: String::from("Unknown user"),
+        });
+    }
+
+    let entity = entity.unwrap();
+
+    let failed_logins = check_lockout(&mut tx, entity.id).await?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    let is_valid = verify_password(&entity.password, credentials.password, context)?;
+
+    if !is_valid {
+        record_failed_login(entity.id, organization_id, &credentials.username, context).await?;
+        return Err(error::Error::MiscError {
+            msg: String::from("Invalid credentials"),
+        });
+    }
+
+    // Only tell the user the account is inactive once it has proven who it is.
+    // Soft deleted users are not found at all.
+    if !entity.active {
+        return Err(error::Error::InactiveAccountError {
+            msg: String::from(
+                "The account is deactivated, or its email address has not been verified",
+            ),
+        });
+    }
+
+    if failed_logins > 0 {
+        let mut conn = pool.conn().await.context(error::DBError {
+            msg: "could not get connection",
+        })?;
+        // Users with MFA have not proven who they are yet, wrong codes must keep
+        // counting towards the lockout.
+        if !mfa::is_enabled(&mut conn, entity.id).await? {
+            conn.clear_login_lockout(entity.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not clear login lockout",
+                })?;
+        }
+    }
+
+    context
+        .audit(AuditEvent {
+            actor_id: Some(entity.id),
+            target_id: Some(entity.id),
+            organization_id: Some(organization_id),
+            ..AuditEvent::new(AuditAction::PasswordVerified)
+        })
+        .await;
+
+    Ok(entity)
+}
+
+/// The failed logins of the user, unless it is locked out.
+/// The failed logins are kept once a lockout is over, so that the next one is longer.
+pub async fn check_lockout(
+    tx: &mut sqlx::PgConnection,
+    user_id: EntityId,
+) -> Result<i32, error::Error> {
+    let failures = tx
+        .get_login_lockout(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get login lockout",
+        })?;
+
+    match failures {
+        Some(failures) => {
+            let now = Utc::now();
+            if let Some(locked_until) = failures.locked_until.filter(|until| *until > now) {
+                return Err(locked_out(
+                    "The account is locked out, after too many failed logins",
+                    locked_until - now,
+                ));
+            }
+            Ok(failures.failed_logins)
+        }
+        None => Ok(0),
+    }
+}
+
+/// Count a failed login of the user, and of the client's IP address, and lock the
+/// user out once it has failed too often.
+pub async fn record_failed_login(
+    user_id: EntityId,
+    organization_id: EntityId,
+    details: &str,
+    context: &Context,
+) -> Result<(), error::Error> {
+    let lockout = &context.state.lockout;
+
+    if let Some(ip) = context.ip {
+        lockout.record_ip_failure(ip);
+    }
+
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    // The count is locked until we commit, so that concurrent failed logins each see
+    // their own count, and each extend the lockout.
+    let failures = tx
+        .record_failed_login(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not record failed login",
+        })?;
+
+    let locked_until = lockout.locked_until(failures.failed_logins);
+    tx.lock_out_user(user_id, locked_until)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not lock out user",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    context
+        .audit(AuditEvent {
+            target_id: Some(user_id),
+            organization_id: Some(organization_id),
+            details: Some(String::from(details)),
+            ..AuditEvent::new(AuditAction::LoginFailed)
+        })
+        .await;
+
+    if let Some(locked_until) = locked_until {
+        context
+            .audit(AuditEvent {
+                target_id: Some(user_id),
+                organization_id: Some(organization_id),
+                details: Some(locked_until.to_rfc3339()),
+                ..AuditEvent::new(AuditAction::AccountLocked)
+            })
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Verify the user's email, with the token we sent, and activate its account.
+/// A token sent to a new email replaces the user's email with it.
+/// The token can only be used once.
+pub async fn verify_email(
+    token: &str,
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let verification = tx
+            .use_email_verification(&auth::hash_token(token))
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not use email verification",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Invalid or expired verification token"),
+            })?;
+
+        if let Some(email) = verification.email {
+            let user = tx
+                .get_user_by_id(verification.user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get user by id",
+                })?
+                .ok_or(error::Error::MiscError {
+                    msg: String::from("Unknown user"),
+                })?;
+            tx.update_user(&UserEntity { email, ..user })
+                .await
+                .map_err(|err| conflict(err, "Could not update user"))?;
+        }
+
+        let entity =
+            tx.verify_user_email(verification.user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not verify user email",
+                })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        Ok(SingleUserResponseBody::from(User::from(entity)))
+    }
+    .await
+}
+
+/// Exchange a refresh token for a new token and a new refresh token.
+/// The refresh token is rotated: it can only be used once. If a refresh token
+/// is presented a second time, we assume it has been stolen, and we revoke
+/// all the refresh tokens of its family.
+pub async fn refresh_token(
+    refresh_token: &str,
+    context: &Context,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = tx
+            .get_refresh_token_by_hash(&auth::hash_token(refresh_token))
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get refresh token",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Invalid refresh token"),
+            })?;
+
+        if entity.revoked_at.is_some() {
+            return Err(error::Error::MiscError {
+                msg: String::from("Invalid refresh token"),
+            });
+        }
+
+        // If we cannot rotate the token, it means it has already been used.
+        let rotated = entity.rotated_at.is_none()
+            && tx
+                .rotate_refresh_token(entity.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not rotate refresh token",
+                })?;
+
+        if !rotated {
+            info!(
+                context.state.logger,
+                "Refresh token reuse detected, revoking family {}", entity.family_id
+            );
+            tx.revoke_refresh_token_family(entity.family_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not revoke refresh token family",
+                })?;
+            tx.commit().await.context(error::DBError {
+                msg: "could not commit transaction",
+            })?;
+            return Err(error::Error::MiscError {
+                msg: String::from("Refresh token reuse detected"),
+            });
+        }
+
+        if entity.expires_at < Utc::now() {
+            return Err(error::Error::MiscError {
+                msg: String::from("Expired refresh token"),
+            });
+        }
+
+        let user = tx
+            .get_user_by_id(entity.user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        // Deactivating a user revokes its refresh tokens, yet we do not rely on it to
+        // refuse tokens to inactive users. Soft deleted users are not found at all.
+        if !user.active {
+            return Err(error::Error::InactiveAccountError {
+                msg: String::from("The account is deactivated"),
+            });
+        }
+
+        let refresh_token = issue_refresh_token(
+            &mut tx,
+            user.id,
+            entity.organization_id,
+            entity.family_id,
+            context,
+        )
+        .await?;
+
+        let claims = auth::user_claims(&mut tx, &user, entity.organization_id).await?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        let user = User::from(user);
+        let token = context.state.jwt.encode(user.id, claims)?;
+
+        Ok(AuthenticatedUserResponseBody::from((
+            user,
+            token,
+            refresh_token,
+        )))
+    }
+    .await
+}
+
+/// Logout, by revoking the caller's token.
+/// If the token comes from a session, the session is deleted, and if a refresh token
+/// is given, its whole family is revoked.
+pub async fn logout_user(
+    refresh_token: Option<String>,
+    context: &Context,
+) -> Result<LogoutResponseBody, error::Error> {
+    async move {
+        let claims = context.claims_for_user().await?;
+        let user_id = auth::subject(&claims)?;
+        let jti = claims.registered.id.unwrap_or_default();
+        let expires_at = claims
+            .registered
+            .expiry
+            .map(|exp| *exp)
+            .unwrap_or_else(Utc::now);
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        tx.revoke_token(&jti, user_id, expires_at)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not revoke token",
+            })?;
+
+        if let Some(session) = &context.session {
+            tx.delete_session(&session.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not delete session",
+                })?;
+        }
+
+        if let Some(refresh_token) = refresh_token {
+            let entity = tx
+                .get_refresh_token_by_hash(&auth::hash_token(&refresh_token))
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get refresh token",
+                })?;
+            // We only revoke the refresh tokens of the caller.
+            if let Some(entity) = entity.filter(|entity| entity.user_id == user_id) {
+                tx.revoke_refresh_token_family(entity.family_id)
+                    .await
+                    .context(error::DBProvideError {
+                        msg: "Could not revoke refresh token family",
+                    })?;
+            }
+        }
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        Ok(LogoutResponseBody { success: true })
+    }
+    .await
+}
+
+/// Logout from everywhere.
+/// All the tokens issued to the caller so far are revoked, as well as all
+/// its refresh tokens and sessions.
+pub async fn logout_all_sessions(context: &Context) -> Result<LogoutResponseBody, error::Error> {
+    async move {
+        let claims = context.claims_for_user().await?;
+        let user_id = auth::subject(&claims)?;
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        revoke_all_sessions(&mut tx, user_id, context).await?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        info!(
+            context.state.logger,
+            "Logged out all sessions of {}", user_id
+        );
+
+        Ok(LogoutResponseBody { success: true })
+    }
+    .await
+}
+
+/// Send a password reset token to the user with this email, in the given organization
+/// or the default one.
+/// The answer is the same whether there is such a user or not, so it cannot be used to
+/// find out which emails are registered.
+pub async fn request_password_reset(
+    email: &str,
+    organization: Option<&str>,
+    context: &Context,
+) -> Result<PasswordResetResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let organization_id = organizations::organization_id(&mut tx, organization).await?;
+
+        let user =
+            tx.get_user_by_email(organization_id, email)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get user by email",
+                })?;
+
+        let message = match user {
+            None => None,
+            Some(user) => {
+                let token = auth::random_token(32);
+                let reset = &context.state.password_reset;
+                tx.create_password_reset(user.id, &auth::hash_token(&token), reset.expires_at())
+                    .await
+                    .context(error::DBProvideError {
+                        msg: "Could not create password reset",
+                    })?;
+                Some(reset.message(&user.email, &user.username, &token))
+            }
+        };
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        // The email is sent in the background, so that the response does not take
+        // longer when there is a user.
+        if let Some(message) = message {
+            let mailer = context.state.mailer.clone();
+            let logger = context.state.logger.clone();
+            tokio::spawn(async move {
+                if let Err(err) = mailer.send(message).await {
+                    warn!(logger, "Could not send password reset email: {}", err);
+                }
+            });
+        }
+
+        Ok(PasswordResetResponseBody { success: true })
+    }
+    .await
+}
+
+/// Set a new password, with the token sent to the user.
+/// The token can only be used once, and all the user's tokens, refresh tokens and
+/// sessions are revoked, in case they were obtained with the lost password.
+pub async fn reset_password(
+    token: &str,
+    new_password: String,
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let password = hash_password(new_password, context)?;
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let reset = tx
+            .use_password_reset(&auth::hash_token(token))
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not use password reset",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Invalid or expired password reset token"),
+            })?;
+
+        let user = tx
+            .get_user_by_id(reset.user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        let user = tx
+            .update_user(&UserEntity { password, ..user })
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not update user",
+            })?;
+
+        revoke_all_sessions(&mut tx, user.id, context).await?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        info!(context.state.logger, "Reset the password of {}", user.id);
+
+        // The token proves who the user is.
+        context
+            .audit(AuditEvent {
+                actor_id: Some(user.id),
+                target_id: Some(user.id),
+                organization_id: Some(user.organization_id),
+                ..AuditEvent::new(AuditAction::PasswordReset)
+            })
+            .await;
+
+        Ok(SingleUserResponseBody::from(User::from(user)))
+    }
+    .await
+}
+
+/// Change the user's password, provided it knows the current one.
+/// Wrong passwords count towards the lockout, like at login. Once the password has
+/// changed, the user's other tokens and sessions are revoked, but the token with the
+/// given id, which the user is changing its password with.
+pub async fn change_password(
+    user_id: EntityId,
+    jti: &str,
+    current_password: String,
+    new_password: String,
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let user = tx
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        check_lockout(&mut tx, user.id).await?;
+
+        if !verify_password(&user.password, current_password, context)? {
+            record_failed_login(user.id, user.organization_id, "change password", context).await?;
+            return Err(error::Error::MiscError {
+                msg: String::from("Invalid credentials"),
+            });
+        }
+
+        let password = hash_password(new_password, context)?;
+
+        let user = tx
+            .update_user(&UserEntity { password, ..user })
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not update user",
+            })?;
+
+        revoke_other_sessions(&mut tx, user.id, jti, context).await?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                target_id: Some(user.id),
+                organization_id: Some(user.organization_id),
+                ..AuditEvent::new(AuditAction::PasswordChanged)
+            })
+            .await;
+
+        Ok(SingleUserResponseBody::from(User::from(user)))
+    }
+    .await
+}
+
+/// Change the user's username and/or email.
+/// The username and the email must not be taken by another user of its organization.
+/// The new email only replaces the current one once the user verifies it, with the token
+/// we send to it.
+pub async fn update_profile(
+    user_id: EntityId,
+    username: Option<String>,
+    email: Option<String>,
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let user = tx
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?
+            .ok_or(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            })?;
+
+        if let Some(email) = email.filter(|email| *email != user.email) {
+            let taken = tx
+                .get_user_by_email(user.organization_id, &email)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get user by email",
+                })?;
+            if taken.is_some() {
+                return Err(error::Error::ConflictError {
+                    field: String::from("email"),
+                    msg: String::from("This email is already taken"),
+                });
+            }
+            send_verification(&mut tx, &user, Some(&email), context).await?;
+        }
+
+        let updated = UserEntity {
+            username: username.unwrap_or_else(|| user.username.clone()),
+            ..user
+        };
+
+        let user = tx
+            .update_user(&updated)
+            .await
+            .map_err(|err| conflict(err, "Could not update user"))?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        Ok(SingleUserResponseBody::from(User::from(user)))
+    }
+    .await
+}
+
+/// Deactivate a user of the organization. The user cannot login anymore, and
+/// its tokens, refresh tokens and sessions are revoked.
+pub async fn deactivate_user(
+    organization_id: EntityId,
+    user_id: EntityId,
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        get_user(&mut tx, organization_id, user_id).await?;
+
+        let user = tx
+            .deactivate_user(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not deactivate user",
+            })?;
+
+        revoke_all_sessions(&mut tx, user_id, context).await?;
+        drop_email_verifications(&mut tx, user_id).await?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                target_id: Some(user_id),
+                organization_id: Some(organization_id),
+                ..AuditEvent::new(AuditAction::UserDeactivated)
+            })
+            .await;
+
+        Ok(SingleUserResponseBody::from(User::from(user)))
+    }
+    .await
+}
+
+/// Reactivate a user of the organization, so that it can login again.
+pub async fn reactivate_user(
+    organization_id: EntityId,
+    user_id: EntityId,
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        get_user(&mut tx, organization_id, user_id).await?;
+
+        let user = tx
+            .activate_user(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not reactivate user",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                target_id: Some(user_id),
+                organization_id: Some(organization_id),
+                ..AuditEvent::new(AuditAction::UserReactivated)
+            })
+            .await;
+
+        Ok(SingleUserResponseBody::from(User::from(user)))
+    }
+    .await
+}
+
+/// Lift the lockout of a user of the organization, and forget its failed logins.
+pub async fn unlock_user(
+    organization_id: EntityId,
+    user_id: EntityId,
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let user = get_user(&mut tx, organization_id, user_id).await?;
+
+        tx.clear_login_lockout(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not clear login lockout",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                target_id: Some(user_id),
+                organization_id: Some(organization_id),
+                ..AuditEvent::new(AuditAction::AccountUnlocked)
+            })
+            .await;
+
+        Ok(SingleUserResponseBody::from(User::from(user)))
+    }
+    .await
+}
+
+/// Delete a member of the organization.
+/// A soft deleted user is deactivated and hidden, but its username stays taken. Otherwise
+/// the user is purged. The member of another organization keeps its account: it is only
+/// removed from this organization.
+pub async fn delete_user(
+    organization_id: EntityId,
+    user_id: EntityId,
+    soft: bool,
+    context: &Context,
+) -> Result<DeleteUserResponseBody, error::Error> {
+    if !soft {
+        return purge_user(organization_id, user_id, context).await;
+    }
+
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let user = get_member(&mut tx, organization_id, user_id).await?;
+
+        let action = if user.organization_id == organization_id {
+            tx.soft_delete_user(user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not delete user",
+                })?;
+
+            revoke_all_sessions(&mut tx, user_id, context).await?;
+            drop_email_verifications(&mut tx, user_id).await?;
+
+            AuditAction::UserDeleted
+        } else {
+            remove_member(&mut tx, organization_id, user_id).await?;
+            AuditAction::MemberRemoved
+        };
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                target_id: Some(user_id),
+                organization_id: Some(organization_id),
+                ..AuditEvent::new(action)
+            })
+            .await;
+
+        Ok(DeleteUserResponseBody { success: true })
+    }
+    .await
+}
+
+/// Purge a member of the organization, soft deleted or not, with everything that
+/// belongs to it. Its username is released. The member of another organization keeps its
+/// account: it is only removed from this organization.
+pub async fn purge_user(
+    organization_id: EntityId,
+    user_id: EntityId,
+    context: &Context,
+) -> Result<DeleteUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        // Soft deleted users are hidden, but they are still members.
+        let membership =
+            tx.get_membership(organization_id, user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get membership",
+                })?;
+
+        if membership.is_none() {
+            return Err(error::Error::MiscError {
+                msg: String::from("Unknown user"),
+            });
+        }
+
+        let purged =
+            tx.delete_user(organization_id, user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not purge user",
+                })?;
+
+        // Nothing was purged when the user belongs to another organization.
+        let action = if purged > 0 {
+            AuditAction::UserPurged
+        } else {
+            remove_member(&mut tx, organization_id, user_id).await?;
+            AuditAction::MemberRemoved
+        };
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        context
+            .audit(AuditEvent {
+                target_id: Some(user_id),
+                organization_id: Some(organization_id),
+                ..AuditEvent::new(action)
+            })
+            .await;
+
+        Ok(DeleteUserResponseBody { success: true })
+    }
+    .await
+}
+
+/// The member of the organization with this id, unless it is soft deleted.
+async fn get_member(
+    tx: &mut sqlx::PgConnection,
+    organization_id: EntityId,
+    user_id: EntityId,
+) -> Result<UserEntity, error::Error> {
+    let membership =
+        tx.get_membership(organization_id, user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get membership",
+            })?;
+
+    let user = match membership {
+        None => None,
+        Some(_) => tx
+            .get_user_by_id(user_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get user by id",
+            })?,
+    };
+
+    user.ok_or(error::Error::MiscError {
+        msg: String::from("Unknown user"),
+    })
+}
+
+/// The user of the organization with this id, unless it is soft deleted.
+/// Only the organization a user belongs to can act on its account: the other
+/// organizations the user is a member of are refused.
+async fn get_user(
+    tx: &mut sqlx::PgConnection,
+    organization_id: EntityId,
+    user_id: EntityId,
+) -> Result<UserEntity, error::Error> {
+    let user = get_member(tx, organization_id, user_id).await?;
+
+    if user.organization_id != organization_id {
+        return Err(error::Error::AuthorizationError {
+            msg: String::from("Only the organization of the user can act on its account"),
+        });
+    }
+
+    Ok(user)
+}
+
+/// Remove the user from the organization, which is not its own. Its tokens for the
+/// organization stay valid until they expire.
+async fn remove_member(
+    tx: &mut sqlx::PgConnection,
+    organization_id: EntityId,
+    user_id: EntityId,
+) -> Result<(), error::Error> {
+    tx.delete_membership(organization_id, user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete membership",
+        })?;
+
+    Ok(())
+}
+
+/// Name the field of a user which is already taken, when the error is a uniqueness
+/// violation on the username or the email.
+fn conflict(err: ProvideError, msg: &str) -> error::Error {
+    if let ProvideError::UniqueViolation { details } = &err {
+        // The details look like 'Key (organization_id, email)=(..., ...) already exists.'
+        let key = details.split('=').next().unwrap_or_default();
+        if let Some(field) = ["username", "email"]
+            .iter()
+            .find(|field| key.contains(*field))
+        {
+            return error::Error::ConflictError {
+                field: String::from(*field),
+                msg: format!("This {} is already taken", field),
+            };
+        }
+    }
+    error::Error::DBProvideError {
+        msg: String::from(msg),
+        source: err,
+    }
+}
+
+/// The error of a login refused without even checking the password.
+fn locked_out(msg: &str, retry_after: Duration) -> error::Error {
+    error::Error::LockoutError {
+        msg: String::from(msg),
+        // Round up, so that retrying after that many seconds is never too early.
+        retry_after: retry_after.num_seconds() + 1,
+    }
+}
+
+/// Check the password, or the secret of a service client, against the stored hash.
+pub fn verify_password(
+    hash: &str,
+    password: String,
+    context: &Context,
+) -> Result<bool, error::Error> {
+    context
+        .state
+        .argon
+        .verifier()
+        .with_hash(hash)
+        .with_password(password)
+        .verify()
+        .map_err(|err| error::Error::HasherError {
+            msg: format!("could not verify password: {}", err),
+        })
+}
+
+/// Hash a password, or the secret of a service client, to store it.
+pub fn hash_password(password: String, context: &Context) -> Result<String, error::Error> {
+    context
+        .state
+        .argon
+        .hasher()
+        .with_password(password)
+        .hash()
+        .map_err(|err| error::Error::HasherError {
+            msg: format!("could not hash password: {}", err),
+        })
+}
+
+/// Revoke all the tokens issued to the user so far, as well as all its refresh tokens
+/// and sessions.
+pub async fn revoke_all_sessions(
+    tx: &mut sqlx::PgConnection,
+    user_id: EntityId,
+    context: &Context,
+) -> Result<(), error::Error> {
+    revoke_sessions(tx, user_id, None, None, context).await
+}
+
+/// Revoke all the tokens issued to the user so far but the caller's token, and its
+/// session if it has one, as well as all its refresh tokens and other sessions.
+/// The caller's refresh token cannot be told apart from the others, so the caller must
+/// login again once its token expires.
+pub async fn revoke_other_sessions(
+    tx: &mut sqlx::PgConnection,
+    user_id: EntityId,
+    jti: &str,
+    context: &Context,
+) -> Result<(), error::Error> {
+    let session = context.session.as_ref().map(|session| session.id.as_str());
+    revoke_sessions(tx, user_id, Some(jti), session, context).await
+}
+
+async fn revoke_sessions(
+    tx: &mut sqlx::PgConnection,
+    user_id: EntityId,
+    kept_jti: Option<&str>,
+    kept_session: Option<&str>,
+    context: &Context,
+) -> Result<(), error::Error> {
+    // The revocation must outlive the longest lived token.
+    let now = Utc::now();
+    let lifetime = std::cmp::max(
+        context.state.jwt.duration(),
+        context.state.session.duration(),
+    );
+
+    tx.revoke_user_tokens(user_id, now, now + lifetime, kept_jti)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not revoke tokens",
+        })?;
+
+    tx.delete_user_sessions(user_id, kept_session)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete sessions",
+        })?;
+
+    tx.revoke_user_refresh_tokens(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not revoke refresh tokens",
+        })?;
+
+    Ok(())
+}
+
+/// Drop the pending verifications of a user who is deactivated or deleted, so that it
+/// cannot activate itself with one.
+async fn drop_email_verifications(
+    tx: &mut sqlx::PgConnection,
+    user_id: EntityId,
+) -> Result<(), error::Error> {
+    tx.delete_email_verifications(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete email verifications",
+        })?;
+
+    Ok(())
+}
+
+/// Create a verification token for the user, and email it.
+/// Only its hash is stored. With a new email, the token is sent to that email, and
+/// verifying it replaces the user's email.
+async fn send_verification(
+    tx: &mut sqlx::PgConnection,
+    user: &UserEntity,
+    new_email: Option<&str>,
+    context: &Context,
+) -> Result<(), error::Error> {
+    let token = auth::random_token(32);
+    let verification = &context.state.verification;
+
+    tx.create_email_verification(
+        user.id,
+        new_email,
+        &auth::hash_token(&token),
+        verification.expires_at(),
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not create email verification",
+    })?;
+
+    context
+        .state
+        .mailer
+        .send(verification.message(new_email.unwrap_or(&user.email), &user.username, &token))
+        .await
+}
+
+/// Create a new refresh token in the given family, and return it.
+/// Only its hash is stored.
+pub async fn issue_refresh_token(
+    tx: &mut sqlx::PgConnection,
+    user_id: EntityId,
+    organization_id: EntityId,
+    family_id: EntityId,
+    context: &Context,
+) -> Result<String, error::Error> {
+    let token = auth::random_token(64);
+    let expires_at = Utc::now() + context.state.jwt.refresh_duration();
+
+    tx.create_refresh_token(
+        user_id,
+        organization_id,
+        family_id,
+        &auth::hash_token(&token),
+        expires_at,
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not create refresh token",
+    })?;
+
+    Ok(token)
+}
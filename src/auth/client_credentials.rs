@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use uuid::Uuid;
+use warp::{self, http, Reply};
+
+use super::oidc::OauthError;
+use super::PrivateClaims;
+use crate::api::gql::Context;
+use crate::api::users::verify_password;
+use crate::db::model::{ProvideAuthn, ServiceClientEntity};
+use crate::db::Db;
+use crate::error;
+use crate::state::state::State;
+
+/// The body of a client credentials token request (RFC 6749, section 4.4).
+/// The client authenticates with HTTP Basic, or else with its id and secret in the body.
+/// Without a scope, the token carries all the scopes of the client.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ClientCredentialsRequest {
+    pub grant_type: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// The response of the token endpoint: a token for our services, without refresh token,
+/// as the client can get another one with its credentials.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientCredentialsResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub scope: String,
+}
+
+/// Issue a token to a service client, for the scopes it requests.
+pub async fn token_filter(
+    state: State,
+    authorization: Option<String>,
+    req: ClientCredentialsRequest,
+) -> Result<impl Reply, warp::Rejection> {
+    let response = issue_token(state, authorization, req)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let reply = warp::reply::json(&response);
+    let reply = warp::reply::with_header(reply, http::header::CACHE_CONTROL, "no-store");
+    Ok(warp::reply::with_header(
+        reply,
+        http::header::PRAGMA,
+        "no-cache",
+    ))
+}
+
+async fn issue_token(
+    state: State,
+    authorization: Option<String>,
+    req: ClientCredentialsRequest,
+) -> Result<ClientCredentialsResponse, OauthError> {
+    if req.grant_type.as_deref() != Some("client_credentials") {
+        return Err(OauthError::unsupported_grant_type(
+            "Only the client_credentials grant is supported",
+        ));
+    }
+
+    let (client_id, secret) = credentials(authorization.as_deref(), &req)?;
+    let context = Context {
+        state,
+        token: None,
+        session: None,
+        ip: None,
+        user_agent: None,
+    };
+    let client = authenticate(&client_id, secret, &context).await?;
+    let scopes = scopes(&client, req.scope.as_deref())?;
+    let scope = scopes.join(" ");
+
+    let claims = PrivateClaims {
+        org: Some(client.organization_id),
+        permissions: scopes,
+        scope: Some(scope.clone()),
+        machine: true,
+        ..Default::default()
+    };
+    let access_token = context.state.jwt.encode(client.id, claims)?;
+
+    Ok(ClientCredentialsResponse {
+        access_token,
+        token_type: String::from("Bearer"),
+        expires_in: context.state.jwt.duration().num_seconds(),
+        scope,
+    })
+}
+
+/// The client id and secret, from the HTTP Basic authorization header, or else from the
+/// body. A client must not use both.
+fn credentials(
+    authorization: Option<&str>,
+    req: &ClientCredentialsRequest,
+) -> Result<(String, String), OauthError> {
+    let in_body = req.client_id.is_some() || req.client_secret.is_some();
+    match authorization {
+        Some(authorization) if authorization.starts_with("Basic ") => {
+            if in_body {
+                return Err(OauthError::invalid_request(
+                    "The client must authenticate with a single method",
+                ));
+            }
+            // Our client ids and secrets are alphanumeric, so their form encoding is a
+            // no-op.
+            let decoded = base64::decode(authorization.trim_start_matches("Basic ").trim())
+                .ok()
+                .and_then(|decoded| String::from_utf8(decoded).ok())
+                .ok_or_else(|| OauthError::invalid_client("Invalid client credentials"))?;
+            let mut parts = decoded.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(id), Some(secret)) => Ok((String::from(id), String::from(secret))),
+                _ => Err(OauthError::invalid_client("Invalid client credentials")),
+            }
+        }
+        _ => match (&req.client_id, &req.client_secret) {
+            (Some(id), Some(secret)) => Ok((id.clone(), secret.clone())),
+            _ => Err(OauthError::invalid_client("Missing client credentials")),
+        },
+    }
+}
+
+/// The client with that id and secret. Unknown clients and wrong secrets are the same
+/// error, so that the caller cannot tell which clients exist.
+async fn authenticate(
+    client_id: &str,
+    secret: String,
+    context: &Context,
+) -> Result<ServiceClientEntity, OauthError> {
+    let client_id = match Uuid::parse_str(client_id) {
+        Ok(client_id) => client_id,
+        Err(_) => return Err(OauthError::invalid_client("Invalid client credentials")),
+    };
+
+    let mut conn = context.state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let client = conn
+        .get_service_client(client_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get service client",
+        })?
+        .ok_or_else(|| OauthError::invalid_client("Invalid client credentials"))?;
+
+    if verify_password(&client.secret_hash, secret, context)? {
+        Ok(client)
+    } else {
+        Err(OauthError::invalid_client("Invalid client credentials"))
+    }
+}
+
+/// The requested scopes, without duplicates, which must all be scopes of the client.
+fn scopes(
+    client: &ServiceClientEntity,
+    requested: Option<&str>,
+) -> Result<Vec<String>, OauthError> {
+    let requested = match requested.filter(|scope| !scope.trim().is_empty()) {
+        Some(requested) => requested,
+        None => return Ok(client.scopes.clone()),
+    };
+    let mut scopes = Vec::new();
+    for scope in requested.split_whitespace() {
+        if !client.scopes.iter().any(|allowed| allowed == scope) {
+            return Err(OauthError::invalid_scope(&format!(
+                "The client may not request the {} scope",
+                scope
+            )));
+        }
+        if !scopes.iter().any(|granted| granted == scope) {
+            scopes.push(String::from(scope));
+        }
+    }
+    Ok(scopes)
+}
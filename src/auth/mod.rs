@@ -1,139 +1,493 @@
-// use crate::{
-//     environment::Environment,
-//     model::{self, session::Identity},
-// };
-// use chrono::{Duration, Utc};
-// use rand::{distributions::Alphanumeric, Rng};
+use biscuit::{ClaimsSet, StringOrUri};
+use chrono::Utc;
+use futures::TryFutureExt;
+use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
-//use serde_json::json;
-//use shrinkwraprs::Shrinkwrap;
-//use std::net::SocketAddr;
-//use thiserror::Error;
-//use warp::{self, http, Reply};
-
-// #[derive(Shrinkwrap, Clone, Serialize, Deserialize, Debug)]
-// pub struct Session(model::Session);
-//
-// #[derive(Serialize, Deserialize, Debug)]
-// pub struct Request {
-//     email: String,
-//     password: String,
-//     lifetime: Option<i64>,
-// }
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use slog::{debug, warn};
+use snafu::ResultExt;
+use sqlx::{Connection, PgConnection};
+use std::net::SocketAddr;
+use uuid::Uuid;
+use warp::{self, http, Reply};
+
+use crate::api::gql::Context;
+use crate::api::mfa;
+use crate::api::model::User;
+use crate::api::users::{
+    audit_login, check_lockout, record_failed_login, verify_credentials, CredentialsRequestBody,
+};
+use crate::db::model::{EntityId, Identity, ProvideAuthn, SessionEntity, UserEntity};
+use crate::db::Db;
+use crate::error;
+use crate::state::state::State;
+use permission::Permission;
+
+pub mod client_credentials;
+pub mod oidc;
+pub mod permission;
+pub mod role;
+
+/// The body of a session login request.
+/// The lifetime, in seconds, is capped by the configured session duration.
+/// The code, a TOTP code or a recovery code, is required from users with MFA.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Request {
+    username: String,
+    password: String,
+    organization: Option<String>,
+    lifetime: Option<i64>,
+    code: Option<String>,
+}
 
 // We're defining our own private claims.
-// This is completely pointless for now (I think registered claims are sufficient)
-// but I use this as a placeholder for claims that will pop up in the future
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// The org claim is the organization the token was issued for, which scopes the queries.
+// The roles are the user's own roles, and its roles in that organization.
+// The permissions are those granted by the roles when the token was issued, so changes
+// to roles take effect on the next token.
+// The session and csrf claims are only present in tokens bound to a browser session,
+// which are carried by a cookie rather than an authorization header.
+// The scope is only present in tokens issued to OpenID Connect clients and to service
+// clients. The tokens of OpenID Connect clients carry no roles nor permissions: they only
+// give access to the user info. The machine claim marks the tokens of service clients,
+// whose subject is the client rather than a user.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct PrivateClaims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<EntityId>,
     pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csrf: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub machine: bool,
 }
 
 impl PrivateClaims {
     pub fn roles(&self) -> Vec<String> {
         self.roles.to_owned()
     }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions
+            .iter()
+            .any(|granted| granted == permission.as_str())
+    }
+}
+
+/// The private claims for a token issued to the user, for the given organization:
+/// its roles there, and the permissions they grant.
+pub async fn user_claims(
+    conn: &mut PgConnection,
+    user: &UserEntity,
+    organization_id: EntityId,
+) -> Result<PrivateClaims, error::Error> {
+    let membership = conn
+        .get_membership(organization_id, user.id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get membership",
+        })?
+        .ok_or(error::Error::AuthorizationError {
+            msg: String::from("Not a member of the organization"),
+        })?;
+
+    let mut roles = user.roles.clone();
+    for role in membership.roles.into_iter() {
+        if !roles.contains(&role) {
+            roles.push(role);
+        }
+    }
+
+    let permissions = conn
+        .get_role_permissions(&roles)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get role permissions",
+        })?;
+
+    Ok(PrivateClaims {
+        org: Some(organization_id),
+        roles,
+        permissions,
+        ..Default::default()
+    })
+}
+
+/// A rejection for requests carrying invalid credentials, or an invalid session.
+#[derive(Debug)]
+pub struct Unauthorized {
+    pub msg: String,
+}
+
+impl warp::reject::Reject for Unauthorized {}
+
+impl From<error::Error> for Unauthorized {
+    fn from(err: error::Error) -> Self {
+        Unauthorized {
+            msg: format!("{}", err),
+        }
+    }
+}
+
+/// Generate a random alphanumeric string, used for opaque tokens handed to clients.
+pub fn random_token(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .collect()
+}
+
+/// Hash an opaque token, so that we only store the hash in the database.
+/// These tokens are random and long enough that a fast hash is fine.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// The id of the user the token was issued to.
+pub fn subject(claims: &ClaimsSet<PrivateClaims>) -> Result<EntityId, error::Error> {
+    match &claims.registered.subject {
+        Some(StringOrUri::String(subject)) => {
+            Uuid::parse_str(subject).map_err(|err| error::Error::MiscError {
+                msg: format!("Invalid token subject: {}", err),
+            })
+        }
+        _ => Err(error::Error::MiscError {
+            msg: String::from("Token has no subject"),
+        }),
+    }
+}
+
+/// The organization the token was issued for.
+pub fn organization(claims: &ClaimsSet<PrivateClaims>) -> Result<EntityId, error::Error> {
+    claims.private.org.ok_or(error::Error::MiscError {
+        msg: String::from("Token has no organization"),
+    })
 }
 
-// #[allow(dead_code)]
-// #[derive(Error, Debug)]
-// pub enum AuthError {
-//     #[error("invalid credentials")]
-//     InvalidCredentials,
-//     #[error("could not hash password")]
-//     ArgonError,
-// }
-//
-// pub async fn filter(
-//     env: Environment,
-//     req: Request,
-//     address: Option<SocketAddr>,
-// ) -> anyhow::Result<impl Reply> {
-//     let (jwt, csrf) = request(env, req, address).await?;
-//
-//     let reply = warp::reply::json(&json!({ "jwt": jwt, "csrf": csrf }));
-//     let reply = warp::reply::with_status(reply, http::StatusCode::OK);
-//
-//     let reply = warp::reply::with_header(
-//         reply,
-//         http::header::CONTENT_TYPE,
-//         http_api_problem::PROBLEM_JSON_MEDIA_TYPE,
-//     );
-//
-//     let reply = warp::reply::with_header(reply, http::header::SET_COOKIE, format!("jwt={}", jwt));
-//
-//     Ok(reply)
-// }
-//
-// async fn request(
-//     env: Environment,
-//     req: Request,
-//     address: Option<SocketAddr>,
-// ) -> anyhow::Result<(String, String)> {
-//     let account = crate::sql::account::get_account_id_password_by_email(env.database(), &req.email)
-//         .await?
-//         .ok_or(AuthError::InvalidCredentials)?;
-//
-//     let is_valid = env
-//         .argon()
-//         .verifier()
-//         .with_hash(&account.password)
-//         .with_password(&req.password)
-//         .verify()
-//         .or(Err(AuthError::ArgonError))?;
-//
-//     if !is_valid {
-//         return Err(AuthError::InvalidCredentials.into());
-//     }
-//
-//     let identity = Identity {
-//         fingerprint: None,
-//         ip: address.map(|addr| addr.ip()),
-//     };
-//
-//     let claims = Claims {
-//         session: rand::thread_rng()
-//             .sample_iter(&Alphanumeric)
-//             .take(64)
-//             .collect(),
-//         csrf: rand::thread_rng()
-//             .sample_iter(&Alphanumeric)
-//             .take(64)
-//             .collect(),
-//     };
-//
-//     let csrf = claims.csrf.clone();
-//     let expiry = Utc::now() + Duration::seconds(env.session_lifetime(req.lifetime));
-//
-//     crate::sql::account::create_session(
-//         env.database(),
-//         &claims.session,
-//         &claims.csrf,
-//         account.id,
-//         identity,
-//         expiry,
-//     )
-//     .await?;
-//
-//     Ok((env.jwt().encode(claims, expiry)?, csrf))
-// }
-//
-// pub fn claims(env: &Environment, jwt: &str, csrf: &str) -> anyhow::Result<Claims> {
-//     let claims: Claims = env.jwt().decode(jwt)?;
-//
-//     if claims.csrf != csrf {
-//         return Err(AuthError::InvalidCredentials.into());
-//     }
-//
-//     Ok(claims)
-// }
-//
-// pub async fn session(env: Environment, jwt: &str, csrf: &str) -> anyhow::Result<Session> {
-//     let claims = claims(&env, &jwt, &csrf)?;
-//
-//     let session =
-//         crate::sql::account::get_csrf_validated_session(env.database(), &claims.session, &csrf)
-//             .await?;
-//
-//     Ok(Session(session.ok_or(AuthError::InvalidCredentials)?))
-// }
+/// Guard for resolvers accessing a user's record: a user can access its own record,
+/// and anyone else's requires the given permission. Without a user, the permission is
+/// required, so that the caller cannot tell whether there was someone else.
+pub fn require_self_or_permission(
+    claims: &ClaimsSet<PrivateClaims>,
+    user_id: Option<EntityId>,
+    permission: Permission,
+) -> Result<(), error::Error> {
+    if user_id.is_some() && user_id == subject(claims).ok() {
+        return Ok(());
+    }
+    if claims.private.has_permission(permission) {
+        Ok(())
+    } else {
+        Err(error::Error::AuthorizationError {
+            msg: format!("This operation requires the {} permission", permission),
+        })
+    }
+}
+
+/// Login and open a session.
+/// The session's jwt is set in an HttpOnly cookie, so it is out of reach of scripts,
+/// and the CSRF token is returned in the body. The client must send it back
+/// in the X-CSRF-Token header.
+pub async fn login_filter(
+    state: State,
+    req: Request,
+    address: Option<SocketAddr>,
+    user_agent: Option<String>,
+) -> Result<impl Reply, warp::Rejection> {
+    let lifetime = state.session.lifetime(req.lifetime);
+
+    let (user, jwt, csrf) = request(state.clone(), req, address, user_agent)
+        .await
+        .map_err(|err| warp::reject::custom(Unauthorized::from(err)))?;
+
+    let reply = warp::reply::json(&json!({ "user": user, "csrf": csrf }));
+    let reply = warp::reply::with_status(reply, http::StatusCode::OK);
+    let reply = warp::reply::with_header(
+        reply,
+        http::header::SET_COOKIE,
+        state.session.cookie(&jwt, lifetime),
+    );
+
+    Ok(reply)
+}
+
+async fn request(
+    state: State,
+    req: Request,
+    address: Option<SocketAddr>,
+    user_agent: Option<String>,
+) -> Result<(User, String, String), error::Error> {
+    let Request {
+        username,
+        password,
+        organization,
+        lifetime,
+        code,
+    } = req;
+
+    let context = Context {
+        state,
+        token: None,
+        session: None,
+        ip: address.map(|addr| addr.ip()),
+        user_agent: user_agent.clone(),
+    };
+    let account = verify_credentials(
+        CredentialsRequestBody {
+            username,
+            password,
+            organization,
+        },
+        &context,
+    )
+    .await?;
+
+    let identity = Identity {
+        fingerprint: user_agent.map(|agent| hash_token(&agent)),
+        ip: address.map(|addr| addr.ip()),
+    };
+
+    let session = random_token(64);
+    let csrf = random_token(64);
+    let expiry = Utc::now() + context.state.session.lifetime(lifetime);
+
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    require_mfa_code(&mut tx, &account, code.as_deref(), &context).await?;
+
+    let claims = PrivateClaims {
+        session: Some(session.clone()),
+        csrf: Some(csrf.clone()),
+        ..user_claims(&mut tx, &account, account.organization_id).await?
+    };
+
+    tx.create_session(&session, &csrf, account.id, &identity, expiry)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create session",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    let jwt = context
+        .state
+        .jwt
+        .encode_with_expiry(account.id, claims, expiry)?;
+
+    audit_login(
+        account.id,
+        account.organization_id,
+        Some("session"),
+        &context,
+    )
+    .await;
+
+    Ok((User::from(account), jwt, csrf))
+}
+
+/// Check the code, a TOTP code or a recovery code, of a user with MFA, who has already
+/// given its password. Users without MFA need no code.
+/// Wrong codes count towards the lockout, like wrong passwords.
+pub async fn require_mfa_code(
+    tx: &mut PgConnection,
+    account: &UserEntity,
+    code: Option<&str>,
+    context: &Context,
+) -> Result<(), error::Error> {
+    if !mfa::is_enabled(tx, account.id).await? {
+        return Ok(());
+    }
+
+    let failed_logins = check_lockout(tx, account.id).await?;
+    let is_valid = match code {
+        Some(code) => mfa::verify_code(tx, account.id, code, context).await?,
+        None => false,
+    };
+    if !is_valid {
+        record_failed_login(account.id, account.organization_id, "mfa", context).await?;
+        return Err(error::Error::MiscError {
+            msg: String::from("A valid MFA code is required"),
+        });
+    }
+    if failed_logins > 0 {
+        tx.clear_login_lockout(account.id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not clear login lockout",
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Decode the session's jwt, and check the CSRF token against the one it carries.
+pub fn claims(
+    state: &State,
+    jwt: &str,
+    csrf: &str,
+) -> Result<ClaimsSet<PrivateClaims>, error::Error> {
+    let claims = state.jwt.decode(jwt)?;
+
+    if claims.private.csrf.as_deref() != Some(csrf) {
+        return Err(error::Error::MiscError {
+            msg: String::from("Invalid CSRF token"),
+        });
+    }
+
+    Ok(claims)
+}
+
+/// Retrieve the session identified by the jwt, and validated by the CSRF token.
+pub async fn session(state: &State, jwt: &str, csrf: &str) -> Result<SessionEntity, error::Error> {
+    let claims = claims(&state, &jwt, &csrf)?;
+
+    let session_id = claims.private.session.ok_or(error::Error::MiscError {
+        msg: String::from("Token is not bound to a session"),
+    })?;
+
+    let mut conn = state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let session = conn
+        .get_csrf_validated_session(&session_id, csrf)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get session",
+        })?;
+
+    session.ok_or(error::Error::MiscError {
+        msg: String::from("Invalid session"),
+    })
+}
+
+/// Logout from a session.
+/// The session is deleted, its jwt is revoked, and the cookie is cleared.
+pub async fn logout_filter(
+    jwt: Option<String>,
+    csrf: Option<String>,
+    state: State,
+) -> Result<impl Reply, warp::Rejection> {
+    let (jwt, session) = match session_filter(jwt, csrf, state.clone()).await? {
+        Some(session) => session,
+        None => {
+            return Err(warp::reject::custom(Unauthorized {
+                msg: String::from("No session"),
+            }))
+        }
+    };
+
+    logout(&state, &jwt, &session)
+        .await
+        .map_err(|err| warp::reject::custom(Unauthorized::from(err)))?;
+
+    let reply = warp::reply::json(&json!({ "success": true }));
+    let reply = warp::reply::with_status(reply, http::StatusCode::OK);
+    let reply = warp::reply::with_header(
+        reply,
+        http::header::SET_COOKIE,
+        state.session.clear_cookie(),
+    );
+
+    Ok(reply)
+}
+
+async fn logout(state: &State, jwt: &str, session: &SessionEntity) -> Result<(), error::Error> {
+    let claims = state.jwt.decode(jwt)?;
+    let jti = claims.registered.id.ok_or(error::Error::MiscError {
+        msg: String::from("Token has no id"),
+    })?;
+
+    let mut tx = state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    tx.revoke_token(&jti, session.user_id, session.expires_at)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not revoke token",
+        })?;
+
+    tx.delete_session(&session.id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete session",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })
+}
+
+/// Periodically forget the revocations of tokens which have expired anyway,
+/// as well as expired sessions.
+pub async fn cleanup_revocations(state: State, period: std::time::Duration) {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let res = match state.pool.conn().await {
+            Ok(mut conn) => {
+                conn.delete_expired_revocations()
+                    .await
+                    .context(error::DBProvideError {
+                        msg: "Could not delete expired revocations",
+                    })
+            }
+            Err(err) => Err(error::Error::DBError {
+                msg: String::from("could not get connection"),
+                source: err,
+            }),
+        };
+        match res {
+            Ok(count) => debug!(state.logger, "Removed {} expired revocations", count),
+            Err(err) => warn!(state.logger, "Revocation cleanup failed: {}", err),
+        }
+    }
+}
+
+/// Validate the session cookie, if there is one.
+/// When the jwt cookie is present, the request must carry a matching X-CSRF-Token header,
+/// otherwise it is rejected. Requests without the cookie go through, and may still
+/// authenticate with a bearer token.
+pub async fn session_filter(
+    jwt: Option<String>,
+    csrf: Option<String>,
+    state: State,
+) -> Result<Option<(String, SessionEntity)>, warp::Rejection> {
+    match jwt {
+        None => Ok(None),
+        Some(jwt) => {
+            let csrf = csrf.ok_or_else(|| {
+                warp::reject::custom(Unauthorized {
+                    msg: String::from("Missing CSRF token"),
+                })
+            })?;
+            session(&state, &jwt, &csrf)
+                .await
+                .map(|session| Some((jwt, session)))
+                .map_err(|err| warp::reject::custom(Unauthorized::from(err)))
+        }
+    }
+}
@@ -0,0 +1,615 @@
+use chrono::Utc;
+use futures::TryFutureExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::net::SocketAddr;
+use uuid::Uuid;
+use warp::{self, http, Reply};
+
+use super::{hash_token, random_token, require_mfa_code, subject, user_claims, PrivateClaims};
+use crate::api::gql::Context;
+use crate::api::users::{audit_login, verify_credentials, CredentialsRequestBody};
+use crate::db::model::{AuthorizationCodeEntity, OauthClientEntity, ProvideAuthn, ProvideData};
+use crate::db::Db;
+use crate::error;
+use crate::state::oidc::{self, IdTokenClaims, Profile, UserInfo};
+use crate::state::state::State;
+
+/// An error of the OAuth 2.0 endpoints (RFC 6749), as a rejection.
+#[derive(Debug)]
+pub struct OauthError {
+    pub status: http::StatusCode,
+    pub error: &'static str,
+    pub description: String,
+}
+
+impl warp::reject::Reject for OauthError {}
+
+impl From<error::Error> for OauthError {
+    fn from(err: error::Error) -> Self {
+        OauthError {
+            status: http::StatusCode::INTERNAL_SERVER_ERROR,
+            error: "server_error",
+            description: format!("{}", err),
+        }
+    }
+}
+
+impl OauthError {
+    pub fn invalid_request(description: &str) -> Self {
+        OauthError {
+            status: http::StatusCode::BAD_REQUEST,
+            error: "invalid_request",
+            description: String::from(description),
+        }
+    }
+
+    pub fn invalid_grant(description: &str) -> Self {
+        OauthError {
+            status: http::StatusCode::BAD_REQUEST,
+            error: "invalid_grant",
+            description: String::from(description),
+        }
+    }
+
+    pub fn invalid_token(description: &str) -> Self {
+        OauthError {
+            status: http::StatusCode::UNAUTHORIZED,
+            error: "invalid_token",
+            description: String::from(description),
+        }
+    }
+
+    pub fn invalid_client(description: &str) -> Self {
+        OauthError {
+            status: http::StatusCode::UNAUTHORIZED,
+            error: "invalid_client",
+            description: String::from(description),
+        }
+    }
+
+    pub fn invalid_scope(description: &str) -> Self {
+        OauthError {
+            status: http::StatusCode::BAD_REQUEST,
+            error: "invalid_scope",
+            description: String::from(description),
+        }
+    }
+
+    pub fn unsupported_grant_type(description: &str) -> Self {
+        OauthError {
+            status: http::StatusCode::BAD_REQUEST,
+            error: "unsupported_grant_type",
+            description: String::from(description),
+        }
+    }
+
+    /// The error, in the body of the response, and for bearer tokens in the
+    /// WWW-Authenticate header as well (RFC 6750). Clients failing to authenticate
+    /// are asked for their credentials with HTTP Basic (RFC 6749).
+    pub fn reply(&self) -> warp::reply::Response {
+        let reply = warp::reply::json(&json!({
+            "error": self.error,
+            "error_description": self.description,
+        }));
+        let reply = warp::reply::with_status(reply, self.status);
+        if self.error == "invalid_client" {
+            warp::reply::with_header(
+                reply,
+                http::header::WWW_AUTHENTICATE,
+                r#"Basic realm="oauth""#,
+            )
+            .into_response()
+        } else if self.status == http::StatusCode::UNAUTHORIZED
+            || self.error == "insufficient_scope"
+        {
+            warp::reply::with_header(
+                reply,
+                http::header::WWW_AUTHENTICATE,
+                format!(r#"Bearer error="{}""#, self.error),
+            )
+            .into_response()
+        } else {
+            reply.into_response()
+        }
+    }
+}
+
+/// The parameters of an authorization request, in the query of GET /authorize, or in
+/// the login form posted to /authorize, along with the credentials.
+/// The code, a TOTP code or a recovery code, is required from users with MFA.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuthorizationRequest {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub organization: Option<String>,
+    pub code: Option<String>,
+}
+
+/// A valid authorization request.
+#[derive(Debug)]
+struct Authorization {
+    client: OauthClientEntity,
+    redirect_uri: String,
+    scope: String,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: String,
+}
+
+/// The body of a token request. Our clients are public, they prove they started the
+/// authorization with the code verifier rather than with a secret.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub client_id: Option<String>,
+    pub code_verifier: Option<String>,
+}
+
+/// The response of the token endpoint: a token for our services, and an ID token for
+/// the client.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub id_token: String,
+    pub scope: String,
+}
+
+/// Show the login form, for a valid authorization request.
+pub async fn authorize_filter(
+    state: State,
+    req: AuthorizationRequest,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    match validate(&state, &req).await {
+        Ok(authorization) => Ok(login_page(
+            &authorization.client,
+            &req,
+            None,
+            http::StatusCode::OK,
+        )),
+        Err(response) => Ok(response),
+    }
+}
+
+/// Check the credentials posted with the login form, and send the user back to the
+/// client with an authorization code. Wrong credentials show the form again.
+pub async fn authorize_login_filter(
+    state: State,
+    req: AuthorizationRequest,
+    address: Option<SocketAddr>,
+    user_agent: Option<String>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let authorization = match validate(&state, &req).await {
+        Ok(authorization) => authorization,
+        Err(response) => return Ok(response),
+    };
+
+    let context = Context {
+        state,
+        token: None,
+        session: None,
+        ip: address.map(|addr| addr.ip()),
+        user_agent,
+    };
+
+    match issue_code(&authorization, &req, &context).await {
+        Ok(code) => {
+            let mut params = vec![("code", code.as_str())];
+            if let Some(state) = &authorization.state {
+                params.push(("state", state.as_str()));
+            }
+            Ok(redirect(&authorization.redirect_uri, &params))
+        }
+        Err(err) => Ok(login_page(
+            &authorization.client,
+            &req,
+            Some(&format!("{}", err)),
+            http::StatusCode::UNAUTHORIZED,
+        )),
+    }
+}
+
+/// Exchange an authorization code for tokens.
+pub async fn token_filter(state: State, req: TokenRequest) -> Result<impl Reply, warp::Rejection> {
+    let response = exchange_code(&state, req)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let reply = warp::reply::json(&response);
+    let reply = warp::reply::with_header(reply, http::header::CACHE_CONTROL, "no-store");
+    Ok(warp::reply::with_header(
+        reply,
+        http::header::PRAGMA,
+        "no-cache",
+    ))
+}
+
+/// The claims about the user the bearer token was issued to, as allowed by its scope.
+pub async fn userinfo_filter(
+    state: State,
+    token: Option<String>,
+) -> Result<impl Reply, warp::Rejection> {
+    let info = user_info(state, token)
+        .await
+        .map_err(warp::reject::custom)?;
+    Ok(warp::reply::json(&info))
+}
+
+/// Check the authorization request. Until we know the redirect URI is registered for the
+/// client, errors are shown to the user, then they are sent back to the client.
+async fn validate(
+    state: &State,
+    req: &AuthorizationRequest,
+) -> Result<Authorization, warp::reply::Response> {
+    let client_id = req
+        .client_id
+        .as_deref()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| OauthError::invalid_request("Missing or invalid client_id").reply())?;
+
+    let mut conn = state
+        .pool
+        .conn()
+        .await
+        .context(error::DBError {
+            msg: "could not get connection",
+        })
+        .map_err(|err| OauthError::from(err).reply())?;
+
+    let client = conn
+        .get_oauth_client(client_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get OAuth client",
+        })
+        .map_err(|err| OauthError::from(err).reply())?
+        .ok_or_else(|| {
+            OauthError {
+                status: http::StatusCode::BAD_REQUEST,
+                error: "invalid_client",
+                description: String::from("Unknown client"),
+            }
+            .reply()
+        })?;
+
+    let redirect_uri = req
+        .redirect_uri
+        .clone()
+        .filter(|uri| client.redirect_uris.contains(uri))
+        .ok_or_else(|| {
+            OauthError::invalid_request("The redirect_uri is not registered for the client").reply()
+        })?;
+
+    let redirect_error = |error: &str, description: &str| {
+        let mut params = vec![("error", error), ("error_description", description)];
+        if let Some(state) = &req.state {
+            params.push(("state", state.as_str()));
+        }
+        redirect(&redirect_uri, &params)
+    };
+
+    if req.response_type.as_deref() != Some("code") {
+        return Err(redirect_error(
+            "unsupported_response_type",
+            "Only the authorization code flow is supported",
+        ));
+    }
+
+    let scope = oidc::scope(req.scope.as_deref().unwrap_or_default())
+        .ok_or_else(|| redirect_error("invalid_scope", "The openid scope is required"))?;
+
+    // Our clients are public, PKCE keeps an intercepted code from being of any use.
+    let code_challenge = req
+        .code_challenge
+        .clone()
+        .filter(|challenge| {
+            req.code_challenge_method.as_deref() == Some("S256")
+                && oidc::is_code_challenge(challenge)
+        })
+        .ok_or_else(|| {
+            redirect_error(
+                "invalid_request",
+                "A code challenge with the S256 method is required",
+            )
+        })?;
+
+    Ok(Authorization {
+        client,
+        redirect_uri: redirect_uri.clone(),
+        scope,
+        state: req.state.clone(),
+        nonce: req.nonce.clone(),
+        code_challenge,
+    })
+}
+
+/// Check the user's credentials, and issue an authorization code for the client.
+async fn issue_code(
+    authorization: &Authorization,
+    req: &AuthorizationRequest,
+    context: &Context,
+) -> Result<String, error::Error> {
+    let account = verify_credentials(
+        CredentialsRequestBody {
+            username: req.username.clone().unwrap_or_default(),
+            password: req.password.clone().unwrap_or_default(),
+            organization: req.organization.clone().filter(|org| !org.is_empty()),
+        },
+        context,
+    )
+    .await?;
+
+    let mut tx = context
+        .state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let code = req.code.as_deref().filter(|code| !code.is_empty());
+    require_mfa_code(&mut tx, &account, code, context).await?;
+
+    let code = random_token(64);
+    tx.create_authorization_code(&AuthorizationCodeEntity {
+        id: Uuid::new_v4(),
+        code_hash: hash_token(&code),
+        client_id: authorization.client.id,
+        user_id: account.id,
+        organization_id: account.organization_id,
+        redirect_uri: authorization.redirect_uri.clone(),
+        scope: authorization.scope.clone(),
+        nonce: authorization.nonce.clone(),
+        code_challenge: authorization.code_challenge.clone(),
+        expires_at: context.state.oidc.code_expires_at(),
+        created_at: Utc::now(),
+    })
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not create authorization code",
+    })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    audit_login(
+        account.id,
+        account.organization_id,
+        Some("openid connect"),
+        context,
+    )
+    .await;
+
+    Ok(code)
+}
+
+async fn exchange_code(state: &State, req: TokenRequest) -> Result<TokenResponse, OauthError> {
+    if req.grant_type.as_deref() != Some("authorization_code") {
+        return Err(OauthError::unsupported_grant_type(
+            "Only the authorization_code grant is supported",
+        ));
+    }
+    let code = req
+        .code
+        .as_deref()
+        .ok_or_else(|| OauthError::invalid_request("Missing code"))?;
+
+    let mut conn = state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    // The code is used up even if the rest of the request is wrong, so that it cannot be
+    // tried again.
+    let code = conn
+        .take_authorization_code(&hash_token(code))
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not take authorization code",
+        })?
+        .ok_or_else(|| OauthError::invalid_grant("Invalid or expired authorization code"))?;
+
+    let client_id = req
+        .client_id
+        .as_deref()
+        .and_then(|id| Uuid::parse_str(id).ok());
+    if client_id != Some(code.client_id) {
+        return Err(OauthError::invalid_grant(
+            "The code was issued to another client",
+        ));
+    }
+    if req.redirect_uri.as_deref() != Some(code.redirect_uri.as_str()) {
+        return Err(OauthError::invalid_grant(
+            "The redirect_uri does not match the authorization request",
+        ));
+    }
+    let verifier = req.code_verifier.as_deref().unwrap_or_default();
+    if !oidc::verify_code_verifier(verifier, &code.code_challenge) {
+        return Err(OauthError::invalid_grant("Invalid code verifier"));
+    }
+
+    // The user may have been deactivated or deleted since it logged in.
+    let user = conn
+        .get_user_by_id(code.user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get user by id",
+        })?
+        .filter(|user| user.active && user.deleted_at.is_none())
+        .ok_or_else(|| OauthError::invalid_grant("Unknown or inactive user"))?;
+
+    // The scopes are about the user info, and grant no permission: the access token only
+    // gives access to the user info, not to the API.
+    let claims = user_claims(&mut conn, &user, code.organization_id)
+        .await
+        .map_err(|err| OauthError::invalid_grant(&format!("{}", err)))?;
+    let claims = PrivateClaims {
+        roles: Vec::new(),
+        permissions: Vec::new(),
+        scope: Some(code.scope.clone()),
+        ..claims
+    };
+
+    let access_token = state.jwt.encode(user.id, claims)?;
+    let id_token = state.jwt.encode_id_token(
+        user.id,
+        code.client_id,
+        IdTokenClaims {
+            nonce: code.nonce.clone(),
+            auth_time: code.created_at.timestamp(),
+            profile: Profile::new(&user, &code.scope),
+        },
+        Utc::now() + state.jwt.duration(),
+    )?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: String::from("Bearer"),
+        expires_in: state.jwt.duration().num_seconds(),
+        id_token,
+        scope: code.scope,
+    })
+}
+
+async fn user_info(state: State, token: Option<String>) -> Result<UserInfo, OauthError> {
+    let context = Context {
+        state,
+        token,
+        session: None,
+        ip: None,
+        user_agent: None,
+    };
+    let claims = context
+        .userinfo_claims()
+        .await
+        .map_err(|err| OauthError::invalid_token(&format!("{}", err)))?;
+
+    let scope = claims.private.scope.clone().unwrap_or_default();
+    if !oidc::has_scope(&scope, "openid") {
+        return Err(OauthError {
+            status: http::StatusCode::FORBIDDEN,
+            error: "insufficient_scope",
+            description: String::from("The token was not issued with the openid scope"),
+        });
+    }
+
+    let user_id = subject(&claims).map_err(|err| OauthError::invalid_token(&format!("{}", err)))?;
+
+    let mut conn = context.state.pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let user = conn
+        .get_user_by_id(user_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get user by id",
+        })?
+        .filter(|user| user.deleted_at.is_none())
+        .ok_or_else(|| OauthError::invalid_token("Unknown user"))?;
+
+    Ok(UserInfo {
+        sub: user.id.to_string(),
+        profile: Profile::new(&user, &scope),
+    })
+}
+
+fn redirect(uri: &str, params: &[(&str, &str)]) -> warp::reply::Response {
+    let reply = warp::reply::with_status(warp::reply(), http::StatusCode::FOUND);
+    warp::reply::with_header(
+        reply,
+        http::header::LOCATION,
+        oidc::redirect_uri(uri, params),
+    )
+    .into_response()
+}
+
+/// The login form, which posts the credentials with the authorization request.
+/// It must not be framed by other sites, which could trick the user into logging in.
+fn login_page(
+    client: &OauthClientEntity,
+    req: &AuthorizationRequest,
+    error: Option<&str>,
+    status: http::StatusCode,
+) -> warp::reply::Response {
+    let params = [
+        ("response_type", &req.response_type),
+        ("client_id", &req.client_id),
+        ("redirect_uri", &req.redirect_uri),
+        ("scope", &req.scope),
+        ("state", &req.state),
+        ("nonce", &req.nonce),
+        ("code_challenge", &req.code_challenge),
+        ("code_challenge_method", &req.code_challenge_method),
+    ];
+    let hidden = params
+        .iter()
+        .filter_map(|(name, value)| {
+            value.as_ref().map(|value| {
+                format!(
+                    r#"<input type="hidden" name="{}" value="{}">"#,
+                    name,
+                    escape(value)
+                )
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let error = error
+        .map(|error| format!(r#"<p class="error">{}</p>"#, escape(error)))
+        .unwrap_or_default();
+
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Log in</title>
+</head>
+<body>
+<h1>Log in to {client}</h1>
+{error}
+<form method="post" action="authorize">
+{hidden}
+<label>Username <input name="username" autocomplete="username" required></label>
+<label>Password <input name="password" type="password" autocomplete="current-password" required></label>
+<label>Organization <input name="organization"></label>
+<label>Code <input name="code" autocomplete="one-time-code"></label>
+<button type="submit">Log in</button>
+</form>
+</body>
+</html>
+"#,
+        client = escape(&client.name),
+        error = error,
+        hidden = hidden,
+    );
+
+    let reply = warp::reply::with_status(warp::reply::html(page), status);
+    let reply = warp::reply::with_header(reply, http::header::CACHE_CONTROL, "no-store");
+    warp::reply::with_header(reply, http::header::X_FRAME_OPTIONS, "DENY").into_response()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
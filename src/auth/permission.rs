@@ -0,0 +1,70 @@
+use juniper::GraphQLEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error;
+
+/// The permissions granted by roles. They are stored as strings in main.role_permissions,
+/// and the effective permissions of a user are copied into the token's private claims.
+/// Serialization follows the GraphQL enum values (eg USERS_READ), not the stored strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, GraphQLEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Permission {
+    UsersRead,
+    UsersWrite,
+    RolesRead,
+    RolesWrite,
+    OrganizationsWrite,
+    ClientsWrite,
+    AuditRead,
+    ContentUser,
+    ContentModerator,
+    ContentAdmin,
+}
+
+impl Permission {
+    /// The permission, as stored in the database and in the token.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::UsersRead => "users:read",
+            Permission::UsersWrite => "users:write",
+            Permission::RolesRead => "roles:read",
+            Permission::RolesWrite => "roles:write",
+            Permission::OrganizationsWrite => "organizations:write",
+            Permission::ClientsWrite => "clients:write",
+            Permission::AuditRead => "audit:read",
+            Permission::ContentUser => "content:user",
+            Permission::ContentModerator => "content:moderator",
+            Permission::ContentAdmin => "content:admin",
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Permission {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "users:read" => Ok(Permission::UsersRead),
+            "users:write" => Ok(Permission::UsersWrite),
+            "roles:read" => Ok(Permission::RolesRead),
+            "roles:write" => Ok(Permission::RolesWrite),
+            "organizations:write" => Ok(Permission::OrganizationsWrite),
+            "clients:write" => Ok(Permission::ClientsWrite),
+            "audit:read" => Ok(Permission::AuditRead),
+            "content:user" => Ok(Permission::ContentUser),
+            "content:moderator" => Ok(Permission::ContentModerator),
+            "content:admin" => Ok(Permission::ContentAdmin),
+            _ => Err(error::Error::MiscError {
+                msg: format!("Unknown permission '{}'", s),
+            }),
+        }
+    }
+}
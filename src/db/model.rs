@@ -2,11 +2,14 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use snafu::Snafu;
 use std::convert::TryFrom;
+use std::net::IpAddr;
 use uuid::Uuid;
 
 pub type EntityId = Uuid;
 
 /// A user registered with the application (ie, stored in DB)
+/// A soft deleted user is kept, so that its username stays taken, but it is
+/// hidden from listings and lookups.
 #[derive(Debug, Clone)]
 pub struct UserEntity {
     pub id: EntityId,
@@ -17,21 +20,345 @@ pub struct UserEntity {
     pub active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub organization_id: EntityId,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// A refresh token issued at login (ie, stored in DB)
+/// Only a hash of the token is kept. All the tokens obtained by rotating
+/// the token issued at login share the same family.
+#[derive(Debug, Clone)]
+pub struct RefreshTokenEntity {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub family_id: EntityId,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub organization_id: EntityId,
+}
+
+/// A single use token sent to a user to verify its email (ie, stored in DB)
+/// Only a hash of the token is kept. The email is the new address of a user changing
+/// it, which replaces the current one once verified.
+#[derive(Debug, Clone)]
+pub struct EmailVerificationEntity {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub email: Option<String>,
+}
+
+/// A single use token sent to a user who forgot its password (ie, stored in DB)
+/// Only a hash of the token is kept.
+#[derive(Debug, Clone)]
+pub struct PasswordResetEntity {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A single use link logging a user in, sent to its email (ie, stored in DB)
+/// The token is signed rather than stored. A link bound to a browser also needs the
+/// binding, of which only a hash is kept.
+#[derive(Debug, Clone)]
+pub struct MagicLinkEntity {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub organization_id: EntityId,
+    pub binding_hash: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What we know about the client which opened a session
+#[derive(Debug, Clone, Default)]
+pub struct Identity {
+    pub fingerprint: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+/// A browser session (ie, stored in DB)
+/// The session id is carried by the jwt cookie, and the CSRF token
+/// must be sent back by the client in a header.
+#[derive(Debug, Clone)]
+pub struct SessionEntity {
+    pub id: String,
+    pub csrf: String,
+    pub user_id: EntityId,
+    pub fingerprint: Option<String>,
+    pub ip: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An organization (ie, stored in DB)
+/// Usernames are unique within an organization.
+#[derive(Debug, Clone)]
+pub struct OrganizationEntity {
+    pub id: EntityId,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The membership of a user in an organization, with the roles the user has there
+/// (ie, stored in DB)
+#[derive(Debug, Clone)]
+pub struct MembershipEntity {
+    pub organization_id: EntityId,
+    pub user_id: EntityId,
+    pub roles: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The record that a user was erased (ie, stored in DB)
+/// The user's row is kept, anonymized, so the tombstone holds nothing personal.
+#[derive(Debug, Clone)]
+pub struct TombstoneEntity {
+    pub user_id: EntityId,
+    pub organization_id: EntityId,
+    pub erased_by: Option<EntityId>,
+    pub erased_at: DateTime<Utc>,
+}
+
+/// The failed logins of a user since its last successful login, and the time until
+/// which it is locked out, if it is (ie, stored in DB)
+#[derive(Debug, Clone)]
+pub struct LoginLockoutEntity {
+    pub user_id: EntityId,
+    pub failed_logins: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The TOTP secret of a user (ie, stored in DB)
+/// It protects logins once confirmed. The last step used keeps codes from being replayed.
+#[derive(Debug, Clone)]
+pub struct TotpEntity {
+    pub user_id: EntityId,
+    pub secret: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The first step of a login with MFA (ie, stored in DB)
+#[derive(Debug, Clone)]
+pub struct MfaChallengeEntity {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub organization_id: EntityId,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A passkey of a user (ie, stored in DB)
+/// The public key is raw: an uncompressed P-256 point, or an Ed25519 key, depending on
+/// the COSE algorithm.
+#[derive(Debug, Clone)]
+pub struct WebauthnCredentialEntity {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub algorithm: i32,
+    pub sign_count: i64,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The challenge of a WebAuthn ceremony (ie, stored in DB)
+#[derive(Debug, Clone)]
+pub struct WebauthnChallengeEntity {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub organization_id: EntityId,
+    pub challenge_hash: String,
+    pub ceremony: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A client of the OpenID Connect provider (ie, stored in DB)
+/// Its id is the client id. Codes are only sent to its redirect URIs.
+#[derive(Debug, Clone)]
+pub struct OauthClientEntity {
+    pub id: EntityId,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An authorization code issued to a client for a user (ie, stored in DB)
+/// Only a hash of the code is kept, with the challenge of the client's code verifier.
+#[derive(Debug, Clone)]
+pub struct AuthorizationCodeEntity {
+    pub id: EntityId,
+    pub code_hash: String,
+    pub client_id: EntityId,
+    pub user_id: EntityId,
+    pub organization_id: EntityId,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub nonce: Option<String>,
+    pub code_challenge: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A client of the client credentials grant, ie another service of the organization
+/// (ie, stored in DB). Its id is the client id, and its scopes are the permissions its
+/// tokens may carry.
+#[derive(Debug, Clone)]
+pub struct ServiceClientEntity {
+    pub id: EntityId,
+    pub organization_id: EntityId,
+    pub name: String,
+    pub secret_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A security relevant event: who (the actor) did what (the action) to whom (the target),
+/// from where, and when (ie, stored in DB)
+/// Events are never updated nor deleted, and they outlive the users they refer to.
+#[derive(Debug, Clone)]
+pub struct AuditEventEntity {
+    pub id: EntityId,
+    pub action: String,
+    pub actor_id: Option<EntityId>,
+    pub target_id: Option<EntityId>,
+    pub organization_id: Option<EntityId>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Criteria the audit events must match. Missing criteria match every event.
+#[derive(Debug, Clone, Default)]
+pub struct AuditEventFilter {
+    pub action: Option<String>,
+    pub actor_id: Option<EntityId>,
+    pub target_id: Option<EntityId>,
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+/// A role, and the permissions it grants (ie, stored in DB)
+#[derive(Debug, Clone)]
+pub struct RoleEntity {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The keys users can be sorted by. Users with the same key are sorted by id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserSortKey {
+    CreatedAt,
+    UpdatedAt,
+    Username,
+    Email,
+}
+
+/// Criteria the users must match. Missing criteria match everyone.
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    pub active: Option<bool>,
+    /// A role the user has, on its own or in the organization
+    pub role: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+/// The position of a user in a sorted list: its sort key, as a string, and its id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserCursor {
+    pub key: String,
+    pub id: EntityId,
+}
+
+/// A page of users, in the order given by the sort key and direction: up to limit users
+/// after the cursor, or, backward, up to limit users before the cursor, which are
+/// returned closest to the cursor first.
+#[derive(Debug, Clone)]
+pub struct UserPage {
+    pub sort: UserSortKey,
+    pub descending: bool,
+    pub backward: bool,
+    pub cursor: Option<UserCursor>,
+    pub limit: i64,
+}
+
+impl UserEntity {
+    /// The position of the user in a list sorted by the key.
+    pub fn cursor(&self, sort: UserSortKey) -> UserCursor {
+        let key = match sort {
+            UserSortKey::CreatedAt => self.created_at.to_rfc3339(),
+            UserSortKey::UpdatedAt => self.updated_at.to_rfc3339(),
+            UserSortKey::Username => self.username.clone(),
+            UserSortKey::Email => self.email.clone(),
+        };
+        UserCursor { key, id: self.id }
+    }
 }
 
 // From sqlx realworld example
 #[async_trait]
 pub trait ProvideData {
+    /// Create a user in the organization, which it becomes a member of.
     async fn create_user(
         &mut self,
+        organization_id: EntityId,
         username: &str,
         email: &str,
         password: &str,
     ) -> ProvideResult<UserEntity>;
 
-    async fn get_all_users(&mut self) -> ProvideResult<Vec<UserEntity>>;
+    /// A page of the members of the organization matching the filter.
+    async fn get_users(
+        &mut self,
+        organization_id: EntityId,
+        filter: &UserFilter,
+        page: &UserPage,
+    ) -> ProvideResult<Vec<UserEntity>>;
+
+    /// The number of members of the organization matching the filter.
+    async fn count_users(
+        &mut self,
+        organization_id: EntityId,
+        filter: &UserFilter,
+    ) -> ProvideResult<i64>;
 
-    async fn get_user_by_username(&mut self, username: &str) -> ProvideResult<Option<UserEntity>>;
+    /// At most limit members of the organization whose username or email resembles
+    /// the query, by trigram similarity, the closest first.
+    /// This must run in a transaction.
+    async fn search_users(
+        &mut self,
+        organization_id: EntityId,
+        query: &str,
+        limit: i64,
+    ) -> ProvideResult<Vec<UserEntity>>;
+
+    /// The user with this username in the organization's namespace.
+    async fn get_user_by_username(
+        &mut self,
+        organization_id: EntityId,
+        username: &str,
+    ) -> ProvideResult<Option<UserEntity>>;
 }
 
 // From sqlx realworld example
@@ -39,6 +366,7 @@ pub trait ProvideData {
 pub trait ProvideAuthn {
     async fn create_user(
         &mut self,
+        organization_id: EntityId,
         username: &str,
         email: &str,
         password: &str,
@@ -46,9 +374,444 @@ pub trait ProvideAuthn {
 
     async fn get_user_by_id(&mut self, user_id: EntityId) -> ProvideResult<Option<UserEntity>>;
 
-    async fn get_user_by_email(&mut self, email: &str) -> ProvideResult<Option<UserEntity>>;
+    /// The user with this email in the organization.
+    async fn get_user_by_email(
+        &mut self,
+        organization_id: EntityId,
+        email: &str,
+    ) -> ProvideResult<Option<UserEntity>>;
 
     async fn update_user(&mut self, updated: &UserEntity) -> ProvideResult<UserEntity>;
+
+    /// Mark the user as active, when it is reactivated.
+    async fn activate_user(&mut self, user_id: EntityId) -> ProvideResult<UserEntity>;
+
+    /// Record that the user verified its email. A user who never did becomes active,
+    /// unless it was deleted, but a user deactivated since stays inactive.
+    async fn verify_user_email(&mut self, user_id: EntityId) -> ProvideResult<UserEntity>;
+
+    /// Mark the user as inactive, so that it cannot login.
+    async fn deactivate_user(&mut self, user_id: EntityId) -> ProvideResult<UserEntity>;
+
+    /// Soft delete the user: it becomes inactive, and hidden, but keeps its username.
+    async fn soft_delete_user(&mut self, user_id: EntityId) -> ProvideResult<UserEntity>;
+
+    /// Delete the user of the organization, soft deleted or not, and everything that
+    /// belongs to it. The users of other organizations are left alone.
+    async fn delete_user(
+        &mut self,
+        organization_id: EntityId,
+        user_id: EntityId,
+    ) -> ProvideResult<u64>;
+
+    /// Erase the user: anonymize it, keeping its id, drop its roles, sessions, refresh
+    /// tokens and pending emails, and record a tombstone. The user is soft deleted.
+    /// Only the users of the organization are erased, but for a user erasing itself:
+    /// nothing changes for the others, and there is no tombstone.
+    async fn erase_user(
+        &mut self,
+        organization_id: EntityId,
+        user_id: EntityId,
+        erased_by: Option<EntityId>,
+    ) -> ProvideResult<Option<TombstoneEntity>>;
+
+    /// Add a role to the user's own roles, unless the user already has it.
+    /// Unlike the roles of a membership, these apply in every organization.
+    async fn add_user_role(&mut self, user_id: EntityId, role: &str) -> ProvideResult<UserEntity>;
+
+    /// Remove a role from the user's roles.
+    async fn remove_user_role(
+        &mut self,
+        user_id: EntityId,
+        role: &str,
+    ) -> ProvideResult<UserEntity>;
+
+    async fn create_organization(&mut self, name: &str) -> ProvideResult<OrganizationEntity>;
+
+    async fn get_organization_by_id(
+        &mut self,
+        organization_id: EntityId,
+    ) -> ProvideResult<Option<OrganizationEntity>>;
+
+    async fn get_organization_by_name(
+        &mut self,
+        name: &str,
+    ) -> ProvideResult<Option<OrganizationEntity>>;
+
+    /// The memberships of the user, in every organization.
+    async fn get_user_memberships(
+        &mut self,
+        user_id: EntityId,
+    ) -> ProvideResult<Vec<MembershipEntity>>;
+
+    /// The organizations the user is a member of.
+    async fn get_user_organizations(
+        &mut self,
+        user_id: EntityId,
+    ) -> ProvideResult<Vec<OrganizationEntity>>;
+
+    async fn create_membership(
+        &mut self,
+        organization_id: EntityId,
+        user_id: EntityId,
+        roles: &[String],
+    ) -> ProvideResult<MembershipEntity>;
+
+    async fn get_membership(
+        &mut self,
+        organization_id: EntityId,
+        user_id: EntityId,
+    ) -> ProvideResult<Option<MembershipEntity>>;
+
+    /// Remove the user from the organization. Returns the number of memberships deleted.
+    async fn delete_membership(
+        &mut self,
+        organization_id: EntityId,
+        user_id: EntityId,
+    ) -> ProvideResult<u64>;
+
+    /// Add a role to the user's roles in the organization, unless the user already has it.
+    async fn add_membership_role(
+        &mut self,
+        organization_id: EntityId,
+        user_id: EntityId,
+        role: &str,
+    ) -> ProvideResult<MembershipEntity>;
+
+    /// Remove a role from the user's roles in the organization.
+    async fn remove_membership_role(
+        &mut self,
+        organization_id: EntityId,
+        user_id: EntityId,
+        role: &str,
+    ) -> ProvideResult<MembershipEntity>;
+
+    async fn create_role(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        permissions: &[String],
+    ) -> ProvideResult<RoleEntity>;
+
+    async fn get_role(&mut self, name: &str) -> ProvideResult<Option<RoleEntity>>;
+
+    async fn get_all_roles(&mut self) -> ProvideResult<Vec<RoleEntity>>;
+
+    /// Delete a role, and remove it from the users and memberships which had it.
+    /// Returns the number of users who lost the role.
+    async fn delete_role(&mut self, name: &str) -> ProvideResult<u64>;
+
+    /// The permissions granted by any of the given roles.
+    async fn get_role_permissions(&mut self, roles: &[String]) -> ProvideResult<Vec<String>>;
+
+    async fn create_email_verification(
+        &mut self,
+        user_id: EntityId,
+        email: Option<&str>,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<EmailVerificationEntity>;
+
+    /// Delete the pending verifications of the user. Return how many there were.
+    async fn delete_email_verifications(&mut self, user_id: EntityId) -> ProvideResult<u64>;
+
+    /// Mark the verification as used, and return it, unless it has already been used
+    /// or it has expired.
+    async fn use_email_verification(
+        &mut self,
+        token_hash: &str,
+    ) -> ProvideResult<Option<EmailVerificationEntity>>;
+
+    async fn create_password_reset(
+        &mut self,
+        user_id: EntityId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<PasswordResetEntity>;
+
+    /// Mark the password reset as used, and return it, unless it has already been used
+    /// or it has expired. The other password resets of the user can no longer be used.
+    async fn use_password_reset(
+        &mut self,
+        token_hash: &str,
+    ) -> ProvideResult<Option<PasswordResetEntity>>;
+
+    async fn create_magic_link(
+        &mut self,
+        user_id: EntityId,
+        organization_id: EntityId,
+        binding_hash: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<MagicLinkEntity>;
+
+    /// The magic link, unless it has already been used or it has expired.
+    async fn get_magic_link(&mut self, id: EntityId) -> ProvideResult<Option<MagicLinkEntity>>;
+
+    /// Mark the magic link as used, and return it, unless it has already been used or it
+    /// has expired.
+    async fn use_magic_link(&mut self, id: EntityId) -> ProvideResult<Option<MagicLinkEntity>>;
+
+    async fn get_login_lockout(
+        &mut self,
+        user_id: EntityId,
+    ) -> ProvideResult<Option<LoginLockoutEntity>>;
+
+    /// Count one more failed login for the user, and return its failed logins so far.
+    /// The count is locked until the end of the transaction, so that concurrent failed
+    /// logins are counted one after the other.
+    async fn record_failed_login(&mut self, user_id: EntityId)
+        -> ProvideResult<LoginLockoutEntity>;
+
+    /// Lock the user out until the given time, or lift its lockout.
+    async fn lock_out_user(
+        &mut self,
+        user_id: EntityId,
+        locked_until: Option<DateTime<Utc>>,
+    ) -> ProvideResult<u64>;
+
+    /// Forget the user's failed logins, and lift its lockout.
+    async fn clear_login_lockout(&mut self, user_id: EntityId) -> ProvideResult<u64>;
+
+    async fn get_totp(&mut self, user_id: EntityId) -> ProvideResult<Option<TotpEntity>>;
+
+    /// Store a new, unconfirmed, secret for the user, replacing the previous one.
+    async fn create_totp(&mut self, user_id: EntityId, secret: &str) -> ProvideResult<TotpEntity>;
+
+    async fn confirm_totp(&mut self, user_id: EntityId) -> ProvideResult<TotpEntity>;
+
+    /// Record the step of a code being used, unless a later step was used already.
+    /// Return whether the step was recorded.
+    async fn use_totp_step(&mut self, user_id: EntityId, step: i64) -> ProvideResult<bool>;
+
+    /// Replace the user's recovery codes.
+    async fn create_recovery_codes(
+        &mut self,
+        user_id: EntityId,
+        code_hashes: &[String],
+    ) -> ProvideResult<()>;
+
+    /// Mark the recovery code as used, unless it was already. Return whether it was.
+    async fn use_recovery_code(
+        &mut self,
+        user_id: EntityId,
+        code_hash: &str,
+    ) -> ProvideResult<bool>;
+
+    async fn create_mfa_challenge(
+        &mut self,
+        user_id: EntityId,
+        organization_id: EntityId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<MfaChallengeEntity>;
+
+    /// Remove the challenge with that hash, and return it, unless it has expired. A
+    /// challenge can only be used once.
+    async fn take_mfa_challenge(
+        &mut self,
+        token_hash: &str,
+    ) -> ProvideResult<Option<MfaChallengeEntity>>;
+
+    async fn get_webauthn_credentials(
+        &mut self,
+        user_id: EntityId,
+    ) -> ProvideResult<Vec<WebauthnCredentialEntity>>;
+
+    async fn get_webauthn_credential(
+        &mut self,
+        credential_id: &[u8],
+    ) -> ProvideResult<Option<WebauthnCredentialEntity>>;
+
+    async fn create_webauthn_credential(
+        &mut self,
+        user_id: EntityId,
+        credential_id: &[u8],
+        public_key: &[u8],
+        algorithm: i32,
+        sign_count: i64,
+        name: Option<&str>,
+    ) -> ProvideResult<WebauthnCredentialEntity>;
+
+    /// Record the use of the passkey, unless its counter went backwards in the
+    /// meantime. Return whether it was recorded.
+    async fn use_webauthn_credential(
+        &mut self,
+        id: EntityId,
+        sign_count: i64,
+    ) -> ProvideResult<bool>;
+
+    async fn create_webauthn_challenge(
+        &mut self,
+        user_id: EntityId,
+        organization_id: EntityId,
+        challenge_hash: &str,
+        ceremony: &str,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<WebauthnChallengeEntity>;
+
+    /// Remove the challenge with that hash for the ceremony, and return it, unless it
+    /// has expired. A challenge can only be used once.
+    async fn take_webauthn_challenge(
+        &mut self,
+        challenge_hash: &str,
+        ceremony: &str,
+    ) -> ProvideResult<Option<WebauthnChallengeEntity>>;
+
+    async fn create_oauth_client(
+        &mut self,
+        name: &str,
+        redirect_uris: &[String],
+    ) -> ProvideResult<OauthClientEntity>;
+
+    async fn get_oauth_client(&mut self, id: EntityId) -> ProvideResult<Option<OauthClientEntity>>;
+
+    async fn get_oauth_clients(&mut self) -> ProvideResult<Vec<OauthClientEntity>>;
+
+    /// Delete the client, and the codes issued to it. Return whether it existed.
+    async fn delete_oauth_client(&mut self, id: EntityId) -> ProvideResult<bool>;
+
+    async fn create_authorization_code(
+        &mut self,
+        code: &AuthorizationCodeEntity,
+    ) -> ProvideResult<()>;
+
+    /// Remove the code with that hash, and return it, unless it has expired. A code can
+    /// only be exchanged once.
+    async fn take_authorization_code(
+        &mut self,
+        code_hash: &str,
+    ) -> ProvideResult<Option<AuthorizationCodeEntity>>;
+
+    async fn create_service_client(
+        &mut self,
+        organization_id: EntityId,
+        name: &str,
+        secret_hash: &str,
+        scopes: &[String],
+    ) -> ProvideResult<ServiceClientEntity>;
+
+    async fn get_service_client(
+        &mut self,
+        id: EntityId,
+    ) -> ProvideResult<Option<ServiceClientEntity>>;
+
+    async fn get_service_clients(
+        &mut self,
+        organization_id: EntityId,
+    ) -> ProvideResult<Vec<ServiceClientEntity>>;
+
+    /// Delete the client of the organization. Return whether it existed.
+    async fn delete_service_client(
+        &mut self,
+        organization_id: EntityId,
+        id: EntityId,
+    ) -> ProvideResult<bool>;
+
+    async fn create_audit_event(&mut self, event: &AuditEventEntity) -> ProvideResult<()>;
+
+    /// At most limit events of the organization matching the filter, the latest first.
+    async fn get_audit_events(
+        &mut self,
+        organization_id: EntityId,
+        filter: &AuditEventFilter,
+        limit: i64,
+    ) -> ProvideResult<Vec<AuditEventEntity>>;
+
+    /// The events the user is the actor or the target of, the latest first.
+    async fn get_user_audit_events(
+        &mut self,
+        user_id: EntityId,
+    ) -> ProvideResult<Vec<AuditEventEntity>>;
+
+    async fn create_refresh_token(
+        &mut self,
+        user_id: EntityId,
+        organization_id: EntityId,
+        family_id: EntityId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<RefreshTokenEntity>;
+
+    async fn get_refresh_token_by_hash(
+        &mut self,
+        token_hash: &str,
+    ) -> ProvideResult<Option<RefreshTokenEntity>>;
+
+    /// Mark a refresh token as rotated.
+    /// Returns false if the token had already been rotated.
+    async fn rotate_refresh_token(&mut self, token_id: EntityId) -> ProvideResult<bool>;
+
+    /// Revoke all the refresh tokens of a family, returning the number of tokens revoked.
+    async fn revoke_refresh_token_family(&mut self, family_id: EntityId) -> ProvideResult<u64>;
+
+    async fn create_session(
+        &mut self,
+        session_id: &str,
+        csrf: &str,
+        user_id: EntityId,
+        identity: &Identity,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<SessionEntity>;
+
+    /// Return the session if it exists, has not expired, and matches the CSRF token.
+    async fn get_csrf_validated_session(
+        &mut self,
+        session_id: &str,
+        csrf: &str,
+    ) -> ProvideResult<Option<SessionEntity>>;
+
+    async fn delete_session(&mut self, session_id: &str) -> ProvideResult<u64>;
+
+    /// The sessions of the user, the latest first.
+    async fn get_user_sessions(&mut self, user_id: EntityId) -> ProvideResult<Vec<SessionEntity>>;
+
+    /// Delete the sessions of the user, but the kept one, if any.
+    async fn delete_user_sessions(
+        &mut self,
+        user_id: EntityId,
+        kept_session: Option<&str>,
+    ) -> ProvideResult<u64>;
+
+    /// The refresh tokens issued to the user, which are its login history, the latest first.
+    async fn get_user_refresh_tokens(
+        &mut self,
+        user_id: EntityId,
+    ) -> ProvideResult<Vec<RefreshTokenEntity>>;
+
+    async fn revoke_user_refresh_tokens(&mut self, user_id: EntityId) -> ProvideResult<u64>;
+
+    /// Revoke a single token, until it expires.
+    async fn revoke_token(
+        &mut self,
+        jti: &str,
+        user_id: EntityId,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<()>;
+
+    /// Revoke all the tokens of a user issued before the given time.
+    /// Token issue times have whole second precision, so the time is truncated to the
+    /// second, and the tokens issued in that second stay valid, like those issued right
+    /// after the revocation.
+    /// The token with the kept id, if any, stays valid too.
+    /// The revocation can be forgotten after expires_at, when all these tokens have expired.
+    async fn revoke_user_tokens(
+        &mut self,
+        user_id: EntityId,
+        revoked_before: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+        kept_jti: Option<&str>,
+    ) -> ProvideResult<()>;
+
+    async fn is_token_revoked(
+        &mut self,
+        jti: &str,
+        user_id: EntityId,
+        issued_at: DateTime<Utc>,
+    ) -> ProvideResult<bool>;
+
+    /// Remove revocations, sessions, email verifications and password resets which have
+    /// expired, returning the number of rows removed.
+    async fn delete_expired_revocations(&mut self) -> ProvideResult<u64>;
 }
 
 pub type ProvideResult<T> = Result<T, ProvideError>;
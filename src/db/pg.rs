@@ -12,8 +12,9 @@ use std::process::Stdio;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 
-use super::model;
+use super::model::{self, ProvideAuthn, ProvideData};
 use super::Db;
+use crate::auth::role::Role;
 use crate::error;
 
 /// There is some overlap in this example between ProvideData and ProvideAuthn, because
@@ -29,6 +30,8 @@ pub struct UserEntity {
     pub active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub organization_id: model::EntityId,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl<'c> FromRow<'c, PgRow<'c>> for UserEntity {
@@ -42,6 +45,8 @@ impl<'c> FromRow<'c, PgRow<'c>> for UserEntity {
             active: row.get(5),
             created_at: row.get(6),
             updated_at: row.get(7),
+            organization_id: row.get(8),
+            deleted_at: row.get(9),
         })
     }
 }
@@ -57,6 +62,8 @@ impl From<UserEntity> for model::UserEntity {
             active,
             created_at,
             updated_at,
+            organization_id,
+            deleted_at,
         } = pg;
 
         model::UserEntity {
@@ -68,208 +75,2873 @@ impl From<UserEntity> for model::UserEntity {
             active,
             created_at,
             updated_at,
+            organization_id,
+            deleted_at,
         }
     }
 }
 
-/// Open a connection to a database
-pub async fn connect(db_url: &str) -> sqlx::Result<PgPool> {
-    let pool = PgPool::new(db_url).await?;
-    Ok(pool)
+/// A refresh token (Postgres version)
+pub struct RefreshTokenEntity {
+    pub id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub family_id: model::EntityId,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub organization_id: model::EntityId,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for RefreshTokenEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(RefreshTokenEntity {
+            id: row.get(0),
+            user_id: row.get(1),
+            family_id: row.get(2),
+            token_hash: row.get(3),
+            expires_at: row.get(4),
+            rotated_at: row.get(5),
+            revoked_at: row.get(6),
+            created_at: row.get(7),
+            organization_id: row.get(8),
+        })
+    }
+}
+
+impl From<RefreshTokenEntity> for model::RefreshTokenEntity {
+    fn from(pg: RefreshTokenEntity) -> Self {
+        let RefreshTokenEntity {
+            id,
+            user_id,
+            family_id,
+            token_hash,
+            expires_at,
+            rotated_at,
+            revoked_at,
+            created_at,
+            organization_id,
+        } = pg;
+
+        model::RefreshTokenEntity {
+            id,
+            user_id,
+            family_id,
+            token_hash,
+            expires_at,
+            rotated_at,
+            revoked_at,
+            created_at,
+            organization_id,
+        }
+    }
+}
+
+/// An email verification (Postgres version)
+pub struct EmailVerificationEntity {
+    pub id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub email: Option<String>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for EmailVerificationEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(EmailVerificationEntity {
+            id: row.get(0),
+            user_id: row.get(1),
+            token_hash: row.get(2),
+            expires_at: row.get(3),
+            used_at: row.get(4),
+            created_at: row.get(5),
+            email: row.get(6),
+        })
+    }
+}
+
+impl From<EmailVerificationEntity> for model::EmailVerificationEntity {
+    fn from(pg: EmailVerificationEntity) -> Self {
+        let EmailVerificationEntity {
+            id,
+            user_id,
+            token_hash,
+            expires_at,
+            used_at,
+            created_at,
+            email,
+        } = pg;
+
+        model::EmailVerificationEntity {
+            id,
+            user_id,
+            token_hash,
+            expires_at,
+            used_at,
+            created_at,
+            email,
+        }
+    }
+}
+
+/// A password reset (Postgres version)
+pub struct PasswordResetEntity {
+    pub id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for PasswordResetEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(PasswordResetEntity {
+            id: row.get(0),
+            user_id: row.get(1),
+            token_hash: row.get(2),
+            expires_at: row.get(3),
+            used_at: row.get(4),
+            created_at: row.get(5),
+        })
+    }
+}
+
+impl From<PasswordResetEntity> for model::PasswordResetEntity {
+    fn from(pg: PasswordResetEntity) -> Self {
+        let PasswordResetEntity {
+            id,
+            user_id,
+            token_hash,
+            expires_at,
+            used_at,
+            created_at,
+        } = pg;
+
+        model::PasswordResetEntity {
+            id,
+            user_id,
+            token_hash,
+            expires_at,
+            used_at,
+            created_at,
+        }
+    }
+}
+
+/// A magic link (Postgres version)
+pub struct MagicLinkEntity {
+    pub id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub organization_id: model::EntityId,
+    pub binding_hash: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for MagicLinkEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(MagicLinkEntity {
+            id: row.get(0),
+            user_id: row.get(1),
+            organization_id: row.get(2),
+            binding_hash: row.get(3),
+            expires_at: row.get(4),
+            used_at: row.get(5),
+            created_at: row.get(6),
+        })
+    }
+}
+
+impl From<MagicLinkEntity> for model::MagicLinkEntity {
+    fn from(pg: MagicLinkEntity) -> Self {
+        let MagicLinkEntity {
+            id,
+            user_id,
+            organization_id,
+            binding_hash,
+            expires_at,
+            used_at,
+            created_at,
+        } = pg;
+
+        model::MagicLinkEntity {
+            id,
+            user_id,
+            organization_id,
+            binding_hash,
+            expires_at,
+            used_at,
+            created_at,
+        }
+    }
+}
+
+/// A browser session (Postgres version)
+pub struct SessionEntity {
+    pub id: String,
+    pub csrf: String,
+    pub user_id: model::EntityId,
+    pub fingerprint: Option<String>,
+    pub ip: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for SessionEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(SessionEntity {
+            id: row.get(0),
+            csrf: row.get(1),
+            user_id: row.get(2),
+            fingerprint: row.get(3),
+            ip: row.get(4),
+            expires_at: row.get(5),
+            created_at: row.get(6),
+        })
+    }
+}
+
+impl From<SessionEntity> for model::SessionEntity {
+    fn from(pg: SessionEntity) -> Self {
+        let SessionEntity {
+            id,
+            csrf,
+            user_id,
+            fingerprint,
+            ip,
+            expires_at,
+            created_at,
+        } = pg;
+
+        model::SessionEntity {
+            id,
+            csrf,
+            user_id,
+            fingerprint,
+            ip,
+            expires_at,
+            created_at,
+        }
+    }
+}
+
+/// A role (Postgres version)
+/// The permissions are aggregated from main.role_permissions.
+pub struct RoleEntity {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for RoleEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(RoleEntity {
+            name: row.get(0),
+            description: row.get(1),
+            permissions: row.get(2),
+            created_at: row.get(3),
+        })
+    }
+}
+
+impl From<RoleEntity> for model::RoleEntity {
+    fn from(pg: RoleEntity) -> Self {
+        let RoleEntity {
+            name,
+            description,
+            permissions,
+            created_at,
+        } = pg;
+
+        model::RoleEntity {
+            name,
+            description,
+            permissions,
+            created_at,
+        }
+    }
+}
+
+/// Select roles with their permissions. The caller appends the WHERE clause, if any,
+/// before the GROUP BY.
+const SELECT_ROLES: &str = r#"
+SELECT r.name, r.description,
+       COALESCE(array_agg(p.permission ORDER BY p.permission)
+                FILTER (WHERE p.permission IS NOT NULL), '{}') AS permissions,
+       r.created_at
+FROM main.roles r
+LEFT JOIN main.role_permissions p ON p.role = r.name
+"#;
+
+/// An organization (Postgres version)
+pub struct OrganizationEntity {
+    pub id: model::EntityId,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for OrganizationEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(OrganizationEntity {
+            id: row.get(0),
+            name: row.get(1),
+            created_at: row.get(2),
+        })
+    }
+}
+
+impl From<OrganizationEntity> for model::OrganizationEntity {
+    fn from(pg: OrganizationEntity) -> Self {
+        let OrganizationEntity {
+            id,
+            name,
+            created_at,
+        } = pg;
+
+        model::OrganizationEntity {
+            id,
+            name,
+            created_at,
+        }
+    }
+}
+
+/// A membership (Postgres version)
+pub struct MembershipEntity {
+    pub organization_id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub roles: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for MembershipEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(MembershipEntity {
+            organization_id: row.get(0),
+            user_id: row.get(1),
+            roles: row.get(2),
+            created_at: row.get(3),
+        })
+    }
+}
+
+impl From<MembershipEntity> for model::MembershipEntity {
+    fn from(pg: MembershipEntity) -> Self {
+        let MembershipEntity {
+            organization_id,
+            user_id,
+            roles,
+            created_at,
+        } = pg;
+
+        model::MembershipEntity {
+            organization_id,
+            user_id,
+            roles,
+            created_at,
+        }
+    }
+}
+
+/// An audit event (Postgres version)
+pub struct AuditEventEntity {
+    pub id: model::EntityId,
+    pub action: String,
+    pub actor_id: Option<model::EntityId>,
+    pub target_id: Option<model::EntityId>,
+    pub organization_id: Option<model::EntityId>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for AuditEventEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(AuditEventEntity {
+            id: row.get(0),
+            action: row.get(1),
+            actor_id: row.get(2),
+            target_id: row.get(3),
+            organization_id: row.get(4),
+            ip: row.get(5),
+            user_agent: row.get(6),
+            details: row.get(7),
+            created_at: row.get(8),
+        })
+    }
+}
+
+impl From<AuditEventEntity> for model::AuditEventEntity {
+    fn from(pg: AuditEventEntity) -> Self {
+        let AuditEventEntity {
+            id,
+            action,
+            actor_id,
+            target_id,
+            organization_id,
+            ip,
+            user_agent,
+            details,
+            created_at,
+        } = pg;
+
+        model::AuditEventEntity {
+            id,
+            action,
+            actor_id,
+            target_id,
+            organization_id,
+            ip,
+            user_agent,
+            details,
+            created_at,
+        }
+    }
+}
+
+/// A tombstone (Postgres version)
+pub struct TombstoneEntity {
+    pub user_id: model::EntityId,
+    pub organization_id: model::EntityId,
+    pub erased_by: Option<model::EntityId>,
+    pub erased_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for TombstoneEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(TombstoneEntity {
+            user_id: row.get(0),
+            organization_id: row.get(1),
+            erased_by: row.get(2),
+            erased_at: row.get(3),
+        })
+    }
+}
+
+impl From<TombstoneEntity> for model::TombstoneEntity {
+    fn from(pg: TombstoneEntity) -> Self {
+        let TombstoneEntity {
+            user_id,
+            organization_id,
+            erased_by,
+            erased_at,
+        } = pg;
+
+        model::TombstoneEntity {
+            user_id,
+            organization_id,
+            erased_by,
+            erased_at,
+        }
+    }
+}
+
+/// A login lockout (Postgres version)
+pub struct LoginLockoutEntity {
+    pub user_id: model::EntityId,
+    pub failed_logins: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for LoginLockoutEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(LoginLockoutEntity {
+            user_id: row.get(0),
+            failed_logins: row.get(1),
+            locked_until: row.get(2),
+            updated_at: row.get(3),
+        })
+    }
+}
+
+impl From<LoginLockoutEntity> for model::LoginLockoutEntity {
+    fn from(pg: LoginLockoutEntity) -> Self {
+        let LoginLockoutEntity {
+            user_id,
+            failed_logins,
+            locked_until,
+            updated_at,
+        } = pg;
+
+        model::LoginLockoutEntity {
+            user_id,
+            failed_logins,
+            locked_until,
+            updated_at,
+        }
+    }
+}
+
+/// A TOTP secret (Postgres version)
+pub struct TotpEntity {
+    pub user_id: model::EntityId,
+    pub secret: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for TotpEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(TotpEntity {
+            user_id: row.get(0),
+            secret: row.get(1),
+            confirmed_at: row.get(2),
+            last_used_step: row.get(3),
+            created_at: row.get(4),
+        })
+    }
+}
+
+impl From<TotpEntity> for model::TotpEntity {
+    fn from(pg: TotpEntity) -> Self {
+        let TotpEntity {
+            user_id,
+            secret,
+            confirmed_at,
+            last_used_step,
+            created_at,
+        } = pg;
+
+        model::TotpEntity {
+            user_id,
+            secret,
+            confirmed_at,
+            last_used_step,
+            created_at,
+        }
+    }
+}
+
+/// An MFA challenge (Postgres version)
+pub struct MfaChallengeEntity {
+    pub id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub organization_id: model::EntityId,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for MfaChallengeEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(MfaChallengeEntity {
+            id: row.get(0),
+            user_id: row.get(1),
+            organization_id: row.get(2),
+            token_hash: row.get(3),
+            expires_at: row.get(4),
+            created_at: row.get(5),
+        })
+    }
+}
+
+impl From<MfaChallengeEntity> for model::MfaChallengeEntity {
+    fn from(pg: MfaChallengeEntity) -> Self {
+        let MfaChallengeEntity {
+            id,
+            user_id,
+            organization_id,
+            token_hash,
+            expires_at,
+            created_at,
+        } = pg;
+
+        model::MfaChallengeEntity {
+            id,
+            user_id,
+            organization_id,
+            token_hash,
+            expires_at,
+            created_at,
+        }
+    }
+}
+
+/// A passkey (Postgres version)
+pub struct WebauthnCredentialEntity {
+    pub id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub algorithm: i32,
+    pub sign_count: i64,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for WebauthnCredentialEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(WebauthnCredentialEntity {
+            id: row.get(0),
+            user_id: row.get(1),
+            credential_id: row.get(2),
+            public_key: row.get(3),
+            algorithm: row.get(4),
+            sign_count: row.get(5),
+            name: row.get(6),
+            created_at: row.get(7),
+            last_used_at: row.get(8),
+        })
+    }
 }
 
-impl TryFrom<&PgError> for model::ProvideError {
-    type Error = ();
+impl From<WebauthnCredentialEntity> for model::WebauthnCredentialEntity {
+    fn from(pg: WebauthnCredentialEntity) -> Self {
+        let WebauthnCredentialEntity {
+            id,
+            user_id,
+            credential_id,
+            public_key,
+            algorithm,
+            sign_count,
+            name,
+            created_at,
+            last_used_at,
+        } = pg;
+
+        model::WebauthnCredentialEntity {
+            id,
+            user_id,
+            credential_id,
+            public_key,
+            algorithm,
+            sign_count,
+            name,
+            created_at,
+            last_used_at,
+        }
+    }
+}
+
+/// The challenge of a WebAuthn ceremony (Postgres version)
+pub struct WebauthnChallengeEntity {
+    pub id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub organization_id: model::EntityId,
+    pub challenge_hash: String,
+    pub ceremony: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for WebauthnChallengeEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(WebauthnChallengeEntity {
+            id: row.get(0),
+            user_id: row.get(1),
+            organization_id: row.get(2),
+            challenge_hash: row.get(3),
+            ceremony: row.get(4),
+            expires_at: row.get(5),
+            created_at: row.get(6),
+        })
+    }
+}
+
+impl From<WebauthnChallengeEntity> for model::WebauthnChallengeEntity {
+    fn from(pg: WebauthnChallengeEntity) -> Self {
+        let WebauthnChallengeEntity {
+            id,
+            user_id,
+            organization_id,
+            challenge_hash,
+            ceremony,
+            expires_at,
+            created_at,
+        } = pg;
+
+        model::WebauthnChallengeEntity {
+            id,
+            user_id,
+            organization_id,
+            challenge_hash,
+            ceremony,
+            expires_at,
+            created_at,
+        }
+    }
+}
+
+/// A client of the OpenID Connect provider (Postgres version)
+pub struct OauthClientEntity {
+    pub id: model::EntityId,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for OauthClientEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(OauthClientEntity {
+            id: row.get(0),
+            name: row.get(1),
+            redirect_uris: row.get(2),
+            created_at: row.get(3),
+        })
+    }
+}
+
+impl From<OauthClientEntity> for model::OauthClientEntity {
+    fn from(pg: OauthClientEntity) -> Self {
+        let OauthClientEntity {
+            id,
+            name,
+            redirect_uris,
+            created_at,
+        } = pg;
+
+        model::OauthClientEntity {
+            id,
+            name,
+            redirect_uris,
+            created_at,
+        }
+    }
+}
+
+/// A client of the client credentials grant (Postgres version)
+pub struct ServiceClientEntity {
+    pub id: model::EntityId,
+    pub organization_id: model::EntityId,
+    pub name: String,
+    pub secret_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for ServiceClientEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(ServiceClientEntity {
+            id: row.get(0),
+            organization_id: row.get(1),
+            name: row.get(2),
+            secret_hash: row.get(3),
+            scopes: row.get(4),
+            created_at: row.get(5),
+        })
+    }
+}
+
+impl From<ServiceClientEntity> for model::ServiceClientEntity {
+    fn from(pg: ServiceClientEntity) -> Self {
+        let ServiceClientEntity {
+            id,
+            organization_id,
+            name,
+            secret_hash,
+            scopes,
+            created_at,
+        } = pg;
+
+        model::ServiceClientEntity {
+            id,
+            organization_id,
+            name,
+            secret_hash,
+            scopes,
+            created_at,
+        }
+    }
+}
+
+/// An authorization code (Postgres version)
+pub struct AuthorizationCodeEntity {
+    pub id: model::EntityId,
+    pub code_hash: String,
+    pub client_id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub organization_id: model::EntityId,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub nonce: Option<String>,
+    pub code_challenge: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for AuthorizationCodeEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(AuthorizationCodeEntity {
+            id: row.get(0),
+            code_hash: row.get(1),
+            client_id: row.get(2),
+            user_id: row.get(3),
+            organization_id: row.get(4),
+            redirect_uri: row.get(5),
+            scope: row.get(6),
+            nonce: row.get(7),
+            code_challenge: row.get(8),
+            expires_at: row.get(9),
+            created_at: row.get(10),
+        })
+    }
+}
+
+impl From<AuthorizationCodeEntity> for model::AuthorizationCodeEntity {
+    fn from(pg: AuthorizationCodeEntity) -> Self {
+        let AuthorizationCodeEntity {
+            id,
+            code_hash,
+            client_id,
+            user_id,
+            organization_id,
+            redirect_uri,
+            scope,
+            nonce,
+            code_challenge,
+            expires_at,
+            created_at,
+        } = pg;
+
+        model::AuthorizationCodeEntity {
+            id,
+            code_hash,
+            client_id,
+            user_id,
+            organization_id,
+            redirect_uri,
+            scope,
+            nonce,
+            code_challenge,
+            expires_at,
+            created_at,
+        }
+    }
+}
+
+/// Anonymize a user ($1), keeping its id, drop everything else that belongs to it,
+/// and record who erased it ($2). Erasing a user twice only updates the tombstone.
+/// The user must belong to the organization ($3), unless it erases itself; otherwise
+/// nothing is anonymized, and the other statements, which act on the anonymized user
+/// only, change nothing either.
+const ERASE_USER: &str = r#"
+WITH u AS (
+  UPDATE main.users
+  SET username = 'erased-' || id,
+      email = 'erased-' || id || '@erased.invalid',
+      password = '',
+      roles = '{}',
+      active = FALSE,
+      deleted_at = COALESCE(deleted_at, NOW()),
+      updated_at = DEFAULT
+  WHERE id = $1 AND ( organization_id = $3 OR id = $2 )
+  RETURNING id, organization_id
+), m AS (
+  UPDATE main.memberships SET roles = '{}' WHERE user_id IN ( SELECT id FROM u )
+), s AS (
+  DELETE FROM main.sessions WHERE user_id IN ( SELECT id FROM u )
+), r AS (
+  DELETE FROM main.refresh_tokens WHERE user_id IN ( SELECT id FROM u )
+), v AS (
+  DELETE FROM main.email_verifications WHERE user_id IN ( SELECT id FROM u )
+), p AS (
+  DELETE FROM main.password_resets WHERE user_id IN ( SELECT id FROM u )
+), t AS (
+  DELETE FROM main.totp_secrets WHERE user_id IN ( SELECT id FROM u )
+), c AS (
+  DELETE FROM main.recovery_codes WHERE user_id IN ( SELECT id FROM u )
+), h AS (
+  DELETE FROM main.mfa_challenges WHERE user_id IN ( SELECT id FROM u )
+), w AS (
+  DELETE FROM main.webauthn_credentials WHERE user_id IN ( SELECT id FROM u )
+), wc AS (
+  DELETE FROM main.webauthn_challenges WHERE user_id IN ( SELECT id FROM u )
+), ml AS (
+  DELETE FROM main.magic_links WHERE user_id IN ( SELECT id FROM u )
+), oc AS (
+  DELETE FROM main.oauth_authorization_codes WHERE user_id IN ( SELECT id FROM u )
+)
+INSERT INTO main.tombstones ( user_id, organization_id, erased_by )
+SELECT id, organization_id, $2 FROM u
+ON CONFLICT (user_id) DO UPDATE SET erased_by = EXCLUDED.erased_by, erased_at = NOW()
+RETURNING user_id, organization_id, erased_by, erased_at
+"#;
+
+/// Create a user, and its membership in its organization.
+const CREATE_USER: &str = r#"
+WITH u AS (
+  INSERT INTO main.users ( organization_id, username, email, password )
+  VALUES ( $1, $2, $3, $4 )
+  RETURNING *
+), m AS (
+  INSERT INTO main.memberships ( organization_id, user_id )
+  SELECT organization_id, id FROM u
+)
+SELECT * FROM u
+"#;
+
+/// Select the members of the organization ($1) matching the filter: active ($2),
+/// role ($3), created after ($4) and before ($5). The missing criteria are null,
+/// and match everyone. Soft deleted users are left out.
+const FILTER_USERS: &str = r#"
+FROM main.users u
+JOIN main.memberships m ON m.user_id = u.id
+WHERE m.organization_id = $1
+  AND u.deleted_at IS NULL
+  AND ($2::BOOLEAN IS NULL OR u.active = $2)
+  AND ($3::TEXT IS NULL OR $3 = ANY(u.roles) OR $3 = ANY(m.roles))
+  AND ($4::TIMESTAMPTZ IS NULL OR u.created_at >= $4)
+  AND ($5::TIMESTAMPTZ IS NULL OR u.created_at < $5)
+"#;
+
+/// The lowest word similarity between a search query and a username or an email.
+const SEARCH_THRESHOLD: f64 = 0.3;
+
+/// Open a connection to a database
+pub async fn connect(db_url: &str) -> sqlx::Result<PgPool> {
+    let pool = PgPool::new(db_url).await?;
+    Ok(pool)
+}
+
+impl TryFrom<&PgError> for model::ProvideError {
+    type Error = ();
+
+    /// Attempt to convert a Postgres error into a generic ProvideError
+    ///
+    /// Unexpected cases will be bounced back to the caller for handling
+    ///
+    /// * [Postgres Error Codes](https://www.postgresql.org/docs/current/errcodes-appendix.html)
+    fn try_from(pg_err: &PgError) -> Result<Self, Self::Error> {
+        let provider_err = match pg_err.code().unwrap() {
+            "23505" => model::ProvideError::UniqueViolation {
+                details: pg_err.details().unwrap().to_owned(),
+            },
+            code if code.starts_with("23") => model::ProvideError::ModelViolation {
+                details: pg_err.message().to_owned(),
+            },
+            _ => return Err(()),
+        };
+
+        Ok(provider_err)
+    }
+}
+
+#[async_trait]
+impl Db for PgPool {
+    type Conn = PoolConnection<PgConnection>;
+
+    async fn conn(&self) -> Result<Self::Conn, sqlx::Error> {
+        self.acquire().await
+    }
+}
+
+#[async_trait]
+impl model::ProvideData for PgConnection {
+    async fn create_user(
+        &mut self,
+        organization_id: model::EntityId,
+        username: &str,
+        email: &str,
+        password: &str,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(CREATE_USER)
+            .bind(organization_id)
+            .bind(username)
+            .bind(email)
+            .bind(password)
+            .fetch_one(self)
+            .await?;
+
+        Ok(user.into())
+    }
+
+    async fn get_users(
+        &mut self,
+        organization_id: model::EntityId,
+        filter: &model::UserFilter,
+        page: &model::UserPage,
+    ) -> model::ProvideResult<Vec<model::UserEntity>> {
+        let (column, cast) = match page.sort {
+            model::UserSortKey::CreatedAt => ("created_at", "TIMESTAMPTZ"),
+            model::UserSortKey::UpdatedAt => ("updated_at", "TIMESTAMPTZ"),
+            model::UserSortKey::Username => ("username", "TEXT"),
+            model::UserSortKey::Email => ("email", "TEXT"),
+        };
+        // Going backward, we read the users in the reverse order, from the cursor.
+        let descending = page.descending != page.backward;
+        let (direction, comparison) = if descending {
+            ("DESC", "<")
+        } else {
+            ("ASC", ">")
+        };
+        let cursor = match page.cursor {
+            Some(_) => format!(
+                "AND (u.{column}, u.id) {comparison} ($6::{cast}, $7)",
+                column = column,
+                comparison = comparison,
+                cast = cast
+            ),
+            None => String::new(),
+        };
+        let sql = format!(
+            "SELECT u.* {filter} {cursor} ORDER BY u.{column} {direction}, u.id {direction} LIMIT {limit}",
+            filter = FILTER_USERS,
+            cursor = cursor,
+            column = column,
+            direction = direction,
+            limit = page.limit
+        );
+
+        let mut query = sqlx::query_as(&sql)
+            .bind(organization_id)
+            .bind(filter.active)
+            .bind(filter.role.clone())
+            .bind(filter.created_after)
+            .bind(filter.created_before);
+        if let Some(cursor) = &page.cursor {
+            query = query.bind(cursor.key.clone()).bind(cursor.id);
+        }
+        let users: Vec<UserEntity> = query.fetch_all(self).await?;
+
+        let users = users
+            .into_iter()
+            .map(model::UserEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(users)
+    }
+
+    async fn count_users(
+        &mut self,
+        organization_id: model::EntityId,
+        filter: &model::UserFilter,
+    ) -> model::ProvideResult<i64> {
+        let (count,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) {}", FILTER_USERS))
+            .bind(organization_id)
+            .bind(filter.active)
+            .bind(filter.role.clone())
+            .bind(filter.created_after)
+            .bind(filter.created_before)
+            .fetch_one(self)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn search_users(
+        &mut self,
+        organization_id: model::EntityId,
+        query: &str,
+        limit: i64,
+    ) -> model::ProvideResult<Vec<model::UserEntity>> {
+        // The default threshold (0.6) misses short queries, like 'jon' for 'john.doe'.
+        // SET LOCAL only lasts until the end of the transaction.
+        sqlx::query(&format!(
+            "SET LOCAL pg_trgm.word_similarity_threshold = {}",
+            SEARCH_THRESHOLD
+        ))
+        .execute(&mut *self)
+        .await?;
+
+        // The <% operator can use the trigram indexes, word_similarity ranks the matches.
+        let users: Vec<UserEntity> = sqlx::query_as(
+            r#"
+SELECT u.*
+FROM main.users u
+JOIN main.memberships m ON m.user_id = u.id
+WHERE m.organization_id = $1
+  AND u.deleted_at IS NULL
+  AND ($2 OPERATOR(main.<%) u.username OR $2 OPERATOR(main.<%) u.email)
+ORDER BY GREATEST(main.word_similarity($2, u.username), main.word_similarity($2, u.email)) DESC,
+  u.username
+LIMIT $3
+            "#,
+        )
+        .bind(organization_id)
+        .bind(query)
+        .bind(limit)
+        .fetch_all(self)
+        .await?;
+
+        let users = users
+            .into_iter()
+            .map(model::UserEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(users)
+    }
+
+    async fn get_user_by_username(
+        &mut self,
+        organization_id: model::EntityId,
+        username: &str,
+    ) -> model::ProvideResult<Option<model::UserEntity>> {
+        let user: Option<UserEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.users
+WHERE organization_id = $1 AND username = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(organization_id)
+        .bind(username)
+        .fetch_optional(self)
+        .await?;
+
+        match user {
+            None => Ok(None),
+            Some(user) => {
+                let user = model::UserEntity::from(user);
+                Ok(Some(user))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl model::ProvideAuthn for PgConnection {
+    async fn create_user(
+        &mut self,
+        organization_id: model::EntityId,
+        username: &str,
+        email: &str,
+        password: &str,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(CREATE_USER)
+            .bind(organization_id)
+            .bind(username)
+            .bind(email)
+            .bind(password)
+            .fetch_one(self)
+            .await?;
+
+        Ok(user.into())
+    }
+
+    async fn get_user_by_id(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::UserEntity>> {
+        let user: Option<UserEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.users
+WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(self)
+        .await?;
+
+        match user {
+            None => Ok(None),
+            Some(user) => {
+                let user = model::UserEntity::from(user);
+                Ok(Some(user))
+            }
+        }
+    }
+
+    async fn get_user_by_email(
+        &mut self,
+        organization_id: model::EntityId,
+        email: &str,
+    ) -> model::ProvideResult<Option<model::UserEntity>> {
+        let user: Option<UserEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.users
+WHERE organization_id = $1 AND email = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(organization_id)
+        .bind(email)
+        .fetch_optional(self)
+        .await?;
+
+        match user {
+            None => Ok(None),
+            Some(user) => {
+                let user = model::UserEntity::from(user);
+                Ok(Some(user))
+            }
+        }
+    }
+
+    async fn update_user(
+        &mut self,
+        updated: &model::UserEntity,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET email = $1, username = $2, password = $3, updated_at = DEFAULT
+WHERE id = $4
+RETURNING *
+            "#,
+        )
+        .bind(updated.email.clone())
+        .bind(updated.username.clone())
+        .bind(updated.password.clone())
+        .bind(updated.id)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn activate_user(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET active = TRUE, updated_at = DEFAULT
+WHERE id = $1
+RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn verify_user_email(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET active = active OR ( email_verified_at IS NULL AND deleted_at IS NULL ),
+    email_verified_at = NOW(),
+    updated_at = DEFAULT
+WHERE id = $1
+RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn deactivate_user(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET active = FALSE, updated_at = DEFAULT
+WHERE id = $1
+RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn soft_delete_user(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET active = FALSE, deleted_at = NOW(), updated_at = DEFAULT
+WHERE id = $1
+RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn delete_user(
+        &mut self,
+        organization_id: model::EntityId,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<u64> {
+        let deleted = sqlx::query(
+            r#"
+DELETE FROM main.users
+WHERE id = $1 AND organization_id = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(organization_id)
+        .execute(&mut *self)
+        .await?;
+
+        Ok(deleted)
+    }
+
+    async fn erase_user(
+        &mut self,
+        organization_id: model::EntityId,
+        user_id: model::EntityId,
+        erased_by: Option<model::EntityId>,
+    ) -> model::ProvideResult<Option<model::TombstoneEntity>> {
+        let tombstone: Option<TombstoneEntity> = sqlx::query_as(ERASE_USER)
+            .bind(user_id)
+            .bind(erased_by)
+            .bind(organization_id)
+            .fetch_optional(self)
+            .await?;
+
+        Ok(tombstone.map(model::TombstoneEntity::from))
+    }
+
+    async fn add_user_role(
+        &mut self,
+        user_id: model::EntityId,
+        role: &str,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET roles = CASE WHEN $2 = ANY(roles) THEN roles ELSE array_append(roles, $2) END,
+    updated_at = DEFAULT
+WHERE id = $1
+RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn remove_user_role(
+        &mut self,
+        user_id: model::EntityId,
+        role: &str,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET roles = array_remove(roles, $2), updated_at = DEFAULT
+WHERE id = $1
+RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn create_organization(
+        &mut self,
+        name: &str,
+    ) -> model::ProvideResult<model::OrganizationEntity> {
+        let organization: OrganizationEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.organizations ( name )
+VALUES ( $1 )
+RETURNING id, name, created_at
+            "#,
+        )
+        .bind(name)
+        .fetch_one(self)
+        .await?;
+
+        Ok(organization.into())
+    }
+
+    async fn get_organization_by_id(
+        &mut self,
+        organization_id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::OrganizationEntity>> {
+        let organization: Option<OrganizationEntity> = sqlx::query_as(
+            r#"
+SELECT id, name, created_at
+FROM main.organizations
+WHERE id = $1
+            "#,
+        )
+        .bind(organization_id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(organization.map(model::OrganizationEntity::from))
+    }
+
+    async fn get_organization_by_name(
+        &mut self,
+        name: &str,
+    ) -> model::ProvideResult<Option<model::OrganizationEntity>> {
+        let organization: Option<OrganizationEntity> = sqlx::query_as(
+            r#"
+SELECT id, name, created_at
+FROM main.organizations
+WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(organization.map(model::OrganizationEntity::from))
+    }
+
+    async fn get_user_memberships(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::MembershipEntity>> {
+        let memberships: Vec<MembershipEntity> = sqlx::query_as(
+            r#"
+SELECT organization_id, user_id, roles, created_at
+FROM main.memberships
+WHERE user_id = $1
+ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(self)
+        .await?;
+
+        Ok(memberships
+            .into_iter()
+            .map(model::MembershipEntity::from)
+            .collect())
+    }
+
+    async fn get_user_organizations(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::OrganizationEntity>> {
+        let organizations: Vec<OrganizationEntity> = sqlx::query_as(
+            r#"
+SELECT o.id, o.name, o.created_at
+FROM main.organizations o
+JOIN main.memberships m ON m.organization_id = o.id
+WHERE m.user_id = $1
+ORDER BY o.name
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(self)
+        .await?;
+
+        Ok(organizations
+            .into_iter()
+            .map(model::OrganizationEntity::from)
+            .collect())
+    }
+
+    async fn create_membership(
+        &mut self,
+        organization_id: model::EntityId,
+        user_id: model::EntityId,
+        roles: &[String],
+    ) -> model::ProvideResult<model::MembershipEntity> {
+        let membership: MembershipEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.memberships ( organization_id, user_id, roles )
+VALUES ( $1, $2, $3 )
+RETURNING organization_id, user_id, roles, created_at
+            "#,
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .bind(roles)
+        .fetch_one(self)
+        .await?;
+
+        Ok(membership.into())
+    }
+
+    async fn get_membership(
+        &mut self,
+        organization_id: model::EntityId,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::MembershipEntity>> {
+        let membership: Option<MembershipEntity> = sqlx::query_as(
+            r#"
+SELECT organization_id, user_id, roles, created_at
+FROM main.memberships
+WHERE organization_id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(membership.map(model::MembershipEntity::from))
+    }
+
+    async fn delete_membership(
+        &mut self,
+        organization_id: model::EntityId,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<u64> {
+        let deleted = sqlx::query(
+            r#"
+DELETE FROM main.memberships
+WHERE organization_id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .execute(self)
+        .await?;
+
+        Ok(deleted)
+    }
+
+    async fn add_membership_role(
+        &mut self,
+        organization_id: model::EntityId,
+        user_id: model::EntityId,
+        role: &str,
+    ) -> model::ProvideResult<model::MembershipEntity> {
+        let membership: MembershipEntity = sqlx::query_as(
+            r#"
+UPDATE main.memberships
+SET roles = CASE WHEN $3 = ANY(roles) THEN roles ELSE array_append(roles, $3) END
+WHERE organization_id = $1 AND user_id = $2
+RETURNING organization_id, user_id, roles, created_at
+            "#,
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .bind(role)
+        .fetch_one(self)
+        .await?;
+
+        Ok(membership.into())
+    }
+
+    async fn remove_membership_role(
+        &mut self,
+        organization_id: model::EntityId,
+        user_id: model::EntityId,
+        role: &str,
+    ) -> model::ProvideResult<model::MembershipEntity> {
+        let membership: MembershipEntity = sqlx::query_as(
+            r#"
+UPDATE main.memberships
+SET roles = array_remove(roles, $3)
+WHERE organization_id = $1 AND user_id = $2
+RETURNING organization_id, user_id, roles, created_at
+            "#,
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .bind(role)
+        .fetch_one(self)
+        .await?;
+
+        Ok(membership.into())
+    }
+
+    async fn create_role(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        permissions: &[String],
+    ) -> model::ProvideResult<model::RoleEntity> {
+        sqlx::query(
+            r#"
+INSERT INTO main.roles ( name, description )
+VALUES ( $1, $2 )
+            "#,
+        )
+        .bind(name)
+        .bind(description)
+        .execute(&mut *self)
+        .await?;
+
+        sqlx::query(
+            r#"
+INSERT INTO main.role_permissions ( role, permission )
+SELECT $1, UNNEST($2::VARCHAR(128)[])
+ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(name)
+        .bind(permissions)
+        .execute(&mut *self)
+        .await?;
+
+        let role = self.get_role(name).await?;
+        role.ok_or(model::ProvideError::NotFound)
+    }
+
+    async fn get_role(&mut self, name: &str) -> model::ProvideResult<Option<model::RoleEntity>> {
+        let role: Option<RoleEntity> = sqlx::query_as(&format!(
+            "{} WHERE r.name = $1 GROUP BY r.name",
+            SELECT_ROLES
+        ))
+        .bind(name)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(role.map(model::RoleEntity::from))
+    }
+
+    async fn get_all_roles(&mut self) -> model::ProvideResult<Vec<model::RoleEntity>> {
+        let roles: Vec<RoleEntity> =
+            sqlx::query_as(&format!("{} GROUP BY r.name ORDER BY r.name", SELECT_ROLES))
+                .fetch_all(self)
+                .await?;
+
+        Ok(roles.into_iter().map(model::RoleEntity::from).collect())
+    }
+
+    async fn delete_role(&mut self, name: &str) -> model::ProvideResult<u64> {
+        sqlx::query(
+            r#"
+UPDATE main.memberships
+SET roles = array_remove(roles, $1)
+WHERE $1 = ANY(roles)
+            "#,
+        )
+        .bind(name)
+        .execute(&mut *self)
+        .await?;
+
+        let users = sqlx::query(
+            r#"
+UPDATE main.users
+SET roles = array_remove(roles, $1), updated_at = DEFAULT
+WHERE $1 = ANY(roles)
+            "#,
+        )
+        .bind(name)
+        .execute(&mut *self)
+        .await?;
+
+        let roles = sqlx::query(
+            r#"
+DELETE FROM main.roles
+WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .execute(&mut *self)
+        .await?;
+
+        if roles == 0 {
+            return Err(model::ProvideError::NotFound);
+        }
+
+        Ok(users)
+    }
+
+    async fn get_role_permissions(
+        &mut self,
+        roles: &[String],
+    ) -> model::ProvideResult<Vec<String>> {
+        let permissions: Vec<(String,)> = sqlx::query_as(
+            r#"
+SELECT DISTINCT permission
+FROM main.role_permissions
+WHERE role = ANY($1)
+ORDER BY permission
+            "#,
+        )
+        .bind(roles)
+        .fetch_all(self)
+        .await?;
+
+        Ok(permissions
+            .into_iter()
+            .map(|(permission,)| permission)
+            .collect())
+    }
+
+    async fn create_email_verification(
+        &mut self,
+        user_id: model::EntityId,
+        email: Option<&str>,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::EmailVerificationEntity> {
+        let verification: EmailVerificationEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.email_verifications ( user_id, email, token_hash, expires_at )
+VALUES ( $1, $2, $3, $4 )
+RETURNING id, user_id, token_hash, expires_at, used_at, created_at, email
+            "#,
+        )
+        .bind(user_id)
+        .bind(email)
+        .bind(token_hash)
+        .bind(expires_at)
+        .fetch_one(self)
+        .await?;
+
+        Ok(verification.into())
+    }
+
+    async fn delete_email_verifications(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<u64> {
+        let deleted = sqlx::query(
+            r#"
+DELETE FROM main.email_verifications
+WHERE user_id = $1 AND used_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .execute(self)
+        .await?;
+
+        Ok(deleted)
+    }
+
+    async fn use_email_verification(
+        &mut self,
+        token_hash: &str,
+    ) -> model::ProvideResult<Option<model::EmailVerificationEntity>> {
+        let verification: Option<EmailVerificationEntity> = sqlx::query_as(
+            r#"
+UPDATE main.email_verifications
+SET used_at = NOW()
+WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+RETURNING id, user_id, token_hash, expires_at, used_at, created_at, email
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(verification.map(model::EmailVerificationEntity::from))
+    }
+
+    async fn create_password_reset(
+        &mut self,
+        user_id: model::EntityId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::PasswordResetEntity> {
+        let reset: PasswordResetEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.password_resets ( user_id, token_hash, expires_at )
+VALUES ( $1, $2, $3 )
+RETURNING id, user_id, token_hash, expires_at, used_at, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .fetch_one(self)
+        .await?;
+
+        Ok(reset.into())
+    }
+
+    async fn use_password_reset(
+        &mut self,
+        token_hash: &str,
+    ) -> model::ProvideResult<Option<model::PasswordResetEntity>> {
+        let reset: Option<PasswordResetEntity> = sqlx::query_as(
+            r#"
+WITH reset AS (
+  UPDATE main.password_resets
+  SET used_at = NOW()
+  WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+  RETURNING id, user_id, token_hash, expires_at, used_at, created_at
+), others AS (
+  UPDATE main.password_resets
+  SET used_at = NOW()
+  WHERE user_id IN ( SELECT user_id FROM reset ) AND token_hash <> $1 AND used_at IS NULL
+)
+SELECT * FROM reset
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(reset.map(model::PasswordResetEntity::from))
+    }
+
+    async fn create_magic_link(
+        &mut self,
+        user_id: model::EntityId,
+        organization_id: model::EntityId,
+        binding_hash: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::MagicLinkEntity> {
+        let link: MagicLinkEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.magic_links ( user_id, organization_id, binding_hash, expires_at )
+VALUES ( $1, $2, $3, $4 )
+RETURNING id, user_id, organization_id, binding_hash, expires_at, used_at, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(organization_id)
+        .bind(binding_hash)
+        .bind(expires_at)
+        .fetch_one(self)
+        .await?;
+
+        Ok(link.into())
+    }
+
+    async fn get_magic_link(
+        &mut self,
+        id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::MagicLinkEntity>> {
+        let link: Option<MagicLinkEntity> = sqlx::query_as(
+            r#"
+SELECT id, user_id, organization_id, binding_hash, expires_at, used_at, created_at
+FROM main.magic_links
+WHERE id = $1 AND used_at IS NULL AND expires_at > NOW()
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(link.map(model::MagicLinkEntity::from))
+    }
+
+    async fn use_magic_link(
+        &mut self,
+        id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::MagicLinkEntity>> {
+        let link: Option<MagicLinkEntity> = sqlx::query_as(
+            r#"
+UPDATE main.magic_links
+SET used_at = NOW()
+WHERE id = $1 AND used_at IS NULL AND expires_at > NOW()
+RETURNING id, user_id, organization_id, binding_hash, expires_at, used_at, created_at
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(link.map(model::MagicLinkEntity::from))
+    }
+
+    async fn get_login_lockout(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::LoginLockoutEntity>> {
+        let lockout: Option<LoginLockoutEntity> = sqlx::query_as(
+            r#"
+SELECT user_id, failed_logins, locked_until, updated_at
+FROM main.login_lockouts
+WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(lockout.map(model::LoginLockoutEntity::from))
+    }
+
+    async fn record_failed_login(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<model::LoginLockoutEntity> {
+        // The upsert locks the row, as a SELECT ... FOR UPDATE would, until the end of
+        // the transaction.
+        let lockout: LoginLockoutEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.login_lockouts ( user_id, failed_logins )
+VALUES ( $1, 1 )
+ON CONFLICT (user_id) DO UPDATE
+SET failed_logins = main.login_lockouts.failed_logins + 1,
+    updated_at = NOW()
+RETURNING user_id, failed_logins, locked_until, updated_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(self)
+        .await?;
+
+        Ok(lockout.into())
+    }
+
+    async fn lock_out_user(
+        &mut self,
+        user_id: model::EntityId,
+        locked_until: Option<DateTime<Utc>>,
+    ) -> model::ProvideResult<u64> {
+        let locked = sqlx::query(
+            r#"
+UPDATE main.login_lockouts
+SET locked_until = $2, updated_at = NOW()
+WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(locked_until)
+        .execute(self)
+        .await?;
+
+        Ok(locked)
+    }
+
+    async fn clear_login_lockout(&mut self, user_id: model::EntityId) -> model::ProvideResult<u64> {
+        let cleared = sqlx::query(
+            r#"
+DELETE FROM main.login_lockouts
+WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(self)
+        .await?;
+
+        Ok(cleared)
+    }
+
+    async fn get_totp(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::TotpEntity>> {
+        let totp: Option<TotpEntity> = sqlx::query_as(
+            r#"
+SELECT user_id, secret, confirmed_at, last_used_step, created_at
+FROM main.totp_secrets
+WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(totp.map(model::TotpEntity::from))
+    }
+
+    async fn create_totp(
+        &mut self,
+        user_id: model::EntityId,
+        secret: &str,
+    ) -> model::ProvideResult<model::TotpEntity> {
+        let totp: TotpEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.totp_secrets ( user_id, secret )
+VALUES ( $1, $2 )
+ON CONFLICT (user_id) DO UPDATE
+SET secret = EXCLUDED.secret,
+    confirmed_at = NULL,
+    last_used_step = NULL,
+    created_at = NOW()
+RETURNING user_id, secret, confirmed_at, last_used_step, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(secret)
+        .fetch_one(self)
+        .await?;
+
+        Ok(totp.into())
+    }
+
+    async fn confirm_totp(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<model::TotpEntity> {
+        let totp: TotpEntity = sqlx::query_as(
+            r#"
+UPDATE main.totp_secrets
+SET confirmed_at = NOW()
+WHERE user_id = $1
+RETURNING user_id, secret, confirmed_at, last_used_step, created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(self)
+        .await?;
+
+        Ok(totp.into())
+    }
+
+    async fn use_totp_step(
+        &mut self,
+        user_id: model::EntityId,
+        step: i64,
+    ) -> model::ProvideResult<bool> {
+        let updated = sqlx::query(
+            r#"
+UPDATE main.totp_secrets
+SET last_used_step = $2
+WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(self)
+        .await?;
+
+        Ok(updated > 0)
+    }
+
+    async fn create_recovery_codes(
+        &mut self,
+        user_id: model::EntityId,
+        code_hashes: &[String],
+    ) -> model::ProvideResult<()> {
+        sqlx::query(
+            r#"
+DELETE FROM main.recovery_codes
+WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *self)
+        .await?;
+
+        sqlx::query(
+            r#"
+INSERT INTO main.recovery_codes ( user_id, code_hash )
+SELECT $1, UNNEST($2::TEXT[])
+            "#,
+        )
+        .bind(user_id)
+        .bind(code_hashes)
+        .execute(&mut *self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn use_recovery_code(
+        &mut self,
+        user_id: model::EntityId,
+        code_hash: &str,
+    ) -> model::ProvideResult<bool> {
+        let updated = sqlx::query(
+            r#"
+UPDATE main.recovery_codes
+SET used_at = NOW()
+WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(code_hash)
+        .execute(self)
+        .await?;
+
+        Ok(updated > 0)
+    }
+
+    async fn create_mfa_challenge(
+        &mut self,
+        user_id: model::EntityId,
+        organization_id: model::EntityId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::MfaChallengeEntity> {
+        let challenge: MfaChallengeEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.mfa_challenges ( user_id, organization_id, token_hash, expires_at )
+VALUES ( $1, $2, $3, $4 )
+RETURNING id, user_id, organization_id, token_hash, expires_at, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(organization_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .fetch_one(self)
+        .await?;
+
+        Ok(challenge.into())
+    }
+
+    async fn take_mfa_challenge(
+        &mut self,
+        token_hash: &str,
+    ) -> model::ProvideResult<Option<model::MfaChallengeEntity>> {
+        let challenge: Option<MfaChallengeEntity> = sqlx::query_as(
+            r#"
+DELETE FROM main.mfa_challenges
+WHERE token_hash = $1 AND expires_at > NOW()
+RETURNING id, user_id, organization_id, token_hash, expires_at, created_at
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(challenge.map(model::MfaChallengeEntity::from))
+    }
+
+    async fn get_webauthn_credentials(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::WebauthnCredentialEntity>> {
+        let credentials: Vec<WebauthnCredentialEntity> = sqlx::query_as(
+            r#"
+SELECT id, user_id, credential_id, public_key, algorithm, sign_count, name, created_at, last_used_at
+FROM main.webauthn_credentials
+WHERE user_id = $1
+ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(self)
+        .await?;
+
+        Ok(credentials
+            .into_iter()
+            .map(model::WebauthnCredentialEntity::from)
+            .collect())
+    }
+
+    async fn get_webauthn_credential(
+        &mut self,
+        credential_id: &[u8],
+    ) -> model::ProvideResult<Option<model::WebauthnCredentialEntity>> {
+        let credential: Option<WebauthnCredentialEntity> = sqlx::query_as(
+            r#"
+SELECT id, user_id, credential_id, public_key, algorithm, sign_count, name, created_at, last_used_at
+FROM main.webauthn_credentials
+WHERE credential_id = $1
+            "#,
+        )
+        .bind(credential_id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(credential.map(model::WebauthnCredentialEntity::from))
+    }
+
+    async fn create_webauthn_credential(
+        &mut self,
+        user_id: model::EntityId,
+        credential_id: &[u8],
+        public_key: &[u8],
+        algorithm: i32,
+        sign_count: i64,
+        name: Option<&str>,
+    ) -> model::ProvideResult<model::WebauthnCredentialEntity> {
+        let credential: WebauthnCredentialEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.webauthn_credentials ( user_id, credential_id, public_key, algorithm, sign_count, name )
+VALUES ( $1, $2, $3, $4, $5, $6 )
+RETURNING id, user_id, credential_id, public_key, algorithm, sign_count, name, created_at, last_used_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(credential_id)
+        .bind(public_key)
+        .bind(algorithm)
+        .bind(sign_count)
+        .bind(name)
+        .fetch_one(self)
+        .await?;
+
+        Ok(credential.into())
+    }
+
+    async fn use_webauthn_credential(
+        &mut self,
+        id: model::EntityId,
+        sign_count: i64,
+    ) -> model::ProvideResult<bool> {
+        // Authenticators without a counter always report 0.
+        let updated = sqlx::query(
+            r#"
+UPDATE main.webauthn_credentials
+SET sign_count = $2, last_used_at = NOW()
+WHERE id = $1 AND (sign_count < $2 OR (sign_count = 0 AND $2 = 0))
+            "#,
+        )
+        .bind(id)
+        .bind(sign_count)
+        .execute(self)
+        .await?;
+
+        Ok(updated > 0)
+    }
+
+    async fn create_webauthn_challenge(
+        &mut self,
+        user_id: model::EntityId,
+        organization_id: model::EntityId,
+        challenge_hash: &str,
+        ceremony: &str,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::WebauthnChallengeEntity> {
+        let challenge: WebauthnChallengeEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.webauthn_challenges ( user_id, organization_id, challenge_hash, ceremony, expires_at )
+VALUES ( $1, $2, $3, $4, $5 )
+RETURNING id, user_id, organization_id, challenge_hash, ceremony, expires_at, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(organization_id)
+        .bind(challenge_hash)
+        .bind(ceremony)
+        .bind(expires_at)
+        .fetch_one(self)
+        .await?;
+
+        Ok(challenge.into())
+    }
+
+    async fn take_webauthn_challenge(
+        &mut self,
+        challenge_hash: &str,
+        ceremony: &str,
+    ) -> model::ProvideResult<Option<model::WebauthnChallengeEntity>> {
+        let challenge: Option<WebauthnChallengeEntity> = sqlx::query_as(
+            r#"
+DELETE FROM main.webauthn_challenges
+WHERE challenge_hash = $1 AND ceremony = $2 AND expires_at > NOW()
+RETURNING id, user_id, organization_id, challenge_hash, ceremony, expires_at, created_at
+            "#,
+        )
+        .bind(challenge_hash)
+        .bind(ceremony)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(challenge.map(model::WebauthnChallengeEntity::from))
+    }
+
+    async fn create_oauth_client(
+        &mut self,
+        name: &str,
+        redirect_uris: &[String],
+    ) -> model::ProvideResult<model::OauthClientEntity> {
+        let client: OauthClientEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.oauth_clients ( name, redirect_uris )
+VALUES ( $1, $2 )
+RETURNING id, name, redirect_uris, created_at
+            "#,
+        )
+        .bind(name)
+        .bind(redirect_uris)
+        .fetch_one(self)
+        .await?;
+
+        Ok(client.into())
+    }
+
+    async fn get_oauth_client(
+        &mut self,
+        id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::OauthClientEntity>> {
+        let client: Option<OauthClientEntity> = sqlx::query_as(
+            r#"
+SELECT id, name, redirect_uris, created_at
+FROM main.oauth_clients
+WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(client.map(model::OauthClientEntity::from))
+    }
+
+    async fn get_oauth_clients(&mut self) -> model::ProvideResult<Vec<model::OauthClientEntity>> {
+        let clients: Vec<OauthClientEntity> = sqlx::query_as(
+            r#"
+SELECT id, name, redirect_uris, created_at
+FROM main.oauth_clients
+ORDER BY created_at, id
+            "#,
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(clients
+            .into_iter()
+            .map(model::OauthClientEntity::from)
+            .collect())
+    }
+
+    async fn delete_oauth_client(&mut self, id: model::EntityId) -> model::ProvideResult<bool> {
+        let deleted = sqlx::query(
+            r#"
+DELETE FROM main.oauth_clients
+WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(self)
+        .await?;
+
+        Ok(deleted > 0)
+    }
+
+    async fn create_authorization_code(
+        &mut self,
+        code: &model::AuthorizationCodeEntity,
+    ) -> model::ProvideResult<()> {
+        sqlx::query(
+            r#"
+INSERT INTO main.oauth_authorization_codes ( id, code_hash, client_id, user_id, organization_id, redirect_uri, scope, nonce, code_challenge, expires_at, created_at )
+VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11 )
+            "#,
+        )
+        .bind(code.id)
+        .bind(code.code_hash.clone())
+        .bind(code.client_id)
+        .bind(code.user_id)
+        .bind(code.organization_id)
+        .bind(code.redirect_uri.clone())
+        .bind(code.scope.clone())
+        .bind(code.nonce.clone())
+        .bind(code.code_challenge.clone())
+        .bind(code.expires_at)
+        .bind(code.created_at)
+        .execute(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn take_authorization_code(
+        &mut self,
+        code_hash: &str,
+    ) -> model::ProvideResult<Option<model::AuthorizationCodeEntity>> {
+        let code: Option<AuthorizationCodeEntity> = sqlx::query_as(
+            r#"
+DELETE FROM main.oauth_authorization_codes
+WHERE code_hash = $1 AND expires_at > NOW()
+RETURNING id, code_hash, client_id, user_id, organization_id, redirect_uri, scope, nonce, code_challenge, expires_at, created_at
+            "#,
+        )
+        .bind(code_hash)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(code.map(model::AuthorizationCodeEntity::from))
+    }
+
+    async fn create_service_client(
+        &mut self,
+        organization_id: model::EntityId,
+        name: &str,
+        secret_hash: &str,
+        scopes: &[String],
+    ) -> model::ProvideResult<model::ServiceClientEntity> {
+        let client: ServiceClientEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.service_clients ( organization_id, name, secret_hash, scopes )
+VALUES ( $1, $2, $3, $4 )
+RETURNING id, organization_id, name, secret_hash, scopes, created_at
+            "#,
+        )
+        .bind(organization_id)
+        .bind(name)
+        .bind(secret_hash)
+        .bind(scopes)
+        .fetch_one(self)
+        .await?;
+
+        Ok(client.into())
+    }
+
+    async fn get_service_client(
+        &mut self,
+        id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::ServiceClientEntity>> {
+        let client: Option<ServiceClientEntity> = sqlx::query_as(
+            r#"
+SELECT id, organization_id, name, secret_hash, scopes, created_at
+FROM main.service_clients
+WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(client.map(model::ServiceClientEntity::from))
+    }
+
+    async fn get_service_clients(
+        &mut self,
+        organization_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::ServiceClientEntity>> {
+        let clients: Vec<ServiceClientEntity> = sqlx::query_as(
+            r#"
+SELECT id, organization_id, name, secret_hash, scopes, created_at
+FROM main.service_clients
+WHERE organization_id = $1
+ORDER BY created_at, id
+            "#,
+        )
+        .bind(organization_id)
+        .fetch_all(self)
+        .await?;
+
+        Ok(clients
+            .into_iter()
+            .map(model::ServiceClientEntity::from)
+            .collect())
+    }
+
+    async fn delete_service_client(
+        &mut self,
+        organization_id: model::EntityId,
+        id: model::EntityId,
+    ) -> model::ProvideResult<bool> {
+        let deleted = sqlx::query(
+            r#"
+DELETE FROM main.service_clients
+WHERE organization_id = $1 AND id = $2
+            "#,
+        )
+        .bind(organization_id)
+        .bind(id)
+        .execute(self)
+        .await?;
+
+        Ok(deleted > 0)
+    }
+
+    async fn create_audit_event(
+        &mut self,
+        event: &model::AuditEventEntity,
+    ) -> model::ProvideResult<()> {
+        sqlx::query(
+            r#"
+INSERT INTO main.audit_events ( id, action, actor_id, target_id, organization_id, ip, user_agent, details, created_at )
+VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9 )
+            "#,
+        )
+        .bind(event.id)
+        .bind(event.action.clone())
+        .bind(event.actor_id)
+        .bind(event.target_id)
+        .bind(event.organization_id)
+        .bind(event.ip.clone())
+        .bind(event.user_agent.clone())
+        .bind(event.details.clone())
+        .bind(event.created_at)
+        .execute(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_audit_events(
+        &mut self,
+        organization_id: model::EntityId,
+        filter: &model::AuditEventFilter,
+        limit: i64,
+    ) -> model::ProvideResult<Vec<model::AuditEventEntity>> {
+        let events: Vec<AuditEventEntity> = sqlx::query_as(
+            r#"
+SELECT id, action, actor_id, target_id, organization_id, ip, user_agent, details, created_at
+FROM main.audit_events
+WHERE organization_id = $1
+  AND ($2::TEXT IS NULL OR action = $2)
+  AND ($3::UUID IS NULL OR actor_id = $3)
+  AND ($4::UUID IS NULL OR target_id = $4)
+  AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+  AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)
+ORDER BY created_at DESC, id
+LIMIT $7
+            "#,
+        )
+        .bind(organization_id)
+        .bind(filter.action.clone())
+        .bind(filter.actor_id)
+        .bind(filter.target_id)
+        .bind(filter.after)
+        .bind(filter.before)
+        .bind(limit)
+        .fetch_all(self)
+        .await?;
 
-    /// Attempt to convert a Postgres error into a generic ProvideError
-    ///
-    /// Unexpected cases will be bounced back to the caller for handling
-    ///
-    /// * [Postgres Error Codes](https://www.postgresql.org/docs/current/errcodes-appendix.html)
-    fn try_from(pg_err: &PgError) -> Result<Self, Self::Error> {
-        let provider_err = match pg_err.code().unwrap() {
-            "23505" => model::ProvideError::UniqueViolation {
-                details: pg_err.details().unwrap().to_owned(),
-            },
-            code if code.starts_with("23") => model::ProvideError::ModelViolation {
-                details: pg_err.message().to_owned(),
-            },
-            _ => return Err(()),
-        };
+        Ok(events
+            .into_iter()
+            .map(model::AuditEventEntity::from)
+            .collect())
+    }
 
-        Ok(provider_err)
+    async fn get_user_audit_events(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::AuditEventEntity>> {
+        let events: Vec<AuditEventEntity> = sqlx::query_as(
+            r#"
+SELECT id, action, actor_id, target_id, organization_id, ip, user_agent, details, created_at
+FROM main.audit_events
+WHERE actor_id = $1 OR target_id = $1
+ORDER BY created_at DESC, id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(self)
+        .await?;
+
+        Ok(events
+            .into_iter()
+            .map(model::AuditEventEntity::from)
+            .collect())
     }
-}
 
-#[async_trait]
-impl Db for PgPool {
-    type Conn = PoolConnection<PgConnection>;
+    async fn create_refresh_token(
+        &mut self,
+        user_id: model::EntityId,
+        organization_id: model::EntityId,
+        family_id: model::EntityId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::RefreshTokenEntity> {
+        let token: RefreshTokenEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.refresh_tokens ( user_id, family_id, token_hash, expires_at, organization_id )
+VALUES ( $1, $2, $3, $4, $5 )
+RETURNING id, user_id, family_id, token_hash, expires_at, rotated_at, revoked_at, created_at, organization_id
+            "#,
+        )
+        .bind(user_id)
+        .bind(family_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .bind(organization_id)
+        .fetch_one(self)
+        .await?;
 
-    async fn conn(&self) -> Result<Self::Conn, sqlx::Error> {
-        self.acquire().await
+        Ok(token.into())
     }
-}
 
-#[async_trait]
-impl model::ProvideData for PgConnection {
-    async fn create_user(
+    async fn get_refresh_token_by_hash(
         &mut self,
-        username: &str,
-        email: &str,
-        password: &str,
-    ) -> model::ProvideResult<model::UserEntity> {
-        let user: UserEntity = sqlx::query_as(
+        token_hash: &str,
+    ) -> model::ProvideResult<Option<model::RefreshTokenEntity>> {
+        let token: Option<RefreshTokenEntity> = sqlx::query_as(
             r#"
-INSERT INTO main.users ( username, email, password )
-VALUES ( $1, $2, $3 )
-RETURNING *
-        "#,
+SELECT id, user_id, family_id, token_hash, expires_at, rotated_at, revoked_at, created_at, organization_id
+FROM main.refresh_tokens
+WHERE token_hash = $1
+            "#,
         )
-        .bind(username)
-        .bind(email)
-        .bind(password)
+        .bind(token_hash)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(token.map(model::RefreshTokenEntity::from))
+    }
+
+    async fn rotate_refresh_token(
+        &mut self,
+        token_id: model::EntityId,
+    ) -> model::ProvideResult<bool> {
+        let count = sqlx::query(
+            r#"
+UPDATE main.refresh_tokens
+SET rotated_at = NOW()
+WHERE id = $1 AND rotated_at IS NULL
+            "#,
+        )
+        .bind(token_id)
+        .execute(self)
+        .await?;
+
+        Ok(count == 1)
+    }
+
+    async fn revoke_refresh_token_family(
+        &mut self,
+        family_id: model::EntityId,
+    ) -> model::ProvideResult<u64> {
+        let count = sqlx::query(
+            r#"
+UPDATE main.refresh_tokens
+SET revoked_at = NOW()
+WHERE family_id = $1 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(family_id)
+        .execute(self)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn create_session(
+        &mut self,
+        session_id: &str,
+        csrf: &str,
+        user_id: model::EntityId,
+        identity: &model::Identity,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::SessionEntity> {
+        let session: SessionEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.sessions ( id, csrf, user_id, fingerprint, ip, expires_at )
+VALUES ( $1, $2, $3, $4, $5, $6 )
+RETURNING id, csrf, user_id, fingerprint, ip, expires_at, created_at
+            "#,
+        )
+        .bind(session_id)
+        .bind(csrf)
+        .bind(user_id)
+        .bind(identity.fingerprint.clone())
+        .bind(identity.ip.map(|ip| ip.to_string()))
+        .bind(expires_at)
         .fetch_one(self)
         .await?;
 
-        Ok(user.into())
+        Ok(session.into())
     }
 
-    async fn get_all_users(&mut self) -> model::ProvideResult<Vec<model::UserEntity>> {
-        let users: Vec<UserEntity> = sqlx::query_as(
+    async fn get_csrf_validated_session(
+        &mut self,
+        session_id: &str,
+        csrf: &str,
+    ) -> model::ProvideResult<Option<model::SessionEntity>> {
+        let session: Option<SessionEntity> = sqlx::query_as(
             r#"
-SELECT *
-FROM main.users
-ORDER BY created_at
+SELECT id, csrf, user_id, fingerprint, ip, expires_at, created_at
+FROM main.sessions
+WHERE id = $1 AND csrf = $2 AND expires_at > NOW()
+            "#,
+        )
+        .bind(session_id)
+        .bind(csrf)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(session.map(model::SessionEntity::from))
+    }
+
+    async fn delete_session(&mut self, session_id: &str) -> model::ProvideResult<u64> {
+        let count = sqlx::query(
+            r#"
+DELETE FROM main.sessions
+WHERE id = $1
+            "#,
+        )
+        .bind(session_id)
+        .execute(self)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn get_user_sessions(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::SessionEntity>> {
+        let sessions: Vec<SessionEntity> = sqlx::query_as(
+            r#"
+SELECT id, csrf, user_id, fingerprint, ip, expires_at, created_at
+FROM main.sessions
+WHERE user_id = $1
+ORDER BY created_at DESC
             "#,
         )
+        .bind(user_id)
         .fetch_all(self)
         .await?;
 
-        let users = users
+        Ok(sessions
             .into_iter()
-            .map(model::UserEntity::from)
-            .collect::<Vec<_>>();
+            .map(model::SessionEntity::from)
+            .collect())
+    }
 
-        Ok(users)
+    async fn delete_user_sessions(
+        &mut self,
+        user_id: model::EntityId,
+        kept_session: Option<&str>,
+    ) -> model::ProvideResult<u64> {
+        let count = sqlx::query(
+            r#"
+DELETE FROM main.sessions
+WHERE user_id = $1 AND id IS DISTINCT FROM $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(kept_session)
+        .execute(self)
+        .await?;
+
+        Ok(count)
     }
 
-    async fn get_user_by_username(
+    async fn revoke_user_refresh_tokens(
         &mut self,
-        username: &str,
-    ) -> model::ProvideResult<Option<model::UserEntity>> {
-        let user: Option<UserEntity> = sqlx::query_as(
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<u64> {
+        let count = sqlx::query(
             r#"
-SELECT *
-FROM main.users
-WHERE username = $1
+UPDATE main.refresh_tokens
+SET revoked_at = NOW()
+WHERE user_id = $1 AND revoked_at IS NULL
             "#,
         )
-        .bind(username)
-        .fetch_optional(self)
+        .bind(user_id)
+        .execute(self)
         .await?;
 
-        match user {
-            None => Ok(None),
-            Some(user) => {
-                let user = model::UserEntity::from(user);
-                Ok(Some(user))
-            }
-        }
+        Ok(count)
     }
-}
 
-#[async_trait]
-impl model::ProvideAuthn for PgConnection {
-    async fn create_user(
+    async fn get_user_refresh_tokens(
         &mut self,
-        username: &str,
-        email: &str,
-        password: &str,
-    ) -> model::ProvideResult<model::UserEntity> {
-        let user: UserEntity = sqlx::query_as(
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::RefreshTokenEntity>> {
+        let tokens: Vec<RefreshTokenEntity> = sqlx::query_as(
             r#"
-INSERT INTO main.users ( username, email, password )
-VALUES ( $1, $2, $3 )
-RETURNING *
-        "#,
+SELECT id, user_id, family_id, token_hash, expires_at, rotated_at, revoked_at, created_at, organization_id
+FROM main.refresh_tokens
+WHERE user_id = $1
+ORDER BY created_at DESC
+            "#,
         )
-        .bind(username)
-        .bind(email)
-        .bind(password)
-        .fetch_one(self)
+        .bind(user_id)
+        .fetch_all(self)
         .await?;
 
-        Ok(user.into())
+        Ok(tokens
+            .into_iter()
+            .map(model::RefreshTokenEntity::from)
+            .collect())
     }
 
-    async fn get_user_by_id(
+    async fn revoke_token(
         &mut self,
+        jti: &str,
         user_id: model::EntityId,
-    ) -> model::ProvideResult<Option<model::UserEntity>> {
-        let user: Option<UserEntity> = sqlx::query_as(
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<()> {
+        sqlx::query(
             r#"
-SELECT *
-FROM main.users
-WHERE id = $1
+INSERT INTO main.revoked_tokens ( jti, user_id, expires_at )
+VALUES ( $1, $2, $3 )
+ON CONFLICT ( jti ) DO NOTHING
             "#,
         )
+        .bind(jti)
         .bind(user_id)
-        .fetch_optional(self)
+        .bind(expires_at)
+        .execute(self)
         .await?;
 
-        match user {
-            None => Ok(None),
-            Some(user) => {
-                let user = model::UserEntity::from(user);
-                Ok(Some(user))
-            }
-        }
+        Ok(())
     }
 
-    async fn get_user_by_email(
+    async fn revoke_user_tokens(
         &mut self,
-        email: &str,
-    ) -> model::ProvideResult<Option<model::UserEntity>> {
-        let user: Option<UserEntity> = sqlx::query_as(
+        user_id: model::EntityId,
+        revoked_before: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+        kept_jti: Option<&str>,
+    ) -> model::ProvideResult<()> {
+        sqlx::query(
             r#"
-SELECT *
-FROM main.users
-WHERE email = $1
+INSERT INTO main.user_revocations ( user_id, revoked_before, expires_at, kept_jti )
+VALUES ( $1, date_trunc('second', $2::TIMESTAMPTZ), $3, $4 )
+ON CONFLICT ( user_id ) DO UPDATE
+SET revoked_before = EXCLUDED.revoked_before,
+    expires_at = EXCLUDED.expires_at,
+    kept_jti = EXCLUDED.kept_jti
             "#,
         )
-        .bind(email)
-        .fetch_optional(self)
+        .bind(user_id)
+        .bind(revoked_before)
+        .bind(expires_at)
+        .bind(kept_jti)
+        .execute(self)
         .await?;
 
-        match user {
-            None => Ok(None),
-            Some(user) => {
-                let user = model::UserEntity::from(user);
-                Ok(Some(user))
-            }
-        }
+        Ok(())
     }
 
-    async fn update_user(
+    async fn is_token_revoked(
         &mut self,
-        updated: &model::UserEntity,
-    ) -> model::ProvideResult<model::UserEntity> {
-        let user: UserEntity = sqlx::query_as(
+        jti: &str,
+        user_id: model::EntityId,
+        issued_at: DateTime<Utc>,
+    ) -> model::ProvideResult<bool> {
+        let (revoked,): (bool,) = sqlx::query_as(
             r#"
-UPDATE main.users
-SET email = $1, username = $2, password = $3, updated_at = DEFAULT
-WHERE id = $4
-RETURNING *
+SELECT EXISTS ( SELECT 1 FROM main.revoked_tokens WHERE jti = $1 )
+    OR EXISTS (
+        SELECT 1 FROM main.user_revocations
+        WHERE user_id = $2 AND revoked_before > $3 AND kept_jti IS DISTINCT FROM $1
+    )
             "#,
         )
-        .bind(updated.email.clone())
-        .bind(updated.username.clone())
-        .bind(updated.password.clone())
-        .bind(updated.id)
+        .bind(jti)
+        .bind(user_id)
+        .bind(issued_at)
         .fetch_one(self)
         .await?;
 
-        Ok(user.into())
+        Ok(revoked)
+    }
+
+    async fn delete_expired_revocations(&mut self) -> model::ProvideResult<u64> {
+        let tokens = sqlx::query(
+            r#"
+DELETE FROM main.revoked_tokens
+WHERE expires_at < NOW()
+            "#,
+        )
+        .execute(&mut *self)
+        .await?;
+
+        let users = sqlx::query(
+            r#"
+DELETE FROM main.user_revocations
+WHERE expires_at < NOW()
+            "#,
+        )
+        .execute(&mut *self)
+        .await?;
+
+        let sessions = sqlx::query(
+            r#"
+DELETE FROM main.sessions
+WHERE expires_at < NOW()
+            "#,
+        )
+        .execute(&mut *self)
+        .await?;
+
+        let verifications = sqlx::query(
+            r#"
+DELETE FROM main.email_verifications
+WHERE expires_at < NOW()
+            "#,
+        )
+        .execute(&mut *self)
+        .await?;
+
+        let resets = sqlx::query(
+            r#"
+DELETE FROM main.password_resets
+WHERE expires_at < NOW()
+            "#,
+        )
+        .execute(self)
+        .await?;
+
+        Ok(tokens + users + sessions + verifications + resets)
     }
 }
 
@@ -280,6 +2952,52 @@ pub async fn init_db(conn_str: &str, logger: Logger) -> Result<(), error::Error>
     Ok(())
 }
 
+/// Grant a role to a user, identified by its organization and username.
+/// The role applies in every organization. This is how the first administrator
+/// gets its role.
+pub async fn grant_role(
+    conn_str: &str,
+    organization: &str,
+    username: &str,
+    role: Role,
+) -> Result<(), error::Error> {
+    let pool = connect(conn_str).await.context(error::DBError {
+        msg: "could not connect to database",
+    })?;
+
+    let mut conn = pool.conn().await.context(error::DBError {
+        msg: "could not get connection",
+    })?;
+
+    let organization = conn
+        .get_organization_by_name(organization)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get organization by name",
+        })?
+        .ok_or(error::Error::MiscError {
+            msg: format!("Unknown organization {}", organization),
+        })?;
+
+    let user = conn
+        .get_user_by_username(organization.id, username)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get user by username",
+        })?
+        .ok_or(error::Error::MiscError {
+            msg: format!("Unknown user {}", username),
+        })?;
+
+    conn.add_user_role(user.id, role.as_str())
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not add user role",
+        })?;
+
+    Ok(())
+}
+
 pub async fn migration_up(conn_str: &str, logger: &Logger) -> Result<(), error::Error> {
     let clogger = logger.new(o!("database" => String::from(conn_str)));
     debug!(clogger, "Movine Up");
@@ -329,9 +3047,14 @@ pub async fn migration_down(conn_str: &str, logger: &Logger) -> Result<(), error
     // FIXME This relies on a command psql, which is not desibable.
     // We could alternatively try to use sqlx...
     // There may be a tool for doing migrations.
+    // 'movine down' only reverts the latest migration, so we ask for as many as
+    // there are in the migrations directory to get back to an empty schema.
+    let number = count_migrations()?;
     let mut cmd = Command::new("movine");
     cmd.env("DATABASE_URL", conn_str);
     cmd.arg("down");
+    cmd.arg("--number");
+    cmd.arg(number.to_string());
     cmd.stdout(Stdio::piped());
 
     let mut child = cmd.spawn().context(error::TokioIOError {
@@ -361,3 +3084,20 @@ pub async fn migration_down(conn_str: &str, logger: &Logger) -> Result<(), error
 
     Ok(())
 }
+
+/// Count the migrations managed by movine, excluding movine's own bookkeeping migration.
+fn count_migrations() -> Result<usize, error::Error> {
+    let entries = std::fs::read_dir("migrations").context(error::IOError {
+        msg: String::from("Could not read migrations directory"),
+    })?;
+    let mut count = 0;
+    for entry in entries {
+        let entry = entry.context(error::IOError {
+            msg: String::from("Could not read migrations directory entry"),
+        })?;
+        if entry.path().is_dir() && !entry.file_name().to_string_lossy().ends_with("movine_init") {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
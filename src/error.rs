@@ -23,6 +23,22 @@ pub enum Error {
         source: std::env::VarError,
     },
 
+    #[snafu(display("Authorization Error: {}", msg))]
+    #[snafu(visibility(pub))]
+    AuthorizationError { msg: String },
+
+    #[snafu(display("Inactive Account: {}", msg))]
+    #[snafu(visibility(pub))]
+    InactiveAccountError { msg: String },
+
+    #[snafu(display("Lockout: {}", msg))]
+    #[snafu(visibility(pub))]
+    LockoutError { msg: String, retry_after: i64 },
+
+    #[snafu(display("Conflict Error: {}", msg))]
+    #[snafu(visibility(pub))]
+    ConflictError { field: String, msg: String },
+
     #[snafu(display("Miscellaneous Error: {}", msg))]
     #[snafu(visibility(pub))]
     MiscError { msg: String },
@@ -64,6 +80,18 @@ pub enum Error {
         source: biscuit::errors::Error,
     },
 
+    #[snafu(display("Key Error: {}", msg))]
+    #[snafu(visibility(pub))]
+    KeyError { msg: String },
+
+    #[snafu(display("WebAuthn Error: {}", msg))]
+    #[snafu(visibility(pub))]
+    WebauthnError { msg: String },
+
+    #[snafu(display("Mail Error: {}", msg))]
+    #[snafu(visibility(pub))]
+    MailError { msg: String },
+
     #[snafu(display("Hasher Error: {}", msg))]
     #[snafu(visibility(pub))]
     HasherError {
@@ -96,6 +124,36 @@ impl IntoFieldError for Error {
                 )
             }
 
+            err @ Error::AuthorizationError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Authorization Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::InactiveAccountError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Inactive Account Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            Error::LockoutError { msg, retry_after } => {
+                // The code tells lockouts apart, and retry_after is in seconds.
+                let retry_after = retry_after as i32;
+                FieldError::new(
+                    "Lockout Error",
+                    graphql_value!({ "code": "LOCKED_OUT", "retry_after": retry_after, "internal_error": msg }),
+                )
+            }
+
+            Error::ConflictError { field, msg } => FieldError::new(
+                "Conflict Error",
+                graphql_value!({ "field": field, "internal_error": msg }),
+            ),
+
             err @ Error::MiscError { .. } => {
                 let errmsg = format!("{}", err);
                 FieldError::new(
@@ -151,6 +209,24 @@ impl IntoFieldError for Error {
                 )
             }
 
+            err @ Error::KeyError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("Key Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
+            err @ Error::WebauthnError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "WebAuthn Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::MailError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("Mail Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
             err @ Error::HasherError { .. } => {
                 let errmsg = format!("{}", err);
                 FieldError::new("Hasher Error", graphql_value!({ "internal_error": errmsg }))
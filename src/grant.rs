@@ -0,0 +1,21 @@
+use clap::ArgMatches;
+use slog::{info, Logger};
+use std::str::FromStr;
+
+use users::auth::role::Role;
+use users::db;
+use users::error;
+use users::settings::Settings;
+
+#[allow(clippy::needless_lifetimes)]
+pub async fn grant<'a>(matches: &ArgMatches<'a>, logger: Logger) -> Result<(), error::Error> {
+    let settings = Settings::new(matches)?;
+
+    let organization = matches.value_of("organization").unwrap();
+    let username = matches.value_of("username").unwrap();
+    let role = Role::from_str(matches.value_of("role").unwrap())?;
+
+    info!(logger, "Granting role {} to {}", role, username);
+
+    db::pg::grant_role(&settings.database.url, organization, username, role).await
+}
@@ -0,0 +1,226 @@
+use chrono::Utc;
+use clap::ArgMatches;
+use ring::rand::SystemRandom;
+use ring::signature;
+use slog::{info, Logger};
+use snafu::ResultExt;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use users::auth;
+use users::error;
+use users::settings::Settings;
+use users::state::jwt::{load_key, KeyEntry, KeyringManifest, KEYRING_MANIFEST};
+use users::state::keys::Algorithm;
+
+// Rotating keys without downtime goes like this:
+// 1. generate a new key. It is published in the JWKS, and accepted by the running
+//    services once they reload the keyring, but not yet used for signing.
+// 2. promote the new key. New tokens are signed with it, while tokens signed with the
+//    previous key remain valid.
+// 3. once the tokens signed with the previous key have expired, retire it.
+
+#[allow(clippy::needless_lifetimes)]
+pub async fn keys<'a>(matches: &ArgMatches<'a>, logger: Logger) -> Result<(), error::Error> {
+    let settings = Settings::new(matches)?;
+
+    let dir = settings
+        .jwt
+        .keyring
+        .map(PathBuf::from)
+        .ok_or(error::Error::MiscError {
+            msg: String::from("No keyring configured (jwt.keyring)"),
+        })?;
+
+    match matches.subcommand() {
+        ("list", Some(_)) => list(&dir, &logger),
+        ("generate", Some(sm)) => generate(&dir, sm.value_of("algorithm").unwrap(), &logger),
+        ("add", Some(sm)) => add(
+            &dir,
+            sm.value_of("algorithm").unwrap(),
+            sm.value_of("private_key"),
+            sm.value_of("public_key"),
+            &logger,
+        ),
+        ("promote", Some(sm)) => promote(&dir, sm.value_of("kid").unwrap(), &logger),
+        ("retire", Some(sm)) => retire(&dir, sm.value_of("kid").unwrap(), &logger),
+        _ => Err(error::Error::MiscError {
+            msg: String::from("Unrecognized keys subcommand"),
+        }),
+    }
+}
+
+fn list(dir: &Path, logger: &Logger) -> Result<(), error::Error> {
+    let manifest = read_manifest(dir)?;
+    for entry in manifest.keys.iter() {
+        let status = if manifest.active.as_deref() == Some(entry.kid.as_str()) {
+            "active"
+        } else {
+            "verify only"
+        };
+        info!(
+            logger,
+            "{} {} created {} ({})", entry.kid, entry.algorithm, entry.created_at, status
+        );
+    }
+    Ok(())
+}
+
+/// Generate a new key. It only becomes the active key if there is none.
+fn generate(dir: &Path, algorithm: &str, logger: &Logger) -> Result<(), error::Error> {
+    let algorithm = Algorithm::from_str(algorithm)?;
+    let kid = new_kid();
+    let rng = SystemRandom::new();
+
+    let (file, contents) = match algorithm {
+        Algorithm::HS256 => (format!("{}.secret", kid), auth::random_token(64)),
+        Algorithm::ES256 => {
+            let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(
+                &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                &rng,
+            )
+            .map_err(|_| error::Error::KeyError {
+                msg: String::from("Could not generate ECDSA key"),
+            })?;
+            (format!("{}.pem", kid), private_pem(pkcs8.as_ref()))
+        }
+        Algorithm::EdDSA => {
+            let pkcs8 = signature::Ed25519KeyPair::generate_pkcs8(&rng).map_err(|_| {
+                error::Error::KeyError {
+                    msg: String::from("Could not generate Ed25519 key"),
+                }
+            })?;
+            (format!("{}.pem", kid), private_pem(pkcs8.as_ref()))
+        }
+        Algorithm::RS256 => return Err(error::Error::KeyError {
+            msg: String::from(
+                "RSA keys cannot be generated here, generate one with openssl and use 'keys add'",
+            ),
+        }),
+    };
+
+    std::fs::create_dir_all(dir).context(error::IOError {
+        msg: format!("Could not create keyring directory {}", dir.display()),
+    })?;
+    write_private(&dir.join(&file), contents.as_bytes())?;
+
+    let entry = KeyEntry {
+        kid,
+        algorithm: algorithm.to_string(),
+        private_key: Some(file),
+        public_key: None,
+        created_at: Utc::now(),
+    };
+    insert(dir, entry, logger)
+}
+
+/// Add a key generated elsewhere, such as an RSA key, or a key only used for verification.
+fn add(
+    dir: &Path,
+    algorithm: &str,
+    private_key: Option<&str>,
+    public_key: Option<&str>,
+    logger: &Logger,
+) -> Result<(), error::Error> {
+    let algorithm = Algorithm::from_str(algorithm)?;
+    let entry = KeyEntry {
+        kid: new_kid(),
+        algorithm: algorithm.to_string(),
+        private_key: private_key.map(String::from),
+        public_key: public_key.map(String::from),
+        created_at: Utc::now(),
+    };
+    insert(dir, entry, logger)
+}
+
+fn insert(dir: &Path, entry: KeyEntry, logger: &Logger) -> Result<(), error::Error> {
+    // Make sure the key can be loaded before it is published.
+    let key = load_key(dir, &entry)?;
+
+    let mut manifest = read_manifest(dir)?;
+    if manifest.active.is_none() && key.can_sign() {
+        manifest.active = Some(entry.kid.clone());
+    }
+    info!(logger, "Adding key {} to the keyring", entry.kid);
+    manifest.keys.push(entry);
+    manifest.write(dir)
+}
+
+/// Make the key the active one. The previous active key still verifies tokens.
+fn promote(dir: &Path, kid: &str, logger: &Logger) -> Result<(), error::Error> {
+    let mut manifest = read_manifest(dir)?;
+    let entry = manifest.get(kid).ok_or(error::Error::KeyError {
+        msg: format!("No key {} in the keyring", kid),
+    })?;
+    if !load_key(dir, entry)?.can_sign() {
+        return Err(error::Error::KeyError {
+            msg: format!("Key {} cannot sign tokens", kid),
+        });
+    }
+    if let Some(previous) = manifest.active.replace(String::from(kid)) {
+        info!(
+            logger,
+            "Key {} replaces {}, retire it once the tokens it signed have expired", kid, previous
+        );
+    }
+    manifest.write(dir)
+}
+
+/// Remove a key from the keyring. Tokens it signed will be rejected.
+fn retire(dir: &Path, kid: &str, logger: &Logger) -> Result<(), error::Error> {
+    let mut manifest = read_manifest(dir)?;
+    if manifest.active.as_deref() == Some(kid) {
+        return Err(error::Error::KeyError {
+            msg: format!("Key {} is active, promote another key first", kid),
+        });
+    }
+    let count = manifest.keys.len();
+    manifest.keys.retain(|entry| entry.kid != kid);
+    if manifest.keys.len() == count {
+        return Err(error::Error::KeyError {
+            msg: format!("No key {} in the keyring", kid),
+        });
+    }
+    info!(logger, "Retiring key {}", kid);
+    manifest.write(dir)
+}
+
+fn read_manifest(dir: &Path) -> Result<KeyringManifest, error::Error> {
+    if dir.join(KEYRING_MANIFEST).exists() {
+        KeyringManifest::read(dir)
+    } else {
+        Ok(KeyringManifest::default())
+    }
+}
+
+fn new_kid() -> String {
+    format!(
+        "{}-{}",
+        Utc::now().format("%Y%m%d"),
+        auth::random_token(8).to_lowercase()
+    )
+}
+
+fn private_pem(pkcs8: &[u8]) -> String {
+    pem::encode(&pem::Pem {
+        tag: String::from("PRIVATE KEY"),
+        contents: pkcs8.to_vec(),
+    })
+}
+
+/// Write a file only readable by its owner.
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), error::Error> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .context(error::IOError {
+            msg: format!("Could not create key file {}", path.display()),
+        })?;
+    file.write_all(contents).context(error::IOError {
+        msg: format!("Could not write key file {}", path.display()),
+    })
+}
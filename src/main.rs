@@ -1,7 +1,9 @@
 use clap::{App, Arg, SubCommand};
 use slog::{o, warn, Drain};
 
+mod grant;
 mod init;
+mod keys;
 mod server;
 mod test;
 
@@ -38,6 +40,93 @@ async fn main() -> Result<(), error::Error> {
                 .version("0.1")
                 .author("Matthieu Paindavoine <matt@area403.org>"),
         )
+        .subcommand(
+            SubCommand::with_name("grant")
+                .about("Grant a role to a user, eg to bootstrap the first admin")
+                .version("0.1")
+                .author("Matthieu Paindavoine <matt@area403.org>")
+                .arg(
+                    Arg::with_name("username")
+                        .value_name("USERNAME")
+                        .short("u")
+                        .long("username")
+                        .required(true)
+                        .help("The user's username"),
+                )
+                .arg(
+                    Arg::with_name("organization")
+                        .value_name("ORGANIZATION")
+                        .short("o")
+                        .long("organization")
+                        .default_value("default")
+                        .help("The user's organization"),
+                )
+                .arg(
+                    Arg::with_name("role")
+                        .value_name("ROLE")
+                        .short("r")
+                        .long("role")
+                        .possible_values(&["admin", "moderator", "user"])
+                        .default_value("admin")
+                        .help("The role to grant"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("keys")
+                .about("Manage the jwt signing keys")
+                .version("0.1")
+                .author("Matthieu Paindavoine <matt@area403.org>")
+                .subcommand(SubCommand::with_name("list").about("List the keys in the keyring"))
+                .subcommand(
+                    SubCommand::with_name("generate")
+                        .about("Generate a new key, used for verification until promoted")
+                        .arg(
+                            Arg::with_name("algorithm")
+                                .value_name("ALGORITHM")
+                                .short("a")
+                                .long("algorithm")
+                                .possible_values(&["HS256", "ES256", "EdDSA"])
+                                .default_value("ES256")
+                                .help("Signature algorithm"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add a key generated elsewhere")
+                        .arg(
+                            Arg::with_name("algorithm")
+                                .value_name("ALGORITHM")
+                                .short("a")
+                                .long("algorithm")
+                                .possible_values(&["RS256", "ES256", "EdDSA"])
+                                .required(true)
+                                .help("Signature algorithm"),
+                        )
+                        .arg(
+                            Arg::with_name("private_key")
+                                .value_name("PATH")
+                                .long("private-key")
+                                .required_unless("public_key")
+                                .help("PEM private key"),
+                        )
+                        .arg(
+                            Arg::with_name("public_key")
+                                .value_name("PATH")
+                                .long("public-key")
+                                .help("PEM public key"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("promote")
+                        .about("Sign new tokens with this key")
+                        .arg(Arg::with_name("kid").value_name("KID").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("retire")
+                        .about("Remove a key from the keyring")
+                        .arg(Arg::with_name("kid").value_name("KID").required(true)),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("test")
                 .about("Test Something")
@@ -54,6 +143,8 @@ async fn main() -> Result<(), error::Error> {
     match matches.subcommand() {
         ("run", Some(sm)) => server::run(sm, logger).await,
         ("init", Some(sm)) => init::init(sm, logger).await,
+        ("grant", Some(sm)) => grant::grant(sm, logger).await,
+        ("keys", Some(sm)) => keys::keys(sm, logger).await,
         ("test", Some(sm)) => test::test(sm, logger).await,
         _ => {
             warn!(logger, "Unrecognized subcommand");
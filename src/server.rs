@@ -1,14 +1,19 @@
 use clap::ArgMatches;
-use slog::{info, Logger};
+use juniper::http::GraphQLBatchRequest;
+use slog::{info, warn, Logger};
 use snafu::ResultExt;
 // use sqlx::postgres::PgPool;
-use std::net::ToSocketAddrs;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
 use users::api::gql;
+use users::auth::{self, client_credentials, oidc};
+use users::db::model::{EntityId, SessionEntity};
 // use users::db::pg;
 use users::error;
 use users::settings::Settings;
+use users::state::rate_limit::{self, RateLimited};
 use users::state::state::State;
-use warp::{self, http, Filter};
+use warp::{self, http, Filter, Reply};
 
 #[allow(clippy::needless_lifetimes)]
 pub async fn run<'a>(matches: &ArgMatches<'a>, logger: Logger) -> Result<(), error::Error> {
@@ -21,12 +26,30 @@ pub async fn run_server(settings: Settings, state: State) -> Result<(), error::E
     // We keep a copy of the logger before the context takes ownership of it.
     let logger = state.logger.clone();
 
+    // Revocations are only needed until the tokens they revoke have expired.
+    tokio::spawn(auth::cleanup_revocations(
+        state.clone(),
+        std::time::Duration::from_secs(600),
+    ));
+
+    // Pick up keys generated, promoted or retired in the keyring.
+    let keyring_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(err) = keyring_state.jwt.reload() {
+                warn!(keyring_state.logger, "Could not reload keyring: {}", err);
+            }
+        }
+    });
+
     let state = warp::any().map(move || state.clone());
 
     let cors = warp::cors()
         .allow_any_origin()
         .allow_methods(vec!["GET", "POST"])
-        .allow_headers(vec!["content-type", "authorization"])
+        .allow_headers(vec!["content-type", "authorization", "x-csrf-token"])
         .allow_any_origin()
         .build();
 
@@ -40,20 +63,153 @@ pub async fn run_server(settings: Settings, state: State) -> Result<(), error::E
         .or(warp::any().map(|| None))
         .unify();
 
+    // A request carrying the session cookie must also carry the CSRF token,
+    // and then the session's jwt takes precedence over the authorization header.
+    let session = warp::cookie::optional("jwt")
+        .and(warp::header::optional::<String>("x-csrf-token"))
+        .and(state.clone())
+        .and_then(auth::session_filter);
+
     let context = warp::any()
+        .and(state.clone())
+        .and(auth.clone())
+        .and(session)
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>("user-agent"))
+        .map(
+            move |state,
+                  token,
+                  session: Option<(String, SessionEntity)>,
+                  address: Option<SocketAddr>,
+                  user_agent| match session {
+                Some((jwt, session)) => gql::Context {
+                    state,
+                    token: Some(jwt),
+                    session: Some(session),
+                    ip: address.map(|addr| addr.ip()),
+                    user_agent,
+                },
+                None => gql::Context {
+                    state,
+                    token,
+                    session: None,
+                    ip: address.map(|addr| addr.ip()),
+                    user_agent,
+                },
+            },
+        );
+
+    // The endpoints outside of GraphQL go through the same rate limiter, by IP address.
+    // The login endpoints share the limit of the loginUser field, and the token endpoints
+    // that of the oauthToken endpoint.
+    let rate_limited = |operation: &'static str| {
+        state
+            .clone()
+            .and(warp::addr::remote())
+            .and_then(
+                move |state: State, address: Option<SocketAddr>| async move {
+                    let clients = rate_limit_clients(None, address.map(|addr| addr.ip()));
+                    check_rate_limit(&state, Some(operation), &clients)
+                },
+            )
+            .untuple_one()
+    };
+
+    let login = warp::post()
+        .and(warp::path!("auth" / "login"))
+        .and(rate_limited("loginUser"))
+        .and(state.clone())
+        .and(warp::body::json())
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>("user-agent"))
+        .and_then(auth::login_filter);
+
+    let logout = warp::post()
+        .and(warp::path!("auth" / "logout"))
+        .and(warp::cookie::optional("jwt"))
+        .and(warp::header::optional::<String>("x-csrf-token"))
+        .and(state.clone())
+        .and_then(auth::logout_filter);
+
+    // Other services can verify our tokens with these public keys.
+    let jwks = warp::get()
+        .and(warp::path!(".well-known" / "jwks.json"))
+        .and(state.clone())
+        .map(|state: State| warp::reply::json(&state.jwt.jwks()));
+
+    // The OpenID Connect provider, with its endpoints under the issuer.
+    let openid_configuration = warp::get()
+        .and(warp::path!(".well-known" / "openid-configuration"))
+        .and(state.clone())
+        .map(|state: State| warp::reply::json(&state.oidc.configuration(&state.jwt)));
+
+    let authorize = warp::get()
+        .and(warp::path!("authorize"))
+        .and(state.clone())
+        .and(warp::query::<oidc::AuthorizationRequest>())
+        .and_then(oidc::authorize_filter);
+
+    let authorize_login = warp::post()
+        .and(warp::path!("authorize"))
+        .and(rate_limited("loginUser"))
+        .and(state.clone())
+        .and(warp::body::form())
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>("user-agent"))
+        .and_then(oidc::authorize_login_filter);
+
+    let token = warp::post()
+        .and(warp::path!("token"))
+        .and(rate_limited("oauthToken"))
+        .and(state.clone())
+        .and(warp::body::form())
+        .and_then(oidc::token_filter);
+
+    let userinfo = warp::get()
+        .or(warp::post())
+        .unify()
+        .and(warp::path!("userinfo"))
         .and(state.clone())
         .and(auth)
-        .map(move |state, token| gql::Context { state, token });
+        .and_then(oidc::userinfo_filter);
+
+    // Other services get their own tokens with the client credentials grant.
+    let oauth_token = warp::post()
+        .and(warp::path!("oauth" / "token"))
+        .and(rate_limited("oauthToken"))
+        .and(state.clone())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::form())
+        .and_then(client_credentials::token_filter);
 
     let playground = warp::get()
         .and(warp::path("playground"))
         .and(playground_filter("/graphql", Some("/subscriptions")));
 
-    let graphql_filter = juniper_warp::make_graphql_filter(gql::schema(), context.boxed());
-
-    let graphql = warp::post().and(warp::path("graphql")).and(graphql_filter);
+    // The operations are rate limited before they are executed, so we execute them
+    // ourselves rather than with juniper_warp, which keeps the body to itself.
+    let schema = Arc::new(gql::schema());
+    let graphql = warp::post()
+        .and(warp::path("graphql"))
+        .and(warp::any().map(move || schema.clone()))
+        .and(warp::body::json())
+        .and(context)
+        .and_then(graphql_filter);
 
-    let routes = playground.or(graphql).with(cors).with(log);
+    let routes = playground
+        .or(jwks)
+        .or(openid_configuration)
+        .or(authorize)
+        .or(authorize_login)
+        .or(token)
+        .or(userinfo)
+        .or(oauth_token)
+        .or(login)
+        .or(logout)
+        .or(graphql)
+        .recover(handle_rejection)
+        .with(cors)
+        .with(log);
 
     let host = settings.service.host;
     let port = settings.service.port;
@@ -74,6 +230,110 @@ pub async fn run_server(settings: Settings, state: State) -> Result<(), error::E
     Ok(())
 }
 
+/// The clients of the rate limiter, and whether they are shared: the subject of a valid
+/// token and its IP address, shared by the subjects behind it, or else the IP address.
+/// A request goes through the limits of both, so that a subject cannot go around its
+/// limit from many addresses, nor an address with many tokens.
+fn rate_limit_clients(subject: Option<EntityId>, ip: Option<IpAddr>) -> Vec<(String, bool)> {
+    match (subject, ip) {
+        (Some(subject), Some(ip)) => vec![
+            (format!("subject:{}", subject), false),
+            (format!("shared-ip:{}", ip), true),
+        ],
+        (Some(subject), None) => vec![(format!("subject:{}", subject), false)],
+        (None, Some(ip)) => vec![(format!("ip:{}", ip), false)],
+        (None, None) => vec![(String::from("unknown"), false)],
+    }
+}
+
+/// Take a token from each client's bucket for the operation, or reject the request.
+fn check_rate_limit(
+    state: &State,
+    operation: Option<&str>,
+    clients: &[(String, bool)],
+) -> Result<(), warp::Rejection> {
+    for (client, shared) in clients {
+        state
+            .rate_limit
+            .check(operation, client, *shared)
+            .map_err(|retry_after| {
+                // Round up, so that retrying after that many seconds is never too early.
+                warp::reject::custom(RateLimited {
+                    retry_after: retry_after.as_secs() + 1,
+                })
+            })?;
+    }
+    Ok(())
+}
+
+/// Execute a GraphQL request, or a batch of requests, unless the client goes over the
+/// rate limit of one of the root fields they select.
+async fn graphql_filter(
+    schema: Arc<gql::Schema>,
+    body: serde_json::Value,
+    context: gql::Context,
+) -> Result<http::Response<Vec<u8>>, warp::Rejection> {
+    let subject = context
+        .token
+        .as_deref()
+        .and_then(|token| context.state.jwt.decode(token).ok())
+        .and_then(|claims| auth::subject(&claims).ok());
+    let clients = rate_limit_clients(subject, context.ip);
+
+    for field in rate_limit::root_fields(&body) {
+        check_rate_limit(&context.state, field.as_deref(), &clients)?;
+    }
+
+    let (status, body) = match serde_json::from_value::<GraphQLBatchRequest>(body) {
+        Ok(request) => {
+            let response = request.execute(&schema, &context).await;
+            let status = if response.is_ok() {
+                http::StatusCode::OK
+            } else {
+                http::StatusCode::BAD_REQUEST
+            };
+            (status, serde_json::to_vec(&response))
+        }
+        Err(err) => (
+            http::StatusCode::BAD_REQUEST,
+            serde_json::to_vec(&serde_json::json!({ "error": format!("{}", err) })),
+        ),
+    };
+
+    let body = body.map_err(|err| {
+        warn!(
+            context.state.logger,
+            "Could not serialize GraphQL response: {}", err
+        );
+        warp::reject::reject()
+    })?;
+
+    http::Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .map_err(|_| warp::reject::reject())
+}
+
+/// Turn our own rejections into a response, and leave the others to warp.
+async fn handle_rejection(err: warp::Rejection) -> Result<warp::reply::Response, warp::Rejection> {
+    if let Some(auth::Unauthorized { msg }) = err.find() {
+        let reply = warp::reply::json(&serde_json::json!({ "error": msg }));
+        Ok(warp::reply::with_status(reply, http::StatusCode::UNAUTHORIZED).into_response())
+    } else if let Some(err) = err.find::<oidc::OauthError>() {
+        Ok(err.reply())
+    } else if let Some(RateLimited { retry_after }) = err.find() {
+        let reply = warp::reply::json(&serde_json::json!({ "error": "Too many requests" }));
+        let reply = warp::reply::with_status(reply, http::StatusCode::TOO_MANY_REQUESTS);
+        Ok(
+            warp::reply::with_header(reply, http::header::RETRY_AFTER, retry_after.to_string())
+                .into_response(),
+        )
+    } else {
+        Err(err)
+    }
+}
+
 /// Create a filter that replies with an HTML page containing GraphQL Playground.
 /// This does not handle routing, so you can mount it on any endpoint.
 pub fn playground_filter(
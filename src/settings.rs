@@ -2,6 +2,7 @@ use clap::ArgMatches;
 use config::{Config, Environment, File};
 use serde::Deserialize;
 use snafu::ResultExt;
+use std::collections::HashMap;
 use std::env;
 
 use super::error;
@@ -26,8 +27,181 @@ pub struct Argon {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Jwt {
+    /// One of HS256 (the default), RS256, ES256 or EdDSA
+    pub algorithm: Option<String>,
+    /// The shared secret, for HS256
+    pub secret: Option<String>,
+    /// Path to the PEM private key, for asymmetric algorithms
+    pub private_key: Option<String>,
+    /// Path to the PEM public key, derived from the private key if missing
+    pub public_key: Option<String>,
+    /// The key id written in the tokens' header
+    pub kid: Option<String>,
+    /// Path to a keyring directory. When set, the keys are described by the
+    /// keyring's manifest, and the key settings above are ignored.
+    pub keyring: Option<String>,
+    /// The issuer (iss) of our tokens
+    pub issuer: String,
+    /// The audiences (aud) of our tokens. Decoded tokens must be intended for one of them.
+    #[serde(default)]
+    pub audience: Vec<String>,
+    pub duration: i64,
+    pub refresh_duration: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Session {
+    pub duration: i64,
+    pub secure: bool,
+}
+
+/// How emails are delivered
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    /// Through the SMTP server
+    Smtp,
+    /// To files in a directory, one per recipient, holding its last email
+    File,
+}
+
+impl Default for MailTransport {
+    fn default() -> Self {
+        MailTransport::Smtp
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mailer {
+    #[serde(default)]
+    pub transport: MailTransport,
+    /// The directory the emails are written to, with the file transport
+    pub path: Option<String>,
+    pub host: String,
+    pub port: u16,
+    /// Upgrade the connection with STARTTLS
+    #[serde(default)]
+    pub tls: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The sender of the emails
+    pub from: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Verification {
+    /// The lifetime of an email verification token, in minutes
+    pub duration: i64,
+    /// The page verifying the email, the token is appended to it
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordReset {
+    /// The lifetime of a password reset token, in minutes
+    pub duration: i64,
+    /// The page resetting the password, the token is appended to it
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MagicLink {
+    /// The key signing the magic links
     pub secret: String,
+    /// The lifetime of a magic link, in minutes
     pub duration: i64,
+    /// The page logging the user in, the token is appended to it
+    pub url: String,
+}
+
+/// Where audit events are written
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSink {
+    /// The main.audit_events table, which the auditEvents query reads
+    Postgres,
+    /// A file of JSON lines, one per event
+    File,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Audit {
+    pub sinks: Vec<AuditSink>,
+    /// The file the events are appended to, with the file sink
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mfa {
+    /// The issuer shown by authenticator apps, next to the username
+    pub issuer: String,
+    /// The lifetime of the challenge completing a login, in minutes
+    pub challenge_duration: i64,
+    /// The recovery codes issued when TOTP is confirmed
+    pub recovery_codes: usize,
+}
+
+/// The relying party of passkeys, ie the site they are registered with
+#[derive(Debug, Clone, Deserialize)]
+pub struct Webauthn {
+    /// The domain the passkeys are scoped to, eg acme.com
+    pub rp_id: String,
+    /// The name shown by authenticators
+    pub rp_name: String,
+    /// The origin of the pages running the ceremonies, eg https://app.acme.com
+    pub origin: String,
+    /// The lifetime of the challenge of a ceremony, in minutes
+    pub challenge_duration: i64,
+}
+
+/// The OpenID Connect provider. Its issuer is the issuer of the jwt settings, which must
+/// be the URL the service is reachable at.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Oidc {
+    /// The lifetime of an authorization code, in minutes
+    pub code_duration: i64,
+}
+
+/// The protection against brute-force logins
+#[derive(Debug, Clone, Deserialize)]
+pub struct Security {
+    /// The failed logins after which an account is locked out
+    pub max_failed_logins: i32,
+    /// The first lockout, in seconds. It doubles with every further failed login.
+    pub lockout_duration: i64,
+    /// The longest lockout, in seconds
+    pub max_lockout_duration: i64,
+    /// The failed logins allowed from an IP address, within the window
+    pub ip_max_failed_logins: u32,
+    /// The window counting the failed logins of an IP address, in seconds
+    pub ip_window: i64,
+}
+
+/// A token bucket
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Limit {
+    /// The requests allowed in a burst
+    pub capacity: u32,
+    /// The requests allowed per minute, once the burst is spent
+    pub per_minute: u32,
+}
+
+/// The rate limit of the GraphQL endpoint, per client: the authenticated subject and its
+/// IP address, or else the IP address alone, and of the login and token endpoints, per IP
+/// address.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    /// How many times the limits of a subject the IP address of authenticated requests
+    /// gets, as the subjects behind an address, eg a NAT, share it
+    pub shared_ip_factor: u32,
+    /// The limit of the operations not listed below
+    pub default: Limit,
+    /// The limits by operation: the root fields of the GraphQL documents, eg loginUser,
+    /// which also limits /auth/login and /authorize, and oauthToken for /oauth/token and
+    /// /token.
+    /// The names are case insensitive.
+    #[serde(default)]
+    pub operations: HashMap<String, Limit>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -37,6 +211,17 @@ pub struct Settings {
     pub mode: String,
     pub argon: Argon,
     pub jwt: Jwt,
+    pub session: Session,
+    pub mailer: Mailer,
+    pub verification: Verification,
+    pub password_reset: PasswordReset,
+    pub magic_link: MagicLink,
+    pub audit: Audit,
+    pub security: Security,
+    pub mfa: Mfa,
+    pub webauthn: Webauthn,
+    pub oidc: Oidc,
+    pub rate_limit: RateLimit,
     pub database: Database,
     pub service: Service,
 }
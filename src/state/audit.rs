@@ -0,0 +1,297 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use juniper::GraphQLEnum;
+use serde::{Deserialize, Serialize, Serializer};
+use snafu::ResultExt;
+use sqlx::postgres::PgPool;
+use std::fmt::{self, Debug};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::db::model::{AuditEventEntity, EntityId, ProvideAuthn};
+use crate::db::Db;
+use crate::error;
+use crate::settings::{self, Settings};
+
+/// The security relevant actions. They are stored as strings in main.audit_events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, GraphQLEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuditAction {
+    UserRegistered,
+    UserAdded,
+    LoginSucceeded,
+    LoginFailed,
+    PasswordVerified,
+    PasswordChanged,
+    PasswordReset,
+    RoleGranted,
+    RoleRevoked,
+    UserDeactivated,
+    UserReactivated,
+    UserDeleted,
+    UserPurged,
+    UserErased,
+    MemberRemoved,
+    AccountLocked,
+    AccountUnlocked,
+    MfaEnabled,
+    MfaVerified,
+    RecoveryCodeUsed,
+    PasskeyRegistered,
+}
+
+impl AuditAction {
+    /// The action, as stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::UserRegistered => "user.registered",
+            AuditAction::UserAdded => "user.added",
+            AuditAction::LoginSucceeded => "login.succeeded",
+            AuditAction::LoginFailed => "login.failed",
+            AuditAction::PasswordVerified => "login.password_verified",
+            AuditAction::PasswordChanged => "password.changed",
+            AuditAction::PasswordReset => "password.reset",
+            AuditAction::RoleGranted => "role.granted",
+            AuditAction::RoleRevoked => "role.revoked",
+            AuditAction::UserDeactivated => "user.deactivated",
+            AuditAction::UserReactivated => "user.reactivated",
+            AuditAction::UserDeleted => "user.deleted",
+            AuditAction::UserPurged => "user.purged",
+            AuditAction::UserErased => "user.erased",
+            AuditAction::MemberRemoved => "member.removed",
+            AuditAction::AccountLocked => "account.locked",
+            AuditAction::AccountUnlocked => "account.unlocked",
+            AuditAction::MfaEnabled => "mfa.enabled",
+            AuditAction::MfaVerified => "mfa.verified",
+            AuditAction::RecoveryCodeUsed => "mfa.recovery_code_used",
+            AuditAction::PasskeyRegistered => "passkey.registered",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user.registered" => Ok(AuditAction::UserRegistered),
+            "user.added" => Ok(AuditAction::UserAdded),
+            "login.succeeded" => Ok(AuditAction::LoginSucceeded),
+            "login.failed" => Ok(AuditAction::LoginFailed),
+            "login.password_verified" => Ok(AuditAction::PasswordVerified),
+            "password.changed" => Ok(AuditAction::PasswordChanged),
+            "password.reset" => Ok(AuditAction::PasswordReset),
+            "role.granted" => Ok(AuditAction::RoleGranted),
+            "role.revoked" => Ok(AuditAction::RoleRevoked),
+            "user.deactivated" => Ok(AuditAction::UserDeactivated),
+            "user.reactivated" => Ok(AuditAction::UserReactivated),
+            "user.deleted" => Ok(AuditAction::UserDeleted),
+            "user.purged" => Ok(AuditAction::UserPurged),
+            "user.erased" => Ok(AuditAction::UserErased),
+            "member.removed" => Ok(AuditAction::MemberRemoved),
+            "account.locked" => Ok(AuditAction::AccountLocked),
+            "account.unlocked" => Ok(AuditAction::AccountUnlocked),
+            "mfa.enabled" => Ok(AuditAction::MfaEnabled),
+            "mfa.verified" => Ok(AuditAction::MfaVerified),
+            "mfa.recovery_code_used" => Ok(AuditAction::RecoveryCodeUsed),
+            "passkey.registered" => Ok(AuditAction::PasskeyRegistered),
+            _ => Err(error::Error::MiscError {
+                msg: format!("Unknown audit action '{}'", s),
+            }),
+        }
+    }
+}
+
+/// Who (the actor) did what (the action) to whom (the target), from where, and when.
+/// The details depend on the action, eg the role granted, or the username of a failed
+/// login.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    pub id: EntityId,
+    /// The action is written as stored in the database, eg login.failed.
+    #[serde(serialize_with = "serialize_action")]
+    pub action: AuditAction,
+    pub actor_id: Option<EntityId>,
+    pub target_id: Option<EntityId>,
+    pub organization_id: Option<EntityId>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEvent {
+    /// An event for the action, happening now, about which we know nothing else yet.
+    pub fn new(action: AuditAction) -> Self {
+        AuditEvent {
+            id: Uuid::new_v4(),
+            action,
+            actor_id: None,
+            target_id: None,
+            organization_id: None,
+            ip: None,
+            user_agent: None,
+            details: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+fn serialize_action<S: Serializer>(action: &AuditAction, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(action.as_str())
+}
+
+impl From<&AuditEvent> for AuditEventEntity {
+    fn from(event: &AuditEvent) -> Self {
+        AuditEventEntity {
+            id: event.id,
+            action: String::from(event.action.as_str()),
+            actor_id: event.actor_id,
+            target_id: event.target_id,
+            organization_id: event.organization_id,
+            ip: event.ip.clone(),
+            user_agent: event.user_agent.clone(),
+            details: event.details.clone(),
+            created_at: event.created_at,
+        }
+    }
+}
+
+/// Something that keeps audit events.
+#[async_trait]
+pub trait AuditSink: Debug + Send + Sync {
+    async fn record(&self, event: &AuditEvent) -> Result<(), error::Error>;
+}
+
+/// A sink appending the events to the main.audit_events table.
+#[derive(Clone, Debug)]
+pub struct Postgres {
+    pool: PgPool,
+}
+
+impl Postgres {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditSink for Postgres {
+    async fn record(&self, event: &AuditEvent) -> Result<(), error::Error> {
+        let mut conn = self.pool.conn().await.context(error::DBError {
+            msg: "could not get connection",
+        })?;
+
+        conn.create_audit_event(&AuditEventEntity::from(event))
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not record audit event",
+            })
+    }
+}
+
+/// A sink appending the events to a file, as JSON lines.
+#[derive(Clone, Debug)]
+pub struct JsonLines {
+    path: PathBuf,
+    // Lines written concurrently must not interleave.
+    lock: Arc<Mutex<()>>,
+}
+
+impl JsonLines {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+}
+
+#[async_trait]
+impl AuditSink for JsonLines {
+    async fn record(&self, event: &AuditEvent) -> Result<(), error::Error> {
+        let mut line = serde_json::to_string(event).context(error::JSONError {
+            msg: String::from("Could not serialize audit event"),
+        })?;
+        line.push('\n');
+
+        let path = self.path.clone();
+        let lock = self.lock.clone();
+
+        // File IO is blocking, so keep it off the runtime's threads.
+        tokio::task::spawn_blocking(move || {
+            let _guard = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(line.as_bytes()))
+        })
+        .await
+        .map_err(|err| error::Error::MiscError {
+            msg: format!("Could not write audit event: {}", err),
+        })?
+        .context(error::IOError {
+            msg: String::from("Could not write audit event"),
+        })
+    }
+}
+
+/// A sink writing the events to several sinks.
+/// Every sink gets the event, even when another one fails.
+#[derive(Debug)]
+pub struct Fanout {
+    sinks: Vec<Arc<dyn AuditSink>>,
+}
+
+#[async_trait]
+impl AuditSink for Fanout {
+    async fn record(&self, event: &AuditEvent) -> Result<(), error::Error> {
+        let mut res = Ok(());
+        for sink in &self.sinks {
+            if let Err(err) = sink.record(event).await {
+                if res.is_ok() {
+                    res = Err(err);
+                }
+            }
+        }
+        res
+    }
+}
+
+/// The sinks given in the settings.
+pub fn new(settings: &Settings, pool: &PgPool) -> Result<Arc<dyn AuditSink>, error::Error> {
+    let sinks = settings
+        .audit
+        .sinks
+        .iter()
+        .map(|sink| -> Result<Arc<dyn AuditSink>, error::Error> {
+            match sink {
+                settings::AuditSink::Postgres => Ok(Arc::new(Postgres::new(pool.clone()))),
+                settings::AuditSink::File => {
+                    let path = settings
+                        .audit
+                        .path
+                        .as_ref()
+                        .ok_or(error::Error::MiscError {
+                            msg: String::from("The file audit sink requires a path"),
+                        })?;
+                    Ok(Arc::new(JsonLines::new(PathBuf::from(path))))
+                }
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Arc::new(Fanout { sinks }))
+}
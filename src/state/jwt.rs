@@ -1,70 +1,384 @@
-use biscuit::{jwa, jws, ClaimsSet, RegisteredClaims, SingleOrMultiple, JWT};
-use chrono::Utc;
+use biscuit::{
+    jws, ClaimsSet, RegisteredClaims, SingleOrMultiple, StringOrUri, TemporalOptions, Validation,
+    JWT,
+};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use snafu::ResultExt;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
 
 use crate::auth;
+use crate::db::model::EntityId;
 use crate::error;
 use crate::settings::Settings;
+use crate::state::keys::{base64_url, read_pem, Algorithm, Key};
 
 // type DateTimeUtc = chrono::DateTime<chrono::Utc>;
 
+/// The name of the file describing the keys in a keyring directory.
+pub const KEYRING_MANIFEST: &str = "keyring.json";
+
+/// A key, as described in the keyring manifest.
+/// File names are relative to the keyring directory. For HS256, the private key
+/// file contains the shared secret.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEntry {
+    pub kid: String,
+    pub algorithm: String,
+    pub private_key: Option<String>,
+    pub public_key: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The keyring manifest.
+/// The active key signs new tokens, and all the keys verify tokens, so a key can be
+/// published before it is promoted, and still verify tokens after it has been replaced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyringManifest {
+    pub active: Option<String>,
+    pub keys: Vec<KeyEntry>,
+}
+
+impl KeyringManifest {
+    pub fn read(dir: &Path) -> Result<Self, error::Error> {
+        let path = dir.join(KEYRING_MANIFEST);
+        let file = std::fs::File::open(&path).context(error::IOError {
+            msg: format!("Could not open keyring manifest {}", path.display()),
+        })?;
+        serde_json::from_reader(file).context(error::JSONError {
+            msg: format!("Could not read keyring manifest {}", path.display()),
+        })
+    }
+
+    /// Write the manifest, replacing the previous one atomically, since running
+    /// services may be reloading it.
+    pub fn write(&self, dir: &Path) -> Result<(), error::Error> {
+        let path = dir.join(KEYRING_MANIFEST);
+        let tmp = dir.join(format!("{}.tmp", KEYRING_MANIFEST));
+        let json = serde_json::to_vec_pretty(self).context(error::JSONError {
+            msg: String::from("Could not serialize keyring manifest"),
+        })?;
+        std::fs::write(&tmp, json).context(error::IOError {
+            msg: format!("Could not write keyring manifest {}", tmp.display()),
+        })?;
+        std::fs::rename(&tmp, &path).context(error::IOError {
+            msg: format!("Could not replace keyring manifest {}", path.display()),
+        })
+    }
+
+    pub fn get(&self, kid: &str) -> Option<&KeyEntry> {
+        self.keys.iter().find(|entry| entry.kid == kid)
+    }
+}
+
+/// The keys known to the service, by key id.
+#[derive(Debug)]
+pub struct Keyring {
+    active: String,
+    keys: HashMap<String, Key>,
+}
+
+impl Keyring {
+    /// A keyring with a single key, described directly in the jwt settings.
+    fn from_settings(settings: &Settings) -> Result<Self, error::Error> {
+        let key = Key::new(&settings.jwt)?;
+        let kid = settings
+            .jwt
+            .kid
+            .clone()
+            .unwrap_or_else(|| String::from("default"));
+        let mut keys = HashMap::new();
+        keys.insert(kid.clone(), key);
+        Ok(Keyring { active: kid, keys })
+    }
+
+    /// A keyring described by the manifest in the given directory.
+    pub fn load(dir: &Path) -> Result<Self, error::Error> {
+        let manifest = KeyringManifest::read(dir)?;
+        let active = manifest.active.clone().ok_or(error::Error::KeyError {
+            msg: String::from("The keyring has no active key"),
+        })?;
+
+        let mut keys = HashMap::new();
+        for entry in manifest.keys.iter() {
+            keys.insert(entry.kid.clone(), load_key(dir, entry)?);
+        }
+
+        match keys.get(&active) {
+            Some(key) if key.can_sign() => Ok(Keyring { active, keys }),
+            Some(_) => Err(error::Error::KeyError {
+                msg: format!("The active key {} cannot sign tokens", active),
+            }),
+            None => Err(error::Error::KeyError {
+                msg: format!("The active key {} is not in the keyring", active),
+            }),
+        }
+    }
+}
+
+/// Load a key described in a keyring manifest.
+pub fn load_key(dir: &Path, entry: &KeyEntry) -> Result<Key, error::Error> {
+    let algorithm = Algorithm::from_str(&entry.algorithm)?;
+    if algorithm == Algorithm::HS256 {
+        let file = entry.private_key.as_ref().ok_or(error::Error::KeyError {
+            msg: format!("HS256 key {} has no secret", entry.kid),
+        })?;
+        let path = dir.join(file);
+        let secret = std::fs::read_to_string(&path).context(error::IOError {
+            msg: format!("Could not read secret {}", path.display()),
+        })?;
+        return Ok(Key::hmac(secret.trim()));
+    }
+    let read = |file: &String| read_pem(&dir.join(file).to_string_lossy());
+    let private = entry.private_key.as_ref().map(read).transpose()?;
+    let public = entry.public_key.as_ref().map(read).transpose()?;
+    Key::from_pem(algorithm, private.as_ref(), public.as_ref())
+}
+
 #[derive(Clone, Debug)]
 pub struct Jwt {
-    secret: String,
+    keyring: Arc<RwLock<Keyring>>,
+    keyring_dir: Option<PathBuf>,
+    issuer: StringOrUri,
+    audience: Vec<StringOrUri>,
     duration: chrono::Duration,
+    refresh_duration: chrono::Duration,
 }
 
 impl Jwt {
-    pub fn new(settings: &Settings) -> Self {
-        Self {
-            secret: String::from(&settings.jwt.secret),
+    pub fn new(settings: &Settings) -> Result<Self, error::Error> {
+        let keyring_dir = settings.jwt.keyring.as_ref().map(PathBuf::from);
+        let keyring = match &keyring_dir {
+            Some(dir) => Keyring::load(dir)?,
+            None => Keyring::from_settings(settings)?,
+        };
+        if !keyring.keys[&keyring.active].can_sign() {
+            return Err(error::Error::KeyError {
+                msg: String::from("The jwt key cannot sign tokens, a private key is required"),
+            });
+        }
+        Ok(Self {
+            keyring: Arc::new(RwLock::new(keyring)),
+            keyring_dir,
+            issuer: parse_claim(&settings.jwt.issuer)?,
+            audience: settings
+                .jwt
+                .audience
+                .iter()
+                .map(|audience| parse_claim(audience))
+                .collect::<Result<Vec<_>, _>>()?,
             duration: chrono::Duration::minutes(settings.jwt.duration),
+            refresh_duration: chrono::Duration::minutes(settings.jwt.refresh_duration),
+        })
+    }
+
+    /// Reload the keyring from its directory, so that keys generated, promoted or
+    /// retired since we started are taken into account.
+    /// If the keyring cannot be loaded, we keep the current one.
+    pub fn reload(&self) -> Result<(), error::Error> {
+        if let Some(dir) = &self.keyring_dir {
+            let keyring = Keyring::load(dir)?;
+            let mut current = self.keyring.write().map_err(|_| error::Error::KeyError {
+                msg: String::from("The keyring lock is poisoned"),
+            })?;
+            *current = keyring;
         }
+        Ok(())
+    }
+
+    fn keyring(&self) -> Result<std::sync::RwLockReadGuard<'_, Keyring>, error::Error> {
+        self.keyring.read().map_err(|_| error::Error::KeyError {
+            msg: String::from("The keyring lock is poisoned"),
+        })
+    }
+
+    /// The public keys used to verify our tokens, as a JWK Set.
+    pub fn jwks(&self) -> serde_json::Value {
+        let keys = match self.keyring() {
+            Ok(keyring) => keyring
+                .keys
+                .iter()
+                .filter_map(|(kid, key)| {
+                    key.jwk().map(|mut jwk| {
+                        jwk["kid"] = json!(kid);
+                        jwk
+                    })
+                })
+                .collect::<Vec<_>>(),
+            Err(_) => Vec::new(),
+        };
+        json!({ "keys": keys })
+    }
+
+    /// How long a token remains valid after it has been issued.
+    pub fn duration(&self) -> chrono::Duration {
+        self.duration
+    }
+
+    /// How long a refresh token remains valid after it has been issued.
+    pub fn refresh_duration(&self) -> chrono::Duration {
+        self.refresh_duration
     }
 
-    pub fn encode(&self, claims: auth::PrivateClaims) -> Result<String, error::Error> {
-        let expiry = Utc::now() + self.duration;
+    pub fn encode(
+        &self,
+        subject: EntityId,
+        claims: auth::PrivateClaims,
+    ) -> Result<String, error::Error> {
+        self.encode_with_expiry(subject, claims, Utc::now() + self.duration)
+    }
+
+    /// Encode a token which expires at the given time, rather than after the
+    /// configured duration. This is used for tokens bound to a session.
+    /// Each token gets a unique id (jti), so that it can be revoked.
+    /// The token is signed with the active key, and its id is written in the header.
+    pub fn encode_with_expiry(
+        &self,
+        subject: EntityId,
+        claims: auth::PrivateClaims,
+        expiry: DateTime<Utc>,
+    ) -> Result<String, error::Error> {
+        let now = Utc::now();
+        let audience = match self.audience.as_slice() {
+            [] => None,
+            [audience] => Some(SingleOrMultiple::Single(audience.clone())),
+            audiences => Some(SingleOrMultiple::Multiple(audiences.to_vec())),
+        };
         let registered = RegisteredClaims {
-            issuer: Some(FromStr::from_str("https://www.acme.com").unwrap()),
-            subject: Some(FromStr::from_str("John Doe").unwrap()),
-            audience: Some(SingleOrMultiple::Single(
-                FromStr::from_str("htts://acme-customer.com").unwrap(),
-            )),
+            issuer: Some(self.issuer.clone()),
+            subject: Some(StringOrUri::String(subject.to_string())),
+            audience,
             expiry: Some(expiry.into()),
-            ..Default::default()
+            not_before: Some(now.into()),
+            issued_at: Some(now.into()),
+            id: Some(Uuid::new_v4().to_string()),
         };
         let private = claims;
-        let claims = ClaimsSet::<auth::PrivateClaims> {
+        self.sign(ClaimsSet::<auth::PrivateClaims> {
             registered,
             private,
+        })
+    }
+
+    /// Encode an OpenID Connect ID token, for the client with the given id rather than
+    /// for our audiences. The client reads it, it is never presented back to us.
+    pub fn encode_id_token<T: Serialize + DeserializeOwned>(
+        &self,
+        subject: EntityId,
+        client_id: EntityId,
+        claims: T,
+        expiry: DateTime<Utc>,
+    ) -> Result<String, error::Error> {
+        let now = Utc::now();
+        let registered = RegisteredClaims {
+            issuer: Some(self.issuer.clone()),
+            subject: Some(StringOrUri::String(subject.to_string())),
+            audience: Some(SingleOrMultiple::Single(StringOrUri::String(
+                client_id.to_string(),
+            ))),
+            expiry: Some(expiry.into()),
+            issued_at: Some(now.into()),
+            ..Default::default()
         };
+        self.sign(ClaimsSet {
+            registered,
+            private: claims,
+        })
+    }
 
-        let jwt = biscuit::JWT::new_decoded(
-            From::from(jws::RegisteredHeader {
-                algorithm: jwa::SignatureAlgorithm::HS256,
-                ..Default::default()
-            }),
-            claims,
-        );
+    /// The algorithm of the active key, which signs new tokens.
+    pub fn algorithm(&self) -> Result<Algorithm, error::Error> {
+        let keyring = self.keyring()?;
+        Ok(keyring.keys[&keyring.active].algorithm())
+    }
 
-        let secret = jws::Secret::bytes_from_str(&self.secret);
+    /// Sign the claims with the active key, and write its id in the header.
+    fn sign<T: Serialize + DeserializeOwned>(
+        &self,
+        claims: ClaimsSet<T>,
+    ) -> Result<String, error::Error> {
+        let keyring = self.keyring()?;
+        let kid = keyring.active.clone();
+        let key = &keyring.keys[&kid];
 
-        jwt.into_encoded(&secret)
-            .map(|t| t.unwrap_encoded().to_string())
-            .context(error::BiscuitError {
-                msg: String::from("could not encode jwt"),
-            })
+        match key.signature_algorithm() {
+            Some(algorithm) => {
+                let jwt = biscuit::JWT::new_decoded(
+                    From::from(jws::RegisteredHeader {
+                        algorithm,
+                        key_id: Some(kid),
+                        ..Default::default()
+                    }),
+                    claims,
+                );
+
+                let secret = key.signing_secret()?;
+
+                jwt.into_encoded(&secret)
+                    .map(|t| t.unwrap_encoded().to_string())
+                    .context(error::BiscuitError {
+                        msg: String::from("could not encode jwt"),
+                    })
+            }
+            None => encode_eddsa(key, &kid, &claims),
+        }
     }
 
+    /// Decode the token, and validate its registered claims: it must have been issued by
+    /// us, for one of our audiences, and be within its validity period.
     pub fn decode(
         &self,
         token: &str,
     ) -> Result<biscuit::ClaimsSet<auth::PrivateClaims>, error::Error> {
+        let claims = self.verify(token)?;
+        self.validate(&claims.registered)
+            .map_err(|err| error::Error::MiscError {
+                msg: format!("Invalid token: {}", err),
+            })?;
+        Ok(claims)
+    }
+
+    fn validate(&self, claims: &RegisteredClaims) -> Result<(), biscuit::errors::ValidationError> {
+        let temporal = || Validation::Validate(TemporalOptions::default());
+        claims.validate_exp(temporal())?;
+        claims.validate_nbf(temporal())?;
+        claims.validate_iss(Validation::Validate(self.issuer.clone()))?;
+        // The token must be intended for at least one of our audiences.
+        let mut result = Ok(());
+        for audience in self.audience.iter() {
+            result = claims.validate_aud(Validation::Validate(audience.clone()));
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    /// Verify the token's signature, with the key identified in its header.
+    /// Tokens without a key id are verified with the active key.
+    fn verify(&self, token: &str) -> Result<biscuit::ClaimsSet<auth::PrivateClaims>, error::Error> {
+        let header = unverified_header(token)?;
+
+        let keyring = self.keyring()?;
+        let kid = header["kid"].as_str().unwrap_or(&keyring.active);
+        let key = keyring.keys.get(kid).ok_or(error::Error::MiscError {
+            msg: format!("could not decode jwt: unknown key {}", kid),
+        })?;
+
+        let algorithm = match key.signature_algorithm() {
+            Some(algorithm) => algorithm,
+            None => return decode_eddsa(key, token),
+        };
         let token = JWT::<auth::PrivateClaims, biscuit::Empty>::new_encoded(&token);
-        let secret = jws::Secret::bytes_from_str(&self.secret);
+        let secret = key.verifying_secret()?;
         let token = token
-            .into_decoded(&secret, jwa::SignatureAlgorithm::HS256)
+            .into_decoded(&secret, algorithm)
             .context(error::BiscuitError {
                 msg: String::from("could not decode jwt"),
             })?;
@@ -78,3 +392,70 @@ impl Jwt {
         Ok(payload)
     }
 }
+
+/// Parse an issuer or audience. Like biscuit, we take values containing a ':' for URIs,
+/// so that they compare equal to the claims of decoded tokens.
+fn parse_claim(value: &str) -> Result<StringOrUri, error::Error> {
+    StringOrUri::from_str(value).context(error::BiscuitError {
+        msg: format!("Invalid issuer or audience '{}'", value),
+    })
+}
+
+fn decode_segment(segment: &str) -> Result<Vec<u8>, error::Error> {
+    base64::decode_config(segment, base64::URL_SAFE_NO_PAD).map_err(|err| error::Error::MiscError {
+        msg: format!("could not decode jwt: {}", err),
+    })
+}
+
+/// Read the header of a token, before its signature is verified, to find out
+/// which key signed it.
+fn unverified_header(token: &str) -> Result<serde_json::Value, error::Error> {
+    let segment = token.split('.').next().unwrap_or_default();
+    serde_json::from_slice(&decode_segment(segment)?).context(error::JSONError {
+        msg: String::from("could not deserialize jwt header"),
+    })
+}
+
+// biscuit does not support EdDSA, so we build the compact serialization ourselves.
+fn encode_eddsa<T: Serialize>(
+    key: &Key,
+    kid: &str,
+    claims: &ClaimsSet<T>,
+) -> Result<String, error::Error> {
+    let header = json!({ "alg": "EdDSA", "typ": "JWT", "kid": kid });
+    let header = serde_json::to_vec(&header).context(error::JSONError {
+        msg: String::from("could not serialize jwt header"),
+    })?;
+    let payload = serde_json::to_vec(claims).context(error::JSONError {
+        msg: String::from("could not serialize jwt claims"),
+    })?;
+    let input = format!("{}.{}", base64_url(&header), base64_url(&payload));
+    let signature = key.sign_ed25519(input.as_bytes())?;
+    Ok(format!("{}.{}", input, base64_url(&signature)))
+}
+
+fn decode_eddsa(
+    key: &Key,
+    token: &str,
+) -> Result<biscuit::ClaimsSet<auth::PrivateClaims>, error::Error> {
+    let parts = token.split('.').collect::<Vec<_>>();
+    if parts.len() != 3 {
+        return Err(error::Error::MiscError {
+            msg: String::from("could not decode jwt: malformed token"),
+        });
+    }
+    let header: serde_json::Value =
+        serde_json::from_slice(&decode_segment(parts[0])?).context(error::JSONError {
+            msg: String::from("could not deserialize jwt header"),
+        })?;
+    if header["alg"] != "EdDSA" {
+        return Err(error::Error::MiscError {
+            msg: String::from("could not decode jwt: unexpected algorithm"),
+        });
+    }
+    let input = format!("{}.{}", parts[0], parts[1]);
+    key.verify_ed25519(input.as_bytes(), &decode_segment(parts[2])?)?;
+    serde_json::from_slice(&decode_segment(parts[1])?).context(error::JSONError {
+        msg: String::from("could not deserialize jwt claims"),
+    })
+}
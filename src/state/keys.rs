@@ -0,0 +1,391 @@
+use biscuit::jws;
+use ring::signature::{self, KeyPair};
+use serde_json::json;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::error;
+use crate::settings;
+
+/// The algorithms we can sign tokens with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    HS256,
+    RS256,
+    ES256,
+    EdDSA,
+}
+
+impl FromStr for Algorithm {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HS256" => Ok(Algorithm::HS256),
+            "RS256" => Ok(Algorithm::RS256),
+            "ES256" => Ok(Algorithm::ES256),
+            "EdDSA" => Ok(Algorithm::EdDSA),
+            _ => Err(error::Error::KeyError {
+                msg: format!("Unsupported jwt algorithm {}", s),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Algorithm::HS256 => "HS256",
+            Algorithm::RS256 => "RS256",
+            Algorithm::ES256 => "ES256",
+            Algorithm::EdDSA => "EdDSA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The key material. The private part is optional for asymmetric keys, since
+/// a key can be used only to verify tokens. The public part is kept in the
+/// form ring expects for verification: a DER RSAPublicKey for RSA, and the
+/// raw public key for ECDSA and Ed25519.
+#[derive(Clone)]
+enum Material {
+    Hmac(Vec<u8>),
+    Rsa {
+        private: Option<Arc<signature::RsaKeyPair>>,
+        public: Vec<u8>,
+    },
+    Ecdsa {
+        private: Option<Arc<signature::EcdsaKeyPair>>,
+        public: Vec<u8>,
+    },
+    Ed25519 {
+        private: Option<Arc<signature::Ed25519KeyPair>>,
+        public: Vec<u8>,
+    },
+}
+
+/// A key used to sign and / or verify tokens.
+#[derive(Clone)]
+pub struct Key {
+    algorithm: Algorithm,
+    material: Material,
+}
+
+// We don't want key material to end up in logs.
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl Key {
+    /// Load the key described in the jwt settings.
+    /// HS256 uses the shared secret, while the other algorithms use PEM files.
+    /// The public key is optional, it is derived from the private key if missing.
+    pub fn new(settings: &settings::Jwt) -> Result<Self, error::Error> {
+        let algorithm = match &settings.algorithm {
+            Some(algorithm) => Algorithm::from_str(algorithm)?,
+            None => Algorithm::HS256,
+        };
+
+        if algorithm == Algorithm::HS256 {
+            let secret = settings.secret.as_ref().ok_or(error::Error::KeyError {
+                msg: String::from("HS256 requires jwt.secret"),
+            })?;
+            return Ok(Key::hmac(secret));
+        }
+
+        let private = settings.private_key.as_deref().map(read_pem).transpose()?;
+        let public = settings.public_key.as_deref().map(read_pem).transpose()?;
+
+        Key::from_pem(algorithm, private.as_ref(), public.as_ref())
+    }
+
+    pub fn hmac(secret: &str) -> Self {
+        Key {
+            algorithm: Algorithm::HS256,
+            material: Material::Hmac(secret.as_bytes().to_vec()),
+        }
+    }
+
+    /// Build a key from its PEM encoded private and / or public parts.
+    pub fn from_pem(
+        algorithm: Algorithm,
+        private: Option<&pem::Pem>,
+        public: Option<&pem::Pem>,
+    ) -> Result<Self, error::Error> {
+        if private.is_none() && public.is_none() {
+            return Err(error::Error::KeyError {
+                msg: format!("{} requires a private or a public key", algorithm),
+            });
+        }
+
+        let public = public.map(public_key_der).transpose()?;
+
+        let material = match algorithm {
+            Algorithm::HS256 => {
+                return Err(error::Error::KeyError {
+                    msg: String::from("HS256 uses a secret, not a PEM key"),
+                })
+            }
+            Algorithm::RS256 => {
+                let private = private
+                    .map(|pem| {
+                        let key = match pem.tag.as_str() {
+                            "RSA PRIVATE KEY" => signature::RsaKeyPair::from_der(&pem.contents),
+                            _ => signature::RsaKeyPair::from_pkcs8(&pem.contents),
+                        };
+                        key.map(Arc::new).map_err(|err| error::Error::KeyError {
+                            msg: format!("Invalid RSA private key: {}", err),
+                        })
+                    })
+                    .transpose()?;
+                let public = match (public, &private) {
+                    (Some(public), _) => public,
+                    (None, Some(private)) => private.public_key().as_ref().to_vec(),
+                    (None, None) => unreachable!(),
+                };
+                Material::Rsa { private, public }
+            }
+            Algorithm::ES256 => {
+                let private = private
+                    .map(|pem| {
+                        signature::EcdsaKeyPair::from_pkcs8(
+                            &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                            &pem.contents,
+                        )
+                        .map(Arc::new)
+                        .map_err(|err| error::Error::KeyError {
+                            msg: format!("Invalid ECDSA private key: {}", err),
+                        })
+                    })
+                    .transpose()?;
+                let public = match (public, &private) {
+                    (Some(public), _) => public,
+                    (None, Some(private)) => private.public_key().as_ref().to_vec(),
+                    (None, None) => unreachable!(),
+                };
+                Material::Ecdsa { private, public }
+            }
+            Algorithm::EdDSA => {
+                let private = private
+                    .map(|pem| {
+                        signature::Ed25519KeyPair::from_pkcs8_maybe_unchecked(&pem.contents)
+                            .map(Arc::new)
+                            .map_err(|err| error::Error::KeyError {
+                                msg: format!("Invalid Ed25519 private key: {}", err),
+                            })
+                    })
+                    .transpose()?;
+                let public = match (public, &private) {
+                    (Some(public), _) => public,
+                    (None, Some(private)) => private.public_key().as_ref().to_vec(),
+                    (None, None) => unreachable!(),
+                };
+                Material::Ed25519 { private, public }
+            }
+        };
+
+        Ok(Key {
+            algorithm,
+            material,
+        })
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Whether this key can sign tokens.
+    pub fn can_sign(&self) -> bool {
+        match &self.material {
+            Material::Hmac(_) => true,
+            Material::Rsa { private, .. } => private.is_some(),
+            Material::Ecdsa { private, .. } => private.is_some(),
+            Material::Ed25519 { private, .. } => private.is_some(),
+        }
+    }
+
+    /// The biscuit signature algorithm, biscuit does not support EdDSA.
+    pub fn signature_algorithm(&self) -> Option<biscuit::jwa::SignatureAlgorithm> {
+        match self.algorithm {
+            Algorithm::HS256 => Some(biscuit::jwa::SignatureAlgorithm::HS256),
+            Algorithm::RS256 => Some(biscuit::jwa::SignatureAlgorithm::RS256),
+            Algorithm::ES256 => Some(biscuit::jwa::SignatureAlgorithm::ES256),
+            Algorithm::EdDSA => None,
+        }
+    }
+
+    /// The biscuit secret used to sign tokens.
+    pub fn signing_secret(&self) -> Result<jws::Secret, error::Error> {
+        let secret = match &self.material {
+            Material::Hmac(bytes) => Some(jws::Secret::Bytes(bytes.clone())),
+            Material::Rsa { private, .. } => private.clone().map(jws::Secret::RsaKeyPair),
+            Material::Ecdsa { private, .. } => private.clone().map(jws::Secret::EcdsaKeyPair),
+            Material::Ed25519 { .. } => None,
+        };
+        secret.ok_or(error::Error::KeyError {
+            msg: format!("No {} signing key", self.algorithm),
+        })
+    }
+
+    /// The biscuit secret used to verify tokens.
+    pub fn verifying_secret(&self) -> Result<jws::Secret, error::Error> {
+        match &self.material {
+            Material::Hmac(bytes) => Ok(jws::Secret::Bytes(bytes.clone())),
+            Material::Rsa { public, .. } => Ok(jws::Secret::PublicKey(public.clone())),
+            Material::Ecdsa { public, .. } => Ok(jws::Secret::PublicKey(public.clone())),
+            Material::Ed25519 { .. } => Err(error::Error::KeyError {
+                msg: String::from("Ed25519 keys are not supported by biscuit"),
+            }),
+        }
+    }
+
+    /// Sign with an Ed25519 key.
+    pub fn sign_ed25519(&self, message: &[u8]) -> Result<Vec<u8>, error::Error> {
+        match &self.material {
+            Material::Ed25519 {
+                private: Some(private),
+                ..
+            } => Ok(private.sign(message).as_ref().to_vec()),
+            _ => Err(error::Error::KeyError {
+                msg: String::from("No Ed25519 signing key"),
+            }),
+        }
+    }
+
+    /// Verify an Ed25519 signature.
+    pub fn verify_ed25519(&self, message: &[u8], sig: &[u8]) -> Result<(), error::Error> {
+        match &self.material {
+            Material::Ed25519 { public, .. } => {
+                signature::UnparsedPublicKey::new(&signature::ED25519, public)
+                    .verify(message, sig)
+                    .map_err(|_| error::Error::KeyError {
+                        msg: String::from("Invalid Ed25519 signature"),
+                    })
+            }
+            _ => Err(error::Error::KeyError {
+                msg: String::from("Not an Ed25519 key"),
+            }),
+        }
+    }
+
+    /// The public key as a JWK, as published in our JWKS.
+    /// We never publish HMAC secrets.
+    pub fn jwk(&self) -> Option<serde_json::Value> {
+        match &self.material {
+            Material::Hmac(_) => None,
+            Material::Rsa { public, .. } => {
+                let (n, e) = rsa_modulus_exponent(public).ok()?;
+                Some(json!({
+                    "kty": "RSA",
+                    "use": "sig",
+                    "alg": self.algorithm.to_string(),
+                    "n": base64_url(&n),
+                    "e": base64_url(&e),
+                }))
+            }
+            Material::Ecdsa { public, .. } => {
+                // Uncompressed point: 0x04 | x | y
+                if public.len() != 65 {
+                    return None;
+                }
+                Some(json!({
+                    "kty": "EC",
+                    "use": "sig",
+                    "alg": self.algorithm.to_string(),
+                    "crv": "P-256",
+                    "x": base64_url(&public[1..33]),
+                    "y": base64_url(&public[33..65]),
+                }))
+            }
+            Material::Ed25519 { public, .. } => Some(json!({
+                "kty": "OKP",
+                "use": "sig",
+                "alg": self.algorithm.to_string(),
+                "crv": "Ed25519",
+                "x": base64_url(public),
+            })),
+        }
+    }
+}
+
+pub fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Read a PEM file.
+pub fn read_pem(path: &str) -> Result<pem::Pem, error::Error> {
+    let bytes = std::fs::read(path).map_err(|err| error::Error::KeyError {
+        msg: format!("Could not read key file {}: {}", path, err),
+    })?;
+    pem::parse(bytes).map_err(|err| error::Error::KeyError {
+        msg: format!("Invalid PEM in {}: {:?}", path, err),
+    })
+}
+
+/// Extract the public key in the form ring expects from a PEM public key.
+/// 'PUBLIC KEY' is a SubjectPublicKeyInfo, and we keep the content of its bit string.
+/// 'RSA PUBLIC KEY' is already a DER RSAPublicKey.
+fn public_key_der(pem: &pem::Pem) -> Result<Vec<u8>, error::Error> {
+    match pem.tag.as_str() {
+        "RSA PUBLIC KEY" => Ok(pem.contents.clone()),
+        "PUBLIC KEY" => {
+            let (spki, _) = der_element(&pem.contents, 0x30)?;
+            let (_algorithm, rest) = der_element(spki, 0x30)?;
+            let (key, _) = der_element(rest, 0x03)?;
+            // The first byte of a bit string is the number of unused bits.
+            match key.split_first() {
+                Some((0, key)) => Ok(key.to_vec()),
+                _ => Err(error::Error::KeyError {
+                    msg: String::from("Invalid public key bit string"),
+                }),
+            }
+        }
+        tag => Err(error::Error::KeyError {
+            msg: format!("Unexpected PEM tag {}", tag),
+        }),
+    }
+}
+
+/// Extract the modulus and exponent from a DER RSAPublicKey, without leading zeros.
+fn rsa_modulus_exponent(der: &[u8]) -> Result<(Vec<u8>, Vec<u8>), error::Error> {
+    let (key, _) = der_element(der, 0x30)?;
+    let (n, rest) = der_element(key, 0x02)?;
+    let (e, _) = der_element(rest, 0x02)?;
+    let trim = |bytes: &[u8]| {
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+        bytes[start..].to_vec()
+    };
+    Ok((trim(n), trim(e)))
+}
+
+/// Read a DER element with the expected tag, returning its content, and what follows it.
+fn der_element(input: &[u8], tag: u8) -> Result<(&[u8], &[u8]), error::Error> {
+    let invalid = || error::Error::KeyError {
+        msg: String::from("Invalid DER encoding"),
+    };
+    if input.len() < 2 || input[0] != tag {
+        return Err(invalid());
+    }
+    let (length, offset) = if input[1] < 0x80 {
+        (input[1] as usize, 2)
+    } else {
+        let count = (input[1] & 0x7f) as usize;
+        if count == 0 || count > 4 || input.len() < 2 + count {
+            return Err(invalid());
+        }
+        let length = input[2..2 + count]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (length, 2 + count)
+    };
+    if input.len() < offset + length {
+        return Err(invalid());
+    }
+    Ok((&input[offset..offset + length], &input[offset + length..]))
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::settings::Settings;
+
+/// The failed logins of an IP address, within the current window.
+#[derive(Clone, Copy, Debug)]
+struct IpFailures {
+    count: u32,
+    since: DateTime<Utc>,
+}
+
+/// The brute-force protection of logins: accounts are locked out after too many
+/// failed logins, for longer and longer, and IP addresses are throttled.
+#[derive(Clone, Debug)]
+pub struct Lockout {
+    max_failed_logins: i32,
+    duration: Duration,
+    max_duration: Duration,
+    ip_max_failed_logins: u32,
+    ip_window: Duration,
+    // The throttling of IP addresses is kept in memory, per instance.
+    ips: Arc<Mutex<HashMap<IpAddr, IpFailures>>>,
+}
+
+impl Lockout {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            max_failed_logins: settings.security.max_failed_logins,
+            duration: Duration::seconds(settings.security.lockout_duration),
+            max_duration: Duration::seconds(settings.security.max_lockout_duration),
+            ip_max_failed_logins: settings.security.ip_max_failed_logins,
+            ip_window: Duration::seconds(settings.security.ip_window),
+            ips: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Until when an account with that many failed logins is locked out, if it is.
+    /// The lockout doubles with every failed login past the threshold.
+    pub fn locked_until(&self, failed_logins: i32) -> Option<DateTime<Utc>> {
+        if failed_logins < self.max_failed_logins {
+            return None;
+        }
+        // Past 2^20 times the first lockout, we are way beyond any sensible maximum.
+        let exponent = std::cmp::min(failed_logins - self.max_failed_logins, 20) as u32;
+        let duration = std::cmp::min(self.duration * 2i32.pow(exponent), self.max_duration);
+        Some(Utc::now() + duration)
+    }
+
+    /// How long the IP address must wait before trying to login again, if it must.
+    pub fn ip_retry_after(&self, ip: IpAddr) -> Option<Duration> {
+        let ips = self
+            .ips
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let failures = ips.get(&ip)?;
+        let retry_at = failures.since + self.ip_window;
+        let now = Utc::now();
+        if failures.count >= self.ip_max_failed_logins && retry_at > now {
+            Some(retry_at - now)
+        } else {
+            None
+        }
+    }
+
+    /// Count a failed login from the IP address.
+    pub fn record_ip_failure(&self, ip: IpAddr) {
+        let mut ips = self
+            .ips
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Utc::now();
+        let window = self.ip_window;
+        // Forget the windows which are over, so that the map does not grow forever.
+        ips.retain(|_, failures| failures.since + window > now);
+        let failures = ips.entry(ip).or_insert(IpFailures {
+            count: 0,
+            since: now,
+        });
+        failures.count += 1;
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use ring::hmac;
+use uuid::Uuid;
+
+use crate::db::model::EntityId;
+use crate::error;
+use crate::settings::Settings;
+use crate::state::mailer::Message;
+
+/// Magic links, logging users in with a link sent to their email.
+/// The token is the id of the link and its expiry, signed, so that forged and expired
+/// tokens are turned down before we even look them up.
+#[derive(Clone, Debug)]
+pub struct MagicLink {
+    key: hmac::Key,
+    duration: Duration,
+    url: String,
+}
+
+impl MagicLink {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, settings.magic_link.secret.as_bytes()),
+            duration: Duration::minutes(settings.magic_link.duration),
+            url: settings.magic_link.url.to_owned(),
+        }
+    }
+
+    /// The expiry of a magic link issued now.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc::now() + self.duration
+    }
+
+    /// The token of the link: '<id>.<expiry>.<signature>', safe in a URL.
+    pub fn sign(&self, id: EntityId, expires_at: DateTime<Utc>) -> String {
+        let payload = format!("{}.{}", id.to_simple(), expires_at.timestamp());
+        let tag = hmac::sign(&self.key, payload.as_bytes());
+        format!(
+            "{}.{}",
+            payload,
+            base64::encode_config(tag.as_ref(), base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    /// The id of the link, if the token is ours, and has not expired.
+    pub fn verify(&self, token: &str) -> Result<EntityId, error::Error> {
+        let invalid = || error::Error::MiscError {
+            msg: String::from("Invalid or expired magic link"),
+        };
+
+        let mut parts = token.rsplitn(2, '.');
+        let (tag, payload) = match (parts.next(), parts.next()) {
+            (Some(tag), Some(payload)) => (tag, payload),
+            _ => return Err(invalid()),
+        };
+        let tag = base64::decode_config(tag, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+        hmac::verify(&self.key, payload.as_bytes(), &tag).map_err(|_| invalid())?;
+
+        let mut parts = payload.splitn(2, '.');
+        let (id, expires_at) = match (parts.next(), parts.next()) {
+            (Some(id), Some(expires_at)) => (id, expires_at),
+            _ => return Err(invalid()),
+        };
+        let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+        let expires_at = expires_at.parse::<i64>().map_err(|_| invalid())?;
+        if Utc.timestamp(expires_at, 0) <= Utc::now() {
+            return Err(invalid());
+        }
+
+        Ok(id)
+    }
+
+    /// The email logging the user in.
+    pub fn message(&self, email: &str, username: &str, token: &str) -> Message {
+        Message {
+            to: String::from(email),
+            subject: String::from("Your login link"),
+            body: format!(
+                "Hello {},\n\nFollow this link to log in:\n{}{}\n\nor use this login code:\n{}\n\nThe link expires in {} minutes, and only works once. If you did not ask for it, ignore this email.\n",
+                username,
+                self.url,
+                token,
+                token,
+                self.duration.num_minutes()
+            ),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use async_trait::async_trait;
+use lettre::smtp::authentication::Credentials;
+use lettre::smtp::client::net::ClientTlsParameters;
+use lettre::smtp::{ClientSecurity, SmtpClient};
+use lettre::Transport;
+use lettre_email::EmailBuilder;
+use snafu::ResultExt;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::error;
+use crate::settings::{MailTransport, Settings};
+
+/// An email sent to a user.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Something that delivers emails to users.
+#[async_trait]
+pub trait Mailer: Debug + Send + Sync {
+    async fn send(&self, message: Message) -> Result<(), error::Error>;
+}
+
+/// A mailer delivering emails through an SMTP server.
+/// With tls, the connection is upgraded with STARTTLS, which the server must support.
+#[derive(Clone, Debug)]
+pub struct Smtp {
+    host: String,
+    port: u16,
+    tls: bool,
+    credentials: Option<(String, String)>,
+    from: String,
+}
+
+impl Smtp {
+    pub fn new(settings: &Settings) -> Self {
+        let mailer = &settings.mailer;
+        let credentials = match (&mailer.username, &mailer.password) {
+            (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+            _ => None,
+        };
+        Self {
+            host: mailer.host.to_owned(),
+            port: mailer.port,
+            tls: mailer.tls,
+            credentials,
+            from: mailer.from.to_owned(),
+        }
+    }
+
+    fn client(&self) -> Result<SmtpClient, error::Error> {
+        let security = if self.tls {
+            let connector =
+                native_tls::TlsConnector::new().map_err(|err| error::Error::MailError {
+                    msg: format!("Could not create TLS connector: {}", err),
+                })?;
+            ClientSecurity::Required(ClientTlsParameters::new(self.host.clone(), connector))
+        } else {
+            ClientSecurity::None
+        };
+
+        let client = SmtpClient::new((self.host.as_str(), self.port), security).map_err(|err| {
+            error::Error::MailError {
+                msg: format!("Could not create SMTP client: {}", err),
+            }
+        })?;
+
+        Ok(match &self.credentials {
+            Some((username, password)) => {
+                client.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            None => client,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for Smtp {
+    async fn send(&self, message: Message) -> Result<(), error::Error> {
+        let email = EmailBuilder::new()
+            .to(message.to.as_str())
+            .from(self.from.as_str())
+            .subject(message.subject)
+            .text(message.body)
+            .build()
+            .map_err(|err| error::Error::MailError {
+                msg: format!("Could not build email: {}", err),
+            })?;
+
+        let client = self.client()?;
+
+        // The SMTP transport is blocking, so keep it off the runtime's threads.
+        tokio::task::spawn_blocking(move || {
+            let mut transport = client.transport();
+            let res = transport.send(email.into());
+            transport.close();
+            res
+        })
+        .await
+        .map_err(|err| error::Error::MailError {
+            msg: format!("Could not send email: {}", err),
+        })?
+        .map(|_| ())
+        .map_err(|err| error::Error::MailError {
+            msg: format!("Could not send email: {}", err),
+        })
+    }
+}
+
+/// A mailer writing emails to a directory, in a file named after the recipient, which
+/// holds its last email. It is meant for tests, which read the emails back.
+#[derive(Clone, Debug)]
+pub struct File {
+    path: PathBuf,
+    from: String,
+}
+
+impl File {
+    pub fn new(path: PathBuf, settings: &Settings) -> Self {
+        Self {
+            path,
+            from: settings.mailer.from.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for File {
+    async fn send(&self, message: Message) -> Result<(), error::Error> {
+        let email = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}",
+            self.from, message.to, message.subject, message.body
+        );
+        let dir = self.path.clone();
+        let path = dir.join(&message.to);
+
+        // File IO is blocking, so keep it off the runtime's threads.
+        tokio::task::spawn_blocking(move || {
+            // Readers must never see half an email.
+            let tmp = path.with_extension("tmp");
+            std::fs::create_dir_all(&dir)
+                .and_then(|_| std::fs::write(&tmp, email))
+                .and_then(|_| std::fs::rename(&tmp, &path))
+        })
+        .await
+        .map_err(|err| error::Error::MailError {
+            msg: format!("Could not write email: {}", err),
+        })?
+        .context(error::IOError {
+            msg: String::from("Could not write email"),
+        })
+    }
+}
+
+/// The mailer given in the settings.
+pub fn new(settings: &Settings) -> Result<Arc<dyn Mailer>, error::Error> {
+    match settings.mailer.transport {
+        MailTransport::Smtp => Ok(Arc::new(Smtp::new(settings))),
+        MailTransport::File => {
+            let path = settings
+                .mailer
+                .path
+                .as_ref()
+                .ok_or(error::Error::MiscError {
+                    msg: String::from("The file mail transport requires a path"),
+                })?;
+            Ok(Arc::new(File::new(PathBuf::from(path), settings)))
+        }
+    }
+}
@@ -1,3 +1,15 @@
 pub mod argon;
+pub mod audit;
 pub mod jwt;
+pub mod keys;
+pub mod lockout;
+pub mod magic_link;
+pub mod mailer;
+pub mod oidc;
+pub mod password_reset;
+pub mod rate_limit;
+pub mod session;
 pub mod state;
+pub mod totp;
+pub mod verification;
+pub mod webauthn;
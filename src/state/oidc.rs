@@ -0,0 +1,181 @@
+use chrono::{DateTime, Duration, Utc};
+use ring::{constant_time, digest};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use warp::http::Uri;
+
+use crate::db::model::UserEntity;
+use crate::error;
+use crate::settings::Settings;
+use crate::state::jwt::Jwt;
+use crate::state::keys::base64_url;
+
+/// The scopes we know. The openid scope is required, profile and email add the claims
+/// of the same name to the ID token and to the user info.
+pub const SCOPES: [&str; 3] = ["openid", "profile", "email"];
+
+// The length of a PKCE code verifier, and of its S256 challenge (RFC 7636).
+const MIN_VERIFIER_LENGTH: usize = 43;
+const MAX_VERIFIER_LENGTH: usize = 128;
+
+/// The claims about the user, as allowed by the scope.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl Profile {
+    pub fn new(user: &UserEntity, scope: &str) -> Self {
+        Profile {
+            preferred_username: Some(user.username.clone()).filter(|_| has_scope(scope, "profile")),
+            email: Some(user.email.clone()).filter(|_| has_scope(scope, "email")),
+        }
+    }
+}
+
+/// The private claims of an ID token. The nonce is the one the client sent with the
+/// authorization request, so that it can tell its own ID tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// When the user logged in, in seconds since the epoch
+    pub auth_time: i64,
+    #[serde(flatten)]
+    pub profile: Profile,
+}
+
+/// The response of the user info endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub sub: String,
+    #[serde(flatten)]
+    pub profile: Profile,
+}
+
+/// The OpenID Connect provider (https://openid.net/specs/openid-connect-core-1_0.html).
+/// Its issuer is the issuer of our tokens, and its endpoints are served under it.
+#[derive(Clone, Debug)]
+pub struct Oidc {
+    issuer: String,
+    code_duration: Duration,
+}
+
+impl Oidc {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            issuer: settings.jwt.issuer.trim_end_matches('/').to_owned(),
+            code_duration: Duration::minutes(settings.oidc.code_duration),
+        }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The expiry of an authorization code issued now.
+    pub fn code_expires_at(&self) -> DateTime<Utc> {
+        Utc::now() + self.code_duration
+    }
+
+    /// The discovery document, served at /.well-known/openid-configuration.
+    pub fn configuration(&self, jwt: &Jwt) -> serde_json::Value {
+        let algorithms = jwt
+            .algorithm()
+            .map(|algorithm| vec![algorithm.to_string()])
+            .unwrap_or_default();
+        json!({
+            "issuer": self.issuer,
+            "authorization_endpoint": format!("{}/authorize", self.issuer),
+            "token_endpoint": format!("{}/token", self.issuer),
+            "userinfo_endpoint": format!("{}/userinfo", self.issuer),
+            "jwks_uri": format!("{}/.well-known/jwks.json", self.issuer),
+            "scopes_supported": SCOPES,
+            "response_types_supported": ["code"],
+            "response_modes_supported": ["query"],
+            "grant_types_supported": ["authorization_code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": algorithms,
+            "token_endpoint_auth_methods_supported": ["none"],
+            "code_challenge_methods_supported": ["S256"],
+            "claims_supported": [
+                "iss", "sub", "aud", "exp", "iat", "auth_time", "nonce",
+                "preferred_username", "email"
+            ],
+        })
+    }
+}
+
+/// The scopes we know among the requested ones, without duplicates, unless the openid
+/// scope is missing. The others are ignored.
+pub fn scope(requested: &str) -> Option<String> {
+    let mut scopes = Vec::new();
+    for scope in requested.split(' ') {
+        if SCOPES.contains(&scope) && !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.contains(&"openid") {
+        Some(scopes.join(" "))
+    } else {
+        None
+    }
+}
+
+pub fn has_scope(scope: &str, name: &str) -> bool {
+    scope.split(' ').any(|scope| scope == name)
+}
+
+/// Check a redirect URI before it is registered. It must be absolute, without fragment,
+/// and use https, unless it is on the loopback interface.
+/// The redirect URIs of an authorization request are then compared exactly with the
+/// registered ones.
+pub fn validate_redirect_uri(uri: &str) -> Result<(), error::Error> {
+    let invalid = |reason: &str| error::Error::MiscError {
+        msg: format!("Invalid redirect URI '{}': {}", uri, reason),
+    };
+
+    if uri.contains('#') {
+        return Err(invalid("it has a fragment"));
+    }
+    let parsed = uri
+        .parse::<Uri>()
+        .map_err(|err| invalid(&err.to_string()))?;
+    let host = parsed.host().ok_or_else(|| invalid("it is not absolute"))?;
+    match parsed.scheme_str() {
+        Some("https") => Ok(()),
+        Some("http") if ["localhost", "127.0.0.1", "[::1]"].contains(&host) => Ok(()),
+        _ => Err(invalid("it must use https")),
+    }
+}
+
+/// Whether the code challenge could be the S256 challenge of a code verifier.
+pub fn is_code_challenge(challenge: &str) -> bool {
+    challenge.len() == MIN_VERIFIER_LENGTH
+        && challenge
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Whether the code verifier is the one the S256 challenge was derived from.
+pub fn verify_code_verifier(verifier: &str, challenge: &str) -> bool {
+    let is_verifier = (MIN_VERIFIER_LENGTH..=MAX_VERIFIER_LENGTH).contains(&verifier.len())
+        && verifier
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b));
+    if !is_verifier {
+        return false;
+    }
+    let expected = base64_url(digest::digest(&digest::SHA256, verifier.as_bytes()).as_ref());
+    constant_time::verify_slices_are_equal(expected.as_bytes(), challenge.as_bytes()).is_ok()
+}
+
+/// The redirect URI, with the parameters added to its query.
+pub fn redirect_uri(uri: &str, params: &[(&str, &str)]) -> String {
+    let query = serde_urlencoded::to_string(params).unwrap_or_default();
+    let separator = if uri.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", uri, separator, query)
+}
@@ -0,0 +1,40 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::settings::Settings;
+use crate::state::mailer::Message;
+
+#[derive(Clone, Debug)]
+pub struct PasswordReset {
+    duration: Duration,
+    url: String,
+}
+
+impl PasswordReset {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            duration: Duration::minutes(settings.password_reset.duration),
+            url: settings.password_reset.url.to_owned(),
+        }
+    }
+
+    /// The expiry of a password reset token issued now.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc::now() + self.duration
+    }
+
+    /// The email letting the user choose a new password.
+    pub fn message(&self, email: &str, username: &str, token: &str) -> Message {
+        Message {
+            to: String::from(email),
+            subject: String::from("Reset your password"),
+            body: format!(
+                "Hello {},\n\nSomeone asked to reset your password. If it was you, follow this link:\n{}{}\n\nor use this reset code:\n{}\n\nThe link expires in {} minutes. If you did not ask for it, ignore this email.\n",
+                username,
+                self.url,
+                token,
+                token,
+                self.duration.num_minutes()
+            ),
+        }
+    }
+}
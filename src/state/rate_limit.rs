@@ -0,0 +1,257 @@
+use graphql_parser::query::{
+    parse_query, Definition, OperationDefinition, Selection, SelectionSet,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::settings::{Limit, Settings};
+
+// Past that many buckets, the least recently used are dropped, down to KEPT_BUCKETS, so
+// that the next eviction is as many new clients away, and a flood of clients cannot make
+// us scan the buckets on every request.
+const MAX_BUCKETS: usize = 10_000;
+const KEPT_BUCKETS: usize = 9_000;
+
+/// A rejection for clients going over their rate limit.
+/// They can retry after that many seconds.
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_after: u64,
+}
+
+impl warp::reject::Reject for RateLimited {}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Limit {
+    fn scaled(&self, factor: u32) -> Self {
+        Limit {
+            capacity: self.capacity.saturating_mul(factor),
+            per_minute: self.per_minute.saturating_mul(factor),
+        }
+    }
+}
+
+impl Bucket {
+    fn full(limit: &Limit, now: Instant) -> Self {
+        Bucket {
+            tokens: f64::from(limit.capacity),
+            updated_at: now,
+        }
+    }
+
+    /// The tokens the bucket would hold now.
+    fn refill(&self, limit: &Limit, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        let tokens = self.tokens + elapsed * f64::from(limit.per_minute) / 60.0;
+        tokens.min(f64::from(limit.capacity))
+    }
+}
+
+/// Token buckets, by operation and client, kept in memory, per instance.
+/// The operations are the root fields of the GraphQL documents, and the endpoints outside
+/// of GraphQL, which are named after them.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    shared_factor: u32,
+    default: Limit,
+    operations: HashMap<String, Limit>,
+    buckets: Arc<Mutex<HashMap<(String, String), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            shared_factor: settings.rate_limit.shared_ip_factor,
+            default: settings.rate_limit.default,
+            operations: settings
+                .rate_limit
+                .operations
+                .iter()
+                .map(|(operation, limit)| (operation.to_lowercase(), *limit))
+                .collect(),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The limit of the operation. Unknown operations have the default limit.
+    fn limit(&self, operation: Option<&str>) -> (String, Limit) {
+        let operation = operation.map(str::to_lowercase).unwrap_or_default();
+        match self.operations.get(&operation) {
+            Some(limit) => (operation, *limit),
+            // Operations sharing the default limit share their bucket, or a client
+            // could go around the limit with as many operation names.
+            None => (String::new(), self.default),
+        }
+    }
+
+    /// Take a token from the client's bucket for the operation, or tell how long the
+    /// client must wait until there is one. A shared client, the IP address of
+    /// authenticated requests, gets the limit times the shared IP factor.
+    pub fn check(
+        &self,
+        operation: Option<&str>,
+        client: &str,
+        shared: bool,
+    ) -> Result<(), Duration> {
+        let (operation, limit) = self.limit(operation);
+        let limit = if shared {
+            limit.scaled(self.shared_factor)
+        } else {
+            limit
+        };
+        let now = Instant::now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let key = (operation, String::from(client));
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+            evict(&mut buckets, KEPT_BUCKETS);
+        }
+
+        let bucket = buckets
+            .entry(key)
+            .or_insert_with(|| Bucket::full(&limit, now));
+
+        bucket.tokens = bucket.refill(&limit, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if limit.per_minute == 0 {
+            // The bucket is never refilled, a minute is as good as any delay.
+            Err(Duration::from_secs(60))
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(Duration::from_secs_f64(
+                missing * 60.0 / f64::from(limit.per_minute),
+            ))
+        }
+    }
+}
+
+/// Drop the least recently used buckets, until there are no more than kept buckets.
+/// The buckets used at the same time as the last dropped one are dropped too.
+fn evict(buckets: &mut HashMap<(String, String), Bucket>, kept: usize) {
+    if buckets.len() <= kept {
+        return;
+    }
+
+    let mut used_at = buckets
+        .values()
+        .map(|bucket| bucket.updated_at)
+        .collect::<Vec<_>>();
+    used_at.sort_unstable();
+    let last_dropped = used_at[used_at.len() - kept - 1];
+
+    buckets.retain(|_, bucket| bucket.updated_at > last_dropped);
+}
+
+/// The root fields selected by a GraphQL request, or by a batch of requests, eg loginUser
+/// in 'mutation login($credentials: ...) { loginUser(...) { ... } }'. Each field costs a
+/// token, so that aliases and batches cannot go around the limits. Fields are known by
+/// their name rather than their alias, and fragments are followed.
+/// A request we cannot parse counts as a single field, with the default limit.
+pub fn root_fields(body: &serde_json::Value) -> Vec<Option<String>> {
+    let requests = match body {
+        serde_json::Value::Array(requests) => requests.iter().collect(),
+        request => vec![request],
+    };
+
+    requests
+        .into_iter()
+        .flat_map(|request| {
+            let fields = request["query"]
+                .as_str()
+                .and_then(|query| query_root_fields(query, request["operationName"].as_str()));
+            match fields {
+                Some(fields) if !fields.is_empty() => fields.into_iter().map(Some).collect(),
+                _ => vec![None],
+            }
+        })
+        .collect()
+}
+
+/// The root fields of the operation of the document to execute: the operation with that
+/// name, or else the first one.
+fn query_root_fields(query: &str, operation_name: Option<&str>) -> Option<Vec<String>> {
+    let document = parse_query::<String>(query).ok()?;
+
+    let fragments = document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::Fragment(fragment) => {
+                Some((fragment.name.clone(), &fragment.selection_set))
+            }
+            Definition::Operation(_) => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut operations = document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::Operation(operation) => Some(operation),
+            Definition::Fragment(_) => None,
+        });
+    let operation = match operation_name {
+        Some(name) => operations.find(|operation| name_of(operation) == Some(name))?,
+        None => operations.next()?,
+    };
+    let selection_set = match operation {
+        OperationDefinition::SelectionSet(selection_set) => selection_set,
+        OperationDefinition::Query(query) => &query.selection_set,
+        OperationDefinition::Mutation(mutation) => &mutation.selection_set,
+        OperationDefinition::Subscription(subscription) => &subscription.selection_set,
+    };
+
+    let mut fields = Vec::new();
+    collect_fields(selection_set, &fragments, &mut Vec::new(), &mut fields);
+    Some(fields)
+}
+
+fn name_of<'a>(operation: &'a OperationDefinition<'_, String>) -> Option<&'a str> {
+    match operation {
+        OperationDefinition::SelectionSet(_) => None,
+        OperationDefinition::Query(query) => query.name.as_deref(),
+        OperationDefinition::Mutation(mutation) => mutation.name.as_deref(),
+        OperationDefinition::Subscription(subscription) => subscription.name.as_deref(),
+    }
+}
+
+/// Collect the names of the fields of the selection set, and of its fragments. A fragment
+/// spread within itself is only followed once: the document is invalid anyway.
+fn collect_fields<'q>(
+    selection_set: &SelectionSet<'q, String>,
+    fragments: &HashMap<String, &SelectionSet<'q, String>>,
+    spread: &mut Vec<String>,
+    fields: &mut Vec<String>,
+) {
+    for selection in &selection_set.items {
+        match selection {
+            Selection::Field(field) => fields.push(field.name.clone()),
+            Selection::InlineFragment(fragment) => {
+                collect_fields(&fragment.selection_set, fragments, spread, fields)
+            }
+            Selection::FragmentSpread(fragment) => {
+                if spread.contains(&fragment.fragment_name) {
+                    continue;
+                }
+                if let Some(selection_set) = fragments.get(&fragment.fragment_name) {
+                    spread.push(fragment.fragment_name.clone());
+                    collect_fields(selection_set, fragments, spread, fields);
+                    spread.pop();
+                }
+            }
+        }
+    }
+}
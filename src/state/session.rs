@@ -0,0 +1,52 @@
+use chrono::Duration;
+
+use crate::settings::Settings;
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    duration: Duration,
+    secure: bool,
+}
+
+impl Session {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            duration: Duration::minutes(settings.session.duration),
+            secure: settings.session.secure,
+        }
+    }
+
+    /// The longest lifetime of a session.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The lifetime of a new session. The client may ask for a shorter
+    /// lifetime (in seconds), but not for a longer one.
+    pub fn lifetime(&self, requested: Option<i64>) -> Duration {
+        match requested {
+            Some(seconds) if seconds > 0 => {
+                std::cmp::min(Duration::seconds(seconds), self.duration)
+            }
+            _ => self.duration,
+        }
+    }
+
+    /// The Set-Cookie header value carrying the session's jwt.
+    pub fn cookie(&self, jwt: &str, lifetime: Duration) -> String {
+        let mut cookie = format!(
+            "jwt={}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}",
+            jwt,
+            lifetime.num_seconds()
+        );
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    /// The Set-Cookie header value removing the session's cookie.
+    pub fn clear_cookie(&self) -> String {
+        String::from("jwt=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0")
+    }
+}
@@ -1,12 +1,27 @@
 use super::argon;
+use super::audit::{self, AuditSink};
 use super::jwt;
+use super::lockout::Lockout;
+use super::magic_link::MagicLink;
+use super::mailer::{self, Mailer};
+use super::oidc::Oidc;
+use super::password_reset;
+use super::rate_limit::RateLimiter;
+use super::session;
+use super::totp::Totp;
+use super::verification;
+use super::webauthn::Webauthn;
 use crate::error;
 use crate::settings::Settings;
 use argon::Argon;
 use jwt::Jwt;
+use password_reset::PasswordReset;
+use session::Session;
 use slog::{o, Logger};
 use snafu::ResultExt;
 use sqlx::postgres::PgPool;
+use std::sync::Arc;
+use verification::Verification;
 
 // FIXME Move this struct and its implementation to mod.rs
 
@@ -16,6 +31,17 @@ pub struct State {
     pub logger: Logger,
     pub argon: Argon,
     pub jwt: Jwt,
+    pub session: Session,
+    pub mailer: Arc<dyn Mailer>,
+    pub verification: Verification,
+    pub password_reset: PasswordReset,
+    pub magic_link: MagicLink,
+    pub audit: Arc<dyn AuditSink>,
+    pub lockout: Lockout,
+    pub rate_limit: RateLimiter,
+    pub totp: Totp,
+    pub webauthn: Webauthn,
+    pub oidc: Oidc,
 }
 
 impl State {
@@ -29,7 +55,18 @@ impl State {
             })?;
         // FIXME ping the pool to know quickly if we have a db connection
         let argon = Argon::new(&settings);
-        let jwt = Jwt::new(&settings);
+        let jwt = Jwt::new(&settings)?;
+        let session = Session::new(&settings);
+        let mailer = mailer::new(&settings)?;
+        let verification = Verification::new(&settings);
+        let password_reset = PasswordReset::new(&settings);
+        let magic_link = MagicLink::new(&settings);
+        let audit = audit::new(&settings, &pool)?;
+        let lockout = Lockout::new(&settings);
+        let rate_limit = RateLimiter::new(&settings);
+        let totp = Totp::new(&settings);
+        let webauthn = Webauthn::new(&settings);
+        let oidc = Oidc::new(&settings);
         let logger = logger.new(
             o!("host" => String::from(&settings.service.host), "port" => settings.service.port, "database" => String::from(&settings.database.url)),
         );
@@ -39,6 +76,17 @@ impl State {
             logger,
             argon,
             jwt,
+            session,
+            mailer,
+            verification,
+            password_reset,
+            magic_link,
+            audit,
+            lockout,
+            rate_limit,
+            totp,
+            webauthn,
+            oidc,
         })
     }
 }
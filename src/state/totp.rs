@@ -0,0 +1,151 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use ring::{constant_time, hmac};
+
+use crate::settings::Settings;
+
+// The parameters every authenticator app supports: SHA1, 6 digits, every 30 seconds.
+const PERIOD: i64 = 30;
+const DIGITS: usize = 6;
+const SECRET_LENGTH: usize = 20;
+
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Time-based one-time passwords (RFC 6238), the second factor of logins.
+#[derive(Clone, Debug)]
+pub struct Totp {
+    issuer: String,
+    challenge_duration: Duration,
+    recovery_codes: usize,
+}
+
+impl Totp {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            issuer: settings.mfa.issuer.to_owned(),
+            challenge_duration: Duration::minutes(settings.mfa.challenge_duration),
+            recovery_codes: settings.mfa.recovery_codes,
+        }
+    }
+
+    /// A new random secret, in base32, as authenticator apps expect it.
+    pub fn generate_secret(&self) -> String {
+        let mut secret = [0u8; SECRET_LENGTH];
+        rand::thread_rng().fill_bytes(&mut secret);
+        base32_encode(&secret)
+    }
+
+    /// The otpauth URI of the secret, usually shown as a QR code.
+    pub fn uri(&self, secret: &str, username: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+            issuer = percent_encode(&self.issuer),
+            username = percent_encode(username),
+            secret = secret,
+            digits = DIGITS,
+            period = PERIOD
+        )
+    }
+
+    /// The time step of the code, if it is valid now, and more recent than the last
+    /// step used. A step before and after the current one are allowed, for the clocks
+    /// which drift.
+    pub fn verify(&self, secret: &str, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+        let current = step(Utc::now());
+        (current - 1..=current + 1)
+            .filter(|step| last_used_step.map_or(true, |last| *step > last))
+            .find(|step| match self::code(secret, *step) {
+                Some(expected) => {
+                    constant_time::verify_slices_are_equal(expected.as_bytes(), code.as_bytes())
+                        .is_ok()
+                }
+                None => false,
+            })
+    }
+
+    /// The expiry of a challenge issued now.
+    pub fn challenge_expires_at(&self) -> DateTime<Utc> {
+        Utc::now() + self.challenge_duration
+    }
+
+    /// The number of recovery codes to issue.
+    pub fn recovery_codes(&self) -> usize {
+        self.recovery_codes
+    }
+}
+
+/// The time step at the given time.
+pub fn step(time: DateTime<Utc>) -> i64 {
+    time.timestamp() / PERIOD
+}
+
+/// The code of the secret at the given time step, unless the secret is not valid base32.
+pub fn code(secret: &str, step: i64) -> Option<String> {
+    let secret = base32_decode(secret)?;
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &secret);
+    let tag = hmac::sign(&key, &(step as u64).to_be_bytes());
+    let hash = tag.as_ref();
+
+    // Dynamic truncation (RFC 4226, section 5.3)
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = ((u32::from(hash[offset]) & 0x7f) << 24)
+        | (u32::from(hash[offset + 1]) << 16)
+        | (u32::from(hash[offset + 2]) << 8)
+        | u32::from(hash[offset + 3]);
+
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS as u32),
+        width = DIGITS
+    ))
+}
+
+/// Base32 (RFC 4648), without padding.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+/// Base32 (RFC 4648), ignoring case, spaces and padding.
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Percent encode what is not unreserved (RFC 3986), for the labels of otpauth URIs.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
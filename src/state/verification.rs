@@ -0,0 +1,40 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::settings::Settings;
+use crate::state::mailer::Message;
+
+#[derive(Clone, Debug)]
+pub struct Verification {
+    duration: Duration,
+    url: String,
+}
+
+impl Verification {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            duration: Duration::minutes(settings.verification.duration),
+            url: settings.verification.url.to_owned(),
+        }
+    }
+
+    /// The expiry of a verification token issued now.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc::now() + self.duration
+    }
+
+    /// The email asking the user to verify its email address.
+    pub fn message(&self, email: &str, username: &str, token: &str) -> Message {
+        Message {
+            to: String::from(email),
+            subject: String::from("Please verify your email address"),
+            body: format!(
+                "Hello {},\n\nPlease verify your email address by following this link:\n{}{}\n\nor by using this verification code:\n{}\n\nThe link expires in {} hours.\n",
+                username,
+                self.url,
+                token,
+                token,
+                self.duration.num_hours()
+            ),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use ring::{constant_time, digest, signature};
+use serde::Deserialize;
+use serde_cbor::Value;
+
+use crate::error;
+use crate::settings::Settings;
+
+/// The COSE algorithms (RFC 8152) we accept: ECDSA with P-256 and SHA-256, which every
+/// authenticator supports, and Ed25519.
+pub const ES256: i32 = -7;
+pub const EDDSA: i32 = -8;
+
+const CHALLENGE_LENGTH: usize = 32;
+// The longest credential id the specification allows.
+const MAX_CREDENTIAL_ID_LENGTH: usize = 1023;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// The two ceremonies of WebAuthn. They are stored as strings in
+/// main.webauthn_challenges, and match the type of the client data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ceremony {
+    Registration,
+    Authentication,
+}
+
+impl Ceremony {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Ceremony::Registration => "webauthn.create",
+            Ceremony::Authentication => "webauthn.get",
+        }
+    }
+}
+
+/// A passkey, as registered by an authenticator.
+#[derive(Debug, Clone)]
+pub struct Credential {
+    pub id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub algorithm: i32,
+    pub sign_count: u32,
+}
+
+/// The part of the client data we check. The browser builds it, and the authenticator
+/// signs its hash.
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony: String,
+    challenge: String,
+    origin: String,
+}
+
+/// The authenticator data, with the credential when it is registered.
+#[derive(Debug)]
+struct AuthenticatorData {
+    flags: u8,
+    sign_count: u32,
+    credential: Option<(Vec<u8>, Value)>,
+}
+
+/// The relying party of WebAuthn (https://www.w3.org/TR/webauthn-2/): the registration
+/// and authentication of passkeys.
+#[derive(Clone, Debug)]
+pub struct Webauthn {
+    rp_id: String,
+    rp_name: String,
+    origin: String,
+    challenge_duration: Duration,
+}
+
+impl Webauthn {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            rp_id: settings.webauthn.rp_id.to_owned(),
+            rp_name: settings.webauthn.rp_name.to_owned(),
+            origin: settings.webauthn.origin.to_owned(),
+            challenge_duration: Duration::minutes(settings.webauthn.challenge_duration),
+        }
+    }
+
+    pub fn rp_id(&self) -> &str {
+        &self.rp_id
+    }
+
+    pub fn rp_name(&self) -> &str {
+        &self.rp_name
+    }
+
+    /// A new random challenge, in base64url, as it comes back in the client data.
+    pub fn generate_challenge(&self) -> String {
+        let mut challenge = [0u8; CHALLENGE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut challenge);
+        base64::encode_config(&challenge, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// The expiry of a challenge issued now.
+    pub fn challenge_expires_at(&self) -> DateTime<Utc> {
+        Utc::now() + self.challenge_duration
+    }
+
+    /// The challenge of the client data, once we know it is for the ceremony, and that
+    /// it comes from our origin.
+    pub fn challenge(
+        &self,
+        client_data_json: &[u8],
+        ceremony: Ceremony,
+    ) -> Result<String, error::Error> {
+        let client_data: ClientData =
+            serde_json::from_slice(client_data_json).map_err(|err| invalid(&err.to_string()))?;
+
+        if client_data.ceremony != ceremony.as_str() {
+            return Err(invalid("The client data is for another ceremony"));
+        }
+        if client_data.origin != self.origin {
+            return Err(invalid("The client data is from another origin"));
+        }
+
+        Ok(client_data.challenge)
+    }
+
+    /// Check the attestation object of a registration, and return the new credential.
+    /// The attestation statement is not verified: we ask for none, and accept any
+    /// authenticator.
+    pub fn verify_registration(
+        &self,
+        attestation_object: &[u8],
+    ) -> Result<Credential, error::Error> {
+        let attestation: Value =
+            serde_cbor::from_slice(attestation_object).map_err(|err| invalid(&err.to_string()))?;
+
+        let auth_data = match map_get(&attestation, Value::Text(String::from("authData"))) {
+            Some(Value::Bytes(auth_data)) => auth_data,
+            _ => return Err(invalid("The attestation has no authenticator data")),
+        };
+
+        let auth_data = self.authenticator_data(auth_data)?;
+
+        let (id, key) = auth_data
+            .credential
+            .ok_or_else(|| invalid("The authenticator data has no credential"))?;
+        let (algorithm, public_key) = public_key(&key)?;
+
+        Ok(Credential {
+            id,
+            public_key,
+            algorithm,
+            sign_count: auth_data.sign_count,
+        })
+    }
+
+    /// Check the signature of an authentication with the passkey, and return the new
+    /// value of its counter. The user must have been verified, eg with a PIN or
+    /// biometrics, since the passkey replaces the password.
+    pub fn verify_authentication(
+        &self,
+        client_data_json: &[u8],
+        authenticator_data: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+        algorithm: i32,
+    ) -> Result<u32, error::Error> {
+        let auth_data = self.authenticator_data(authenticator_data)?;
+
+        if auth_data.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(invalid("The user was not verified"));
+        }
+
+        // The authenticator signs its data followed by the hash of the client data.
+        let mut message = authenticator_data.to_vec();
+        message.extend_from_slice(digest::digest(&digest::SHA256, client_data_json).as_ref());
+
+        let verification: &'static dyn signature::VerificationAlgorithm = match algorithm {
+            ES256 => &signature::ECDSA_P256_SHA256_ASN1,
+            EDDSA => &signature::ED25519,
+            _ => return Err(invalid("Unsupported algorithm")),
+        };
+
+        signature::UnparsedPublicKey::new(verification, public_key)
+            .verify(&message, signature)
+            .map_err(|_| invalid("Invalid signature"))?;
+
+        Ok(auth_data.sign_count)
+    }
+
+    /// Parse the authenticator data, and check it is for us, with the user present.
+    fn authenticator_data(&self, data: &[u8]) -> Result<AuthenticatorData, error::Error> {
+        if data.len() < 37 {
+            return Err(invalid("The authenticator data is too short"));
+        }
+
+        let rp_id_hash = &data[0..32];
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+        let expected = digest::digest(&digest::SHA256, self.rp_id.as_bytes());
+        if constant_time::verify_slices_are_equal(expected.as_ref(), rp_id_hash).is_err() {
+            return Err(invalid(
+                "The authenticator data is for another relying party",
+            ));
+        }
+        if flags & FLAG_USER_PRESENT == 0 {
+            return Err(invalid("The user was not present"));
+        }
+
+        let credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+            Some(attested_credential(&data[37..])?)
+        } else {
+            None
+        };
+
+        Ok(AuthenticatorData {
+            flags,
+            sign_count,
+            credential,
+        })
+    }
+}
+
+/// The id and the COSE key of the credential: after the AAGUID (16 bytes), the length of
+/// the id (2 bytes), the id, and the key, possibly followed by extensions.
+fn attested_credential(data: &[u8]) -> Result<(Vec<u8>, Value), error::Error> {
+    if data.len() < 18 {
+        return Err(invalid("The attested credential data is too short"));
+    }
+    let length = usize::from(u16::from_be_bytes([data[16], data[17]]));
+    if length > MAX_CREDENTIAL_ID_LENGTH || data.len() < 18 + length {
+        return Err(invalid("Invalid credential id"));
+    }
+    let id = data[18..18 + length].to_vec();
+
+    let mut deserializer = serde_cbor::Deserializer::from_slice(&data[18 + length..]);
+    let key = Value::deserialize(&mut deserializer).map_err(|err| invalid(&err.to_string()))?;
+
+    Ok((id, key))
+}
+
+/// The algorithm and the raw public key of a COSE key: the uncompressed point of an EC2
+/// key, or the bytes of an OKP key.
+fn public_key(key: &Value) -> Result<(i32, Vec<u8>), error::Error> {
+    let int = |label: i128| match map_get(key, Value::Integer(label)) {
+        Some(Value::Integer(value)) => Some(*value),
+        _ => None,
+    };
+    let bytes = |label: i128| match map_get(key, Value::Integer(label)) {
+        Some(Value::Bytes(value)) => Some(value.clone()),
+        _ => None,
+    };
+
+    // The labels: 1 is the key type, 3 the algorithm, -1 the curve, -2 and -3 the
+    // coordinates.
+    match (int(1), int(3), int(-1)) {
+        (Some(2), Some(alg), Some(1)) if alg == i128::from(ES256) => match (bytes(-2), bytes(-3)) {
+            (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => {
+                let mut point = vec![0x04];
+                point.extend_from_slice(&x);
+                point.extend_from_slice(&y);
+                Ok((ES256, point))
+            }
+            _ => Err(invalid("Invalid P-256 key")),
+        },
+        (Some(1), Some(alg), Some(6)) if alg == i128::from(EDDSA) => match bytes(-2) {
+            Some(x) if x.len() == 32 => Ok((EDDSA, x)),
+            _ => Err(invalid("Invalid Ed25519 key")),
+        },
+        _ => Err(invalid("Unsupported key")),
+    }
+}
+
+fn map_get(value: &Value, key: Value) -> Option<&Value> {
+    match value {
+        Value::Map(map) => map.get(&key),
+        _ => None,
+    }
+}
+
+fn invalid(msg: &str) -> error::Error {
+    error::Error::WebauthnError {
+        msg: format!("Invalid passkey: {}", msg),
+    }
+}
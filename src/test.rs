@@ -3,19 +3,56 @@ use cucumber::{
     after, before, steps, CucumberBuilder, DefaultOutput, OutputVisitor, Scenario, Steps,
 };
 use futures::future::TryFutureExt;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use serde_cbor::Value;
 use slog::{info, Logger};
 use slog::{o, Drain};
 use snafu::futures::try_future::TryFutureExt as SnafuTryFutureExt;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::thread;
 
 use super::server::run_server;
-use users::api::client::blocking::{add_user, find_user_by_username, list_users};
-use users::api::users::{MultiUsersResponseBody, SingleUserResponseBody, UserRequestBody};
+use users::api::audit::{AuditEventFilter, MultiAuditEventsResponseBody};
+use users::api::client::blocking::{
+    add_member, add_user, audit_events, begin_passkey_login, begin_passkey_registration,
+    change_password, confirm_totp, consume_magic_link, content_for_admin, content_for_user,
+    create_oauth_client, create_organization, create_role, create_service_client, deactivate_user,
+    delete_service_client, delete_user, enroll_totp, erase_user, export_my_data,
+    find_user_by_username, finish_passkey_login, finish_passkey_registration, grant_role,
+    list_users, login_user, logout_user, me, purge_user, reactivate_user, refresh_token,
+    register_user, request_magic_link, request_password_reset, reset_password, revoke_role,
+    search_users, switch_organization, unlock_user, update_profile, user_by_email, user_by_id,
+    verify_email, verify_mfa,
+};
+use users::api::model::Pagination;
+use users::api::model::{OauthClient, Organization, ServiceClient, Tombstone};
+use users::api::oauth_clients::OauthClientRequestBody;
+use users::api::passkeys::{
+    PasskeyLoginOptions, PasskeyLoginRequestBody, PasskeyRegistrationOptions,
+    PasskeyRegistrationRequestBody,
+};
+use users::api::roles::RoleRequestBody;
+use users::api::service_clients::ServiceClientRequestBody;
+use users::api::users::{
+    AuthenticatedUserResponseBody, CredentialsRequestBody, LoginResponseBody,
+    MultiUsersResponseBody, OrderDirection, SingleUserResponseBody, UserConnection, UserFilter,
+    UserOrder, UserOrderField, UserRequestBody,
+};
+use users::auth::client_credentials::ClientCredentialsResponse;
+use users::auth::oidc::TokenResponse;
+use users::auth::permission::Permission;
+use users::auth::random_token;
+use users::auth::role::Role;
+use users::db::model::EntityId;
 use users::db::pg;
 use users::error;
 use users::settings::Settings;
+use users::state::audit::AuditAction;
+use users::state::oidc::UserInfo;
 use users::state::state::State;
+use users::state::totp;
 use users::utils::{construct_headers, get_database_url, get_service_url};
 
 #[allow(clippy::needless_lifetimes)]
@@ -82,8 +119,32 @@ fn get_cucumber_instance() -> CucumberBuilder<MyWorld, DefaultOutput> {
 }
 
 pub struct MyWorld {
-    multi_resp: Option<MultiUsersResponseBody>,
+    audit_resp: Option<MultiAuditEventsResponseBody>,
+    multi_resp: Option<UserConnection>,
+    search_resp: Option<MultiUsersResponseBody>,
     single_resp: Option<SingleUserResponseBody>,
+    auth_resp: Option<AuthenticatedUserResponseBody>,
+    refresh_tokens: Vec<String>,
+    mfa_challenge: Option<String>,
+    mfa_code: Option<String>,
+    totp_secret: Option<String>,
+    recovery_codes: Vec<String>,
+    authenticator: Option<Authenticator>,
+    magic_link: Option<String>,
+    magic_link_binding: Option<String>,
+    passkey_login: Option<PasskeyLoginRequestBody>,
+    oauth_client: Option<OauthClient>,
+    code_verifier: Option<String>,
+    authorization_code: Option<String>,
+    oidc_configuration: Option<serde_json::Value>,
+    oidc_tokens: Option<TokenResponse>,
+    service_client: Option<ServiceClient>,
+    service_client_secret: Option<String>,
+    machine_token: Option<ClientCredentialsResponse>,
+    admin_token: Option<String>,
+    organization: Option<Organization>,
+    export: Option<serde_json::Value>,
+    tombstone: Option<Tombstone>,
     error: Option<String>,
 }
 
@@ -93,8 +154,32 @@ impl std::default::Default for MyWorld {
     fn default() -> MyWorld {
         // This function is called every time a new scenario is started
         MyWorld {
+            audit_resp: None,
             multi_resp: None,
+            search_resp: None,
             single_resp: None,
+            auth_resp: None,
+            refresh_tokens: Vec::new(),
+            mfa_challenge: None,
+            mfa_code: None,
+            totp_secret: None,
+            recovery_codes: Vec::new(),
+            authenticator: None,
+            magic_link: None,
+            magic_link_binding: None,
+            passkey_login: None,
+            oauth_client: None,
+            code_verifier: None,
+            authorization_code: None,
+            oidc_configuration: None,
+            oidc_tokens: None,
+            service_client: None,
+            service_client_secret: None,
+            machine_token: None,
+            admin_token: None,
+            organization: None,
+            export: None,
+            tombstone: None,
             error: None,
         }
     }
@@ -110,15 +195,119 @@ steps!(MyWorld => {
             username: matches[1].clone(),
             email: matches[2].clone(),
             password: matches[3].clone(),
+            organization: None,
+        };
+        match add_user(user, world.admin_token.clone()) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    given regex r"I have registered a user with username (.*) and email (.*) and password (.*)$" |world, matches, _step| {
+        let user = UserRequestBody {
+            username: matches[1].clone(),
+            email: matches[2].clone(),
+            password: matches[3].clone(),
+            organization: None,
         };
-        match add_user(user) {
+        match register_user(user) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    given "I have verified my email" |world, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        match verify_email(mailed_code(&user.email, "verification code:")) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    given "I am logged in as an administrator" |world, _step| {
+        let user = UserRequestBody {
+            username: String::from("admin"),
+            email: String::from("admin@secret.org"),
+            password: String::from("4dm1n"),
+            organization: None,
+        };
+        register_user(user).expect("admin registration");
+        verify_email(mailed_code("admin@secret.org", "verification code:")).expect("admin email verification");
+        grant_global_role("default", String::from("admin"), Role::Admin);
+        let credentials = CredentialsRequestBody {
+            username: String::from("admin"),
+            password: String::from("4dm1n"),
+            organization: None,
+        };
+        let resp = login_user(credentials).expect("admin login");
+        let token = resp.token.expect("an administrator without MFA");
+        world.admin_token = Some(token.clone());
+        world.auth_resp = Some(AuthenticatedUserResponseBody {
+            user: resp.user,
+            token,
+            refresh_token: resp.refresh_token.expect("a refresh token"),
+        });
+    };
+
+    given regex r"I have added the users (.*)$" |world, matches, _step| {
+        for username in matches[1].split(", ") {
+            let user = UserRequestBody {
+                username: String::from(username),
+                email: format!("{}@secret.org", username),
+                password: String::from("s3cr3t"),
+                organization: None,
+            };
+            add_user(user, world.admin_token.clone()).expect("user creation");
+        }
+    };
+
+    given regex r"I have created the organization (.*)$" |world, matches, _step| {
+        create_organization(matches[1].clone(), world.admin_token.clone().expect("an admin"))
+            .expect("organization creation");
+    };
+
+    when regex r"I create the organization (.*) with my token$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match create_organization(matches[1].clone(), token) {
+            Ok(resp) => { world.organization = resp.organization; }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I switch to the organization" |world, _step| {
+        let organization = world.organization.as_ref().expect("an organization");
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match switch_organization(organization.id, token) {
+            Ok(resp) => {
+                world.refresh_tokens.push(resp.refresh_token.clone());
+                world.auth_resp = Some(resp);
+            }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I add the user as a member" |world, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        if let Err(err) = add_member(user.id, vec![String::from("user")], world.admin_token.clone().expect("an admin")) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I register a user with username (.*) and email (.*) and password (.*) in organization (.*)$" |world, matches, _step| {
+        let user = UserRequestBody {
+            username: matches[1].clone(),
+            email: matches[2].clone(),
+            password: matches[3].clone(),
+            organization: Some(matches[4].clone()),
+        };
+        match register_user(user) {
             Ok(resp) => { world.single_resp = Some(resp); }
             Err(err) => { world.error = Some(format!("{}", err)); }
         }
     };
 
     when "I list users" |world, _step| {
-        match list_users() {
+        match list_users(None, None, Pagination::default(), world.admin_token.clone()) {
             Ok(resp) => { world.multi_resp = Some(resp); }
             Err(err) => {
                 println!("Could not deserialize server's response {}", err);
@@ -126,13 +315,92 @@ steps!(MyWorld => {
         }
     };
 
+    when regex r"I list the first (\d+) users$" |world, matches, _step| {
+        let pagination = Pagination {
+            first: Some(matches[1].parse::<i32>().unwrap()),
+            ..Pagination::default()
+        };
+        match list_users(None, None, pagination, world.admin_token.clone()) {
+            Ok(resp) => { world.multi_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I list the next (\d+) users$" |world, matches, _step| {
+        let resp = world.multi_resp.as_ref().expect("a page of users");
+        let pagination = Pagination {
+            first: Some(matches[1].parse::<i32>().unwrap()),
+            after: resp.page_info.end_cursor.clone(),
+            ..Pagination::default()
+        };
+        match list_users(None, None, pagination, world.admin_token.clone()) {
+            Ok(resp) => { world.multi_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I list the (\d+) users before the first one$" |world, matches, _step| {
+        let resp = world.multi_resp.as_ref().expect("a page of users");
+        let pagination = Pagination {
+            last: Some(matches[1].parse::<i32>().unwrap()),
+            before: resp.page_info.start_cursor.clone(),
+            ..Pagination::default()
+        };
+        match list_users(None, None, pagination, world.admin_token.clone()) {
+            Ok(resp) => { world.multi_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I list the (active|inactive) users$" |world, matches, _step| {
+        let filter = UserFilter {
+            active: Some(matches[1] == "active"),
+            ..UserFilter::default()
+        };
+        match list_users(Some(filter), None, Pagination::default(), world.admin_token.clone()) {
+            Ok(resp) => { world.multi_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I list users by username (ascending|descending)$" |world, matches, _step| {
+        let order = UserOrder {
+            field: UserOrderField::Username,
+            direction: if matches[1] == "ascending" { OrderDirection::Asc } else { OrderDirection::Desc },
+        };
+        match list_users(None, Some(order), Pagination::default(), world.admin_token.clone()) {
+            Ok(resp) => { world.multi_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I list the first (-?\d+) users and the last (-?\d+) users$" |world, matches, _step| {
+        let pagination = Pagination {
+            first: Some(matches[1].parse::<i32>().unwrap()),
+            last: Some(matches[2].parse::<i32>().unwrap()),
+            ..Pagination::default()
+        };
+        match list_users(None, None, pagination, world.admin_token.clone()) {
+            Ok(resp) => { world.multi_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I search users for (.*)$" |world, matches, _step| {
+        match search_users(matches[1].clone(), None, world.admin_token.clone()) {
+            Ok(resp) => { world.search_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
     when regex r"I add a new user with username (.*) and email (.*) and password (.*)$" |world, matches, _step| {
         let user = UserRequestBody {
             username: matches[1].clone(),
             email: matches[2].clone(),
             password: matches[3].clone(),
+            organization: None,
         };
-        match add_user(user) {
+        match add_user(user, world.admin_token.clone()) {
             Ok(resp) => { world.single_resp = Some(resp); }
             Err(err) => { world.error = Some(format!("{}", err)); }
         }
@@ -143,8 +411,9 @@ steps!(MyWorld => {
             username: String::from(""),
             email: matches[1].clone(),
             password: matches[2].clone(),
+            organization: None,
         };
-        match add_user(user) {
+        match add_user(user, world.admin_token.clone()) {
             Ok(resp) => { world.single_resp = Some(resp); }
             Err(err) => { world.error = Some(format!("{}", err)); }
         }
@@ -193,16 +462,570 @@ steps!(MyWorld => {
 
     when regex r"I search for a user with username (.*)$" |world, matches, _step| {
         let username = matches[1].clone();
-        match find_user_by_username(username) {
+        match find_user_by_username(username, world.admin_token.clone()) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I fail to login as (.*) (\d+) times$" |_world, matches, _step| {
+        let times = matches[2].parse::<usize>().expect("a number of logins");
+        for _ in 0..times {
+            let credentials = CredentialsRequestBody {
+                username: matches[1].clone(),
+                password: String::from("wrong"),
+                organization: None,
+            };
+            assert!(login_user(credentials).is_err());
+        }
+    };
+
+    when regex r"I login with username (.*) and password (.*)$" |world, matches, _step| {
+        let credentials = CredentialsRequestBody {
+            username: matches[1].clone(),
+            password: matches[2].clone(),
+            organization: None,
+        };
+        match login_user(credentials) {
+            Ok(resp) => logged_in(world, resp),
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    given regex r"I have registered the OpenID Connect client (.*) with redirect URI (.*)$" |world, matches, _step| {
+        let client = OauthClientRequestBody {
+            name: matches[1].clone(),
+            redirect_uris: vec![matches[2].clone()],
+        };
+        let resp = create_oauth_client(client, world.admin_token.clone().expect("an admin"))
+            .expect("OAuth client registration");
+        world.oauth_client = resp.client;
+    };
+
+    when regex r"I register the OpenID Connect client (.*) with redirect URI (.*)$" |world, matches, _step| {
+        let client = OauthClientRequestBody {
+            name: matches[1].clone(),
+            redirect_uris: vec![matches[2].clone()],
+        };
+        match create_oauth_client(client, world.admin_token.clone().expect("an admin")) {
+            Ok(resp) => { world.oauth_client = resp.client; }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I fetch the OpenID Connect configuration" |world, _step| {
+        let url = format!("{}/.well-known/openid-configuration", service_root());
+        let configuration = block_on(async move {
+            reqwest::get(&url).await.expect("a response").json::<serde_json::Value>().await
+        });
+        world.oidc_configuration = Some(configuration.expect("the configuration"));
+    };
+
+    when regex r"I authorize the client as (\S+) with password (\S+)(?: and redirect URI (.*))?$" |world, matches, _step| {
+        let client = world.oauth_client.as_ref().expect("an OAuth client");
+        let redirect_uri = if matches[3].is_empty() { client.redirect_uris[0].clone() } else { matches[3].clone() };
+        let verifier = random_token(64);
+        match authorize(client.id, &redirect_uri, &matches[1], &matches[2], &verifier) {
+            Ok(code) => { world.authorization_code = Some(code); }
+            Err(err) => { world.error = Some(err); }
+        }
+        world.code_verifier = Some(verifier);
+    };
+
+    when regex r"I exchange the (?:same )?authorization code( with another code verifier)?$" |world, matches, _step| {
+        let client = world.oauth_client.as_ref().expect("an OAuth client");
+        let code = world.authorization_code.clone().expect("an authorization code");
+        let verifier = if matches[1].is_empty() {
+            world.code_verifier.clone().expect("a code verifier")
+        } else {
+            random_token(64)
+        };
+        match exchange_code(client.id, &client.redirect_uris[0], &code, &verifier) {
+            Ok(tokens) => { world.oidc_tokens = Some(tokens); }
+            Err(err) => { world.error = Some(err); }
+        }
+    };
+
+    given regex r"I have registered the service client (.*) with scopes (.*)$" |world, matches, _step| {
+        let client = ServiceClientRequestBody {
+            name: matches[1].clone(),
+            scopes: matches[2]
+                .split_whitespace()
+                .map(|scope| scope.parse::<Permission>().expect("a permission"))
+                .collect(),
+        };
+        let resp = create_service_client(client, world.admin_token.clone().expect("an admin"))
+            .expect("service client registration");
+        world.service_client = Some(resp.client);
+        world.service_client_secret = Some(resp.secret);
+    };
+
+    when "I delete the service client" |world, _step| {
+        let client = world.service_client.as_ref().expect("a service client");
+        if let Err(err) = delete_service_client(client.id, world.admin_token.clone().expect("an admin")) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"the service client requests a token( with a wrong secret)?(?: for scope (.*))?$" |world, matches, _step| {
+        let client = world.service_client.as_ref().expect("a service client");
+        let secret = if matches[1].is_empty() {
+            world.service_client_secret.clone().expect("a client secret")
+        } else {
+            random_token(48)
+        };
+        let scope = Some(matches[2].clone()).filter(|scope| !scope.is_empty());
+        match client_credentials_token(client.id, &secret, scope) {
+            Ok(token) => { world.machine_token = Some(token); }
+            Err(err) => { world.error = Some(err); }
+        }
+    };
+
+    when "the service client looks up the user by id" |world, _step| {
+        let token = world.machine_token.as_ref().expect("a machine token").access_token.clone();
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        match user_by_id(user.id, token) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "the service client asks who it is" |world, _step| {
+        let token = world.machine_token.as_ref().expect("a machine token").access_token.clone();
+        match me(token) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I request a (browser bound )?magic link for (.*)$" |world, matches, _step| {
+        let bind_browser = !matches[1].is_empty();
+        match request_magic_link(matches[2].clone(), bind_browser) {
+            Ok(resp) => { world.magic_link_binding = resp.binding; }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I follow the magic link sent to (.*?)( in another browser)?$" |world, matches, _step| {
+        let token = mailed_code(&matches[1], "login code:");
+        world.magic_link = Some(token.clone());
+        let binding = if matches[2].is_empty() { world.magic_link_binding.clone() } else { None };
+        match consume_magic_link(token, binding) {
+            Ok(resp) => logged_in(world, resp),
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I follow (the same|a forged) magic link$" |world, matches, _step| {
+        let mut token = world.magic_link.clone().expect("a magic link");
+        if matches[1] == "a forged" {
+            // Push the expiry back, which the signature no longer matches.
+            let mut parts: Vec<String> = token.split('.').map(String::from).collect();
+            parts[1] = (parts[1].parse::<i64>().expect("an expiry") + 3600).to_string();
+            token = parts.join(".");
+        }
+        match consume_magic_link(token, world.magic_link_binding.clone()) {
+            Ok(resp) => logged_in(world, resp),
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I enroll in TOTP" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        let resp = enroll_totp(token).expect("TOTP enrollment");
+        assert!(resp.uri.starts_with("otpauth://totp/"));
+        world.totp_secret = Some(resp.secret);
+    };
+
+    when "I confirm TOTP with a valid code" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        let secret = world.totp_secret.as_ref().expect("a TOTP secret");
+        let code = totp::code(secret, totp::step(chrono::Utc::now())).expect("a TOTP code");
+        match confirm_totp(code, token) {
+            Ok(resp) => { world.recovery_codes = resp.recovery_codes; }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I register a passkey" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        let options = begin_passkey_registration(token.clone()).expect("passkey registration options");
+        let authenticator = world.authenticator.get_or_insert_with(Authenticator::new);
+        let credential = authenticator.register(&options);
+        if let Err(err) = finish_passkey_registration(credential, token) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I login with my passkey as (.*)$" |world, matches, _step| {
+        let authenticator = world.authenticator.as_mut().expect("an authenticator");
+        match begin_passkey_login(matches[1].clone()) {
+            Ok(options) => {
+                let credential = authenticator.login(&options);
+                world.passkey_login = Some(credential.clone());
+                match finish_passkey_login(credential) {
+                    Ok(resp) => { world.auth_resp = Some(resp); }
+                    Err(err) => { world.error = Some(format!("{}", err)); }
+                }
+            }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I login with another authenticator as (.*)$" |world, matches, _step| {
+        let authenticator = world.authenticator.as_ref().expect("an authenticator");
+        // Same passkey id, different key.
+        let mut forger = Authenticator::new();
+        forger.credential_id = authenticator.credential_id.clone();
+        let options = begin_passkey_login(matches[1].clone()).expect("passkey login options");
+        if let Err(err) = finish_passkey_login(forger.login(&options)) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when "I replay my passkey login" |world, _step| {
+        let credential = world.passkey_login.clone().expect("a passkey login");
+        world.auth_resp = None;
+        match finish_passkey_login(credential) {
+            Ok(resp) => { world.auth_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I confirm TOTP with an invalid code" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        if let Err(err) = confirm_totp(String::from("000000"), token) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I verify the login with (a TOTP code|a recovery code|the same code|a wrong code)$" |world, matches, _step| {
+        let challenge = world.mfa_challenge.clone().expect("an MFA challenge");
+        let code = match matches[1].as_str() {
+            "a TOTP code" => {
+                let secret = world.totp_secret.as_ref().expect("a TOTP secret");
+                totp::code(secret, totp::step(chrono::Utc::now())).expect("a TOTP code")
+            }
+            "a recovery code" => world.recovery_codes.first().expect("a recovery code").clone(),
+            "the same code" => world.mfa_code.clone().expect("a code already used"),
+            _ => String::from("wrong"),
+        };
+        world.mfa_code = Some(code.clone());
+        match verify_mfa(challenge, code) {
+            Ok(resp) => {
+                world.refresh_tokens.push(resp.refresh_token.clone());
+                world.auth_resp = Some(resp);
+            }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I refresh my token" |world, _step| {
+        let token = world.refresh_tokens.last().expect("a refresh token").clone();
+        match refresh_token(token) {
+            Ok(resp) => {
+                world.refresh_tokens.push(resp.refresh_token.clone());
+                world.auth_resp = Some(resp);
+            }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I refresh my token with my first refresh token" |world, _step| {
+        let token = world.refresh_tokens.first().expect("a refresh token").clone();
+        match refresh_token(token) {
+            Ok(resp) => { world.auth_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I list users with my token" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match list_users(None, None, Pagination::default(), Some(token)) {
+            Ok(resp) => { world.multi_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I create a role (.*) allowed to read users$" |world, matches, _step| {
+        let role = RoleRequestBody {
+            name: matches[1].clone(),
+            description: None,
+            permissions: vec![Permission::UsersRead],
+        };
+        if let Err(err) = create_role(role, world.admin_token.clone().expect("an admin")) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I create a role (.*) allowed to read users with my token$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        let role = RoleRequestBody {
+            name: matches[1].clone(),
+            description: None,
+            permissions: vec![Permission::UsersRead],
+        };
+        if let Err(err) = create_role(role, token) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    given regex r"the user has the role (.*) in every organization$" |world, matches, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        let role = matches[1].parse::<Role>().expect("a built-in role");
+        grant_global_role("default", user.username.clone(), role);
+    };
+
+    when regex r"I grant the role (.*) to the user$" |world, matches, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        if let Err(err) = grant_role(user.id, matches[1].clone(), world.admin_token.clone().expect("an admin")) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I revoke the role (.*) from the user$" |world, matches, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        if let Err(err) = revoke_role(user.id, matches[1].clone(), world.admin_token.clone().expect("an admin")) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I (deactivate|reactivate|unlock|soft delete|purge) the user( with my token)?$" |world, matches, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        let token = if matches[2].is_empty() {
+            world.admin_token.clone().expect("an admin")
+        } else {
+            world.auth_resp.as_ref().expect("an authenticated user").token.clone()
+        };
+        let res = match matches[1].as_str() {
+            "deactivate" => deactivate_user(user.id, token).map(|_| ()),
+            "reactivate" => reactivate_user(user.id, token).map(|_| ()),
+            "unlock" => unlock_user(user.id, token).map(|_| ()),
+            "soft delete" => delete_user(user.id, true, token).map(|_| ()),
+            _ => purge_user(user.id, token).map(|_| ()),
+        };
+        if let Err(err) = res {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I export my data (\d+) times$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        let times = matches[1].parse::<usize>().expect("a number of exports");
+        for _ in 0..times {
+            if let Err(err) = export_my_data(token.clone()) {
+                world.error = Some(format!("{}", err));
+            }
+        }
+    };
+
+    when regex r"I export my data (\d+) times in a (single request|batch)$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        let times = matches[1].parse::<usize>().expect("a number of exports");
+        let body = if matches[2] == "batch" {
+            serde_json::Value::Array(
+                (0..times).map(|_| serde_json::json!({ "query": "query { exportMyData { document } }" })).collect(),
+            )
+        } else {
+            let fields = (0..times).map(|i| format!("export{}: exportMyData {{ document }}", i)).collect::<Vec<_>>();
+            serde_json::json!({ "query": format!("query {{ {} }}", fields.join(" ")) })
+        };
+        if let Err(err) = graphql(body, &token) {
+            world.error = Some(err);
+        }
+    };
+
+    when "I export my data" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match export_my_data(token) {
+            Ok(resp) => { world.export = Some(serde_json::from_str(&resp.document).expect("a JSON document")); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I erase the user" |world, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        match erase_user(user.id, world.admin_token.clone().expect("an admin")) {
+            Ok(resp) => { world.tombstone = Some(resp.tombstone); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I erase my account" |world, _step| {
+        let resp = world.auth_resp.as_ref().expect("an authenticated user");
+        match erase_user(resp.user.id, resp.token.clone()) {
+            Ok(resp) => { world.tombstone = Some(resp.tombstone); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I list the audit events" |world, _step| {
+        match audit_events(None, None, world.admin_token.clone().expect("an admin")) {
+            Ok(resp) => { world.audit_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I list the ([a-z]+\.[a-z_]+) audit events$" |world, matches, _step| {
+        let filter = AuditEventFilter {
+            action: Some(matches[1].parse::<AuditAction>().expect("an audit action")),
+            ..AuditEventFilter::default()
+        };
+        match audit_events(Some(filter), None, world.admin_token.clone().expect("an admin")) {
+            Ok(resp) => { world.audit_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I list the audit events with my token" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match audit_events(None, None, token) {
+            Ok(resp) => { world.audit_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I verify my email(?: again)?$" |world, _matches, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        if let Err(err) = verify_email(mailed_code(&user.email, "verification code:")) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I verify the email sent to (.*)$" |world, matches, _step| {
+        match verify_email(mailed_code(&matches[1], "verification code:")) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I request a password reset" |world, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        if let Err(err) = request_password_reset(user.email.clone()) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I request a password reset for (.*)$" |world, matches, _step| {
+        if let Err(err) = request_password_reset(matches[1].clone()) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I reset my password to (.*)$" |world, matches, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        let code = mailed_code(&user.email, "reset code:");
+        if let Err(err) = reset_password(code, matches[1].clone()) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I change my password from (.*) to (.*)$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        if let Err(err) = change_password(matches[1].clone(), matches[2].clone(), token) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
+    when regex r"I change my username to (.*)$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match update_profile(Some(matches[1].clone()), None, token) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I change my email to (.*)$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match update_profile(None, Some(matches[1].clone()), token) {
             Ok(resp) => { world.single_resp = Some(resp); }
             Err(err) => { world.error = Some(format!("{}", err)); }
         }
     };
 
+    when "I ask who I am" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match me(token) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I look up the user by id" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        match user_by_id(user.id, token) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I look up the user with email (.*)$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match user_by_email(matches[1].clone(), token) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when regex r"I look up the user with username (.*)$" |world, matches, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        match find_user_by_username(matches[1].clone(), token) {
+            Ok(resp) => { world.single_resp = Some(resp); }
+            Err(err) => { world.error = Some(format!("{}", err)); }
+        }
+    };
+
+    when "I logout" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        if let Err(err) = logout_user(token) {
+            world.error = Some(format!("{}", err));
+        }
+    };
+
     then regex r"the response's users count is (.*)$" |world, matches, _step| {
         let count = matches[1].parse::<i32>().unwrap();
         let resp = world.multi_resp.as_ref().unwrap();
-        assert_eq!(resp.users_count,count);
+        assert_eq!(resp.total_count,count);
+    };
+
+    then regex r"the first user found is (.*)$" |world, matches, _step| {
+        let resp = world.search_resp.as_ref().expect("a search response");
+        let user = resp.users.first().expect("a user");
+        assert_eq!(user.username, matches[1]);
+    };
+
+    then "no user is found" |world, _step| {
+        let resp = world.search_resp.as_ref().expect("a search response");
+        assert_eq!(resp.users_count, 0);
+    };
+
+    then regex r"the page has (\d+) users$" |world, matches, _step| {
+        let count = matches[1].parse::<usize>().unwrap();
+        let resp = world.multi_resp.as_ref().unwrap();
+        assert_eq!(resp.edges.len(), count);
+    };
+
+    then regex r"there is (a|no) next page$" |world, matches, _step| {
+        let resp = world.multi_resp.as_ref().unwrap();
+        assert_eq!(resp.page_info.has_next_page, matches[1] == "a");
+    };
+
+    then regex r"there is (a|no) previous page$" |world, matches, _step| {
+        let resp = world.multi_resp.as_ref().unwrap();
+        assert_eq!(resp.page_info.has_previous_page, matches[1] == "a");
+    };
+
+    then regex r"the users are (.*)$" |world, matches, _step| {
+        let usernames = matches[1].split(", ").collect::<Vec<_>>();
+        let resp = world.multi_resp.as_ref().unwrap();
+        let listed = resp.edges.iter().map(|edge| edge.node.username.as_str()).collect::<Vec<_>>();
+        assert_eq!(listed, usernames);
+    };
+
+    then "I get an invalid pagination error" |world, _step| {
+        let err = world.error.as_ref().expect("an error");
+        assert!(err.find("Paginate").is_some() || err.find("page size").is_some());
     };
 
     then regex r"I can verify the username (.*) in the response" |world, matches, _step| {
@@ -214,7 +1037,193 @@ steps!(MyWorld => {
 
     then "I get a duplicate username error" |world, _step| {
         let err = world.error.as_ref().unwrap();
-        assert_ne!(err.find("Operation violates uniqueness constraint: Key (username)"), None);
+        assert_ne!(err.find("Operation violates uniqueness constraint: Key (organization_id, username)"), None);
+    };
+
+    then "my account is active" |world, _step| {
+        let resp = world.single_resp.as_ref().unwrap();
+        assert!(resp.user.as_ref().unwrap().active);
+    };
+
+    then "I get an inactive account error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Inactive Account"), None);
+    };
+
+    then "I get an invalid verification token error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Invalid or expired verification token"), None);
+    };
+
+    then "I get an invalid password reset token error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Invalid or expired password reset token"), None);
+    };
+
+    then regex r"I get a conflict error on the (.*)$" |world, matches, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Conflict Error"), None);
+        assert_ne!(err.find(&format!(r#""field":"{}""#, matches[1])), None);
+    };
+
+    then regex r"I can verify the email (.*) in the response" |world, matches, _step| {
+        let resp = world.single_resp.as_ref().unwrap();
+        assert_eq!(resp.user.as_ref().unwrap().email, matches[1]);
+    };
+
+    then "I get an invalid credentials error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Invalid credentials"), None);
+    };
+
+    then "I am asked for an MFA code" |world, _step| {
+        assert!(world.mfa_challenge.is_some());
+        assert!(world.error.is_none());
+    };
+
+    then regex r"I get a passkey error: (.*)$" |world, matches, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find(matches[1].as_str()), None);
+    };
+
+    then regex r"the OpenID Connect (issuer|token endpoint) is (.*)$" |world, matches, _step| {
+        let configuration = world.oidc_configuration.as_ref().expect("the configuration");
+        let field = if matches[1] == "issuer" { "issuer" } else { "token_endpoint" };
+        assert_eq!(configuration[field], matches[2].as_str());
+    };
+
+    then regex r"I receive an ID token for (.*)$" |world, matches, _step| {
+        let tokens = world.oidc_tokens.as_ref().expect("tokens");
+        let client = world.oauth_client.as_ref().expect("an OAuth client");
+        let claims = token_claims(&tokens.id_token);
+        assert_eq!(claims["aud"], client.id.to_string());
+        assert_eq!(claims["nonce"], OIDC_NONCE);
+        assert_eq!(claims["preferred_username"], matches[1].as_str());
+    };
+
+    then regex r"the user info is for (.*)$" |world, matches, _step| {
+        let tokens = world.oidc_tokens.as_ref().expect("tokens");
+        let info = user_info(&tokens.access_token).expect("the user info");
+        assert_eq!(info.profile.preferred_username.as_deref(), Some(matches[1].as_str()));
+    };
+
+    then "the access token only gives access to the user info" |world, _step| {
+        let tokens = world.oidc_tokens.as_ref().expect("tokens");
+        let res = me(tokens.access_token.clone());
+        assert!(res.is_err());
+        assert_ne!(format!("{}", res.unwrap_err()).find("only gives access to the user info"), None);
+    };
+
+    then regex r"I get an OAuth error: (.*)$" |world, matches, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find(matches[1].as_str()), None);
+    };
+
+    then "I get an invalid redirect URI error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Invalid redirect URI"), None);
+    };
+
+    then regex r"I receive a machine token for the service client with scope (.*)$" |world, matches, _step| {
+        let token = world.machine_token.as_ref().expect("a machine token");
+        let client = world.service_client.as_ref().expect("a service client");
+        assert_eq!(token.scope, matches[1]);
+        let claims = token_claims(&token.access_token);
+        assert_eq!(claims["sub"], client.id.to_string());
+        assert_eq!(claims["machine"], true);
+        assert_eq!(claims["scope"], matches[1].as_str());
+        let permissions = matches[1].split(' ').collect::<Vec<_>>();
+        assert_eq!(claims["permissions"], serde_json::json!(permissions));
+    };
+
+    then "I get an invalid magic link error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Invalid or expired magic link"), None);
+    };
+
+    then "I get a browser binding error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("must be opened in the browser which asked for it"), None);
+    };
+
+    then "I get an invalid MFA code error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Invalid MFA code"), None);
+    };
+
+    then "I get a lockout error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("LOCKED_OUT"), None);
+    };
+
+    then "I get a too many requests error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Too many requests"), None);
+    };
+
+    then "I get an unknown user error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Unknown user"), None);
+    };
+
+    then "I get an unknown service client error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Unknown service client"), None);
+    };
+
+    then regex r"my data export holds the username (.*) and (\d+) logins?$" |world, matches, _step| {
+        let export = world.export.as_ref().expect("a data export");
+        assert_eq!(export["user"]["username"], matches[1].as_str());
+        let count = matches[2].parse::<usize>().unwrap();
+        assert_eq!(export["logins"].as_array().expect("logins").len(), count);
+    };
+
+    then "my data export holds no password" |world, _step| {
+        let export = world.export.as_ref().expect("a data export");
+        assert_eq!(export.to_string().find("password"), None);
+    };
+
+    then "a tombstone records the erasure of the user" |world, _step| {
+        let tombstone = world.tombstone.as_ref().expect("a tombstone");
+        let user_id = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).map(|user| user.id)
+            .or_else(|| world.auth_resp.as_ref().map(|resp| resp.user.id))
+            .expect("a user");
+        assert_eq!(tombstone.user_id, user_id);
+    };
+
+    then regex r"the latest audit event is (.*) targeting the user$" |world, matches, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        let resp = world.audit_resp.as_ref().expect("audit events");
+        let event = resp.events.first().expect("an audit event");
+        assert_eq!(event.action, matches[1]);
+        assert_eq!(event.target_id, Some(user.id));
+    };
+
+    then regex r"the audit file records (.*) of the user$" |world, matches, _step| {
+        let user = world.single_resp.as_ref().and_then(|resp| resp.user.as_ref()).expect("a user");
+        let settings = Settings::new(None).expect("settings");
+        let path = settings.audit.path.expect("an audit file");
+        let lines = std::fs::read_to_string(path).expect("the audit file");
+        let target_id = user.id.to_string();
+        assert!(lines.lines().any(|line| {
+            let event: serde_json::Value = serde_json::from_str(line).expect("an audit event");
+            event["action"] == matches[1].as_str() && event["targetId"] == target_id.as_str()
+        }));
+    };
+
+    then "I get no error" |world, _step| {
+        assert_eq!(world.error, None);
+    };
+
+    then regex r"I cannot login with username (.*) and password (.*)$" |_world, matches, _step| {
+        let credentials = CredentialsRequestBody {
+            username: matches[1].clone(),
+            password: matches[2].clone(),
+            organization: None,
+        };
+        let res = login_user(credentials);
+        assert!(res.is_err());
+        assert_ne!(format!("{}", res.unwrap_err()).find("Invalid credentials"), None);
     };
 
     then "I get a model violation error" |world, _step| {
@@ -227,6 +1236,74 @@ steps!(MyWorld => {
         assert_ne!(err.find("Invalid request header"), None);
     };
 
+    then "I receive a token and a refresh token" |world, _step| {
+        let resp = world.auth_resp.as_ref().unwrap();
+        assert!(!resp.token.is_empty());
+        assert!(!resp.refresh_token.is_empty());
+    };
+
+    then "my token identifies me" |world, _step| {
+        let resp = world.auth_resp.as_ref().unwrap();
+        let payload = resp.token.split('.').nth(1).expect("a jwt payload");
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).unwrap();
+        let claims: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(claims["sub"], resp.user.id.to_string());
+        assert_eq!(claims["iss"], "https://auth.acme.com");
+        assert_eq!(claims["aud"], "https://api.acme.com");
+        assert!(claims["nbf"].is_number());
+        assert!(claims["iat"].is_number());
+        assert!(claims["jti"].is_string());
+    };
+
+    then "I receive a new refresh token" |world, _step| {
+        assert_eq!(world.refresh_tokens.len(), 2);
+        assert_ne!(world.refresh_tokens[0], world.refresh_tokens[1]);
+    };
+
+    then "I get a refresh token reuse error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Refresh token reuse detected"), None);
+    };
+
+    then "I cannot refresh my token with my latest refresh token" |world, _step| {
+        let token = world.refresh_tokens.last().expect("a refresh token").clone();
+        let res = refresh_token(token);
+        assert!(res.is_err());
+        assert_ne!(format!("{}", res.unwrap_err()).find("Invalid refresh token"), None);
+    };
+
+    then "I can access content for users" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        assert!(content_for_user(token).is_ok());
+    };
+
+    then "I cannot access content for users" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        assert!(content_for_user(token).is_err());
+    };
+
+    then "I can list users with my token" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        assert!(list_users(None, None, Pagination::default(), Some(token)).is_ok());
+    };
+
+    then "I can access content for admins" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        assert!(content_for_admin(token).is_ok());
+    };
+
+    then "I cannot access content for admins" |world, _step| {
+        let token = world.auth_resp.as_ref().expect("an authenticated user").token.clone();
+        let res = content_for_admin(token);
+        assert!(res.is_err());
+        assert_ne!(format!("{}", res.unwrap_err()).find("requires the content:admin permission"), None);
+    };
+
+    then "I get an authorization error" |world, _step| {
+        let err = world.error.as_ref().unwrap();
+        assert_ne!(err.find("Authorization Error"), None);
+    };
+
     then "I can verify the user does not exists" |world, _step| {
         let resp = world.single_resp.as_ref().unwrap();
         assert!(resp.user.is_none())
@@ -244,6 +1321,377 @@ after!(an_after_fn => |_scenario| {
 
 });
 
+// Grant a role directly in the database, the way an operator bootstraps the first admin.
+fn grant_global_role(organization: &'static str, username: String, role: Role) {
+    let db_url = get_database_url();
+    let handle = tokio::runtime::Handle::current();
+    let th = std::thread::spawn(move || {
+        handle.block_on(async { pg::grant_role(&db_url, organization, &username, role).await })
+    });
+    th.join()
+        .expect("Waiting for role grant to complete")
+        .expect("Could not grant role");
+}
+
+// Keep the tokens of a login, or the challenge of a user with MFA.
+fn logged_in(world: &mut MyWorld, resp: LoginResponseBody) {
+    match resp {
+        LoginResponseBody {
+            user,
+            token: Some(token),
+            refresh_token: Some(refresh_token),
+            ..
+        } => {
+            world.refresh_tokens.push(refresh_token.clone());
+            world.auth_resp = Some(AuthenticatedUserResponseBody {
+                user,
+                token,
+                refresh_token,
+            });
+        }
+        resp => {
+            world.auth_resp = None;
+            world.mfa_challenge = resp.mfa_required;
+        }
+    }
+}
+
+// Where the file mailer writes the emails, one file per recipient, holding the last
+// email it received.
+fn mailbox() -> PathBuf {
+    let settings = Settings::new(None).expect("settings");
+    PathBuf::from(settings.mailer.path.expect("a mailbox"))
+}
+
+// The code following the marker in the last email sent to this address.
+// Some emails are sent in the background, so we wait a little for them.
+fn mailed_code(email: &str, marker: &str) -> String {
+    let path: PathBuf = mailbox().join(email);
+    for _ in 0..50 {
+        if let Ok(email) = std::fs::read_to_string(&path) {
+            let mut lines = email.lines();
+            if let Some(code) = lines
+                .find(|line| line.ends_with(marker))
+                .and_then(|_| lines.next())
+            {
+                return String::from(code.trim());
+            }
+        }
+        thread::sleep(std::time::Duration::from_millis(100));
+    }
+    panic!("No email with a {} for {}", marker, email);
+}
+
+// Run a request from a step, on the runtime of the service.
+fn block_on<F>(future: F) -> F::Output
+where
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let handle = tokio::runtime::Handle::current();
+    thread::spawn(move || handle.block_on(future))
+        .join()
+        .expect("Waiting for the request to complete")
+}
+
+// Where the OpenID Connect endpoints are served.
+fn service_root() -> String {
+    get_service_url().trim_end_matches("/graphql").to_owned()
+}
+
+const OIDC_STATE: &str = "af0ifjsldkj";
+const OIDC_NONCE: &str = "n-0S6_WzA2Mj";
+
+// Post the login form of the authorization endpoint, as the browser would, and return
+// the code the user is sent back to the client with. Otherwise, return the error sent
+// back to the client, or the page shown to the user.
+fn authorize(
+    client_id: EntityId,
+    redirect_uri: &str,
+    username: &str,
+    password: &str,
+    verifier: &str,
+) -> Result<String, String> {
+    let challenge = ring::digest::digest(&ring::digest::SHA256, verifier.as_bytes());
+    let form = vec![
+        ("response_type", String::from("code")),
+        ("client_id", client_id.to_string()),
+        ("redirect_uri", String::from(redirect_uri)),
+        ("scope", String::from("openid profile email")),
+        ("state", String::from(OIDC_STATE)),
+        ("nonce", String::from(OIDC_NONCE)),
+        (
+            "code_challenge",
+            base64::encode_config(challenge.as_ref(), base64::URL_SAFE_NO_PAD),
+        ),
+        ("code_challenge_method", String::from("S256")),
+        ("username", String::from(username)),
+        ("password", String::from(password)),
+    ];
+    let url = format!("{}/authorize", service_root());
+    block_on(async move {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|err| err.to_string())?;
+        let resp = client
+            .post(&url)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let location = match resp.headers().get(reqwest::header::LOCATION) {
+            Some(location) => String::from(location.to_str().map_err(|err| err.to_string())?),
+            None => return Err(resp.text().await.map_err(|err| err.to_string())?),
+        };
+        let query = location.splitn(2, '?').nth(1).unwrap_or_default();
+        let params: HashMap<String, String> =
+            serde_urlencoded::from_str(query).map_err(|err| err.to_string())?;
+        assert_eq!(params.get("state").map(String::as_str), Some(OIDC_STATE));
+        match (params.get("code"), params.get("error")) {
+            (Some(code), _) => Ok(code.clone()),
+            (None, Some(error)) => Err(error.clone()),
+            (None, None) => Err(location),
+        }
+    })
+}
+
+// The claims of a token. A client would check the signature, we only read them.
+fn token_claims(token: &str) -> serde_json::Value {
+    let payload = token.split('.').nth(1).expect("a payload");
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).expect("base64url");
+    serde_json::from_slice(&payload).expect("the claims")
+}
+
+// Exchange the code at the token endpoint. Errors are returned as their OAuth code.
+fn exchange_code(
+    client_id: EntityId,
+    redirect_uri: &str,
+    code: &str,
+    verifier: &str,
+) -> Result<TokenResponse, String> {
+    let form = vec![
+        ("grant_type", String::from("authorization_code")),
+        ("code", String::from(code)),
+        ("redirect_uri", String::from(redirect_uri)),
+        ("client_id", client_id.to_string()),
+        ("code_verifier", String::from(verifier)),
+    ];
+    let url = format!("{}/token", service_root());
+    block_on(async move {
+        let resp = reqwest::Client::new()
+            .post(&url)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let status = resp.status();
+        let json = resp
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|err| err.to_string())?;
+        if status.is_success() {
+            serde_json::from_value(json).map_err(|err| err.to_string())
+        } else {
+            Err(json.to_string())
+        }
+    })
+}
+
+// Get a token with the client credentials grant, authenticating with HTTP Basic.
+// Errors are returned as their OAuth code.
+fn client_credentials_token(
+    client_id: EntityId,
+    secret: &str,
+    scope: Option<String>,
+) -> Result<ClientCredentialsResponse, String> {
+    let mut form = vec![("grant_type", String::from("client_credentials"))];
+    if let Some(scope) = scope {
+        form.push(("scope", scope));
+    }
+    let url = format!("{}/oauth/token", service_root());
+    let secret = String::from(secret);
+    block_on(async move {
+        let resp = reqwest::Client::new()
+            .post(&url)
+            .basic_auth(client_id, Some(secret))
+            .form(&form)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let status = resp.status();
+        let json = resp
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|err| err.to_string())?;
+        if status.is_success() {
+            serde_json::from_value(json).map_err(|err| err.to_string())
+        } else {
+            Err(json.to_string())
+        }
+    })
+}
+
+// Post a GraphQL request, or a batch of requests, as is.
+fn graphql(body: serde_json::Value, token: &str) -> Result<serde_json::Value, String> {
+    let token = String::from(token);
+    block_on(async move {
+        let resp = reqwest::Client::new()
+            .post(&get_service_url())
+            .bearer_auth(token)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let status = resp.status();
+        let json = resp
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|err| err.to_string())?;
+        if status.is_success() {
+            Ok(json)
+        } else {
+            Err(json.to_string())
+        }
+    })
+}
+
+fn user_info(token: &str) -> Result<UserInfo, String> {
+    let url = format!("{}/userinfo", service_root());
+    let token = String::from(token);
+    block_on(async move {
+        let resp = reqwest::Client::new()
+            .get(&url)
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let status = resp.status();
+        let json = resp
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|err| err.to_string())?;
+        if status.is_success() {
+            serde_json::from_value(json).map_err(|err| err.to_string())
+        } else {
+            Err(json.to_string())
+        }
+    })
+}
+
+// A software authenticator, with a single P-256 passkey. It always verifies its user,
+// and its counter goes up with every login.
+struct Authenticator {
+    key_pair: EcdsaKeyPair,
+    credential_id: Vec<u8>,
+    sign_count: u32,
+    origin: String,
+}
+
+impl Authenticator {
+    fn new() -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .expect("a P-256 key");
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())
+            .expect("a P-256 key pair");
+        let mut credential_id = vec![0u8; 16];
+        rng.fill(&mut credential_id).expect("a credential id");
+        let settings = Settings::new(None).expect("settings");
+        Authenticator {
+            key_pair,
+            credential_id,
+            sign_count: 0,
+            origin: settings.webauthn.origin,
+        }
+    }
+
+    fn client_data(&self, ceremony: &str, challenge: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "type": ceremony,
+            "challenge": challenge,
+            "origin": self.origin,
+            "crossOrigin": false,
+        }))
+        .expect("client data")
+    }
+
+    // The hash of the relying party id, the flags (user present and verified), and the
+    // counter.
+    fn authenticator_data(&self, rp_id: &str, flags: u8) -> Vec<u8> {
+        let mut data = ring::digest::digest(&ring::digest::SHA256, rp_id.as_bytes())
+            .as_ref()
+            .to_vec();
+        data.push(flags | 0x05);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+
+    fn register(&mut self, options: &PasskeyRegistrationOptions) -> PasskeyRegistrationRequestBody {
+        let client_data_json = self.client_data("webauthn.create", &options.challenge);
+
+        // The COSE key: EC2, ES256, P-256, and the coordinates.
+        let point = self.key_pair.public_key().as_ref();
+        let mut key = BTreeMap::new();
+        key.insert(Value::Integer(1), Value::Integer(2));
+        key.insert(Value::Integer(3), Value::Integer(-7));
+        key.insert(Value::Integer(-1), Value::Integer(1));
+        key.insert(Value::Integer(-2), Value::Bytes(point[1..33].to_vec()));
+        key.insert(Value::Integer(-3), Value::Bytes(point[33..65].to_vec()));
+
+        // The attested credential data: the AAGUID (none), the id, and the key.
+        let mut auth_data = self.authenticator_data(&options.rp_id, 0x40);
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        auth_data.extend(serde_cbor::to_vec(&Value::Map(key)).expect("a COSE key"));
+
+        let mut attestation = BTreeMap::new();
+        attestation.insert(
+            Value::Text(String::from("fmt")),
+            Value::Text(String::from("none")),
+        );
+        attestation.insert(
+            Value::Text(String::from("attStmt")),
+            Value::Map(BTreeMap::new()),
+        );
+        attestation.insert(
+            Value::Text(String::from("authData")),
+            Value::Bytes(auth_data),
+        );
+        let attestation_object =
+            serde_cbor::to_vec(&Value::Map(attestation)).expect("an attestation object");
+
+        PasskeyRegistrationRequestBody {
+            client_data_json: base64::encode_config(&client_data_json, base64::URL_SAFE_NO_PAD),
+            attestation_object: base64::encode_config(&attestation_object, base64::URL_SAFE_NO_PAD),
+            name: Some(String::from("software")),
+        }
+    }
+
+    fn login(&mut self, options: &PasskeyLoginOptions) -> PasskeyLoginRequestBody {
+        self.sign_count += 1;
+        let client_data_json = self.client_data("webauthn.get", &options.challenge);
+        let authenticator_data = self.authenticator_data(&options.rp_id, 0);
+
+        let mut message = authenticator_data.clone();
+        message.extend_from_slice(
+            ring::digest::digest(&ring::digest::SHA256, &client_data_json).as_ref(),
+        );
+        let signature = self
+            .key_pair
+            .sign(&SystemRandom::new(), &message)
+            .expect("a signature");
+
+        PasskeyLoginRequestBody {
+            credential_id: base64::encode_config(&self.credential_id, base64::URL_SAFE_NO_PAD),
+            client_data_json: base64::encode_config(&client_data_json, base64::URL_SAFE_NO_PAD),
+            authenticator_data: base64::encode_config(&authenticator_data, base64::URL_SAFE_NO_PAD),
+            signature: base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD),
+        }
+    }
+}
+
 // A setup function to be called before everything else
 pub fn setup() {
     let decorator = slog_term::TermDecorator::new().build();
@@ -253,6 +1701,7 @@ pub fn setup() {
     // let logger = slog::Logger::root(slog::Discard, o!());
     let db_url = get_database_url();
     info!(logger, "database url: {}", db_url);
+    let _ = std::fs::remove_dir_all(mailbox());
     let handle = tokio::runtime::Handle::current();
     let th = std::thread::spawn(move || {
         handle.block_on(async {